@@ -0,0 +1,281 @@
+//! RFC 6381 codec strings, as in the `codecs` of a rendition.
+
+use std::fmt;
+
+/// One entry of a codec string, e.g. `avc1.64001F`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// H.264, `avc1.PPCCLL` with profile, constraint flags and level in hex.
+    Avc {
+        profile: u8,
+        constraints: u8,
+        level: u8,
+    },
+    /// H.265, `hvc1.P.C.TL.B`; `hev1` keeps the parameter sets in the
+    /// stream instead of the sample entry.
+    Hevc {
+        hev1: bool,
+        profile: u8,
+        high_tier: bool,
+        /// 30 times the level number.
+        level: u16,
+    },
+    /// `av01.P.LLT.DD`.
+    Av1 {
+        profile: u8,
+        level: u8,
+        high_tier: bool,
+        bit_depth: u8,
+    },
+    /// `vp09.PP.LL.DD`.
+    Vp9 {
+        profile: u8,
+        /// 10 times the level number.
+        level: u8,
+        bit_depth: u8,
+    },
+    /// `mp4a.40.<object type>`.
+    Aac {
+        object_type: u8,
+    },
+    Mp3,
+    Opus,
+    Ac3,
+    Eac3,
+    Flac,
+    /// Anything not recognized, as given.
+    Unknown(String),
+}
+
+impl Codec {
+    /// Parses a single codec; `codecs` may still carry JSON quotes.
+    pub fn parse(codec: &str) -> Codec {
+        let codec = codec.trim().trim_matches('"');
+        Codec::parse_known(codec).unwrap_or_else(|| Codec::Unknown(codec.to_string()))
+    }
+
+    /// Parses a comma-separated codec list.
+    pub fn parse_list(codecs: &str) -> Vec<Codec> {
+        codecs
+            .trim_matches('"')
+            .split(',')
+            .filter(|c| !c.trim().is_empty())
+            .map(Codec::parse)
+            .collect()
+    }
+
+    fn parse_known(codec: &str) -> Option<Codec> {
+        let (tag, rest) = codec.split_once('.').unwrap_or((codec, ""));
+        let fields: Vec<_> = rest.split('.').collect();
+        Some(match tag {
+            "avc1" | "avc3" => {
+                if rest.len() != 6 {
+                    return None;
+                }
+                let byte = |i: usize| u8::from_str_radix(rest.get(i..i + 2)?, 16).ok();
+                Codec::Avc {
+                    profile: byte(0)?,
+                    constraints: byte(2)?,
+                    level: byte(4)?,
+                }
+            }
+            "hvc1" | "hev1" => {
+                let profile = fields.first()?.trim_start_matches(['A', 'B', 'C']);
+                // Manifests are not to be trusted, the tier may be missing
+                // or not a single byte.
+                let (tier, level) = fields.get(2)?.split_at_checked(1)?;
+                Codec::Hevc {
+                    hev1: tag == "hev1",
+                    profile: profile.parse().ok()?,
+                    high_tier: match tier {
+                        "L" => false,
+                        "H" => true,
+                        _ => return None,
+                    },
+                    level: level.parse().ok()?,
+                }
+            }
+            "av01" => {
+                let level_tier = fields.get(1)?;
+                if level_tier.len() != 3 {
+                    return None;
+                }
+                let (level, tier) = level_tier.split_at_checked(2)?;
+                Codec::Av1 {
+                    profile: fields.first()?.parse().ok()?,
+                    level: level.parse().ok()?,
+                    high_tier: match tier {
+                        "M" => false,
+                        "H" => true,
+                        _ => return None,
+                    },
+                    bit_depth: fields.get(2)?.parse().ok()?,
+                }
+            }
+            "vp09" => Codec::Vp9 {
+                profile: fields.first()?.parse().ok()?,
+                level: fields.get(1)?.parse().ok()?,
+                bit_depth: fields.get(2)?.parse().ok()?,
+            },
+            "mp4a" => match fields[..] {
+                ["6B" | "6b" | "69"] | ["40", "34"] => Codec::Mp3,
+                ["40", object_type] => Codec::Aac {
+                    object_type: object_type.parse().ok()?,
+                },
+                _ => return None,
+            },
+            "mp3" => Codec::Mp3,
+            "opus" | "Opus" => Codec::Opus,
+            "ac-3" => Codec::Ac3,
+            "ec-3" => Codec::Eac3,
+            "fLaC" | "flac" => Codec::Flac,
+            _ => return None,
+        })
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(
+            self,
+            Codec::Avc { .. } | Codec::Hevc { .. } | Codec::Av1 { .. } | Codec::Vp9 { .. }
+        )
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::Avc {
+                profile,
+                constraints,
+                level,
+            } => {
+                let name = match profile {
+                    // constraint_set1_flag narrows Baseline down.
+                    66 if constraints & 0x40 != 0 => "Constrained Baseline".to_string(),
+                    66 => "Baseline".to_string(),
+                    77 => "Main".to_string(),
+                    88 => "Extended".to_string(),
+                    100 => "High".to_string(),
+                    110 => "High 10".to_string(),
+                    122 => "High 4:2:2".to_string(),
+                    244 => "High 4:4:4".to_string(),
+                    profile => format!("profile {profile}"),
+                };
+                write!(f, "H.264 {name}@{}.{}", level / 10, level % 10)
+            }
+            Codec::Hevc {
+                profile,
+                high_tier,
+                level,
+                ..
+            } => {
+                let name = match profile {
+                    1 => "Main".to_string(),
+                    2 => "Main10".to_string(),
+                    3 => "Main Still Picture".to_string(),
+                    4 => "Range Extensions".to_string(),
+                    profile => format!("profile {profile}"),
+                };
+                let tier = if *high_tier { " High tier" } else { "" };
+                write!(f, "HEVC {name}@{}.{}{tier}", level / 30, level % 30 / 3)
+            }
+            Codec::Av1 {
+                profile,
+                level,
+                high_tier,
+                bit_depth,
+            } => {
+                let name = match profile {
+                    0 => "Main".to_string(),
+                    1 => "High".to_string(),
+                    2 => "Professional".to_string(),
+                    profile => format!("profile {profile}"),
+                };
+                let tier = if *high_tier { " High tier" } else { "" };
+                write!(
+                    f,
+                    "AV1 {name}@{}.{}{tier} {bit_depth}-bit",
+                    2 + (level >> 2),
+                    level & 3
+                )
+            }
+            Codec::Vp9 {
+                profile,
+                level,
+                bit_depth,
+            } => write!(
+                f,
+                "VP9 Profile {profile}@{}.{} {bit_depth}-bit",
+                level / 10,
+                level % 10
+            ),
+            Codec::Aac { object_type } => f.write_str(match object_type {
+                2 => "AAC-LC",
+                5 => "HE-AAC",
+                29 => "HE-AACv2",
+                23 => "AAC-LD",
+                39 => "AAC-ELD",
+                _ => "AAC",
+            }),
+            Codec::Mp3 => f.write_str("MP3"),
+            Codec::Opus => f.write_str("Opus"),
+            Codec::Ac3 => f.write_str("AC-3"),
+            Codec::Eac3 => f.write_str("E-AC-3"),
+            Codec::Flac => f.write_str("FLAC"),
+            Codec::Unknown(codec) => f.write_str(codec),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_codecs() {
+        assert_eq!(
+            Codec::parse("\"avc1.64001F\""),
+            Codec::Avc {
+                profile: 0x64,
+                constraints: 0,
+                level: 0x1F,
+            }
+        );
+        assert_eq!(
+            Codec::parse("hvc1.1.6.L93.B0"),
+            Codec::Hevc {
+                hev1: false,
+                profile: 1,
+                high_tier: false,
+                level: 93,
+            }
+        );
+        assert_eq!(
+            Codec::parse("av01.0.08M.10"),
+            Codec::Av1 {
+                profile: 0,
+                level: 8,
+                high_tier: false,
+                bit_depth: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_codecs_are_unknown() {
+        for codec in [
+            "hvc1.1.6.",
+            "hvc1.1.6.é93",
+            "hvc1",
+            "av01.0.aé.08",
+            "av01.0.é.08",
+            "av01.0.08X.10",
+            "avc1.6400é",
+            "avc1.64001",
+            "vp09.00.é",
+            "mp4a.40.é",
+        ] {
+            assert_eq!(Codec::parse(codec), Codec::Unknown(codec.to_string()));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! The built-in extractors.
+
+use eyre::{eyre, Result};
+use url::Url;
+
+use crate::{config_url, page_metadata, player_config, Extractor, Http, MediaInfo};
+
+/// Any page embedding the player, such as `vimeo.com/event/<id>/embed` or
+/// the site of an event host. The page is scraped for the player config.
+pub struct EventPage;
+
+impl Extractor for EventPage {
+    fn name(&self) -> &'static str {
+        "Event page"
+    }
+
+    fn matches_url(&self, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+    }
+
+    fn extract(&self, http: &mut dyn Http, url: &str, referer: &str) -> Result<MediaInfo> {
+        let page = http.get(url, Some(referer))?;
+        let mut media = player_config(http, &config_url(&page)?)?;
+        page_metadata(&page, &mut media);
+        Ok(media)
+    }
+}
+
+/// A single video, `vimeo.com/<id>`, `vimeo.com/<id>/<hash>` for unlisted
+/// ones or `player.vimeo.com/video/<id>`. Its player config has a fixed URL.
+pub struct Video;
+
+impl Video {
+    fn id_and_hash(url: &Url) -> Option<(String, Option<String>)> {
+        let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+        let id = match url.host_str()? {
+            "vimeo.com" | "www.vimeo.com" => segments.next()?,
+            "player.vimeo.com" if segments.next()? == "video" => segments.next()?,
+            _ => return None,
+        };
+        if !id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let hash = match segments.next() {
+            Some(hash) => Some(hash.to_string()),
+            None => url
+                .query_pairs()
+                .find(|(key, _)| key == "h")
+                .map(|(_, hash)| hash.into_owned()),
+        };
+        Some((id.to_string(), hash))
+    }
+}
+
+impl Extractor for Video {
+    fn name(&self) -> &'static str {
+        "Video"
+    }
+
+    fn matches_url(&self, url: &Url) -> bool {
+        Video::id_and_hash(url).is_some()
+    }
+
+    fn extract(&self, http: &mut dyn Http, url: &str, _referer: &str) -> Result<MediaInfo> {
+        let (id, hash) = Video::id_and_hash(&Url::parse(url)?)
+            .ok_or_else(|| eyre!("{url} is not a video URL!"))?;
+        let config_url = match hash {
+            Some(hash) => format!("https://player.vimeo.com/video/{id}/config?h={hash}"),
+            None => format!("https://player.vimeo.com/video/{id}/config"),
+        };
+        player_config(http, &config_url)
+    }
+}
+
+/// A showcase, `vimeo.com/showcase/<id>`, whose embed page plays its first
+/// video, or one video of it, `vimeo.com/showcase/<id>/video/<video id>`.
+pub struct Showcase;
+
+impl Showcase {
+    fn ids(url: &Url) -> Option<(&str, Option<&str>)> {
+        if !matches!(url.host_str()?, "vimeo.com" | "www.vimeo.com") {
+            return None;
+        }
+        let segments: Vec<_> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        match segments[..] {
+            ["showcase", id] | ["showcase", id, "embed"] => Some((id, None)),
+            ["showcase", id, "video", video] => Some((id, Some(video))),
+            _ => None,
+        }
+    }
+}
+
+impl Extractor for Showcase {
+    fn name(&self) -> &'static str {
+        "Showcase"
+    }
+
+    fn matches_url(&self, url: &Url) -> bool {
+        Showcase::ids(url).is_some()
+    }
+
+    fn extract(&self, http: &mut dyn Http, url: &str, referer: &str) -> Result<MediaInfo> {
+        let parsed = Url::parse(url)?;
+        let (id, video) =
+            Showcase::ids(&parsed).ok_or_else(|| eyre!("{url} is not a showcase URL!"))?;
+        let config_url = match video {
+            Some(video) => format!("https://player.vimeo.com/video/{video}/config"),
+            None => {
+                let embed_url = format!("https://vimeo.com/showcase/{id}/embed");
+                config_url(&http.get(&embed_url, Some(referer))?)?
+            }
+        };
+        player_config(http, &config_url)
+    }
+}
@@ -0,0 +1,844 @@
+//! Extraction core of vimeo-event-downloader: finding the player config of
+//! a page and the renditions listed in its manifest.
+//!
+//! Manifests come as `master.json` or, for accounts moved to Vimeo's newer
+//! player, `playlist.json`, which gives every rendition a base URL of its
+//! own. Such a CDN may list the renditions of some codecs, AV1 or HEVC, in
+//! manifests of their own next to `url`, under keys like `avc_url`; these
+//! are merged into one with [`merge_manifest`]. Either is read into a
+//! [`Manifest`] without a JSON tree in between, see manifest.rs. [`Http`]
+//! hands [`extract`] each response whole; callers doing their own requests
+//! can read a manifest as it streams in instead.
+//!
+//! Each kind of page is handled by an [`Extractor`]; the [`Registry`] picks
+//! the one for a URL. Applications can register their own extractors for
+//! pages the built-in ones do not know.
+//!
+//! The crate does no I/O of its own. Requests go through an [`Http`]
+//! callback, so it also builds for WASI, e.g. for serverless workers that
+//! only resolve stream URLs:
+//!
+//! ```sh
+//! cargo build -p vimeo-extract --target wasm32-wasip1
+//! ```
+
+use std::fmt;
+
+pub use codec::Codec;
+pub use extractors::{EventPage, Showcase, Video};
+pub use manifest::{List, Manifest, Rendition, SegmentEntry};
+
+use base64::decode;
+use eyre::{eyre, Result};
+use html_escape::decode_html_entities;
+use regex::Regex;
+use serde_json::Value;
+use url::Url;
+
+/// Sends the GET requests of the extraction.
+///
+/// Implemented for closures taking the URL and the referer to send, if any,
+/// and returning the response body.
+pub trait Http {
+    fn get(&mut self, url: &str, referer: Option<&str>) -> Result<String>;
+}
+
+impl<F: FnMut(&str, Option<&str>) -> Result<String>> Http for F {
+    fn get(&mut self, url: &str, referer: Option<&str>) -> Result<String> {
+        self(url, referer)
+    }
+}
+
+/// The player config asks for a license server instead of offering plain
+/// DASH streams.
+#[derive(Debug)]
+pub struct DrmProtected;
+
+impl fmt::Display for DrmProtected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The video is DRM protected!")
+    }
+}
+
+impl std::error::Error for DrmProtected {}
+
+mod codec;
+mod extractors;
+mod manifest;
+
+/// Finds the player config behind one kind of page.
+pub trait Extractor: Send + Sync {
+    /// Names the page in messages, e.g. `Event page`.
+    fn name(&self) -> &'static str;
+
+    fn matches_url(&self, url: &Url) -> bool;
+
+    /// Fetches the player config for `url`, opened from `referer`.
+    fn extract(&self, http: &mut dyn Http, url: &str, referer: &str) -> Result<MediaInfo>;
+}
+
+/// What the player config, and the page where there is one, tell about a
+/// video.
+pub struct MediaInfo {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    /// Name of the account the video belongs to.
+    pub author: Option<String>,
+    /// Day of the upload, `YYYY-MM-DD`.
+    pub upload_date: Option<String>,
+    /// URL of the largest thumbnail.
+    pub thumbnail: Option<String>,
+    pub description: Option<String>,
+    /// Names of the speakers or performers of an event.
+    pub presenters: Vec<String>,
+    /// When the event was scheduled to start, as the page gives it,
+    /// usually ISO 8601.
+    pub scheduled_start: Option<String>,
+    /// The `dash` part of the player config.
+    pub dash_config: Value,
+    /// Single MP4 files with video and audio, which some configs offer
+    /// next to the DASH streams.
+    pub progressive: Vec<ProgressiveFile>,
+}
+
+/// A file of `request.files.progressive`.
+#[derive(Clone, Debug)]
+pub struct ProgressiveFile {
+    pub url: String,
+    pub width: u64,
+    pub height: u64,
+    /// Label such as `1080p`.
+    pub quality: Option<String>,
+}
+
+/// The extractors to try, in order.
+pub struct Registry {
+    extractors: Vec<Box<dyn Extractor>>,
+}
+
+impl Registry {
+    /// A registry without extractors.
+    pub fn empty() -> Registry {
+        Registry {
+            extractors: Vec::new(),
+        }
+    }
+
+    /// Adds `extractor`, tried before those registered earlier, so it can
+    /// take over URLs of the built-in ones.
+    pub fn register(&mut self, extractor: impl Extractor + 'static) {
+        self.extractors.insert(0, Box::new(extractor));
+    }
+
+    /// The extractor for `url`.
+    pub fn find(&self, url: &str) -> Result<&dyn Extractor> {
+        let parsed = Url::parse(url)?;
+        self.extractors
+            .iter()
+            .find(|extractor| extractor.matches_url(&parsed))
+            .map(|extractor| extractor.as_ref())
+            .ok_or_else(|| eyre!("No extractor for {url}!"))
+    }
+}
+
+/// The built-in extractors, event pages being the fallback for any page
+/// embedding the player.
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::empty();
+        registry.register(EventPage);
+        registry.register(Video);
+        registry.register(Showcase);
+        registry
+    }
+}
+
+/// What a page leads to.
+pub struct Extraction {
+    pub media: MediaInfo,
+    pub cdn: String,
+    pub master_url: String,
+    pub videos: Vec<VideoInfo>,
+    pub audios: Vec<AudioInfo>,
+}
+
+/// Looks up the renditions behind `url` with the built-in extractors, from
+/// the default CDN.
+pub fn extract(http: &mut dyn Http, url: &str, referer: &str) -> Result<Extraction> {
+    let media = Registry::default().find(url)?.extract(http, url, referer)?;
+    let cdn = choose_cdn(&media.dash_config, false);
+    let urls = manifest_urls(&media.dash_config["cdns"][&cdn]);
+    let (master_url, others) = urls
+        .split_first()
+        .ok_or_else(|| eyre!("No manifest URL for CDN {cdn}!"))?;
+    let mut master: Manifest = serde_json::from_str(&http.get(master_url, None)?)?;
+    for url in others {
+        // The main manifest has the renditions of the common codecs, those
+        // of the others can be done without.
+        let other = http
+            .get(url, None)
+            .and_then(|text| Ok(serde_json::from_str::<Manifest>(&text)?));
+        if let Ok(other) = other {
+            merge_manifest(&mut master, master_url, &other, url)?;
+        }
+    }
+    let master_url = master_url.to_string();
+    let videos = video_infos(&master_url, &master)?;
+    let audios = audio_infos(&master_url, &master)?;
+    Ok(Extraction {
+        media,
+        cdn,
+        master_url,
+        videos,
+        audios,
+    })
+}
+
+/// URL of the player config embedded in an event page.
+pub fn config_url(page: &str) -> Result<String> {
+    let re = Regex::new(r##"data-config-url="([^"]+)""##).unwrap();
+    let captures = re
+        .captures(page)
+        .ok_or(eyre!("Did not find video config url!"))?;
+    captures
+        .get(1)
+        .map(|m| decode_html_entities(m.as_str()).into_owned())
+        .ok_or(eyre!("Invalid capture group!"))
+}
+
+/// Fetches and reads the player config at `config_url`.
+pub fn player_config(http: &mut dyn Http, config_url: &str) -> Result<MediaInfo> {
+    media_info(&http.get(config_url, None)?)
+}
+
+/// Reads a player config.
+pub fn media_info(config: &str) -> Result<MediaInfo> {
+    let mut config: Value = serde_json::from_str(config)?;
+    let dash = config["request"]["files"]["dash"].take();
+    if dash.is_null() {
+        // Protected videos come with license server settings instead of
+        // plain DASH streams.
+        if !config["request"]["drm"].is_null() {
+            return Err(eyre::Report::new(DrmProtected));
+        }
+        return Err(eyre!("No DASH streams in config!"));
+    }
+    let progressive = progressive_files(&config["request"]["files"]["progressive"]);
+    let video = &config["video"];
+    Ok(MediaInfo {
+        id: match &video["id"] {
+            Value::Null => None,
+            Value::String(id) => Some(id.clone()),
+            id => Some(id.to_string()),
+        },
+        title: video["title"].as_str().map(str::to_string),
+        author: video["owner"]["name"].as_str().map(str::to_string),
+        upload_date: None,
+        thumbnail: thumbnail(&video["thumbs"]),
+        description: None,
+        presenters: Vec::new(),
+        scheduled_start: None,
+        dash_config: dash,
+        progressive,
+    })
+}
+
+/// The files of the `progressive` part of a player config, or of its copy
+/// kept by the manifest cache.
+pub fn progressive_files(progressive: &Value) -> Vec<ProgressiveFile> {
+    let files = progressive.as_array().into_iter().flatten();
+    files
+        .filter_map(|file| {
+            Some(ProgressiveFile {
+                url: file["url"].as_str()?.to_string(),
+                width: file["width"].as_u64().unwrap_or(0),
+                height: file["height"].as_u64().unwrap_or(0),
+                quality: file["quality"].as_str().map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+impl MediaInfo {
+    /// Whether the player config left out any of title, author, upload date
+    /// and thumbnail.
+    pub fn is_sparse(&self) -> bool {
+        self.title.is_none()
+            || self.author.is_none()
+            || self.upload_date.is_none()
+            || self.thumbnail.is_none()
+    }
+}
+
+/// Fills in what `media` lacks from the oEmbed endpoint for the page `url`.
+pub fn enrich(http: &mut dyn Http, media: &mut MediaInfo, url: &str) -> Result<()> {
+    let oembed: Value = serde_json::from_str(&http.get(oembed_url(url)?.as_str(), None)?)?;
+    let field = |name: &str| {
+        oembed[name]
+            .as_str()
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    media.title = media.title.take().or_else(|| field("title"));
+    media.author = media.author.take().or_else(|| field("author_name"));
+    // `2024-05-01 12:00:00`
+    let date = field("upload_date").and_then(|date| Some(date.get(..10)?.to_string()));
+    media.upload_date = media.upload_date.take().or(date);
+    media.thumbnail = media.thumbnail.take().or_else(|| field("thumbnail_url"));
+    media.description = media.description.take().or_else(|| field("description"));
+    Ok(())
+}
+
+/// Adds the description, presenters and start time an event page gives,
+/// from its schema.org data or else its meta tags.
+pub fn page_metadata(page: &str, media: &mut MediaInfo) {
+    let scripts =
+        Regex::new(r#"(?s)<script[^>]+type="application/ld\+json"[^>]*>(.*?)</script>"#).unwrap();
+    let mut items = Vec::new();
+    for captures in scripts.captures_iter(page) {
+        if let Ok(value) = serde_json::from_str::<Value>(&captures[1]) {
+            flatten_json_ld(value, &mut items);
+        }
+    }
+    let text = |value: &Value| {
+        value
+            .as_str()
+            .map(|text| decode_html_entities(text.trim()).into_owned())
+            .filter(|text| !text.is_empty())
+    };
+    for item in &items {
+        if media.description.is_none() {
+            media.description = text(&item["description"]);
+        }
+        if media.scheduled_start.is_none() {
+            media.scheduled_start = text(&item["startDate"]);
+        }
+        if media.presenters.is_empty() {
+            for key in ["performer", "actor"] {
+                let people = match &item[key] {
+                    Value::Array(people) => people.clone(),
+                    person => vec![person.clone()],
+                };
+                media.presenters.extend(
+                    people
+                        .iter()
+                        .filter_map(|person| text(person).or_else(|| text(&person["name"]))),
+                );
+            }
+        }
+    }
+    if media.description.is_none() {
+        let meta = Regex::new(
+            r#"<meta[^>]+(?:property|name)="(?:og:)?description"[^>]+content="([^"]*)""#,
+        )
+        .unwrap();
+        media.description = meta
+            .captures(page)
+            .map(|captures| decode_html_entities(captures[1].trim()).into_owned())
+            .filter(|text| !text.is_empty());
+    }
+}
+
+/// The objects of a JSON-LD document, which may come as a list or a
+/// `@graph`.
+fn flatten_json_ld(value: Value, items: &mut Vec<Value>) {
+    match value {
+        Value::Array(values) => values
+            .into_iter()
+            .for_each(|value| flatten_json_ld(value, items)),
+        Value::Object(mut object) => {
+            if let Some(graph) = object.remove("@graph") {
+                flatten_json_ld(graph, items);
+            }
+            items.push(Value::Object(object));
+        }
+        _ => {}
+    }
+}
+
+/// The oEmbed endpoint for `url`: Vimeo's for its own pages, else the one
+/// of the page's site.
+fn oembed_url(url: &str) -> Result<Url> {
+    let page = Url::parse(url)?;
+    let mut endpoint = page.join("/api/oembed.json")?;
+    if page
+        .host_str()
+        .is_some_and(|host| host == "vimeo.com" || host.ends_with(".vimeo.com"))
+    {
+        endpoint = Url::parse("https://vimeo.com/api/oembed.json")?;
+    }
+    endpoint.query_pairs_mut().append_pair("url", url);
+    Ok(endpoint)
+}
+
+/// The largest of the thumbnails, which come keyed by width next to a
+/// `base` URL without one.
+fn thumbnail(thumbs: &Value) -> Option<String> {
+    let thumbs = thumbs.as_object()?;
+    thumbs
+        .iter()
+        .filter_map(|(width, url)| Some((width.parse::<u64>().ok()?, url.as_str()?)))
+        .max_by_key(|(width, _)| *width)
+        .map(|(_, url)| url)
+        .or_else(|| thumbs.get("base")?.as_str())
+        .map(str::to_string)
+}
+
+/// Name of the CDN to download from unless benchmarking finds a faster one.
+pub fn choose_cdn(dash_config: &Value, prefer_quic: bool) -> String {
+    let cdns = &dash_config["cdns"];
+    let names: Vec<&String> = cdns
+        .as_object()
+        .into_iter()
+        .flat_map(|cdns| cdns.keys())
+        .collect();
+    // The default may be missing, or name a CDN the config does not list.
+    let default_cdn = dash_config["default_cdn"]
+        .as_str()
+        .filter(|name| master_url(dash_config, name).is_some())
+        .or_else(|| {
+            let name = names
+                .iter()
+                .find(|name| master_url(dash_config, name).is_some())?;
+            Some(name.as_str())
+        })
+        .unwrap_or_default();
+    // CDNs reachable over QUIC are advertised under names like
+    // `akfire_interconnect_quic`.
+    let quic_cdn = names
+        .into_iter()
+        .find(|name| name.contains("quic"))
+        .filter(|_| prefer_quic);
+    quic_cdn
+        .map_or(default_cdn, |name| name.as_str())
+        .to_string()
+}
+
+/// URL of the main manifest `cdn` serves.
+pub fn master_url<'a>(dash_config: &'a Value, cdn: &str) -> Option<&'a str> {
+    manifest_urls(&dash_config["cdns"][cdn]).first().copied()
+}
+
+/// URLs of the manifests of a CDN of the DASH config: `url` first, then
+/// those of single codecs, such as `avc_url`. Where `url` is missing, one of
+/// the latter is the main one.
+pub fn manifest_urls(cdn: &Value) -> Vec<&str> {
+    let Some(entry) = cdn.as_object() else {
+        return Vec::new();
+    };
+    let others = entry
+        .iter()
+        .filter(|(key, _)| key.ends_with("_url"))
+        .filter_map(|(_, url)| url.as_str());
+    let url = entry.get("url").and_then(Value::as_str);
+    url.into_iter().chain(others).collect()
+}
+
+/// Adds the renditions of `other`, the manifest at `other_url`, that
+/// `master` at `master_url` lacks. They keep their segment URLs by getting
+/// absolute base URLs.
+pub fn merge_manifest(
+    master: &mut Manifest,
+    master_url: &str,
+    other: &Manifest,
+    other_url: &str,
+) -> Result<()> {
+    let base_url = other.get("base_url").as_str().unwrap_or_default();
+    let base_url = Url::parse(other_url)?.join(base_url)?;
+    for (list, others) in [
+        (&mut master.video, &other.video),
+        (&mut master.audio, &other.audio),
+    ] {
+        let Some(List::Items(renditions)) = others else {
+            continue;
+        };
+        if matches!(list, None | Some(List::Other(Value::Null))) {
+            *list = Some(List::Items(Vec::new()));
+        }
+        let Some(List::Items(list)) = list else {
+            return Err(eyre!("Invalid manifest at {master_url}!"));
+        };
+        for rendition in renditions.iter().filter(|r| r.other.is_none()) {
+            if list
+                .iter()
+                .any(|known| known.get("id") == rendition.get("id"))
+            {
+                continue;
+            }
+            let own = rendition.get("base_url").as_str().unwrap_or_default();
+            let mut rendition = rendition.clone();
+            let base_url = Value::String(base_url.join(own)?.to_string());
+            rendition.fields.insert("base_url".to_string(), base_url);
+            list.push(rendition);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct VideoInfo {
+    pub base_url: String,
+    pub id: String,
+    pub codecs: String,
+    pub bitrate: u64,
+    pub duration: f64,
+    pub width: u64,
+    pub height: u64,
+    pub init_segment: Vec<u8>,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Clone)]
+pub struct Segment {
+    pub path: String,
+    pub size: u64,
+    /// Where the segment starts in the recording, in seconds.
+    pub start: f64,
+    pub end: f64,
+}
+
+pub struct AudioInfo {
+    pub base_url: String,
+    pub id: String,
+    pub codecs: String,
+    pub bitrate: u64,
+    pub duration: f64,
+    /// Language tag such as `de` or `en-US`, for events with interpretation
+    /// channels.
+    pub language: Option<String>,
+    /// Number of channels, 2 for stereo and 6 for 5.1.
+    pub channels: Option<u64>,
+    pub init_segment: Vec<u8>,
+    pub segments: Vec<Segment>,
+}
+
+/// What downloading needs of a rendition, video or audio.
+pub trait Track {
+    fn id(&self) -> &str;
+    fn base_url(&self) -> &str;
+    fn init_segment(&self) -> &[u8];
+    fn segments(&self) -> &[Segment];
+    fn duration(&self) -> f64;
+
+    /// Number of bytes the downloaded file will have.
+    ///
+    /// Each segment arrives with one byte more than its advertised size.
+    fn output_len(&self) -> u64 {
+        let segments: u64 = self.segments().iter().map(|s| s.size + 1).sum();
+        self.init_segment().len() as u64 + segments
+    }
+}
+
+macro_rules! impl_track {
+    ($type:ty) => {
+        impl Track for $type {
+            fn id(&self) -> &str {
+                &self.id
+            }
+
+            fn base_url(&self) -> &str {
+                &self.base_url
+            }
+
+            fn init_segment(&self) -> &[u8] {
+                &self.init_segment
+            }
+
+            fn segments(&self) -> &[Segment] {
+                &self.segments
+            }
+
+            fn duration(&self) -> f64 {
+                self.duration
+            }
+        }
+    };
+}
+
+impl_track!(VideoInfo);
+impl_track!(AudioInfo);
+
+impl AudioInfo {
+    /// The channel layout as commonly named, like `stereo` or `5.1`.
+    pub fn layout(&self) -> Option<String> {
+        Some(match self.channels? {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            6 => "5.1".to_string(),
+            8 => "7.1".to_string(),
+            channels => format!("{channels} channels"),
+        })
+    }
+
+    /// Whether the track is in `language`, where `de` also matches `de-CH`.
+    pub fn has_language(&self, language: &str) -> bool {
+        let Some(own) = &self.language else {
+            return false;
+        };
+        let own = own.replace('_', "-").to_ascii_lowercase();
+        let wanted = language.replace('_', "-").to_ascii_lowercase();
+        own == wanted
+            || own
+                .strip_prefix(&wanted)
+                .is_some_and(|rest| rest.starts_with('-'))
+    }
+}
+
+impl fmt::Display for VideoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, {}x{}, {} seconds, {} bitrate",
+            self.id, self.codecs, self.width, self.height, self.duration, self.bitrate
+        )
+    }
+}
+
+/// A part of a manifest that cannot be read: where it is, like
+/// `video[1].segments[3].size`, and what is wrong with it.
+#[derive(Debug)]
+pub struct ManifestError {
+    pub path: String,
+    pub problem: String,
+}
+
+impl ManifestError {
+    fn missing(path: String) -> ManifestError {
+        ManifestError {
+            path,
+            problem: "missing".to_string(),
+        }
+    }
+
+    fn mistyped(path: String, expected: &str, found: &Value) -> ManifestError {
+        let mut found = found.to_string();
+        if found.len() > 40 {
+            let end = (0..=37).rev().find(|&end| found.is_char_boundary(end));
+            found.truncate(end.unwrap_or(0));
+            found.push_str("...");
+        }
+        ManifestError {
+            path,
+            problem: format!("expected {expected}, found {found}"),
+        }
+    }
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.problem)
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// The video renditions of a manifest, with segment paths relative to
+/// `master_url`. Fails on the first one that cannot be read.
+pub fn video_infos(master_url: &str, master: &Manifest) -> Result<Vec<VideoInfo>> {
+    strict(video_infos_lenient(master_url, master)?)
+}
+
+/// The video renditions of a manifest that can be read, and what is wrong
+/// with the others.
+pub fn video_infos_lenient(
+    master_url: &str,
+    master: &Manifest,
+) -> Result<(Vec<VideoInfo>, Vec<ManifestError>)> {
+    let Some(videos) = &master.video else {
+        return Err(ManifestError::missing("video".to_string()).into());
+    };
+    renditions(master_url, master, "video", videos, |base_url, v, path| {
+        Ok(VideoInfo {
+            base_url: rendition_base_url(base_url, v, path)?,
+            id: v.get("id").to_string(),
+            codecs: v.get("codecs").to_string(),
+            bitrate: bitrate(v, path)?,
+            duration: number(v, path, "duration")?,
+            width: integer(v, path, "width")?,
+            height: integer(v, path, "height")?,
+            init_segment: init_segment(v, path)?,
+            segments: segments(v, path)?,
+        })
+    })
+}
+
+/// The audio renditions of a manifest, with segment paths relative to
+/// `master_url`. Fails on the first one that cannot be read.
+pub fn audio_infos(master_url: &str, master: &Manifest) -> Result<Vec<AudioInfo>> {
+    strict(audio_infos_lenient(master_url, master)?)
+}
+
+/// The audio renditions of a manifest that can be read, and what is wrong
+/// with the others.
+pub fn audio_infos_lenient(
+    master_url: &str,
+    master: &Manifest,
+) -> Result<(Vec<AudioInfo>, Vec<ManifestError>)> {
+    let audios = match &master.audio {
+        None | Some(List::Other(Value::Null)) => return Ok((Vec::new(), Vec::new())),
+        Some(audios) => audios,
+    };
+    renditions(master_url, master, "audio", audios, |base_url, a, path| {
+        Ok(AudioInfo {
+            base_url: rendition_base_url(base_url, a, path)?,
+            id: a.get("id").to_string(),
+            codecs: a.get("codecs").to_string(),
+            bitrate: bitrate(a, path)?,
+            duration: number(a, path, "duration")?,
+            language: a
+                .get("language")
+                .as_str()
+                .or(a.get("lang").as_str())
+                .map(str::to_string),
+            channels: a.get("channels").as_u64(),
+            init_segment: init_segment(a, path)?,
+            segments: segments(a, path)?,
+        })
+    })
+}
+
+fn strict<T>((renditions, mut errors): (Vec<T>, Vec<ManifestError>)) -> Result<Vec<T>> {
+    match errors.is_empty() {
+        true => Ok(renditions),
+        false => Err(errors.swap_remove(0).into()),
+    }
+}
+
+/// Reads every rendition of `list`, the `kind` array of `master`.
+fn renditions<T>(
+    master_url: &str,
+    master: &Manifest,
+    kind: &str,
+    list: &List<Rendition>,
+    read: impl Fn(&Url, &Rendition, &str) -> Result<T, ManifestError>,
+) -> Result<(Vec<T>, Vec<ManifestError>)> {
+    let base_url = match master.fields.get("base_url") {
+        None => "",
+        Some(base_url) => base_url
+            .as_str()
+            .ok_or_else(|| ManifestError::mistyped("base_url".to_string(), "a string", base_url))?,
+    };
+    let base_url = Url::parse(master_url)?.join(base_url)?;
+    let list = match list {
+        List::Items(list) => list,
+        List::Other(value) => {
+            return Err(ManifestError::mistyped(kind.to_string(), "an array", value).into())
+        }
+    };
+    let (mut renditions, mut errors) = (Vec::new(), Vec::new());
+    for (index, rendition) in list.iter().enumerate() {
+        let path = format!("{kind}[{index}]");
+        if let Some(other) = &rendition.other {
+            errors.push(ManifestError::mistyped(path, "an object", other));
+            continue;
+        }
+        match read(&base_url, rendition, &path) {
+            Ok(rendition) => renditions.push(rendition),
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok((renditions, errors))
+}
+
+/// `name` of the rendition at `path`, which must be there.
+fn field<'a>(rendition: &'a Rendition, path: &str, name: &str) -> Result<&'a Value, ManifestError> {
+    match rendition.get(name) {
+        Value::Null => Err(ManifestError::missing(format!("{path}.{name}"))),
+        value => Ok(value),
+    }
+}
+
+/// The base URL of a rendition, which `playlist.json` gives relative to
+/// that of the manifest.
+fn rendition_base_url(
+    base_url: &Url,
+    rendition: &Rendition,
+    path: &str,
+) -> Result<String, ManifestError> {
+    let Some(own) = rendition.fields.get("base_url") else {
+        return Ok(base_url.to_string());
+    };
+    let path = format!("{path}.base_url");
+    let own = own
+        .as_str()
+        .ok_or_else(|| ManifestError::mistyped(path.clone(), "a string", own))?;
+    let url = base_url.join(own).map_err(|e| ManifestError {
+        path,
+        problem: e.to_string(),
+    })?;
+    Ok(url.to_string())
+}
+
+fn number(rendition: &Rendition, path: &str, name: &str) -> Result<f64, ManifestError> {
+    let value = field(rendition, path, name)?;
+    value
+        .as_f64()
+        .ok_or_else(|| ManifestError::mistyped(format!("{path}.{name}"), "a number", value))
+}
+
+fn integer(rendition: &Rendition, path: &str, name: &str) -> Result<u64, ManifestError> {
+    let value = field(rendition, path, name)?;
+    value.as_u64().ok_or_else(|| {
+        ManifestError::mistyped(format!("{path}.{name}"), "a non-negative integer", value)
+    })
+}
+
+/// `playlist.json` may only have the average bitrate.
+fn bitrate(rendition: &Rendition, path: &str) -> Result<u64, ManifestError> {
+    match rendition.get("bitrate") {
+        Value::Null if !rendition.get("avg_bitrate").is_null() => {
+            integer(rendition, path, "avg_bitrate")
+        }
+        _ => integer(rendition, path, "bitrate"),
+    }
+}
+
+fn init_segment(rendition: &Rendition, path: &str) -> Result<Vec<u8>, ManifestError> {
+    let value = field(rendition, path, "init_segment")?;
+    let path = format!("{path}.init_segment");
+    let init_segment = value
+        .as_str()
+        .ok_or_else(|| ManifestError::mistyped(path.clone(), "a string", value))?;
+    decode(init_segment).map_err(|e| ManifestError {
+        path,
+        problem: format!("invalid base64: {e}"),
+    })
+}
+
+fn segments(rendition: &Rendition, path: &str) -> Result<Vec<Segment>, ManifestError> {
+    let path = format!("{path}.segments");
+    let segments = match &rendition.segments {
+        Some(List::Items(segments)) => segments,
+        None | Some(List::Other(Value::Null)) => return Err(ManifestError::missing(path)),
+        Some(List::Other(value)) => return Err(ManifestError::mistyped(path, "an array", value)),
+    };
+    segments
+        .iter()
+        .enumerate()
+        .map(|(index, s)| {
+            let path = format!("{path}[{index}]");
+            if let Some(other) = &s.other {
+                return Err(ManifestError::mistyped(path, "an object", other));
+            }
+            if let Some((name, value)) = &s.mistyped {
+                let expected = match *name {
+                    "url" => "a string",
+                    "start" | "end" => "a number",
+                    _ => "a non-negative integer",
+                };
+                return Err(ManifestError::mistyped(
+                    format!("{path}.{name}"),
+                    expected,
+                    value,
+                ));
+            }
+            Ok(Segment {
+                path: (s.url.clone())
+                    .ok_or_else(|| ManifestError::missing(format!("{path}.url")))?,
+                size: (s.size).ok_or_else(|| ManifestError::missing(format!("{path}.size")))?,
+                start: s.start.unwrap_or_default(),
+                end: s.end.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,356 @@
+//! Manifests read as they arrive, into structures sized for long events.
+//!
+//! The manifest of an event that ran for hours lists tens of thousands of
+//! segments for every rendition. Read into a [`Value`] tree first, each of
+//! them would be a map of its own, and the whole document would be in
+//! memory twice. A [`Manifest`] is deserialized straight from the text, or
+//! from a reader as the response streams in with
+//! [`serde_json::from_reader`]: segments become [`SegmentEntry`]s of a few
+//! fields, and everything else is kept as given, so the manifest can be
+//! written out again.
+//!
+//! Parts of the wrong type, down to a rendition or segment that is not an
+//! object, are kept as well instead of failing the whole document, so
+//! [`video_infos`](crate::video_infos) can say which field of which
+//! rendition is wrong, or leave out just that rendition.
+
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{Map, Value};
+
+static NULL: Value = Value::Null;
+
+/// A `master.json` or `playlist.json` manifest.
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    /// Everything but the renditions, like `clip_id` and `base_url`.
+    pub fields: Map<String, Value>,
+    pub video: Option<List<Rendition>>,
+    pub audio: Option<List<Rendition>>,
+}
+
+/// What is given where an array is expected.
+#[derive(Clone, Debug)]
+pub enum List<T> {
+    Items(Vec<T>),
+    /// Anything but an array.
+    Other(Value),
+}
+
+/// A video or audio rendition of a [`Manifest`].
+#[derive(Clone, Debug, Default)]
+pub struct Rendition {
+    /// Everything but the segments.
+    pub fields: Map<String, Value>,
+    pub segments: Option<List<SegmentEntry>>,
+    /// Anything but an object, given in place of the rendition.
+    pub other: Option<Value>,
+}
+
+/// A segment of a [`Rendition`], with only the fields that are used.
+#[derive(Clone, Debug, Default)]
+pub struct SegmentEntry {
+    pub url: Option<String>,
+    pub size: Option<u64>,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// The first of `url` and `size` given with the wrong type, and its
+    /// value.
+    pub mistyped: Option<(&'static str, Value)>,
+    /// Anything but an object, given in place of the segment.
+    pub other: Option<Value>,
+}
+
+impl Manifest {
+    /// Field `name` outside the renditions, null if missing.
+    pub fn get(&self, name: &str) -> &Value {
+        self.fields.get(name).unwrap_or(&NULL)
+    }
+}
+
+impl Rendition {
+    /// Field `name`, null if missing.
+    pub fn get(&self, name: &str) -> &Value {
+        self.fields.get(name).unwrap_or(&NULL)
+    }
+
+    fn other(value: Value) -> Rendition {
+        Rendition {
+            other: Some(value),
+            ..Rendition::default()
+        }
+    }
+}
+
+impl SegmentEntry {
+    fn other(value: Value) -> SegmentEntry {
+        SegmentEntry {
+            other: Some(value),
+            ..SegmentEntry::default()
+        }
+    }
+}
+
+/// Visitor methods turning everything but arrays and objects into `$other`.
+macro_rules! visit_scalars {
+    ($other:expr) => {
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok($other(Value::Null))
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok($other(Value::Null))
+        }
+
+        fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+            Ok($other(value.into()))
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+            Ok($other(value.into()))
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+            Ok($other(value.into()))
+        }
+
+        fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+            Ok($other(value.into()))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+            Ok($other(value.into()))
+        }
+    };
+}
+
+impl<'de> Deserialize<'de> for Manifest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Manifest, D::Error> {
+        struct ManifestVisitor;
+
+        impl<'de> Visitor<'de> for ManifestVisitor {
+            type Value = Manifest;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a manifest object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Manifest, A::Error> {
+                let mut manifest = Manifest::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "video" => manifest.video = Some(map.next_value()?),
+                        "audio" => manifest.audio = Some(map.next_value()?),
+                        _ => {
+                            let value = map.next_value()?;
+                            manifest.fields.insert(key, value);
+                        }
+                    }
+                }
+                Ok(manifest)
+            }
+        }
+
+        deserializer.deserialize_map(ManifestVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rendition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rendition, D::Error> {
+        struct RenditionVisitor;
+
+        impl<'de> Visitor<'de> for RenditionVisitor {
+            type Value = Rendition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any JSON value")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Rendition, A::Error> {
+                let value = Value::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(Rendition::other(value))
+            }
+
+            visit_scalars!(Rendition::other);
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Rendition, A::Error> {
+                let mut rendition = Rendition::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    if key == "segments" {
+                        rendition.segments = Some(map.next_value()?);
+                    } else {
+                        let value = map.next_value()?;
+                        rendition.fields.insert(key, value);
+                    }
+                }
+                Ok(rendition)
+            }
+        }
+
+        deserializer.deserialize_any(RenditionVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for SegmentEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SegmentEntry, D::Error> {
+        struct SegmentVisitor;
+
+        impl<'de> Visitor<'de> for SegmentVisitor {
+            type Value = SegmentEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any JSON value")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<SegmentEntry, A::Error> {
+                let value = Value::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(SegmentEntry::other(value))
+            }
+
+            visit_scalars!(SegmentEntry::other);
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SegmentEntry, A::Error> {
+                let mut segment = SegmentEntry::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "url" => match map.next_value()? {
+                            Value::String(url) => segment.url = Some(url),
+                            Value::Null => {}
+                            value => {
+                                segment.mistyped.get_or_insert(("url", value));
+                            }
+                        },
+                        "size" => match map.next_value()? {
+                            Value::Null => {}
+                            value => match value.as_u64() {
+                                Some(size) => segment.size = Some(size),
+                                None => {
+                                    segment.mistyped.get_or_insert(("size", value));
+                                }
+                            },
+                        },
+                        "start" => match map.next_value()? {
+                            Value::Null => {}
+                            Value::Number(start) => segment.start = start.as_f64(),
+                            value => {
+                                segment.mistyped.get_or_insert(("start", value));
+                            }
+                        },
+                        "end" => match map.next_value()? {
+                            Value::Null => {}
+                            Value::Number(end) => segment.end = end.as_f64(),
+                            value => {
+                                segment.mistyped.get_or_insert(("end", value));
+                            }
+                        },
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(segment)
+            }
+        }
+
+        deserializer.deserialize_any(SegmentVisitor)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<List<T>, D::Error> {
+        struct ListVisitor<T>(std::marker::PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+            type Value = List<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any JSON value")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<List<T>, A::Error> {
+                let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(List::Items(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<List<T>, A::Error> {
+                let value = Value::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(List::Other(value))
+            }
+
+            visit_scalars!(List::Other);
+        }
+
+        deserializer.deserialize_any(ListVisitor(std::marker::PhantomData))
+    }
+}
+
+impl Serialize for Manifest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (key, value) in &self.fields {
+            map.serialize_entry(key, value)?;
+        }
+        if let Some(video) = &self.video {
+            map.serialize_entry("video", video)?;
+        }
+        if let Some(audio) = &self.audio {
+            map.serialize_entry("audio", audio)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Rendition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(other) = &self.other {
+            return other.serialize(serializer);
+        }
+        let mut map = serializer.serialize_map(None)?;
+        for (key, value) in &self.fields {
+            map.serialize_entry(key, value)?;
+        }
+        if let Some(segments) = &self.segments {
+            map.serialize_entry("segments", segments)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for SegmentEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(other) = &self.other {
+            return other.serialize(serializer);
+        }
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(url) = &self.url {
+            map.serialize_entry("url", url)?;
+        }
+        if let Some(size) = self.size {
+            map.serialize_entry("size", &size)?;
+        }
+        if let Some((name, value)) = &self.mistyped {
+            map.serialize_entry(name, value)?;
+        }
+        if let Some(start) = self.start {
+            map.serialize_entry("start", &start)?;
+        }
+        if let Some(end) = self.end {
+            map.serialize_entry("end", &end)?;
+        }
+        map.end()
+    }
+}
+
+impl<T: Serialize> Serialize for List<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            List::Items(items) => serializer.collect_seq(items),
+            List::Other(value) => value.serialize(serializer),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+/* C interface of vimeo-event-downloader, built with `--features ffi`.
+ *
+ * Strings are UTF-8 and NUL-terminated. Strings returned by the library
+ * are freed with ved_free_string(). */
+
+#ifndef VIMEO_EVENT_DOWNLOADER_H
+#define VIMEO_EVENT_DOWNLOADER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define VED_RUNNING 0
+#define VED_DONE 1
+#define VED_FAILED (-1)
+
+typedef struct VedDownload VedDownload;
+
+typedef struct VedProgress {
+    uint64_t segments_done;
+    /* 0 until the manifest has been read. */
+    uint64_t segments_total;
+    uint64_t bytes_done;
+} VedProgress;
+
+/* Message of the last failed call on this thread, or NULL. */
+const char *ved_last_error(void);
+
+/* Renditions of an event as JSON, or NULL on failure. */
+char *ved_extract_info(const char *url, const char *referer);
+
+void ved_free_string(char *text);
+
+/* Starts downloading the best rendition to filename, or NULL. */
+VedDownload *ved_start_download(const char *url, const char *referer,
+                                const char *filename, uint32_t concurrency);
+
+/* Fills in progress (may be NULL), returns VED_RUNNING, VED_DONE or
+ * VED_FAILED. */
+int ved_poll_progress(VedDownload *download, VedProgress *progress);
+
+/* Why a download failed, or NULL; valid until the handle is freed. */
+const char *ved_download_error(const VedDownload *download);
+
+/* Stops the download after its current segments; other downloads keep
+ * running. */
+void ved_cancel(VedDownload *download);
+
+/* Waits for the download to end and frees the handle. */
+void ved_free_download(VedDownload *download);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
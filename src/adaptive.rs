@@ -0,0 +1,85 @@
+//! Adjusting the number of concurrent segment requests while downloading.
+//!
+//! Throughput is measured over short windows. After every window the limit
+//! takes one step; a step that made things faster is repeated, one that made
+//! them slower is reversed. This settles on the CDN's sweet spot without the
+//! user having to know it.
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(3);
+/// Relative change in throughput that counts as better or worse.
+const THRESHOLD: f64 = 0.05;
+
+pub struct Limiter {
+    max: usize,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+struct State {
+    limit: usize,
+    active: usize,
+    step: isize,
+    window_start: Instant,
+    window_bytes: u64,
+    last_rate: Option<f64>,
+}
+
+impl Limiter {
+    /// Starts with a single request and never allows more than `max`.
+    pub fn new(max: usize) -> Limiter {
+        Limiter {
+            max,
+            state: Mutex::new(State {
+                limit: 1,
+                active: 0,
+                step: 1,
+                window_start: Instant::now(),
+                window_bytes: 0,
+                last_rate: None,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until another request may start.
+    pub fn acquire(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.active >= state.limit {
+            state = self.changed.wait(state).unwrap();
+        }
+        state.active += 1;
+    }
+
+    /// Marks a request as done after it transferred `bytes`.
+    pub fn release(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.active -= 1;
+        state.window_bytes += bytes;
+
+        let elapsed = state.window_start.elapsed();
+        if elapsed >= WINDOW {
+            let rate = state.window_bytes as f64 / elapsed.as_secs_f64();
+            if let Some(last) = state.last_rate {
+                if rate < last * (1.0 - THRESHOLD) {
+                    state.step = -state.step;
+                } else if rate < last * (1.0 + THRESHOLD) {
+                    // No measurable difference, prefer fewer connections.
+                    state.step = -1;
+                }
+            }
+            let limit = state.limit as isize + state.step;
+            state.limit = limit.clamp(1, self.max as isize) as usize;
+            state.last_rate = Some(rate);
+            state.window_start = Instant::now();
+            state.window_bytes = 0;
+        }
+        self.changed.notify_all();
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+}
@@ -0,0 +1,172 @@
+//! The history of finished downloads, and `--dedupe` finding the video
+//! about to be downloaded among them, to not store a second copy of a
+//! recording that takes gigabytes.
+//!
+//! Every download to a file is noted in the history when it finishes, by
+//! default `$XDG_DATA_HOME/vimeo-event-downloader/history`, as a line:
+//!
+//! ```text
+//! <video id>\t<rendition>\t<size>\t<absolute path>
+//! ```
+//!
+//! With `--dedupe` the video ID is looked up there, and in the NFO files,
+//! see nfo.rs, under every `--archive-root`, which also finds copies this
+//! machine did not download itself, e.g. on a shared drive. Copies no
+//! longer where they were put, or of another size than noted, do not count.
+//! `skip` then leaves the output alone and `link` makes it a hard link to
+//! the copy, or skips it where the two are on different file systems.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+
+use clap::ArgEnum;
+use eyre::Result;
+use regex::Regex;
+
+/// Extensions of outputs an NFO file may go with.
+const EXTENSIONS: [&str; 5] = ["mp4", "mkv", "ts", "m4a", "mp3"];
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dedupe {
+    /// download nothing
+    Skip,
+    /// hard-link the output to the earlier copy
+    Link,
+}
+
+/// Notes the download of rendition `rendition` of video `id` to `output`.
+pub fn record(history: &Path, id: &str, rendition: &str, output: &Path) -> Result<()> {
+    let output = fs::canonicalize(output)?;
+    let size = fs::metadata(&output)?.len();
+    if let Some(dir) = history.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(history)?;
+    writeln!(file, "{id}\t{rendition}\t{size}\t{}", output.display())?;
+    Ok(())
+}
+
+/// A finished download as the history notes it.
+pub struct Download {
+    pub id: String,
+    /// Only shown by `--gui`.
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub rendition: String,
+    pub size: u64,
+    pub path: PathBuf,
+}
+
+/// The downloads noted in `history`, the latest first.
+pub fn downloads(history: &Path) -> Result<Vec<Download>> {
+    let text = match fs::read_to_string(history) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let downloads = text.lines().rev().filter_map(|line| {
+        let mut fields = line.splitn(4, '\t');
+        let (Some(id), Some(rendition), Some(size), Some(path)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return None;
+        };
+        Some(Download {
+            id: id.to_string(),
+            rendition: rendition.to_string(),
+            size: size.parse().ok()?,
+            path: PathBuf::from(path),
+        })
+    });
+    Ok(downloads.collect())
+}
+
+/// An earlier download of video `id` that is still there.
+pub fn find(history: Option<&Path>, roots: &[PathBuf], id: &str) -> Result<Option<PathBuf>> {
+    if let Some(history) = history {
+        for download in downloads(history)? {
+            let intact = fs::metadata(&download.path).is_ok_and(|m| m.len() == download.size);
+            if download.id == id && intact {
+                return Ok(Some(download.path));
+            }
+        }
+    }
+    let uniqueid = Regex::new(r#"<uniqueid type="vimeo"[^>]*>([^<]*)</uniqueid>"#).unwrap();
+    for root in roots {
+        if let Some(copy) = find_in(root, id, &uniqueid)? {
+            return Ok(Some(copy));
+        }
+    }
+    Ok(None)
+}
+
+/// The output of an NFO file for video `id` under `dir`.
+fn find_in(dir: &Path, id: &str, uniqueid: &Regex) -> Result<Option<PathBuf>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Some(copy) = find_in(&path, id, uniqueid)? {
+                return Ok(Some(copy));
+            }
+            continue;
+        }
+        if path.extension().is_none_or(|extension| extension != "nfo") {
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let described = uniqueid
+            .captures(&text)
+            .is_some_and(|captures| &captures[1] == id);
+        if !described {
+            continue;
+        }
+        let copy = EXTENSIONS
+            .iter()
+            .map(|extension| path.with_extension(extension))
+            .find(|copy| copy.is_file());
+        if copy.is_some() {
+            return Ok(copy);
+        }
+    }
+    Ok(None)
+}
+
+/// Puts `copy` in place of a new download to `output`, as `dedupe` says.
+pub fn reuse(dedupe: Dedupe, copy: &Path, output: &Path) -> Result<()> {
+    let same = match (fs::canonicalize(output), fs::canonicalize(copy)) {
+        (Ok(output), Ok(copy)) => output == copy,
+        _ => false,
+    };
+    if dedupe == Dedupe::Link && !same {
+        if output.exists() {
+            warning!(
+                "{} exists, not linking it to {}",
+                output.display(),
+                copy.display()
+            );
+            return Ok(());
+        }
+        match fs::hard_link(copy, output) {
+            Ok(()) => {
+                info!(
+                    "Linked {} to the earlier download {}",
+                    output.display(),
+                    copy.display()
+                );
+                return Ok(());
+            }
+            Err(e) => warning!(
+                "Cannot link {} to {} ({e})",
+                output.display(),
+                copy.display()
+            ),
+        }
+    }
+    info!(
+        "Already downloaded to {}, not downloading it again",
+        copy.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Choosing the audio rendition to download.
+//!
+//! Manifests list audio renditions in no particular order, so the choice is
+//! made explicitly: the language narrows the candidates down, the preferred
+//! channel layout and codec win over others, and the quality decides between
+//! the rest.
+
+use std::str::FromStr;
+
+use clap::ArgEnum;
+
+use crate::AudioInfo;
+
+/// `--audio-quality`: `best`, `worst`, or the highest bitrate up to a limit
+/// such as `96k`.
+#[derive(Clone, Copy, Debug)]
+pub enum Quality {
+    Best,
+    Worst,
+    /// Bits per second.
+    AtMost(u64),
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Quality, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "best" => return Ok(Quality::Best),
+            "worst" => return Ok(Quality::Worst),
+            _ => {}
+        }
+        let (number, factor) = match s.strip_suffix(['k', 'K']) {
+            Some(number) => (number, 1000.0),
+            None => (s, 1.0),
+        };
+        let bitrate: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid quality {s}, use best, worst or a bitrate like 96k"))?;
+        Ok(Quality::AtMost((bitrate * factor) as u64))
+    }
+}
+
+/// `--audio-channels`: a layout name (`mono`, `stereo`, `5.1`, `7.1`) or a
+/// number of channels.
+#[derive(Clone, Copy, Debug)]
+pub struct Channels(pub u64);
+
+impl FromStr for Channels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Channels, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "mono" => Ok(Channels(1)),
+            "stereo" => Ok(Channels(2)),
+            "5.1" => Ok(Channels(6)),
+            "7.1" => Ok(Channels(8)),
+            _ => s
+                .parse()
+                .ok()
+                .filter(|&channels| channels > 0)
+                .map(Channels)
+                .ok_or_else(|| {
+                    format!("invalid channels {s}, use mono, stereo, 5.1, 7.1 or a number")
+                }),
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Aac,
+    Opus,
+}
+
+impl Codec {
+    fn matches(self, codecs: &str) -> bool {
+        let codecs = codecs.trim_matches('"');
+        match self {
+            Codec::Aac => codecs.starts_with("mp4a"),
+            Codec::Opus => codecs.starts_with("opus") || codecs.starts_with("Opus"),
+        }
+    }
+}
+
+/// `--audio-format` of `--extract-audio`.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Mp3,
+    M4a,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::M4a => "m4a",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Preferences {
+    pub language: Option<String>,
+    pub quality: Option<Quality>,
+    pub codec: Option<Codec>,
+    pub channels: Option<Channels>,
+}
+
+impl Preferences {
+    /// Whether any audio was asked for.
+    pub fn wanted(&self) -> bool {
+        self.language.is_some()
+            || self.quality.is_some()
+            || self.codec.is_some()
+            || self.channels.is_some()
+    }
+}
+
+/// The rendition matching `preferences` best, if any has the language.
+pub fn select<'a>(audios: &'a [AudioInfo], preferences: &Preferences) -> Option<&'a AudioInfo> {
+    let candidates: Vec<_> = audios
+        .iter()
+        .filter(|a| match &preferences.language {
+            Some(language) => a.has_language(language),
+            None => true,
+        })
+        .collect();
+    let candidates = prefer(candidates, |a| {
+        preferences
+            .channels
+            .is_none_or(|Channels(channels)| a.channels == Some(channels))
+    });
+    let candidates = prefer(candidates, |a| {
+        preferences
+            .codec
+            .is_none_or(|codec| codec.matches(&a.codecs))
+    });
+    match preferences.quality.unwrap_or(Quality::Best) {
+        Quality::Best => candidates.into_iter().max_by_key(|a| a.bitrate),
+        Quality::Worst => candidates.into_iter().min_by_key(|a| a.bitrate),
+        // Nothing small enough means the smallest there is.
+        Quality::AtMost(limit) => candidates
+            .iter()
+            .copied()
+            .filter(|a| a.bitrate <= limit)
+            .max_by_key(|a| a.bitrate)
+            .or_else(|| candidates.into_iter().min_by_key(|a| a.bitrate)),
+    }
+}
+
+/// The candidates `preferred` accepts, or all of them if it accepts none.
+fn prefer(candidates: Vec<&AudioInfo>, preferred: impl Fn(&AudioInfo) -> bool) -> Vec<&AudioInfo> {
+    if candidates.iter().any(|a| preferred(a)) {
+        candidates.into_iter().filter(|a| preferred(a)).collect()
+    } else {
+        candidates
+    }
+}
@@ -0,0 +1,136 @@
+//! Checking that audio tracks, downloaded apart from the video, stay in
+//! step with it.
+//!
+//! The tracks of a live event come from one encoder but are packaged on
+//! their own, and a dropped stretch of audio or a restart of one of them
+//! leaves an archive that plays out of sync, which is rarely noticed
+//! before someone watches it. Before the tracks are muxed, each audio track
+//! is compared with the video:
+//!
+//! - the length of their timelines in the manifest,
+//! - the decode time in the `tfdt` of every fragment, against where the
+//!   manifest puts the segment, where the segments have them.
+//!
+//! Drift beyond `--max-av-drift` is reported as a warning.
+
+use std::fs::File;
+use std::io::{prelude::*, SeekFrom};
+use std::path::Path;
+
+use eyre::Result;
+
+use crate::discontinuity::{decode_time, HEAD};
+use crate::postprocess::{Output, PostProcessor};
+use crate::sprite::timestamp;
+use crate::timestamps::boxes;
+use crate::{Segment, Track, VideoInfo};
+
+/// The timescale in the `mdhd` of the first track of an init segment.
+fn timescale(init_segment: &[u8]) -> Option<u32> {
+    let child = |data, name: &[u8]| {
+        boxes(data)
+            .into_iter()
+            .find(|(kind, _)| *kind == name)
+            .map(|(_, body)| body)
+    };
+    let mdhd = child(init_segment, b"moov")
+        .and_then(|moov| child(moov, b"trak"))
+        .and_then(|trak| child(trak, b"mdia"))
+        .and_then(|mdia| child(mdia, b"mdhd"))?;
+    // Version and flags, then creation and modification time.
+    let at = if *mdhd.first()? == 1 { 20 } else { 12 };
+    let timescale = u32::from_be_bytes(mdhd.get(at..at + 4)?.try_into().unwrap());
+    (timescale > 0).then_some(timescale)
+}
+
+/// For each segment of `track` in the file at `path` with a decode time,
+/// where the manifest starts it and how far its fragment is from there, in
+/// seconds.
+fn offsets(path: &Path, track: &dyn Track) -> Result<Vec<(f64, f64)>> {
+    let Some(timescale) = timescale(track.init_segment()) else {
+        return Ok(Vec::new());
+    };
+    let mut file = File::open(path)?;
+    let mut offsets = Vec::new();
+    let mut offset = track.init_segment().len() as u64;
+    for segment in track.segments() {
+        let mut head = vec![0; (segment.size + 1).min(HEAD) as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut head)?;
+        offset += segment.size + 1;
+        if let Some(decoded) = decode_time(&head) {
+            let time = decoded as f64 / timescale as f64;
+            offsets.push((segment.start, time - segment.start));
+        }
+    }
+    Ok(offsets)
+}
+
+fn length(segments: &[Segment]) -> f64 {
+    segments.last().map_or(0.0, |segment| segment.end)
+}
+
+/// Compares the audio tracks next to the output with its video. It reads
+/// the segments where the download put them, so it runs before anything
+/// rewrites the files.
+pub struct CheckSync {
+    pub video: VideoInfo,
+    /// Largest drift tolerated, in seconds.
+    pub max_drift: f64,
+}
+
+impl PostProcessor for CheckSync {
+    fn name(&self) -> &'static str {
+        "check-sync"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        if output.audios.is_empty() {
+            return Ok(());
+        }
+        let video = offsets(&output.path, &self.video)?;
+        for (path, audio) in &output.audios {
+            let id = audio.id.trim_matches('"');
+            let difference = length(&audio.segments) - length(&self.video.segments);
+            if difference.abs() > self.max_drift {
+                let (amount, relation) = match difference > 0.0 {
+                    true => (difference, "longer"),
+                    false => (-difference, "shorter"),
+                };
+                warning!("The audio track {id} is {amount:.2}s {relation} than the video");
+            }
+            let audio = offsets(path, *audio)?;
+            if video.is_empty() || audio.is_empty() {
+                continue;
+            }
+            // Each audio fragment against the video fragment playing then.
+            let mut worst = (0.0, 0.0);
+            let mut first = None;
+            for &(start, offset) in &audio {
+                let index = video.partition_point(|&(video_start, _)| video_start <= start);
+                let (_, video_offset) = video[index.saturating_sub(1)];
+                let drift = offset - video_offset;
+                if drift.abs() > self.max_drift && first.is_none() {
+                    first = Some(start);
+                }
+                if drift.abs() > f64::abs(worst.1) {
+                    worst = (start, drift);
+                }
+            }
+            let (at, drift) = worst;
+            match first {
+                Some(first) => warning!(
+                    "The audio track {id} drifts from the video from {} on, by up to {:+.0} ms at {}",
+                    timestamp(first),
+                    drift * 1000.0,
+                    timestamp(at)
+                ),
+                None => info!(
+                    "The audio track {id} stays within {:.0} ms of the video",
+                    drift.abs() * 1000.0
+                ),
+            }
+        }
+        Ok(())
+    }
+}
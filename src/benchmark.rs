@@ -0,0 +1,91 @@
+//! Comparing the CDNs a config offers before picking one.
+//!
+//! Every CDN gets its manifest and the first few segments of the best video
+//! requested, using the same client as the real download. Failing CDNs are
+//! reported and left out of the ranking.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result};
+use ureq::serde_json;
+use url::Url;
+
+use crate::get_master;
+use crate::http::Client;
+
+/// Segments downloaded from every CDN.
+const SAMPLE_SEGMENTS: usize = 3;
+
+pub struct Measurement {
+    pub cdn: String,
+    /// Mean time until the response headers of a segment arrived.
+    pub latency: Duration,
+    /// Bytes per second of the segment bodies.
+    pub throughput: f64,
+}
+
+/// Measures every CDN in `cdns`, the `request.files.dash.cdns` object of the
+/// config, and returns the results of those that worked, fastest first.
+pub fn run(agent: &ureq::Agent, client: &Client, cdns: &serde_json::Value) -> Vec<Measurement> {
+    let Some(cdns) = cdns.as_object() else {
+        return Vec::new();
+    };
+    info!("Benchmarking {} CDNs", cdns.len());
+    let mut results = Vec::new();
+    for (name, cdn) in cdns {
+        match measure(agent, client, name, cdn) {
+            Ok(measurement) => {
+                info!(
+                    "{}: {:.1} ms latency, {:.1} KiB/s",
+                    name,
+                    measurement.latency.as_secs_f64() * 1000.0,
+                    measurement.throughput / 1024.0
+                );
+                results.push(measurement);
+            }
+            Err(e) => warning!("{}: failed: {}", name, e),
+        }
+    }
+    results.sort_by(|a, b| b.throughput.total_cmp(&a.throughput));
+    results
+}
+
+fn measure(
+    agent: &ureq::Agent,
+    client: &Client,
+    name: &str,
+    cdn: &serde_json::Value,
+) -> Result<Measurement> {
+    let master_url =
+        (vimeo_extract::manifest_urls(cdn).first().copied()).ok_or(eyre!("No URL for CDN!"))?;
+    let master = get_master(agent, master_url)?;
+    let (videos, _) = vimeo_extract::video_infos_lenient(master_url, &master)?;
+    let video = videos
+        .iter()
+        .max_by_key(|v| v.width)
+        .ok_or(eyre!("No videos in manifest!"))?;
+    let base_url = Url::parse(&video.base_url)?;
+
+    let mut latency = Duration::ZERO;
+    let mut transfer = Duration::ZERO;
+    let mut bytes = 0;
+    let samples = &video.segments[..video.segments.len().min(SAMPLE_SEGMENTS)];
+    for segment in samples {
+        let url = base_url.join(&segment.path)?;
+        let start = Instant::now();
+        let mut response = client.get(url.as_str(), 0, None)?;
+        let headers = Instant::now();
+        bytes += io::copy(&mut response.body, &mut io::sink())?;
+        latency += headers - start;
+        transfer += headers.elapsed();
+    }
+    if samples.is_empty() {
+        return Err(eyre!("No segments in manifest!"));
+    }
+    Ok(Measurement {
+        cdn: name.to_string(),
+        latency: latency / samples.len() as u32,
+        throughput: bytes as f64 / transfer.as_secs_f64().max(1e-6),
+    })
+}
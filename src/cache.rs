@@ -0,0 +1,164 @@
+//! On-disk store of downloaded segments, shared by all runs using the same
+//! directory.
+//!
+//! Segment bytes are stored once under their SHA-256, in
+//! `objects/<first two digits>/<sha256>`. A segment is found through a
+//! reference, `refs/<key>`, holding the SHA-256 of its bytes; the key comes
+//! from the video id and segment path rather than the full URL, since the
+//! signed part of the CDN URL changes between runs. A failed run leaves its
+//! segments behind for the next attempt, and a download of the same event
+//! in another quality finds the audio segments it shares with an earlier
+//! one. Objects are checked against their name when read, so a damaged one
+//! is fetched again.
+//!
+//! After every track, references no download used for the maximum age are
+//! dropped, and then the objects no reference points to. Objects younger
+//! than [`GRACE`] are left alone, as another run may be about to reference
+//! them.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use eyre::Result;
+
+use crate::{sha256_hex, Segment, Track};
+
+const GRACE: Duration = Duration::from_secs(60 * 60);
+
+pub struct SegmentCache {
+    dir: PathBuf,
+    /// How long a reference is kept without being used.
+    max_age: Duration,
+}
+
+impl SegmentCache {
+    pub fn open(dir: &Path, max_age: Duration) -> Result<SegmentCache> {
+        fs::create_dir_all(dir.join("objects"))?;
+        fs::create_dir_all(dir.join("refs"))?;
+        Ok(SegmentCache {
+            dir: dir.to_path_buf(),
+            max_age,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn ref_path(&self, track: &dyn Track, segment: &Segment) -> PathBuf {
+        let key = format!("{}/{}", track.id(), segment.path);
+        self.dir.join("refs").join(sha256_hex(key))
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.dir.join("objects").join(&hash[..2]).join(hash)
+    }
+
+    /// Writes a segment to `out`, calling `download` to fill the store first
+    /// if the segment is not in it yet.
+    pub fn fetch(
+        &self,
+        track: &dyn Track,
+        segment: &Segment,
+        out: &mut impl Write,
+        download: impl FnOnce(&mut Vec<u8>) -> Result<u64>,
+    ) -> Result<u64> {
+        let ref_path = self.ref_path(track, segment);
+        if let Some(data) = self.load(&ref_path, segment) {
+            // Used again, so kept for another max age.
+            File::options()
+                .append(true)
+                .open(&ref_path)?
+                .set_modified(SystemTime::now())?;
+            out.write_all(&data)?;
+            return Ok(data.len() as u64);
+        }
+        let mut data = Vec::new();
+        download(&mut data)?;
+        let hash = sha256_hex(&data);
+        let object = self.object_path(&hash);
+        if fs::metadata(&object).is_err() {
+            fs::create_dir_all(object.parent().unwrap())?;
+            write_atomically(&object, &data)?;
+        }
+        write_atomically(&ref_path, hash.as_bytes())?;
+        out.write_all(&data)?;
+        Ok(data.len() as u64)
+    }
+
+    /// The bytes `ref_path` points to, if they are there and intact.
+    fn load(&self, ref_path: &Path, segment: &Segment) -> Option<Vec<u8>> {
+        let hash = fs::read_to_string(ref_path).ok()?;
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let data = fs::read(self.object_path(&hash)).ok()?;
+        (data.len() as u64 == segment.size + 1 && sha256_hex(&data) == hash).then_some(data)
+    }
+
+    /// Drops references unused for the maximum age, then the objects no
+    /// reference points to; returns the number of bytes freed.
+    pub fn collect_garbage(&self) -> Result<u64> {
+        let now = SystemTime::now();
+        let age = |metadata: &fs::Metadata| {
+            metadata
+                .modified()
+                .ok()
+                .and_then(|time| now.duration_since(time).ok())
+                .unwrap_or_default()
+        };
+        let mut freed = 0;
+        let mut referenced = HashSet::new();
+        for entry in fs::read_dir(self.dir.join("refs"))? {
+            let entry = entry?;
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if age(&metadata) > self.max_age {
+                remove(&entry.path())?;
+            } else if let Ok(hash) = fs::read_to_string(entry.path()) {
+                referenced.insert(hash);
+            }
+        }
+        for prefix in fs::read_dir(self.dir.join("objects"))? {
+            let prefix = prefix?;
+            if !prefix.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(prefix.path())? {
+                let entry = entry?;
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if age(&metadata) > GRACE && !referenced.contains(&name) {
+                    remove(&entry.path())?;
+                    freed += metadata.len();
+                }
+            }
+        }
+        Ok(freed)
+    }
+}
+
+/// Writes `path` through a file of its own, so readers in other runs never
+/// see it half written.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let part = path.with_extension(format!("{}-{write}.part", std::process::id()));
+    fs::write(&part, data)?;
+    fs::rename(&part, path)?;
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,41 @@
+//! Warnings about codecs that may not play in the output container.
+//!
+//! Nothing is refused: the checks only point out combinations that common
+//! players are known to struggle with, before hours of downloading.
+
+use vimeo_extract::Codec;
+
+use crate::mux::Container;
+
+/// Why `codecs` may not play when stored in `container`.
+pub fn warnings(container: Container, codecs: &[Codec]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for codec in codecs {
+        let problem = match (codec, container) {
+            (Codec::Unknown(codec), _) => {
+                warnings.push(format!(
+                    "Unrecognized codec {codec}, the output may not play"
+                ));
+                continue;
+            }
+            (Codec::Avc { profile, .. }, _) if *profile > 100 => {
+                "has few hardware decoders, many devices cannot play it"
+            }
+            (Codec::Hevc { hev1: true, .. }, Container::Mp4) => {
+                "is stored as hev1, Apple players only play hvc1"
+            }
+            (Codec::Vp9 { .. } | Codec::Av1 { .. }, Container::Mp4) => {
+                "plays in browsers, but many other players do not support it in MP4"
+            }
+            (Codec::Opus | Codec::Flac, Container::Mp4) => {
+                "in MP4 needs a recent player, Matroska is safer"
+            }
+            (Codec::Vp9 { .. } | Codec::Av1 { .. } | Codec::Flac, Container::Ts) => {
+                "is not defined for MPEG-TS, ffmpeg will likely refuse it"
+            }
+            _ => continue,
+        };
+        warnings.push(format!("{codec} {problem} ({} output)", container.name()));
+    }
+    warnings
+}
@@ -0,0 +1,195 @@
+//! Finding the places where a long live recording jumps, and
+//! `--on-discontinuity` for what to do about them.
+//!
+//! An encoder that restarts mid-event leaves a recording that looks whole
+//! but skips or desyncs. Between two segments, any of these counts as a
+//! discontinuity:
+//!
+//! - the manifest leaves a gap between the end of one and the start of the
+//!   next,
+//! - the decode time in the `tfdt` of the fragment goes back, as it does
+//!   when an encoder starts counting anew,
+//! - the wall-clock times of their `prft` boxes, see timestamps.rs, differ
+//!   by more than a second from their distance in the recording.
+//!
+//! They are always reported. `chapter` also starts a chapter at each one
+//! and `split` cuts the output into one file per part, both with ffmpeg.
+
+use std::fs::{self, File};
+use std::io::{prelude::*, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use clap::ArgEnum;
+use eyre::Result;
+
+use crate::postprocess::{Output, PostProcessor};
+use crate::timestamps::{boxes, producer_time};
+use crate::{ledger, mux, Segment, VideoInfo};
+
+/// How far into a segment its boxes are looked for; the moof comes first.
+pub const HEAD: u64 = 64 * 1024;
+/// Largest difference between the wall clock and the recording tolerated.
+const DRIFT: f64 = 1.0;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// only report them
+    Warn,
+    /// start a chapter at each one
+    Chapter,
+    /// cut the output into one file per part
+    Split,
+}
+
+/// Where the recording jumps, and why that is known.
+pub struct Discontinuity {
+    /// Position in the recording, in seconds.
+    pub at: f64,
+    pub reason: String,
+}
+
+/// The decode time in the first `tfdt` of a segment.
+pub fn decode_time(segment: &[u8]) -> Option<u64> {
+    let (_, moof) = boxes(segment)
+        .into_iter()
+        .find(|(kind, _)| kind == b"moof")?;
+    let (_, traf) = boxes(moof).into_iter().find(|(kind, _)| kind == b"traf")?;
+    let (_, tfdt) = boxes(traf).into_iter().find(|(kind, _)| kind == b"tfdt")?;
+    match tfdt.first()? {
+        1 => Some(u64::from_be_bytes(tfdt.get(4..12)?.try_into().unwrap())),
+        _ => Some(u32::from_be_bytes(tfdt.get(4..8)?.try_into().unwrap()) as u64),
+    }
+}
+
+/// The discontinuities of `video`, as downloaded to `path`.
+pub fn find(path: &Path, video: &VideoInfo) -> Result<Vec<Discontinuity>> {
+    let mut file = File::open(path)?;
+    let mut found = Vec::new();
+    let mut offset = video.init_segment.len() as u64;
+    let mut previous: Option<(&Segment, Option<u64>, Option<f64>)> = None;
+    for segment in &video.segments {
+        let mut head = vec![0; (segment.size + 1).min(HEAD) as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut head)?;
+        offset += segment.size + 1;
+        let decoded = decode_time(&head);
+        let clock = producer_time(&head).and_then(|time| {
+            let since = time.duration_since(std::time::UNIX_EPOCH).ok()?;
+            Some(since.as_secs_f64())
+        });
+        if let Some((before, before_decoded, before_clock)) = previous {
+            let reason = if segment.start - before.end > 0.5 {
+                Some(format!(
+                    "the manifest skips {:.1}s",
+                    segment.start - before.end
+                ))
+            } else if matches!((before_decoded, decoded), (Some(a), Some(b)) if b < a) {
+                Some("the decode time starts over".to_string())
+            } else {
+                match (before_clock, clock) {
+                    (Some(a), Some(b)) => {
+                        let jump = (b - a) - (segment.start - before.start);
+                        (jump.abs() > DRIFT).then(|| format!("the wall clock jumps {jump:+.1}s"))
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(reason) = reason {
+                found.push(Discontinuity {
+                    at: segment.start,
+                    reason,
+                });
+            }
+        }
+        previous = Some((segment, decoded, clock));
+    }
+    Ok(found)
+}
+
+/// Reports the discontinuities of the output and keeps them for
+/// [`Mark`]. It reads the segments where the download put them, so it runs
+/// before anything rewrites the file.
+pub struct Find {
+    pub video: VideoInfo,
+}
+
+impl PostProcessor for Find {
+    fn name(&self) -> &'static str {
+        "find-discontinuities"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        output.discontinuities = find(&output.path, &self.video)?;
+        for discontinuity in &output.discontinuities {
+            warning!(
+                "The recording jumps at {}: {}",
+                crate::sprite::timestamp(discontinuity.at),
+                discontinuity.reason
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Adds chapters at the discontinuities, or splits the output there.
+pub struct Mark {
+    pub action: Action,
+    pub options: mux::Options,
+    /// Length of the recording in seconds.
+    pub duration: f64,
+}
+
+impl PostProcessor for Mark {
+    fn name(&self) -> &'static str {
+        "mark-discontinuities"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        if output.discontinuities.is_empty() {
+            return Ok(());
+        }
+        let mut bounds = vec![0.0];
+        bounds.extend(output.discontinuities.iter().map(|d| d.at));
+        bounds.push(self.duration);
+        let parts: Vec<_> = bounds.windows(2).map(|w| (w[0], w[1])).collect();
+        match self.action {
+            Action::Warn => {}
+            Action::Chapter => {
+                info!(
+                    "Adding {} chapters to {}",
+                    parts.len(),
+                    output.path.display()
+                );
+                mux::embed_chapters(&output.path, &self.options, &parts)?;
+            }
+            Action::Split => {
+                let mut paths = Vec::new();
+                for (index, &(start, end)) in parts.iter().enumerate() {
+                    let path = part_path(&output.path, index + 1);
+                    info!("Writing part {} to {}", index + 1, path.display());
+                    mux::cut(&output.path, &path, &self.options, start, end)?;
+                    paths.push(path);
+                }
+                // The parts replace the output, whose ledger fits none of them.
+                fs::remove_file(&output.path)?;
+                let ledger_path = ledger::path_for(&output.path);
+                if output.companions.contains(&ledger_path) {
+                    output.companions.retain(|path| *path != ledger_path);
+                    fs::remove_file(ledger_path)?;
+                }
+                output.path = paths.remove(0);
+                output.companions.extend(paths);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `<name>.partN.<ext>` for the output `<name>.<ext>`.
+fn part_path(output: &Path, number: usize) -> PathBuf {
+    let extension = output
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    output.with_extension(format!("part{number}{extension}"))
+}
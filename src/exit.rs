@@ -0,0 +1,128 @@
+//! Exit codes, so wrapper scripts can tell failures apart without parsing
+//! error messages.
+
+use std::fmt;
+use std::io;
+
+/// Exit code for invalid arguments, the same clap uses.
+pub const USAGE: i32 = 2;
+
+/// Listed in `--help`.
+pub const HELP: &str = "EXIT CODES:
+    0    success
+    1    any other error
+    2    invalid arguments
+    3    no video could be extracted from the page, config or manifest
+    4    authentication required or access denied (HTTP 401/403)
+    5    the video is DRM protected
+    6    network error, after all retries
+    7    disk full
+    8    verification failed
+    130  interrupted by Ctrl+C, finished segments are kept
+    143  stopped by SIGTERM, finished segments are kept";
+
+/// Kind of failure, attached to errors with `wrap_err` or `Report::new`.
+#[derive(Clone, Copy, Debug)]
+pub enum Failure {
+    Extraction,
+    AuthRequired,
+    Drm,
+    Network,
+    DiskFull,
+    Verification,
+    Interrupted,
+}
+
+impl Failure {
+    pub fn code(self) -> i32 {
+        match self {
+            Failure::Extraction => 3,
+            Failure::AuthRequired => 4,
+            Failure::Drm => 5,
+            Failure::Network => 6,
+            Failure::DiskFull => 7,
+            Failure::Verification => 8,
+            Failure::Interrupted if crate::signals::terminated() => 143,
+            Failure::Interrupted => 130,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Extraction => "Extraction failed",
+            Failure::AuthRequired => "Authentication required",
+            Failure::Drm => "DRM protected",
+            Failure::Network => "Network error",
+            Failure::DiskFull => "Disk full",
+            Failure::Verification => "Verification failed",
+            Failure::Interrupted => "Interrupted",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Arguments that make no sense, alone or together.
+#[derive(Debug)]
+pub struct Usage(pub String);
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Usage {}
+
+/// Exit code for `error`.
+///
+/// The cause closest to the root decides, so a network error during
+/// extraction is reported as a network error. Kinds attached with
+/// `wrap_err` only count if no cause says more.
+pub fn code(error: &eyre::Report) -> i32 {
+    if error.downcast_ref::<Usage>().is_some() {
+        return USAGE;
+    }
+    let causes: Vec<_> = error.chain().collect();
+    causes
+        .into_iter()
+        .rev()
+        .find_map(classify)
+        .or_else(|| error.downcast_ref::<Failure>().copied())
+        .map_or(1, Failure::code)
+}
+
+fn classify(cause: &(dyn std::error::Error + 'static)) -> Option<Failure> {
+    if let Some(failure) = cause.downcast_ref::<Failure>() {
+        return Some(*failure);
+    }
+    if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+        return Some(match e {
+            ureq::Error::Status(401 | 403, _) => Failure::AuthRequired,
+            // Nothing there, which is up to the context to judge.
+            ureq::Error::Status(404 | 410, _) => return None,
+            _ => Failure::Network,
+        });
+    }
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+        return Some(match e.status().map(|s| s.as_u16()) {
+            Some(401 | 403) => Failure::AuthRequired,
+            Some(404 | 410) => return None,
+            _ => Failure::Network,
+        });
+    }
+    let e = cause.downcast_ref::<io::Error>()?;
+    match e.kind() {
+        io::ErrorKind::StorageFull => Some(Failure::DiskFull),
+        io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::UnexpectedEof => Some(Failure::Network),
+        _ => None,
+    }
+}
@@ -0,0 +1,146 @@
+//! `--faststart`: moving the moov box of a progressive MP4 in front of the
+//! media data, so players can start and seek over HTTP before the whole file
+//! arrived.
+//!
+//! The moov is read into memory, the chunk offsets in its `stco` and `co64`
+//! boxes are shifted by its own size, and the file is written anew with the
+//! moov right before the first `mdat`. Fragmented files already start with
+//! their moov and are left alone.
+
+use std::fs::{self, File};
+use std::io::{self, prelude::*, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use eyre::{eyre, Result};
+
+/// Boxes on the way from the moov to the chunk offset tables.
+const CONTAINERS: [&[u8; 4]; 5] = [b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+/// A box of the file: where it starts and how long it is, header included.
+struct BoxRange {
+    kind: [u8; 4],
+    start: u64,
+    len: u64,
+}
+
+/// Moves the moov of the MP4 at `path` to the front; false if it already
+/// was there.
+pub fn faststart(path: &Path) -> Result<bool> {
+    let mut file = File::open(path)?;
+    let boxes = top_level_boxes(&mut file)?;
+    let moov = boxes
+        .iter()
+        .find(|b| &b.kind == b"moov")
+        .ok_or(eyre!("{} has no moov box!", path.display()))?;
+    let Some(mdat) = boxes.iter().find(|b| &b.kind == b"mdat") else {
+        return Ok(false);
+    };
+    if moov.start < mdat.start {
+        return Ok(false);
+    }
+
+    let mut data = vec![0; moov.len as usize];
+    file.seek(SeekFrom::Start(moov.start))?;
+    file.read_exact(&mut data)?;
+    // Media data from the first mdat up to the moov moves back by its size.
+    shift_offsets(&mut data, mdat.start..moov.start, moov.len)?;
+
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let mut out = io::BufWriter::new(File::create(&part)?);
+    for b in &boxes {
+        if b.start == mdat.start {
+            out.write_all(&data)?;
+        }
+        if b.start != moov.start {
+            file.seek(SeekFrom::Start(b.start))?;
+            io::copy(&mut (&mut file).take(b.len), &mut out)?;
+        }
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&part, path)?;
+    Ok(true)
+}
+
+fn top_level_boxes(file: &mut File) -> Result<Vec<BoxRange>> {
+    let file_len = file.metadata()?.len();
+    let mut boxes = Vec::new();
+    let mut start = 0;
+    while start < file_len {
+        file.seek(SeekFrom::Start(start))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let kind = header[4..8].try_into().unwrap();
+        let len = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            0 => file_len - start,
+            1 => {
+                let mut large = [0; 8];
+                file.read_exact(&mut large)?;
+                u64::from_be_bytes(large)
+            }
+            len => len as u64,
+        };
+        if len < 8 || start + len > file_len {
+            return Err(eyre!("Invalid MP4 box at offset {start}!"));
+        }
+        boxes.push(BoxRange { kind, start, len });
+        start += len;
+    }
+    Ok(boxes)
+}
+
+/// Adds `by` to the chunk offsets in the boxes of `data` that point into
+/// `moved`.
+fn shift_offsets(data: &mut [u8], moved: std::ops::Range<u64>, by: u64) -> Result<()> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        if len < 8 || pos + len > data.len() {
+            return Err(eyre!("Invalid box in moov!"));
+        }
+        let body = &mut data[pos + 8..pos + len];
+        match &kind {
+            kind if CONTAINERS.contains(&kind) => shift_offsets(body, moved.clone(), by)?,
+            b"stco" => {
+                for entry in table(body, 4)? {
+                    let offset = u32::from_be_bytes(entry.try_into().unwrap()) as u64;
+                    if moved.contains(&offset) {
+                        let shifted = u32::try_from(offset + by).map_err(|_| {
+                            eyre!(
+                                "Chunk offsets would exceed 4 GiB, use ffmpeg -movflags +faststart"
+                            )
+                        })?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            }
+            b"co64" => {
+                for entry in table(body, 8)? {
+                    let offset = u64::from_be_bytes(entry.try_into().unwrap());
+                    if moved.contains(&offset) {
+                        entry.copy_from_slice(&(offset + by).to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+    Ok(())
+}
+
+/// The entries of a full box holding a count and a table of `size` byte
+/// entries, as `stco` and `co64` do.
+fn table(body: &mut [u8], size: usize) -> Result<std::slice::ChunksExactMut<'_, u8>> {
+    if body.len() < 8 {
+        return Err(eyre!("Truncated chunk offset box!"));
+    }
+    let count = u32::from_be_bytes(body[4..8].try_into().unwrap()) as usize;
+    let entries = &mut body[8..];
+    if entries.len() < count * size {
+        return Err(eyre!("Truncated chunk offset box!"));
+    }
+    Ok(entries[..count * size].chunks_exact_mut(size))
+}
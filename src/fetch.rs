@@ -0,0 +1,439 @@
+//! Fetching media segments.
+
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use eyre::{eyre, Result, WrapErr};
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use url::Url;
+
+use crate::cache::SegmentCache;
+use crate::exit::Failure;
+use crate::gaps;
+use crate::http::{self, Client};
+use crate::keys;
+use crate::ratelimit::{Pace, Throttled, TokenBucket};
+use crate::renew::Renewal;
+use crate::retry::{self, Policy};
+use crate::signals::{self, Stop};
+use crate::stats::{SegmentRecord, Stats};
+use crate::{Segment, Track};
+
+/// Behaviour of a [`Fetcher`].
+pub struct Settings {
+    pub retry: Policy,
+    pub cache: Option<SegmentCache>,
+    /// Pause before every segment request.
+    pub delay: Option<Duration>,
+    /// Total bandwidth budget of all requests.
+    pub rate_limit: Option<Arc<TokenBucket>>,
+    /// Give up once this many segment requests in a row have failed.
+    pub abort_on_failures: Option<u32>,
+    /// Put a placeholder where a segment cannot be fetched and carry on.
+    pub ignore_errors: bool,
+    /// Segments fetched ahead of the one being written in a sequential
+    /// download.
+    pub prefetch: usize,
+    /// Draw progress bars, not done for downloads run by a library.
+    pub progress_bars: bool,
+    pub stop: Stop,
+}
+
+/// What earlier attempts at a segment received.
+#[derive(Default)]
+struct Partial {
+    buf: Vec<u8>,
+    validator: Option<String>,
+}
+
+/// Everything needed to fetch segments; shared by all download workers.
+pub struct Fetcher {
+    client: Client,
+    settings: Settings,
+    /// Failed attempts since the last successful one, across all workers.
+    failures: AtomicU32,
+    /// Spacing of requests while the server throttles them.
+    pace: Pace,
+    stats: Arc<Stats>,
+    /// How to get fresh URLs once the CDN refuses the old ones.
+    renewal: OnceLock<Renewal>,
+    /// How often URLs were renewed, and the base URLs of the renditions
+    /// from the last time.
+    renewed: Mutex<(u32, HashMap<String, Url>)>,
+}
+
+impl Fetcher {
+    pub fn new(client: Client, settings: Settings) -> Fetcher {
+        Fetcher {
+            client,
+            settings,
+            failures: AtomicU32::new(0),
+            pace: Pace::default(),
+            stats: Arc::new(Stats::new()),
+            renewal: OnceLock::new(),
+            renewed: Mutex::new((0, HashMap::new())),
+        }
+    }
+
+    /// Lets segment URLs the CDN answers with 403 be renewed through
+    /// `renewal`.
+    pub fn set_renewal(&self, renewal: Renewal) {
+        let _ = self.renewal.set(renewal);
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
+    }
+
+    pub fn prefetch(&self) -> usize {
+        self.settings.prefetch
+    }
+
+    /// Writes a segment to `out`, going through the cache if one is configured.
+    pub fn fetch(
+        &self,
+        base_url: &Url,
+        track: &dyn Track,
+        segment: &Segment,
+        out: &mut impl Write,
+    ) -> Result<u64> {
+        if signals::take_progress_request() {
+            self.stats.print_progress(track);
+        }
+        keys::wait_while_paused(&self.settings.stop);
+        if self.stop_requested() {
+            return Err(eyre!("Download stopped{}", self.resume_hint()))
+                .wrap_err(Failure::Interrupted);
+        }
+        match &self.settings.cache {
+            Some(cache) => {
+                let mut downloaded = false;
+                let count = cache.fetch(track, segment, out, |file| {
+                    downloaded = true;
+                    self.download(base_url, track, segment, file)
+                })?;
+                if !downloaded {
+                    self.stats.record_cached();
+                }
+                Ok(count)
+            }
+            None => self.download(base_url, track, segment, out),
+        }
+    }
+
+    /// What to write for segment `index` of `track`, which failed with `e`:
+    /// a placeholder as long as the segment with `--ignore-errors`, else the
+    /// error.
+    pub fn give_up(&self, track: &dyn Track, index: usize, e: eyre::Report) -> Result<Vec<u8>> {
+        if !self.settings.ignore_errors || self.stop_requested() || self.aborted() {
+            return Err(e);
+        }
+        let segment = &track.segments()[index];
+        warning!("Leaving out segment {}: {e:#}", index + 1);
+        self.stats.record_missing(track, segment);
+        Ok(gaps::placeholder(segment.size + 1))
+    }
+
+    /// `bar`, hidden unless progress bars are drawn.
+    pub fn progress_bar(&self, bar: ProgressBar) -> ProgressBar {
+        if !self.settings.progress_bars {
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        bar
+    }
+
+    /// Whether this download is to stop, by a signal or its caller.
+    pub fn stop_requested(&self) -> bool {
+        self.settings.stop.requested()
+    }
+
+    /// Whether the circuit breaker tripped.
+    pub fn aborted(&self) -> bool {
+        self.settings
+            .abort_on_failures
+            .is_some_and(|limit| self.failures.load(Ordering::SeqCst) >= limit)
+    }
+
+    /// Called once all segments of a track have been written.
+    pub fn finish(&self) -> Result<()> {
+        if let Some(cache) = &self.settings.cache {
+            let freed = cache.collect_garbage()?;
+            if freed > 0 {
+                info!(
+                    "Removed {:.1} MiB of unused segments from the cache",
+                    freed as f64 / 1048576.0
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads `segment`, renewing the URLs once if the CDN refuses it.
+    fn download(
+        &self,
+        base_url: &Url,
+        track: &dyn Track,
+        segment: &Segment,
+        out: &mut impl Write,
+    ) -> Result<u64> {
+        let (renewals, url) = self.current_url(base_url, track);
+        match self.download_from(&url, segment, out) {
+            Err(e) if http::status(&e) == Some(403) && self.renewal.get().is_some() => {
+                if let Err(renew_error) = self.renew(track, renewals) {
+                    warning!("Cannot renew the segment URLs: {renew_error:#}");
+                    return Err(e);
+                }
+                let (_, url) = self.current_url(base_url, track);
+                self.download_from(&url, segment, out)
+            }
+            result => result,
+        }
+    }
+
+    /// `base_url` of `track`, or its renewed one, and how often URLs were
+    /// renewed so far.
+    fn current_url(&self, base_url: &Url, track: &dyn Track) -> (u32, Url) {
+        let renewed = self.renewed.lock().unwrap();
+        // Mirrors on other CDNs keep their own URLs.
+        let url = match renewed.1.get(track.id()) {
+            Some(url) if Url::parse(track.base_url()).as_ref() == Ok(base_url) => url.clone(),
+            _ => base_url.clone(),
+        };
+        (renewed.0, url)
+    }
+
+    /// Resolves the event again for fresh URLs, unless another worker did
+    /// since the URLs of `renewals` were refused.
+    fn renew(&self, track: &dyn Track, renewals: u32) -> Result<()> {
+        let mut renewed = self.renewed.lock().unwrap();
+        if renewed.0 != renewals {
+            return Ok(());
+        }
+        let renewal = self.renewal.get().unwrap();
+        warning!("The CDN refuses the segment URLs, resolving the event again");
+        let urls = self
+            .settings
+            .retry
+            .run("Resolving the event", || renewal.base_urls())?;
+        if !urls.contains_key(track.id()) {
+            return Err(eyre!(
+                "Rendition {} is gone from the event",
+                track.id().trim_matches('"')
+            ));
+        }
+        *renewed = (renewals + 1, urls);
+        Ok(())
+    }
+
+    fn download_from(
+        &self,
+        base_url: &Url,
+        segment: &Segment,
+        out: &mut impl Write,
+    ) -> Result<u64> {
+        let url = base_url.join(&segment.path)?;
+        // Buffered so a failed attempt leaves nothing behind in `out`, and
+        // the next one can continue where it stopped.
+        let mut partial = Partial::default();
+        let started = self.stats.now();
+        let mut attempts = 0;
+        self.settings
+            .retry
+            .run(format_args!("Segment {}", segment.path), || {
+                attempts += 1;
+                let result = self.attempt(&url, segment, &mut partial);
+                match &result {
+                    // Not a failure of the link, the circuit breaker keeps
+                    // out of it.
+                    Err(e) if http::is_throttled(e) => {
+                        self.pace.throttled(http::retry_after(e));
+                        self.stats.record_throttled();
+                    }
+                    Ok(()) => {
+                        self.pace.succeeded();
+                        self.check_failures(&result)?;
+                    }
+                    Err(_) => self.check_failures(&result)?,
+                }
+                result
+            })?;
+        self.stats.record(SegmentRecord {
+            path: segment.path.clone(),
+            host: url.host_str().unwrap_or_default().to_string(),
+            bytes: partial.buf.len() as u64,
+            started,
+            elapsed: self.stats.now() - started,
+            attempts,
+        });
+        out.write_all(&partial.buf)?;
+        Ok(partial.buf.len() as u64)
+    }
+
+    /// Writes the whole file at `url` to `out`, as the only request of the
+    /// download. An attempt that breaks off is continued with a range
+    /// request, `out` cannot take back what it was given.
+    pub fn fetch_file(&self, url: &Url, out: &mut impl Write) -> Result<u64> {
+        let mut out = Counting {
+            inner: out,
+            count: 0,
+        };
+        let mut validator = None;
+        let started = self.stats.now();
+        let mut attempts = 0;
+        self.settings.retry.run("File", || {
+            attempts += 1;
+            self.pace.wait(&self.settings.stop);
+            let response = self
+                .client
+                .get(url.as_str(), out.count, validator.as_deref());
+            let response = match response {
+                Err(e) if http::is_throttled(&e) => {
+                    self.pace.throttled(http::retry_after(&e));
+                    self.stats.record_throttled();
+                    return Err(e);
+                }
+                response => response?,
+            };
+            if out.count > 0 && (!response.partial || response.validator != validator) {
+                return Err(eyre!(
+                    "The file changed or the server cannot continue it after {} bytes",
+                    out.count
+                ));
+            }
+            validator = response.validator;
+            match &self.settings.rate_limit {
+                Some(bucket) => io::copy(&mut Throttled::new(response.body, bucket), &mut out)?,
+                None => io::copy(&mut { response.body }, &mut out)?,
+            };
+            self.pace.succeeded();
+            Ok(())
+        })?;
+        self.stats.record(SegmentRecord {
+            path: url.path().to_string(),
+            host: url.host_str().unwrap_or_default().to_string(),
+            bytes: out.count,
+            started,
+            elapsed: self.stats.now() - started,
+            attempts,
+        });
+        Ok(out.count)
+    }
+
+    fn resume_hint(&self) -> String {
+        match &self.settings.cache {
+            Some(cache) => format!(
+                "; finished segments are kept in {}, run again to resume",
+                cache.dir().display()
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Trips the circuit breaker after too many failures in a row.
+    ///
+    /// The returned error is not retryable, so the policy stops at once and
+    /// the other workers stop after their current segment.
+    fn check_failures<T>(&self, result: &Result<T>) -> Result<()> {
+        let (Some(limit), Err(e)) = (self.settings.abort_on_failures, result) else {
+            self.failures.store(0, Ordering::SeqCst);
+            return Ok(());
+        };
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures < limit {
+            return Ok(());
+        }
+        Err(eyre!(
+            "Aborting after {failures} failed segment requests in a row, last error: {}{}",
+            e.root_cause(),
+            self.resume_hint()
+        ))
+        .wrap_err(Failure::Network)
+    }
+
+    fn attempt(&self, url: &Url, segment: &Segment, partial: &mut Partial) -> Result<()> {
+        if let Some(delay) = self.settings.delay {
+            retry::wait(delay, &self.settings.stop);
+        }
+        self.pace.wait(&self.settings.stop);
+        // Whatever an earlier attempt received is kept and only the rest
+        // requested, which matters for large segments on flaky links. The
+        // If-Range validator makes sure both parts are the same object.
+        let response = self.client.get(
+            url.as_str(),
+            partial.buf.len() as u64,
+            partial.validator.as_deref(),
+        )?;
+        if !response.partial || response.validator != partial.validator {
+            partial.buf.clear();
+        }
+        partial.validator = response.validator;
+        let buf = &mut partial.buf;
+        let reader = response.body;
+        let mut out = Receiving {
+            buf: &mut *buf,
+            stats: &self.stats,
+        };
+        match &self.settings.rate_limit {
+            Some(bucket) => io::copy(&mut Throttled::new(reader, bucket), &mut out)?,
+            None => io::copy(&mut { reader }, &mut out)?,
+        };
+        let count = buf.len() as u64;
+        if count > segment.size + 1 {
+            // Not the expected object, start over on the next attempt.
+            buf.clear();
+        }
+        if count != segment.size + 1 {
+            let size = segment.size;
+            // An I/O error, so a truncated transfer counts as retryable.
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Invalid byte count! Read={count}, expected={size}"),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Collects a segment as it arrives, noting every part received.
+struct Receiving<'a> {
+    buf: &'a mut Vec<u8>,
+    stats: &'a Stats,
+}
+
+impl Write for Receiving<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        self.stats.record_received(buf.len() as u64);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Counts the bytes written through it.
+struct Counting<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.count += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,264 @@
+//! C interface, declared in `include/vimeo_event_downloader.h`.
+//!
+//! Strings passed in are UTF-8 and only borrowed for the call. Strings
+//! returned by the library are freed with [`ved_free_string`]. Downloads
+//! run on a thread of their own; the caller polls their handle for progress
+//! and frees it with [`ved_free_download`] once done.
+//!
+//! Signal handlers, the terminal and the process stay with the host
+//! application: downloads draw no progress bars, read no keys, and invalid
+//! arguments fail the download instead of exiting.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use clap::Parser;
+use eyre::{eyre, Result};
+use ureq::serde_json::json;
+
+use crate::signals::Stop;
+use crate::stats::Stats;
+use crate::{run_embedded, Args};
+
+pub const VED_RUNNING: c_int = 0;
+pub const VED_DONE: c_int = 1;
+pub const VED_FAILED: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+#[repr(C)]
+pub struct VedProgress {
+    pub segments_done: u64,
+    /// 0 until the manifest has been read.
+    pub segments_total: u64,
+    pub bytes_done: u64,
+}
+
+/// Segment count of the chosen video and its statistics, once known.
+type Progress = Arc<Mutex<Option<(usize, Arc<Stats>)>>>;
+
+pub struct VedDownload {
+    progress: Progress,
+    stop: Stop,
+    thread: Option<JoinHandle<Result<()>>>,
+    error: Option<CString>,
+}
+
+fn c_string(text: String) -> CString {
+    CString::new(text.replace('\0', " ")).unwrap()
+}
+
+fn set_last_error(e: &eyre::Report) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(c_string(format!("{e:#}"))));
+}
+
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(text: *const c_char, name: &str) -> Result<&'a str> {
+    if text.is_null() {
+        return Err(eyre!("{name} is NULL"));
+    }
+    CStr::from_ptr(text)
+        .to_str()
+        .map_err(|_| eyre!("{name} is not UTF-8"))
+}
+
+/// Message of the last failed call on this thread, or NULL.
+///
+/// Valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn ved_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Looks up the renditions of an event, as a JSON document with the chosen
+/// `cdn`, its `master_url` and the `videos`. NULL on failure.
+///
+/// # Safety
+///
+/// `url` and `referer` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ved_extract_info(
+    url: *const c_char,
+    referer: *const c_char,
+) -> *mut c_char {
+    let result = (|| {
+        let extraction = crate::extract(str_arg(url, "url")?, str_arg(referer, "referer")?)?;
+        let videos: Vec<_> = extraction
+            .videos
+            .iter()
+            .map(|video| {
+                json!({
+                    "id": video.id.trim_matches('"'),
+                    "codecs": video.codecs.trim_matches('"'),
+                    "bitrate": video.bitrate,
+                    "duration": video.duration,
+                    "width": video.width,
+                    "height": video.height,
+                    "segments": video.segments.len(),
+                })
+            })
+            .collect();
+        let info = json!({
+            "cdn": extraction.cdn,
+            "master_url": extraction.master_url,
+            "videos": videos,
+        });
+        Ok(c_string(info.to_string()))
+    })();
+    match result {
+        Ok(info) => info.into_raw(),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a string returned by the library.
+///
+/// # Safety
+///
+/// `text` must be NULL or come from this library, and not be freed before.
+#[no_mangle]
+pub unsafe extern "C" fn ved_free_string(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+/// Starts downloading the best rendition of an event to `filename`, with
+/// up to `concurrency` parallel requests. NULL if the arguments are bad.
+///
+/// # Safety
+///
+/// `url`, `referer` and `filename` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ved_start_download(
+    url: *const c_char,
+    referer: *const c_char,
+    filename: *const c_char,
+    concurrency: u32,
+) -> *mut VedDownload {
+    let args = (|| {
+        Ok(Args::try_parse_from([
+            "vimeo-event-downloader",
+            "--url",
+            str_arg(url, "url")?,
+            "--referer",
+            str_arg(referer, "referer")?,
+            "--filename",
+            str_arg(filename, "filename")?,
+            "--concurrency",
+            &concurrency.max(1).to_string(),
+        ])?)
+    })();
+    let mut args: Args = match args {
+        Ok(args) => args,
+        Err(e) => {
+            set_last_error(&e);
+            return ptr::null_mut();
+        }
+    };
+
+    let stop = Stop::default();
+    args.stop = stop.clone();
+    let progress: Progress = Arc::new(Mutex::new(None));
+    let shared = progress.clone();
+    let thread = thread::spawn(move || {
+        run_embedded(args, &mut |_, video, stats| {
+            *shared.lock().unwrap() = Some((video.segments.len(), stats.clone()));
+        })
+    });
+    Box::into_raw(Box::new(VedDownload {
+        progress,
+        stop,
+        thread: Some(thread),
+        error: None,
+    }))
+}
+
+/// Fills in `progress` and returns `VED_RUNNING`, `VED_DONE` or
+/// `VED_FAILED`. After a failure [`ved_download_error`] has the reason.
+///
+/// # Safety
+///
+/// `download` must come from [`ved_start_download`]; `progress` must be
+/// NULL or point to a `VedProgress`.
+#[no_mangle]
+pub unsafe extern "C" fn ved_poll_progress(
+    download: *mut VedDownload,
+    progress: *mut VedProgress,
+) -> c_int {
+    let download = &mut *download;
+    if let (Some(out), Some((total, stats))) = (
+        progress.as_mut(),
+        download.progress.lock().unwrap().as_ref(),
+    ) {
+        let (done, bytes) = stats.progress();
+        *out = VedProgress {
+            segments_done: done as u64,
+            segments_total: *total as u64,
+            bytes_done: bytes,
+        };
+    }
+    if download.thread.as_ref().is_some_and(|t| !t.is_finished()) {
+        return VED_RUNNING;
+    }
+    if let Some(thread) = download.thread.take() {
+        let result = thread
+            .join()
+            .unwrap_or_else(|_| Err(eyre!("Download thread panicked!")));
+        if let Err(e) = result {
+            download.error = Some(c_string(format!("{e:#}")));
+        }
+    }
+    match download.error {
+        Some(_) => VED_FAILED,
+        None => VED_DONE,
+    }
+}
+
+/// Why a download failed, or NULL. Valid until the handle is freed.
+///
+/// # Safety
+///
+/// `download` must come from [`ved_start_download`].
+#[no_mangle]
+pub unsafe extern "C" fn ved_download_error(download: *const VedDownload) -> *const c_char {
+    (*download)
+        .error
+        .as_ref()
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Stops a download after its current segments, like Ctrl+C does for the
+/// command line tool; its poll then reports `VED_FAILED`. Other downloads
+/// keep running.
+///
+/// # Safety
+///
+/// `download` must come from [`ved_start_download`].
+#[no_mangle]
+pub unsafe extern "C" fn ved_cancel(download: *mut VedDownload) {
+    (*download).stop.request();
+}
+
+/// Waits for the download to end and frees its handle.
+///
+/// # Safety
+///
+/// `download` must come from [`ved_start_download`] and not be freed before.
+#[no_mangle]
+pub unsafe extern "C" fn ved_free_download(download: *mut VedDownload) {
+    let download = Box::from_raw(download);
+    if let Some(thread) = download.thread {
+        let _ = thread.join();
+    }
+}
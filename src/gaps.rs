@@ -0,0 +1,66 @@
+//! Segments left out with `--ignore-errors`.
+//!
+//! A left out segment still takes up its place in the output, as a `free`
+//! box players skip, so the offsets of the ledger and of preallocated
+//! downloads stay as they are.
+
+use crate::sprite::timestamp;
+
+/// A span of a track that is missing from the output, in seconds.
+pub struct Gap {
+    pub track: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A `free` box `len` bytes long, header included.
+pub fn placeholder(len: u64) -> Vec<u8> {
+    let mut data = vec![0; len as usize];
+    // Anything too short for a box header is padding either way.
+    if len >= 8 {
+        data[..4].copy_from_slice(&(len as u32).to_be_bytes());
+        data[4..8].copy_from_slice(b"free");
+    }
+    data
+}
+
+/// `gaps` by track and start, with touching ones joined.
+pub fn merge(gaps: &[Gap]) -> Vec<Gap> {
+    let mut sorted: Vec<_> = gaps.iter().collect();
+    sorted.sort_by(|a, b| {
+        (&a.track, a.start)
+            .partial_cmp(&(&b.track, b.start))
+            .unwrap()
+    });
+    let mut merged: Vec<Gap> = Vec::new();
+    for gap in sorted {
+        match merged.last_mut() {
+            Some(last) if last.track == gap.track && gap.start <= last.end => {
+                last.end = last.end.max(gap.end);
+            }
+            _ => merged.push(Gap {
+                track: gap.track.clone(),
+                start: gap.start,
+                end: gap.end,
+            }),
+        }
+    }
+    merged
+}
+
+pub fn print_report(gaps: &[Gap]) {
+    if gaps.is_empty() {
+        return;
+    }
+    let total: f64 = gaps.iter().map(|gap| gap.end - gap.start).sum();
+    warning!("Missing from the output, {total:.1}s in total:");
+    for gap in gaps {
+        warning!(
+            "  {} from {} to {} ({:.1}s)",
+            gap.track,
+            timestamp(gap.start),
+            timestamp(gap.end),
+            gap.end - gap.start
+        );
+    }
+}
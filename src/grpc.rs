@@ -0,0 +1,391 @@
+//! `--grpc`, the queue of `--gui` as a gRPC service, for other programs to
+//! queue downloads and follow their progress with typed messages. The
+//! service is declared in `proto/vimeo_event_downloader.proto`, from which
+//! clients generate their code.
+//!
+//! gRPC is HTTP/2 with each message prefixed by a flag byte and its length,
+//! and the status of the call in the trailers. The messages of the three
+//! methods are a handful of numbers and strings, so they are encoded by
+//! hand below rather than with generated code. Like the page, the service
+//! takes the `--gui-token` as `authorization: Bearer <token>` metadata and
+//! speaks TLS with `--gui-cert`, and cleartext HTTP/2 otherwise.
+
+use std::net::TcpListener as StdListener;
+use std::time::Duration;
+
+use bytes::Bytes;
+use eyre::{eyre, Result};
+use h2::server::SendResponse;
+use h2::RecvStream;
+use http::{HeaderMap, HeaderValue, Request, Response};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use ureq::serde_json::Value;
+
+use crate::gui::{self, Shared};
+use crate::{httpd, tls};
+
+const SERVICE: &str = "/vimeo_event_downloader.v1.Queue/";
+/// How often `WatchProgress` sends the progress.
+const INTERVAL: Duration = Duration::from_secs(1);
+/// Characters a grpc-message has to percent-encode.
+const MESSAGE: &AsciiSet = &CONTROLS.add(b'%');
+
+/// A call that failed, with its gRPC status code.
+struct Status(u32, String);
+
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const FAILED_PRECONDITION: u32 = 9;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+const UNAUTHENTICATED: u32 = 16;
+
+pub struct Options {
+    /// Token every call has to carry.
+    pub token: Option<String>,
+    pub identity: Option<tls::Identity>,
+}
+
+/// Binds `addr`, so a taken address fails `--gui` right away.
+pub fn bind(addr: &str) -> Result<StdListener> {
+    let listener = StdListener::bind(addr).map_err(|e| eyre!("Could not listen on {addr}: {e}"))?;
+    listener.set_nonblocking(true)?;
+    info!("The queue takes gRPC calls on {}", listener.local_addr()?);
+    Ok(listener)
+}
+
+/// Answers calls on `listener` until the process ends.
+pub fn serve(listener: StdListener, queue: Shared, options: &Options) -> Result<()> {
+    #[cfg(feature = "rustls")]
+    let acceptor = (options.identity.as_ref())
+        .map(|identity| identity.server_config(&[b"h2"]))
+        .transpose()?
+        .map(tokio_rustls::TlsAcceptor::from);
+    #[cfg(not(feature = "rustls"))]
+    if options.identity.is_some() {
+        return Err(eyre!("--gui-cert needs a build with the rustls feature"));
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = TcpListener::from_std(listener)?;
+        loop {
+            let (socket, _) = listener.accept().await?;
+            let queue = queue.clone();
+            let token = options.token.clone();
+            #[cfg(feature = "rustls")]
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                #[cfg(feature = "rustls")]
+                if let Some(acceptor) = acceptor {
+                    match acceptor.accept(socket).await {
+                        Ok(socket) => connection(socket, queue, token).await,
+                        Err(e) => warning!("gRPC connection failed: {e}"),
+                    }
+                    return;
+                }
+                connection(socket, queue, token).await
+            });
+        }
+    })
+}
+
+async fn connection(
+    socket: impl AsyncRead + AsyncWrite + Unpin,
+    queue: Shared,
+    token: Option<String>,
+) {
+    let mut connection = match h2::server::handshake(socket).await {
+        Ok(connection) => connection,
+        Err(e) => return warning!("gRPC connection failed: {e}"),
+    };
+    while let Some(Ok((request, respond))) = connection.accept().await {
+        let (queue, token) = (queue.clone(), token.clone());
+        tokio::spawn(async move {
+            // The client hanging up is its business.
+            let _ = call(request, respond, &queue, token.as_deref()).await;
+        });
+    }
+}
+
+async fn call(
+    request: Request<RecvStream>,
+    mut respond: SendResponse<Bytes>,
+    queue: &Shared,
+    token: Option<&str>,
+) -> Result<(), h2::Error> {
+    if let Some(token) = token {
+        let authorization = (request.headers().get("authorization"))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !authorization.is_some_and(|given| httpd::same_token(given, token)) {
+            let status = Status(UNAUTHENTICATED, "The call lacks the token".to_string());
+            return fail(&mut respond, status);
+        }
+    }
+    let method = request
+        .uri()
+        .path()
+        .strip_prefix(SERVICE)
+        .map(str::to_string);
+    let message = match read_message(request.into_body()).await {
+        Ok(message) => message,
+        Err(status) => return fail(&mut respond, status),
+    };
+    let reply = match method.as_deref() {
+        Some("Enqueue") => enqueue(queue, &message),
+        Some("Cancel") => cancel(queue, &message),
+        Some("WatchProgress") => return watch(queue, &message, respond).await,
+        _ => Err(Status(UNIMPLEMENTED, "No such method".to_string())),
+    };
+    match reply {
+        Ok(reply) => {
+            let mut send = respond.send_response(response(), false)?;
+            send.send_data(frame(&reply), false)?;
+            send.send_trailers(trailers(0, None))
+        }
+        Err(status) => fail(&mut respond, status),
+    }
+}
+
+fn response() -> Response<()> {
+    Response::builder()
+        .header("content-type", "application/grpc")
+        .body(())
+        .unwrap()
+}
+
+fn trailers(code: u32, message: Option<&str>) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from(code));
+    if let Some(message) = message {
+        let message = utf8_percent_encode(message, MESSAGE).to_string();
+        if let Ok(message) = HeaderValue::from_str(&message) {
+            trailers.insert("grpc-message", message);
+        }
+    }
+    trailers
+}
+
+/// Answers with just the status, in the headers as gRPC has it.
+fn fail(respond: &mut SendResponse<Bytes>, Status(code, message): Status) -> Result<(), h2::Error> {
+    let mut response = response();
+    response
+        .headers_mut()
+        .extend(trailers(code, Some(&message)));
+    respond.send_response(response, true)?;
+    Ok(())
+}
+
+/// The single message of a unary or server-streaming call, at most
+/// [`gui::MAX_BODY`] bytes like the requests of the page.
+async fn read_message(mut body: RecvStream) -> Result<Vec<u8>, Status> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| Status(INTERNAL, e.to_string()))?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        if data.len() + chunk.len() > gui::MAX_BODY + 5 {
+            let message = format!("Messages are limited to {} bytes", gui::MAX_BODY);
+            return Err(Status(RESOURCE_EXHAUSTED, message));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    let invalid = || Status(INVALID_ARGUMENT, "Invalid message".to_string());
+    let (&[compressed, a, b, c, d], message) = data.split_at(data.len().min(5)) else {
+        return Err(invalid());
+    };
+    if compressed != 0 {
+        return Err(Status(
+            UNIMPLEMENTED,
+            "Compressed messages are not supported".to_string(),
+        ));
+    }
+    let length = u32::from_be_bytes([a, b, c, d]) as usize;
+    message
+        .get(..length)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(invalid)
+}
+
+fn frame(message: &[u8]) -> Bytes {
+    let mut frame = vec![0];
+    frame.extend((message.len() as u32).to_be_bytes());
+    frame.extend(message);
+    Bytes::from(frame)
+}
+
+fn enqueue(queue: &Shared, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let fields = decode(message)?;
+    let names = [
+        (1, "url"),
+        (2, "referer"),
+        (3, "filename"),
+        (4, "video"),
+        (5, "audio"),
+        (6, "container"),
+    ];
+    let mut form: Vec<_> = names
+        .iter()
+        .map(|&(number, name)| Ok((name.to_string(), string(&fields, number)?)))
+        .collect::<Result<_, Status>>()?;
+    match varint(&fields, 7) {
+        0 => {}
+        priority => form.push(("priority".to_string(), priority.to_string())),
+    }
+    let reply =
+        gui::enqueue(queue, &form).map_err(|e| Status(INVALID_ARGUMENT, format!("{e:#}")))?;
+    let mut encoded = Vec::new();
+    put_varint(&mut encoded, 1, reply["id"].as_u64().unwrap_or_default());
+    Ok(encoded)
+}
+
+fn cancel(queue: &Shared, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let id = job(queue, message)?;
+    gui::cancel_job(queue, id).map_err(|e| Status(FAILED_PRECONDITION, format!("{e:#}")))?;
+    Ok(Vec::new())
+}
+
+/// The job in the `id` field of `message`.
+fn job(queue: &Shared, message: &[u8]) -> Result<usize, Status> {
+    let id = varint(&decode(message)?, 1) as usize;
+    if gui::jobs(queue)[id].is_null() {
+        return Err(Status(NOT_FOUND, format!("No job {id}")));
+    }
+    Ok(id)
+}
+
+async fn watch(
+    queue: &Shared,
+    message: &[u8],
+    mut respond: SendResponse<Bytes>,
+) -> Result<(), h2::Error> {
+    let id = match job(queue, message) {
+        Ok(id) => id,
+        Err(status) => return fail(&mut respond, status),
+    };
+    let mut send = respond.send_response(response(), false)?;
+    loop {
+        let job = gui::jobs(queue)[id].clone();
+        let (progress, finished) = progress(id, &job);
+        send.send_data(frame(&progress), false)?;
+        if finished {
+            return send.send_trailers(trailers(0, None));
+        }
+        tokio::time::sleep(INTERVAL).await;
+    }
+}
+
+/// The `Progress` message for a job as `/jobs` has it, and whether it has
+/// finished.
+fn progress(id: usize, job: &Value) -> (Vec<u8>, bool) {
+    let state = match job["state"].as_str() {
+        Some("queued") => 1,
+        Some("running") => 2,
+        Some("done") => 3,
+        Some("failed") => 4,
+        _ => 0,
+    };
+    let mut encoded = Vec::new();
+    put_varint(&mut encoded, 1, id as u64);
+    put_varint(&mut encoded, 2, state);
+    for (number, name) in [(3, "segments"), (4, "done"), (5, "bytes")] {
+        put_varint(&mut encoded, number, job[name].as_u64().unwrap_or_default());
+    }
+    put_string(&mut encoded, 6, job["error"].as_str().unwrap_or_default());
+    (encoded, state >= 3)
+}
+
+/// A field of a protobuf message: a number, or bytes such as a string.
+enum Field {
+    Varint(u64),
+    Bytes(Vec<u8>),
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The fields of `message` by number, skipping the fixed-size ones none of
+/// the messages have.
+fn decode(mut message: &[u8]) -> Result<Vec<(u64, Field)>, Status> {
+    let invalid = || Status(INVALID_ARGUMENT, "Invalid message".to_string());
+    let mut fields = Vec::new();
+    while !message.is_empty() {
+        let key = read_varint(&mut message).ok_or_else(invalid)?;
+        let field = match key & 7 {
+            0 => Field::Varint(read_varint(&mut message).ok_or_else(invalid)?),
+            2 => {
+                let length = read_varint(&mut message).ok_or_else(invalid)? as usize;
+                let bytes = message.get(..length).ok_or_else(invalid)?.to_vec();
+                message = &message[length..];
+                Field::Bytes(bytes)
+            }
+            1 | 5 => {
+                let size = if key & 7 == 1 { 8 } else { 4 };
+                message = message.get(size..).ok_or_else(invalid)?;
+                continue;
+            }
+            _ => return Err(invalid()),
+        };
+        fields.push((key >> 3, field));
+    }
+    Ok(fields)
+}
+
+/// The last value of field `number`, 0 if missing like in proto3.
+fn varint(fields: &[(u64, Field)], number: u64) -> u64 {
+    fields
+        .iter()
+        .rev()
+        .find_map(|(field, value)| match value {
+            Field::Varint(value) if *field == number => Some(*value),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+fn string(fields: &[(u64, Field)], number: u64) -> Result<String, Status> {
+    let bytes = fields.iter().rev().find_map(|(field, value)| match value {
+        Field::Bytes(bytes) if *field == number => Some(bytes.clone()),
+        _ => None,
+    });
+    String::from_utf8(bytes.unwrap_or_default())
+        .map_err(|_| Status(INVALID_ARGUMENT, format!("Field {number} is not UTF-8")))
+}
+
+fn put_raw_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Appends field `number`, left out at 0 like in proto3.
+fn put_varint(out: &mut Vec<u8>, number: u64, value: u64) {
+    if value != 0 {
+        put_raw_varint(out, number << 3);
+        put_raw_varint(out, value);
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, number: u64, value: &str) {
+    if !value.is_empty() {
+        put_raw_varint(out, number << 3 | 2);
+        put_raw_varint(out, value.len() as u64);
+        out.extend(value.as_bytes());
+    }
+}
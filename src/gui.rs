@@ -0,0 +1,725 @@
+//! A page in the web browser for `--gui`, to queue downloads and watch
+//! their progress without a terminal.
+//!
+//! It takes the place of the egui window first planned. egui needs winit and
+//! an OpenGL or wgpu renderer, so display and GPU libraries on every machine
+//! building or running the downloader, and the machines archiving events are
+//! often headless; a window also only serves whoever sits in front of it.
+//! Every machine has a browser, the page covers the same URL entry, format
+//! choice, queue and progress, and it calls the library the same way a
+//! window would.
+//!
+//! The page is compiled in and served on a random port of 127.0.0.1, or with
+//! `--gui-listen` on a fixed address, so a machine archiving for several
+//! people can take downloads from their browsers. Off loopback the page
+//! requires a `--gui-token`, which every request has to carry as
+//! `Authorization: Bearer <token>` or in the cookie the page gets when
+//! opened as `/?token=<token>`, and with `--gui-cert` and `--gui-key` it is
+//! served over HTTPS. Without a token only requests from the page itself are
+//! answered, so other sites open in the browser cannot queue downloads to
+//! files of their choosing or have the page fetch URLs for them. It talks to
+//! a small JSON API:
+//!
+//! ```text
+//! GET  /                          the page
+//! GET  /formats?url=..&referer=.. title, renditions and audio tracks
+//! POST /queue                     form with url, referer, filename, video,
+//!                                 audio, container and priority
+//! GET  /jobs                      every queued download and its progress
+//! GET  /history                   the latest finished downloads, see
+//!                                 archive.rs
+//! POST /bump                      form with the id of a queued download to
+//!                                 run next
+//! POST /cancel                    stops the running download
+//! ```
+//!
+//! Downloads run one after the other, with the arguments the command line
+//! would have for them. The next is the queued one with the highest
+//! priority, from 1 to 9 like those of calendars, and among those the one
+//! queued or bumped first. The `queue` subcommand lists and bumps the jobs
+//! of a running page from a terminal, finding it through
+//! [`paths::gui_address_file`]. With `--grpc` the queue is also a gRPC
+//! service, see grpc.rs.
+
+use std::io::{self, prelude::*, BufReader};
+use std::net::{IpAddr, TcpListener};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+use eyre::{eyre, Result};
+use ureq::serde_json::{json, Value};
+
+use crate::httpd::{self, same_token, Connections, Head};
+use crate::stats::Stats;
+use crate::{archive, paths, run_observed, signals, style, tls, Args};
+
+const PAGE: &str = include_str!("gui.html");
+const DEFAULT_PRIORITY: u8 = 5;
+/// Downloads of the history the page shows.
+const HISTORY: usize = 100;
+/// Largest request body read, far more than any of the page's forms.
+pub const MAX_BODY: usize = 64 * 1024;
+
+pub struct Options {
+    /// Address to serve the page on, instead of a random local port.
+    pub listen: Option<String>,
+    /// Address to serve the gRPC service on.
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<String>,
+    /// The download history, see archive.rs.
+    pub history: Option<PathBuf>,
+    /// Token every request has to carry.
+    pub token: Option<String>,
+    /// Certificate to serve the page and the gRPC service over TLS with.
+    pub identity: Option<tls::Identity>,
+}
+
+enum State {
+    Queued(Box<Args>),
+    Running,
+    Done,
+    Failed(String),
+}
+
+struct Job {
+    filename: String,
+    state: State,
+    /// 1 is the highest, 9 the lowest.
+    priority: u8,
+    /// Order among jobs of the same priority; bumping puts a job first.
+    rank: i64,
+    /// Number of segments and the statistics, once the download started.
+    progress: Option<(usize, Arc<Stats>)>,
+}
+
+#[derive(Default)]
+pub struct Queue {
+    jobs: Vec<Job>,
+    /// The running download is being stopped from the page, so the stop
+    /// does not end the whole program.
+    cancelling: bool,
+    /// Where the downloads are noted, for the page to show them.
+    history: Option<PathBuf>,
+}
+
+pub type Shared = Arc<(Mutex<Queue>, Condvar)>;
+
+/// Serves the page, and the gRPC service if asked for, until the process is
+/// interrupted.
+pub fn run(options: &Options) -> Result<()> {
+    let addr = options.listen.as_deref().unwrap_or("127.0.0.1:0");
+    let listener = TcpListener::bind(addr).map_err(|e| eyre!("Could not listen on {addr}: {e}"))?;
+    let token = options.token.as_deref();
+    httpd::require_token(listener.local_addr()?, token, "--gui-token")?;
+    #[cfg(feature = "rustls")]
+    let config = (options.identity.as_ref())
+        .map(|identity| identity.server_config(&[b"http/1.1"]))
+        .transpose()?;
+    #[cfg(not(feature = "rustls"))]
+    if options.identity.is_some() {
+        return Err(eyre!("--gui-cert needs a build with the rustls feature"));
+    }
+    #[cfg(feature = "grpc")]
+    let grpc = options.grpc.as_deref().map(crate::grpc::bind).transpose()?;
+    #[cfg(feature = "grpc")]
+    if let Some(listener) = &grpc {
+        httpd::require_token(listener.local_addr()?, token, "--gui-token")?;
+    }
+    let scheme = match options.identity {
+        Some(_) => "https",
+        None => "http",
+    };
+    let url = format!("{scheme}://{}/", listener.local_addr()?);
+    info!("Downloads can be queued at {url}, press Ctrl+C to quit");
+    if options.listen.is_none() {
+        match &options.token {
+            Some(token) => open_browser(&format!("{url}?token={token}")),
+            None => open_browser(&url),
+        }
+    }
+    let address_file = paths::gui_address_file();
+    if let Some(path) = &address_file {
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, &url));
+        if let Err(e) = written {
+            warning!(
+                "Cannot write {} ({e}), the queue subcommand will not find this page",
+                path.display()
+            );
+        }
+    }
+
+    let queue: Shared = Arc::default();
+    queue.0.lock().unwrap().history = options.history.clone();
+    {
+        let queue = queue.clone();
+        thread::spawn(move || work(&queue));
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(listener) = grpc {
+        let queue = queue.clone();
+        let options = crate::grpc::Options {
+            token: options.token.clone(),
+            identity: options.identity.clone(),
+        };
+        thread::spawn(move || {
+            if let Err(e) = crate::grpc::serve(listener, queue, &options) {
+                warning!("The gRPC service stopped: {e:#}");
+            }
+        });
+    }
+    {
+        let queue = queue.clone();
+        let token = Arc::new(options.token.clone());
+        let connections = Connections::default();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Some(admitted) = connections.admit(&stream) else {
+                    continue;
+                };
+                let (queue, token) = (queue.clone(), token.clone());
+                #[cfg(feature = "rustls")]
+                let config = config.clone();
+                thread::spawn(move || {
+                    let _admitted = admitted;
+                    // Browsers hang up on requests they lost interest in.
+                    #[cfg(feature = "rustls")]
+                    if let Some(config) = config {
+                        if let Ok(connection) = rustls::ServerConnection::new(config) {
+                            let stream = rustls::StreamOwned::new(connection, stream);
+                            let _ = handle_connection(stream, &queue, token.as_deref(), true);
+                        }
+                        return;
+                    }
+                    let _ = handle_connection(stream, &queue, token.as_deref(), false);
+                });
+            }
+        });
+    }
+    while !signals::stop_requested() || queue.0.lock().unwrap().cancelling {
+        thread::sleep(Duration::from_millis(200));
+    }
+    if let Some(path) = address_file {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+fn open_browser(url: &str) {
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = Command::new("xdg-open");
+    if let Err(e) = command.arg(url).spawn() {
+        warning!("Cannot open a web browser ({e}), open {url} in one yourself");
+    }
+}
+
+/// Runs the queued downloads one after the other, by priority.
+fn work(queue: &Shared) {
+    let (lock, added) = &**queue;
+    loop {
+        let (index, args) = {
+            let mut queue = lock.lock().unwrap();
+            loop {
+                if let Some(index) = next(&queue) {
+                    let job = &mut queue.jobs[index];
+                    let State::Queued(args) = std::mem::replace(&mut job.state, State::Running)
+                    else {
+                        unreachable!()
+                    };
+                    break (index, args);
+                }
+                queue = added.wait(queue).unwrap();
+            }
+        };
+        let result = run_observed(&args, &mut |_, video, stats| {
+            lock.lock().unwrap().jobs[index].progress = Some((video.segments.len(), stats.clone()));
+        });
+        let mut queue = lock.lock().unwrap();
+        queue.jobs[index].state = match result {
+            Ok(()) => State::Done,
+            Err(e) => State::Failed(format!("{e:#}")),
+        };
+        if queue.cancelling {
+            signals::clear_stop();
+            queue.cancelling = false;
+        }
+    }
+}
+
+/// The queued job to run next.
+fn next(queue: &Queue) -> Option<usize> {
+    queue
+        .jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, job)| matches!(job.state, State::Queued(_)))
+        .min_by_key(|(_, job)| (job.priority, job.rank))
+        .map(|(index, _)| index)
+}
+
+fn handle_connection(
+    mut stream: impl Read + Write,
+    queue: &Shared,
+    token: Option<&str>,
+    secure: bool,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(&mut stream);
+    let head = httpd::read_head(&mut reader)?;
+    let query = head.target.split_once('?').map_or("", |(_, query)| query);
+    match token {
+        Some(token) if !authorized(&head, query, token) => {
+            let error = "Open the page as /?token=<TOKEN> or send Authorization: Bearer <TOKEN>";
+            let error = json!({ "error": error }).to_string();
+            return respond(stream, "401 Unauthorized", "application/json", None, &error);
+        }
+        None if !from_page(&head, secure) => {
+            let error = json!({ "error": "Only the page itself can use it" }).to_string();
+            return respond(stream, "403 Forbidden", "application/json", None, &error);
+        }
+        _ => {}
+    }
+    let length = head.content_length();
+    if length > MAX_BODY {
+        let error = format!("The request body is over {MAX_BODY} bytes");
+        let error = json!({ "error": error }).to_string();
+        return respond(
+            stream,
+            "413 Payload Too Large",
+            "application/json",
+            None,
+            &error,
+        );
+    }
+    let mut form = vec![0; length];
+    reader.read_exact(&mut form)?;
+    let mut cookie = None;
+    let (status, content_type, body) = match (head.method.as_str(), head.path()) {
+        ("GET", "/") => {
+            if let Some(token) = token {
+                let secure = if secure { "; Secure" } else { "" };
+                cookie = Some(format!(
+                    "token={token}; Path=/; HttpOnly; SameSite=Strict{secure}"
+                ));
+            }
+            ("200 OK", "text/html; charset=utf-8", PAGE.to_string())
+        }
+        ("GET", "/formats") => json_response(formats(&field_map(query.as_bytes()))),
+        ("POST", "/queue") => json_response(enqueue(queue, &field_map(&form))),
+        ("GET", "/jobs") => json_response(Ok(jobs(queue))),
+        ("GET", "/history") => json_response(downloads(queue)),
+        ("POST", "/bump") => json_response(bump(queue, &field_map(&form))),
+        ("POST", "/cancel") => {
+            cancel(queue);
+            json_response(Ok(json!({})))
+        }
+        _ => ("404 Not Found", "text/plain", String::new()),
+    };
+    respond(stream, status, content_type, cookie, &body)
+}
+
+fn respond(
+    mut stream: impl Write,
+    status: &str,
+    content_type: &str,
+    cookie: Option<String>,
+    body: &str,
+) -> io::Result<()> {
+    let cookie = cookie
+        .map(|cookie| format!("Set-Cookie: {cookie}\r\n"))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n{cookie}Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// Whether `head` carries `token`, in its headers, its cookie or, for the
+/// page, its `query`.
+fn authorized(head: &Head, query: &str, token: &str) -> bool {
+    let cookie = (head.header("cookie").unwrap_or_default().split(';'))
+        .filter_map(|cookie| cookie.trim().strip_prefix("token="))
+        .any(|given| same_token(given, token));
+    let query =
+        head.path() == "/" && same_token(field(&field_map(query.as_bytes()), "token"), token);
+    head.bearer(token) || cookie || query
+}
+
+/// Whether a request without a token comes from the page or a local client
+/// like the queue subcommand: sent to a loopback name, so not to a name of
+/// another site rebound to 127.0.0.1, and not from another site open in the
+/// browser, as far as `Origin` and `Sec-Fetch-Site` tell.
+fn from_page(head: &Head, secure: bool) -> bool {
+    let host = head.header("host").unwrap_or_default();
+    let name = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next(),
+        None => host.split(':').next(),
+    };
+    let loopback = name.is_some_and(|name| {
+        name == "localhost" || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    });
+    let scheme = if secure { "https" } else { "http" };
+    let origin =
+        (head.header("origin")).is_none_or(|origin| origin == format!("{scheme}://{host}"));
+    let site =
+        (head.header("sec-fetch-site")).is_none_or(|site| matches!(site, "same-origin" | "none"));
+    loopback && origin && site
+}
+
+fn json_response(result: Result<Value>) -> (&'static str, &'static str, String) {
+    let (status, value) = match result {
+        Ok(value) => ("200 OK", value),
+        Err(e) => ("400 Bad Request", json!({ "error": format!("{e:#}") })),
+    };
+    (status, "application/json", value.to_string())
+}
+
+fn field_map(form: &[u8]) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(form).into_owned().collect()
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> &'a str {
+    fields
+        .iter()
+        .find(|(key, _)| key == name)
+        .map_or("", |(_, value)| value.trim())
+}
+
+/// What can be chosen for the event at the `url` field.
+fn formats(fields: &[(String, String)]) -> Result<Value> {
+    let extraction = crate::extract(field(fields, "url"), field(fields, "referer"))?;
+    let mut videos = extraction.videos;
+    videos.sort_by_key(|video| std::cmp::Reverse(video.width));
+    let videos: Vec<_> = videos
+        .iter()
+        .map(|video| {
+            json!({
+                "id": video.id.trim_matches('"'),
+                "label": format!(
+                    "{}x{}, {} kbit/s, {}",
+                    video.width,
+                    video.height,
+                    video.bitrate / 1000,
+                    style::codec_names(&video.codecs)
+                ),
+            })
+        })
+        .collect();
+    let audios: Vec<_> = extraction
+        .audios
+        .iter()
+        .filter_map(|audio| audio.language.as_deref())
+        .collect();
+    Ok(json!({
+        "title": extraction.media.title,
+        "videos": videos,
+        "languages": audios,
+    }))
+}
+
+pub fn enqueue(queue: &Shared, fields: &[(String, String)]) -> Result<Value> {
+    let filename = field(fields, "filename");
+    if filename.is_empty() || filename == "-" {
+        return Err(eyre!("Choose a file to save the download to"));
+    }
+    let mut argv = vec![
+        "vimeo-event-downloader",
+        "--url",
+        field(fields, "url"),
+        "--referer",
+        field(fields, "referer"),
+        "--filename",
+        filename,
+    ];
+    let options = [
+        ("video", "--video-id"),
+        ("audio", "--audio-lang"),
+        ("container", "--container"),
+    ];
+    for (name, flag) in options {
+        let value = field(fields, name);
+        if !value.is_empty() {
+            argv.extend([flag, value]);
+        }
+    }
+    let history = queue.0.lock().unwrap().history.clone();
+    let history = history.as_ref().map(|path| path.to_string_lossy());
+    if let Some(history) = &history {
+        argv.extend(["--history", history]);
+    }
+    let args = Args::try_parse_from(argv)?;
+    let priority = match field(fields, "priority") {
+        "" => DEFAULT_PRIORITY,
+        text => match text.parse() {
+            Ok(priority @ 1..=9) => priority,
+            _ => return Err(eyre!("The priority must be 1 to 9, not {text}")),
+        },
+    };
+    let (lock, added) = &**queue;
+    let mut queue = lock.lock().unwrap();
+    let rank = queue.jobs.len() as i64;
+    queue.jobs.push(Job {
+        filename: filename.to_string(),
+        state: State::Queued(Box::new(args)),
+        priority,
+        rank,
+        progress: None,
+    });
+    added.notify_one();
+    Ok(json!({ "id": rank }))
+}
+
+/// Makes the queued job in the `id` field the next to run.
+fn bump(queue: &Shared, fields: &[(String, String)]) -> Result<Value> {
+    let id: usize = field(fields, "id")
+        .parse()
+        .map_err(|_| eyre!("No job {:?}", field(fields, "id")))?;
+    let mut queue = queue.0.lock().unwrap();
+    let queued = queue
+        .jobs
+        .iter()
+        .filter(|job| matches!(job.state, State::Queued(_)));
+    let first = queued.map(|job| (job.priority, job.rank)).min();
+    let (Some((priority, rank)), Some(job)) = (first, queue.jobs.get_mut(id)) else {
+        return Err(eyre!("No job {id}"));
+    };
+    if !matches!(job.state, State::Queued(_)) {
+        return Err(eyre!("Job {id} is not waiting in the queue"));
+    }
+    if (job.priority, job.rank) != (priority, rank) {
+        job.priority = priority;
+        job.rank = rank - 1;
+    }
+    Ok(json!({}))
+}
+
+pub fn jobs(queue: &Shared) -> Value {
+    let queue = queue.0.lock().unwrap();
+    let jobs: Vec<_> = queue
+        .jobs
+        .iter()
+        .enumerate()
+        .map(|(id, job)| {
+            let (state, error) = match &job.state {
+                State::Queued(_) => ("queued", None),
+                State::Running => ("running", None),
+                State::Done => ("done", None),
+                State::Failed(e) => ("failed", Some(e)),
+            };
+            let (total, done, bytes, throttled) = match &job.progress {
+                Some((total, stats)) => {
+                    // Audio tracks come on top of the segments of the video.
+                    let (done, bytes) = stats.progress();
+                    (*total, done.min(*total), bytes, stats.throttled())
+                }
+                None => (0, 0, 0, 0),
+            };
+            json!({
+                "id": id,
+                "filename": job.filename,
+                "state": state,
+                "priority": job.priority,
+                "error": error,
+                "segments": total,
+                "done": done,
+                "bytes": bytes,
+                "throttled": throttled,
+            })
+        })
+        .collect();
+    Value::from(jobs)
+}
+
+/// The latest downloads of the history, and whether they are still there.
+fn downloads(queue: &Shared) -> Result<Value> {
+    let Some(history) = queue.0.lock().unwrap().history.clone() else {
+        return Ok(json!([]));
+    };
+    let downloads: Vec<_> = archive::downloads(&history)?
+        .into_iter()
+        .take(HISTORY)
+        .map(|download| {
+            json!({
+                "id": download.id,
+                "rendition": download.rendition,
+                "size": download.size,
+                "path": download.path,
+                "present": download.path.is_file(),
+            })
+        })
+        .collect();
+    Ok(Value::from(downloads))
+}
+
+/// Stops the running download like Ctrl+C would, leaving the queue be.
+fn cancel(queue: &Shared) {
+    stop_running(&mut queue.0.lock().unwrap());
+}
+
+/// Stops the running download, if any. The queue is locked throughout, so
+/// it cannot finish in between and have the next one stopped instead.
+fn stop_running(queue: &mut Queue) {
+    let running = queue
+        .jobs
+        .iter()
+        .any(|job| matches!(job.state, State::Running));
+    if running && !queue.cancelling {
+        queue.cancelling = true;
+        signals::request_stop();
+    }
+}
+
+/// Stops job `id` if it runs, or takes it out of the queue.
+#[cfg(feature = "grpc")]
+pub fn cancel_job(queue: &Shared, id: usize) -> Result<()> {
+    let mut locked = queue.0.lock().unwrap();
+    let job = locked
+        .jobs
+        .get_mut(id)
+        .ok_or_else(|| eyre!("No job {id}"))?;
+    match job.state {
+        State::Queued(_) => job.state = State::Failed("Cancelled".to_string()),
+        State::Running => stop_running(&mut locked),
+        State::Done | State::Failed(_) => return Err(eyre!("Job {id} has finished")),
+    }
+    Ok(())
+}
+
+/// `queue list` and `queue bump`, against the page a `--gui` in another
+/// process serves, with the `token` it was started with.
+pub fn remote(agent: &ureq::Agent, bump: Option<usize>, token: Option<&str>) -> Result<()> {
+    let path = paths::gui_address_file().ok_or_else(|| eyre!("No cache directory"))?;
+    let url = std::fs::read_to_string(&path).map_err(|_| eyre!("No page of --gui is running"))?;
+    let url = url.trim();
+    if let Some(id) = bump {
+        let id = id.to_string();
+        match authorize(agent.post(&format!("{url}bump")), token).send_form(&[("id", &id)]) {
+            Ok(_) => info!("Job {id} runs next"),
+            Err(ureq::Error::Status(_, response)) => {
+                let error: Value = response.into_json()?;
+                return Err(eyre!("{}", error["error"].as_str().unwrap_or_default()));
+            }
+            Err(e) => return Err(e.into()),
+        }
+        return Ok(());
+    }
+    let jobs: Value = authorize(agent.get(&format!("{url}jobs")), token)
+        .call()?
+        .into_json()?;
+    for job in jobs.as_array().into_iter().flatten() {
+        println!(
+            "{:>4} {:<8} {} {}",
+            job["id"],
+            job["state"].as_str().unwrap_or_default(),
+            job["priority"],
+            job["filename"].as_str().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+fn authorize(request: ureq::Request, token: Option<&str>) -> ureq::Request {
+    match token {
+        Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+        None => request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        (fields.iter())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn enqueue_file(queue: &Shared, filename: &str, priority: &str) -> Result<Value> {
+        let fields = fields(&[
+            ("url", "https://vimeo.com/event/1"),
+            ("filename", filename),
+            ("priority", priority),
+        ]);
+        enqueue(queue, &fields)
+    }
+
+    /// Runs the queue down, returning the files in the order downloaded.
+    fn run_order(queue: &Shared) -> Vec<String> {
+        let mut queue = queue.0.lock().unwrap();
+        let mut order = Vec::new();
+        while let Some(index) = next(&queue) {
+            order.push(queue.jobs[index].filename.clone());
+            queue.jobs[index].state = State::Done;
+        }
+        order
+    }
+
+    #[test]
+    fn runs_jobs_by_priority_then_order() {
+        let queue: Shared = Arc::default();
+        for (filename, priority) in [("a", ""), ("b", "9"), ("c", "1"), ("d", "5")] {
+            enqueue_file(&queue, filename, priority).unwrap();
+        }
+        assert!(enqueue_file(&queue, "e", "0").is_err());
+        assert!(enqueue_file(&queue, "e", "high").is_err());
+        assert_eq!(run_order(&queue), ["c", "a", "d", "b"]);
+    }
+
+    #[test]
+    fn bumped_job_runs_next() {
+        let queue: Shared = Arc::default();
+        for (filename, priority) in [("a", "1"), ("b", ""), ("c", "9")] {
+            enqueue_file(&queue, filename, priority).unwrap();
+        }
+        bump(&queue, &fields(&[("id", "2")])).unwrap();
+        // Bumping the first changes nothing.
+        bump(&queue, &fields(&[("id", "2")])).unwrap();
+        assert!(bump(&queue, &fields(&[("id", "3")])).is_err());
+        assert!(bump(&queue, &fields(&[("id", "c")])).is_err());
+        assert_eq!(run_order(&queue), ["c", "a", "b"]);
+        assert!(bump(&queue, &fields(&[("id", "0")])).is_err());
+    }
+
+    #[cfg(feature = "grpc")]
+    fn job(state: State) -> Job {
+        Job {
+            filename: String::new(),
+            state,
+            priority: DEFAULT_PRIORITY,
+            rank: 0,
+            progress: None,
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn cancelling_a_finished_job_leaves_the_running_one_alone() {
+        let queue: Shared = Arc::default();
+        queue.0.lock().unwrap().jobs = vec![job(State::Done), job(State::Running)];
+        assert!(cancel_job(&queue, 0).is_err());
+        assert!(cancel_job(&queue, 2).is_err());
+        assert!(!queue.0.lock().unwrap().cancelling);
+        assert!(!signals::stop_requested());
+
+        cancel_job(&queue, 1).unwrap();
+        assert!(queue.0.lock().unwrap().cancelling);
+        assert!(signals::stop_requested());
+        signals::clear_stop();
+    }
+}
@@ -0,0 +1,257 @@
+//! Recording HTTP traffic as a HAR file.
+//!
+//! Every request through the ureq agent is kept with its headers and
+//! timings; text bodies such as the event page, player config and manifest
+//! are kept up to [`MAX_BODY`] bytes. Segment bodies are binary and only
+//! their size is known. Requests of the HTTP/2 and HTTP/3 segment clients
+//! are not recorded.
+//!
+//! Cookie and Authorization values are replaced, so a file can be attached
+//! to a bug report as is.
+
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use eyre::Result;
+use ureq::serde_json::{self, json, Value};
+use ureq::{Middleware, MiddlewareNext, Request, Response};
+
+/// Bytes of a body kept in the file.
+const MAX_BODY: usize = 64 * 1024;
+
+const REDACTED: [&str; 3] = ["authorization", "cookie", "set-cookie"];
+
+#[derive(Debug)]
+pub struct Har {
+    path: Option<PathBuf>,
+    entries: Mutex<Vec<Value>>,
+}
+
+impl Har {
+    /// Records into a file at `path`, written when the last user is dropped.
+    pub fn new(path: &Path) -> Har {
+        Har {
+            path: Some(path.to_path_buf()),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Records for [`Har::log`] only, for `--report`.
+    pub fn in_memory() -> Har {
+        Har {
+            path: None,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The HAR document of what was recorded so far.
+    pub fn log(&self) -> Value {
+        let entries = self.entries.lock().unwrap().clone();
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": entries,
+            }
+        })
+    }
+}
+
+impl Drop for Har {
+    fn drop(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let written = File::create(path)
+            .map_err(eyre::Report::from)
+            .and_then(|file| {
+                Ok(serde_json::to_writer_pretty(
+                    BufWriter::new(file),
+                    &self.log(),
+                )?)
+            });
+        if let Err(e) = written {
+            warning!("Cannot write {}: {e:#}", path.display());
+        }
+    }
+}
+
+/// Shares one [`Har`] between several agents.
+pub struct Recorder(pub std::sync::Arc<Har>);
+
+impl Middleware for Recorder {
+    fn handle(&self, request: Request, next: MiddlewareNext) -> Result<Response, ureq::Error> {
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let request_entry = json!({
+            "method": request.method(),
+            "url": request.url(),
+            "httpVersion": "HTTP/1.1",
+            "headers": headers(request.header_names(), |name| request.header(name)),
+            "queryString": [],
+            "cookies": [],
+            "headersSize": -1,
+            "bodySize": 0,
+        });
+        let result = next.handle(request);
+        let wait = start.elapsed();
+
+        let (response, response_entry) = match result.and_then(capture) {
+            Ok((response, content)) => {
+                let entry = json!({
+                    "status": response.status(),
+                    "statusText": response.status_text(),
+                    "httpVersion": response.http_version(),
+                    "headers": headers(response.headers_names(), |name| response.header(name)),
+                    "cookies": [],
+                    "content": content,
+                    "redirectURL": response.header("Location").unwrap_or_default(),
+                    "headersSize": -1,
+                    "bodySize": -1,
+                });
+                (Ok(response), entry)
+            }
+            Err(e) => {
+                // HAR has no place for failed requests; status 0 is what
+                // browsers export for them.
+                let entry = json!({
+                    "status": 0,
+                    "statusText": "",
+                    "httpVersion": "",
+                    "headers": [],
+                    "cookies": [],
+                    "content": { "size": 0, "mimeType": "" },
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": -1,
+                    "_error": e.to_string(),
+                });
+                (Err(e), entry)
+            }
+        };
+        let total = start.elapsed();
+        self.0.entries.lock().unwrap().push(json!({
+            "startedDateTime": timestamp(started),
+            "time": millis(total),
+            "request": request_entry,
+            "response": response_entry,
+            "cache": {},
+            "timings": {
+                "send": 0,
+                "wait": millis(wait),
+                "receive": millis(total - wait),
+            },
+        }));
+        response
+    }
+}
+
+fn headers<'a>(names: Vec<String>, value: impl Fn(&str) -> Option<&'a str>) -> Value {
+    names
+        .iter()
+        .map(|name| {
+            let value = if REDACTED.contains(&name.to_ascii_lowercase().as_str()) {
+                "[redacted]"
+            } else {
+                value(name).unwrap_or_default()
+            };
+            json!({ "name": name, "value": value })
+        })
+        .collect()
+}
+
+/// Reads text bodies for the record and hands back an equivalent response.
+///
+/// ureq responses cannot be cloned, but they can be parsed from text, so
+/// the body is read and the response rebuilt around it. Anything else is
+/// passed through unread. A body that breaks off fails the request, as it
+/// would have without recording.
+#[allow(clippy::result_large_err)] // ureq's own error type
+fn capture(response: Response) -> Result<(Response, Value), ureq::Error> {
+    let mime = response.content_type().to_string();
+    let text = ["text/", "json", "xml", "javascript"]
+        .iter()
+        .any(|kind| mime.contains(kind));
+    if !text {
+        let size = response
+            .header("Content-Length")
+            .and_then(|len| len.parse::<u64>().ok());
+        return Ok((
+            response,
+            json!({ "size": size.unwrap_or(0), "mimeType": mime }),
+        ));
+    }
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status(),
+        response.status_text()
+    );
+    for name in response.headers_names() {
+        // The body is stored decoded and complete.
+        if matches!(
+            name.to_ascii_lowercase().as_str(),
+            "content-length" | "transfer-encoding"
+        ) {
+            continue;
+        }
+        for value in response.all(&name) {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+    }
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+    let mut content = json!({
+        "size": body.len(),
+        "mimeType": mime,
+        "text": truncate(&body, MAX_BODY),
+    });
+    if body.len() > MAX_BODY {
+        content["comment"] = json!(format!("truncated to {MAX_BODY} bytes"));
+    }
+    let response = format!("{head}\r\n{body}").parse()?;
+    Ok((response, content))
+}
+
+fn truncate(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn millis(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// ISO 8601 in UTC, e.g. `2024-05-01T12:00:00.123Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rest) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60,
+        since_epoch.subsec_millis()
+    )
+}
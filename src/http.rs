@@ -0,0 +1,254 @@
+//! HTTP clients used for fetching segments.
+
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::Result;
+
+use crate::har::{Har, Recorder};
+use crate::resolve::{self, Resolver};
+use crate::tls;
+
+/// Connection settings applied to every client.
+///
+/// There is no pipeline depth: neither ureq nor reqwest pipeline HTTP/1.1
+/// requests, so parallel requests to a host take parallel connections, and
+/// `max_connections_per_host` is what bounds them.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Idle connections kept open per host.
+    pub max_connections_per_host: usize,
+    /// How long idle connections are kept; `Some(0)` disables reuse.
+    ///
+    /// ureq has no idle timeout of its own and keeps connections until the
+    /// server closes them, so for it only disabling reuse has an effect.
+    pub idle_timeout: Option<Duration>,
+    pub resolve: resolve::Options,
+    pub tls: tls::Options,
+    /// Proxy URL; `socks5h://` has the proxy resolve host names.
+    pub proxy: Option<String>,
+    /// Where ureq requests are recorded.
+    pub har: Option<Arc<Har>>,
+}
+
+impl Config {
+    pub fn agent(&self) -> Result<ureq::Agent> {
+        let reuse = self.idle_timeout != Some(Duration::ZERO);
+        let per_host = if reuse {
+            self.max_connections_per_host
+        } else {
+            0
+        };
+        let mut builder = ureq::AgentBuilder::new()
+            .max_idle_connections(per_host.max(100))
+            .max_idle_connections_per_host(per_host)
+            .resolver(Resolver::new(self.resolve.clone()));
+        if let Some(har) = &self.har {
+            builder = builder.middleware(Recorder(har.clone()));
+        }
+        if let Some(proxy) = &self.proxy {
+            // ureq always lets SOCKS5 proxies resolve host names and does
+            // not know the socks5h scheme.
+            let proxy = proxy.replacen("socks5h://", "socks5://", 1);
+            builder = builder.proxy(ureq::Proxy::new(proxy)?);
+        }
+        Ok(self.tls.apply_ureq(builder)?.build())
+    }
+
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    fn reqwest(&self) -> Result<reqwest::blocking::ClientBuilder> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .timeout(None)
+            .pool_max_idle_per_host(self.max_connections_per_host)
+            .dns_resolver(Arc::new(Resolver::new(self.resolve.clone())));
+        if let Some(timeout) = self.idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        self.tls.apply_reqwest(builder)
+    }
+}
+
+/// Client segment requests go through.
+///
+/// Page, config and manifest requests always use ureq; only the many
+/// segment requests benefit from a multiplexing client.
+pub enum Client {
+    Ureq(ureq::Agent),
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    Reqwest(reqwest::blocking::Client),
+}
+
+impl Client {
+    #[cfg(feature = "http2")]
+    pub fn http2(config: &Config) -> Result<Client> {
+        Ok(Client::Reqwest(config.reqwest()?.build()?))
+    }
+
+    /// Speaks HTTP/3 right away instead of upgrading via Alt-Svc.
+    ///
+    /// reqwest only offers HTTP/3 with `RUSTFLAGS="--cfg reqwest_unstable"`.
+    #[cfg(feature = "http3")]
+    pub fn http3(config: &Config) -> Result<Client> {
+        Ok(Client::Reqwest(
+            config.reqwest()?.http3_prior_knowledge().build()?,
+        ))
+    }
+
+    /// Starts a GET request for the bytes from `offset` on.
+    ///
+    /// A range is only asked for together with the `validator` of the
+    /// earlier response, as `If-Range`. Servers send everything if the
+    /// object changed or they ignore ranges, see [`Response::partial`].
+    pub fn get(&self, url: &str, offset: u64, validator: Option<&str>) -> Result<Response> {
+        let range = validator
+            .filter(|_| offset > 0)
+            .map(|validator| (format!("bytes={offset}-"), validator));
+        match self {
+            Client::Ureq(agent) => {
+                let mut request = agent.get(url);
+                if let Some((range, validator)) = &range {
+                    request = request.set("Range", range).set("If-Range", validator);
+                }
+                let response = request.call()?;
+                let etag = response
+                    .header("ETag")
+                    .filter(|etag| !etag.starts_with("W/"));
+                Ok(Response {
+                    partial: response.status() == 206,
+                    validator: etag
+                        .or_else(|| response.header("Last-Modified"))
+                        .map(str::to_string),
+                    body: Box::new(response.into_reader()),
+                })
+            }
+            #[cfg(any(feature = "http2", feature = "http3"))]
+            Client::Reqwest(client) => {
+                use reqwest::header::{ETAG, IF_RANGE, LAST_MODIFIED, RANGE, RETRY_AFTER};
+
+                let mut request = client.get(url);
+                if let Some((range, validator)) = range {
+                    request = request.header(RANGE, range).header(IF_RANGE, validator);
+                }
+                let response = request.send()?;
+                let retry_after = (response.headers().get(RETRY_AFTER))
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after);
+                // The error drops the headers, the wait asked for goes with
+                // it as context.
+                let response = response.error_for_status().map_err(|e| match retry_after {
+                    Some(wait) => eyre::Report::new(e).wrap_err(RetryAfter(wait)),
+                    None => e.into(),
+                })?;
+                let headers = response.headers();
+                let etag = headers
+                    .get(ETAG)
+                    .filter(|etag| !etag.as_bytes().starts_with(b"W/"));
+                let validator = etag
+                    .or_else(|| headers.get(LAST_MODIFIED))
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                Ok(Response {
+                    partial: response.status() == reqwest::StatusCode::PARTIAL_CONTENT,
+                    validator,
+                    body: Box::new(response),
+                })
+            }
+        }
+    }
+}
+
+pub struct Response {
+    /// The body starts at the requested offset instead of at the beginning.
+    pub partial: bool,
+    /// Strong ETag, or else Last-Modified, identifying this version of the
+    /// object for `If-Range`. Weak ETags are not allowed there.
+    pub validator: Option<String>,
+    pub body: Box<dyn Read + Send>,
+}
+
+/// Status code of the HTTP error somewhere in `error`.
+pub fn status(error: &eyre::Report) -> Option<u16> {
+    for cause in error.chain() {
+        if let Some(ureq::Error::Status(status, _)) = cause.downcast_ref::<ureq::Error>() {
+            return Some(*status);
+        }
+        #[cfg(any(feature = "http2", feature = "http3"))]
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.status().map(|status| status.as_u16());
+        }
+    }
+    None
+}
+
+/// The wait a throttled server asked for with `Retry-After`, kept with
+/// errors that lose the response.
+#[derive(Debug)]
+struct RetryAfter(Duration);
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Asked to retry after {}s", self.0.as_secs())
+    }
+}
+
+/// The wait the server asked for in the `Retry-After` header of the HTTP
+/// error somewhere in `error`.
+pub fn retry_after(error: &eyre::Report) -> Option<Duration> {
+    if let Some(RetryAfter(wait)) = error.downcast_ref::<RetryAfter>() {
+        return Some(*wait);
+    }
+    for cause in error.chain() {
+        if let Some(ureq::Error::Status(_, response)) = cause.downcast_ref::<ureq::Error>() {
+            return response.header("Retry-After").and_then(parse_retry_after);
+        }
+    }
+    None
+}
+
+/// Whether `error` is the server turning down a request because it gets
+/// too many: a 429, or a 503 saying when to come back.
+pub fn is_throttled(error: &eyre::Report) -> bool {
+    match status(error) {
+        Some(429) => true,
+        Some(503) => retry_after(error).is_some(),
+        _ => false,
+    }
+}
+
+/// `Retry-After` in seconds or as an HTTP date, such as
+/// `Wed, 21 Oct 2015 07:28:00 GMT`.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let mut fields = value.split_whitespace().skip(1);
+    let (Some(day), Some(month), Some(year), Some(clock), Some("GMT")) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        return None;
+    };
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+    let mut clock = clock.split(':').map(str::parse::<i64>);
+    let (Some(Ok(hour)), Some(Ok(minute)), Some(Ok(second))) =
+        (clock.next(), clock.next(), clock.next())
+    else {
+        return None;
+    };
+    let days = crate::schedule::days_from_civil(year.parse().ok()?, month, day.parse().ok()?);
+    let at = days * 86400 + hour * 3600 + minute * 60 + second;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    Some(Duration::from_secs((at - now).max(0) as u64))
+}
@@ -0,0 +1,150 @@
+//! What the servers taking requests from other machines share, those of
+//! the worker subcommand (remote.rs) and of `--gui` (gui.rs).
+//!
+//! Their clients may be anyone who can reach the port. Request heads are
+//! read within limits and every connection gets a read timeout, so nobody
+//! can hold threads or memory by sending slowly or without end, and tokens
+//! are compared without giving them away through timing. Off loopback a
+//! token is required, or anyone on the network could have the server
+//! download for them.
+
+use std::io::{self, prelude::*};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::Result;
+
+/// Longest line of a request head.
+const MAX_LINE: usize = 8 * 1024;
+/// Most header lines of a request.
+const MAX_HEADERS: usize = 100;
+/// Most connections served at once, further ones are closed right away.
+const MAX_CONNECTIONS: usize = 64;
+/// How long a client may take to send the next part of its request.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request line and headers of a request.
+pub struct Head {
+    pub method: String,
+    /// Path and query.
+    pub target: String,
+    /// With lowercase names.
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    /// The target without its query.
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// The first header `name`, which must be lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        (self.headers.iter())
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Bytes of the body, 0 if not given.
+    pub fn content_length(&self) -> usize {
+        (self.header("content-length"))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Whether the request carries `token` as `Authorization: Bearer`.
+    pub fn bearer(&self, token: &str) -> bool {
+        (self.header("authorization"))
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| same_token(given, token))
+    }
+}
+
+/// Reads the request line and headers, leaving the body to be read once
+/// the request is authorized.
+pub fn read_head(reader: &mut impl BufRead) -> io::Result<Head> {
+    let mut request_line = String::new();
+    read_line(reader, &mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if read_line(reader, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many header lines",
+            ));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    Ok(Head {
+        method,
+        target,
+        headers,
+    })
+}
+
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let read = reader.by_ref().take(MAX_LINE as u64 + 1).read_line(line)?;
+    if line.len() > MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Header line too long",
+        ));
+    }
+    Ok(read)
+}
+
+/// Whether `given` is `token`, taking as long wherever they differ so the
+/// time of a refusal does not give the token away byte by byte.
+pub fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && (given.bytes().zip(token.bytes())).fold(0, |differ, (a, b)| differ | (a ^ b)) == 0
+}
+
+/// Fails unless `token` is given or connections to `addr` can only come
+/// from this machine; `option` is the one giving the token.
+pub fn require_token(addr: SocketAddr, token: Option<&str>, option: &str) -> Result<()> {
+    match token {
+        None if !addr.ip().is_loopback() => Err(crate::usage_error(format!(
+            "Other machines can connect to {addr}, so it needs {option}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Counts the connections being served.
+#[derive(Clone, Default)]
+pub struct Connections(Arc<AtomicUsize>);
+
+/// A connection counted by [`Connections`] until dropped.
+pub struct Admitted(Arc<AtomicUsize>);
+
+impl Connections {
+    /// Counts `stream` and gives it the read timeout, unless as many as
+    /// allowed are served already.
+    pub fn admit(&self, stream: &TcpStream) -> Option<Admitted> {
+        if self.0.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let admitted = Admitted(self.0.clone());
+        stream.set_read_timeout(Some(READ_TIMEOUT)).ok()?;
+        Some(admitted)
+    }
+}
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
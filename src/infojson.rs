@@ -0,0 +1,108 @@
+//! `--print-info-json`, describing the renditions in the shape of yt-dlp's
+//! info dict so tools built around yt-dlp can read it.
+//!
+//! Only the fields this tool knows are filled in. Each rendition is a
+//! format with the `http_dash_segments` protocol, and the video that would
+//! be downloaded is the single entry of `requested_downloads`.
+
+use ureq::serde_json::{json, Value};
+use vimeo_extract::MediaInfo;
+
+use crate::{AudioInfo, Track, VideoInfo};
+
+pub fn info_dict(
+    url: &str,
+    media: &MediaInfo,
+    videos: &[VideoInfo],
+    audios: &[AudioInfo],
+    chosen: &VideoInfo,
+    filename: Option<&str>,
+) -> Value {
+    let mut formats: Vec<_> = videos.iter().map(format).collect();
+    formats.extend(audios.iter().map(audio_format));
+    let mut requested = format(chosen);
+    if let Some(filename) = filename.filter(|name| *name != "-") {
+        requested["filepath"] = json!(filename);
+        requested["_filename"] = json!(filename);
+    }
+    let format_id = chosen.id.trim_matches('"');
+    json!({
+        "_type": "video",
+        "id": media.id.as_deref().unwrap_or(format_id),
+        "title": media.title,
+        "uploader": media.author,
+        "upload_date": media.upload_date.as_ref().map(|date| date.replace('-', "")),
+        "description": media.description,
+        "cast": media.presenters,
+        "release_date": media
+            .scheduled_start
+            .as_ref()
+            .and_then(|start| Some(start.get(..10)?.replace('-', ""))),
+        "thumbnail": media.thumbnail,
+        "webpage_url": url,
+        "original_url": url,
+        "duration": chosen.duration,
+        "formats": formats,
+        "format_id": format_id,
+        "format": requested["format"],
+        "ext": "mp4",
+        "width": chosen.width,
+        "height": chosen.height,
+        "vcodec": requested["vcodec"],
+        "acodec": "none",
+        "tbr": requested["tbr"],
+        "requested_downloads": [requested],
+    })
+}
+
+fn format(video: &VideoInfo) -> Value {
+    let id = video.id.trim_matches('"');
+    let fragments: Vec<_> = video
+        .segments
+        .iter()
+        .map(|segment| json!({ "path": segment.path, "filesize": segment.size }))
+        .collect();
+    json!({
+        "format_id": id,
+        "format": format!("{id} - {}x{}", video.width, video.height),
+        "ext": "mp4",
+        "protocol": "http_dash_segments",
+        "fragment_base_url": video.base_url,
+        "fragments": fragments,
+        "width": video.width,
+        "height": video.height,
+        "resolution": format!("{}x{}", video.width, video.height),
+        "vcodec": video.codecs.trim_matches('"'),
+        "acodec": "none",
+        "tbr": video.bitrate as f64 / 1000.0,
+        "vbr": video.bitrate as f64 / 1000.0,
+        "filesize": video.output_len(),
+        "duration": video.duration,
+    })
+}
+
+fn audio_format(audio: &AudioInfo) -> Value {
+    let id = audio.id.trim_matches('"');
+    let fragments: Vec<_> = audio
+        .segments
+        .iter()
+        .map(|segment| json!({ "path": segment.path, "filesize": segment.size }))
+        .collect();
+    json!({
+        "format_id": id,
+        "format": format!("{id} - audio only"),
+        "ext": "m4a",
+        "protocol": "http_dash_segments",
+        "fragment_base_url": audio.base_url,
+        "fragments": fragments,
+        "resolution": "audio only",
+        "vcodec": "none",
+        "acodec": audio.codecs.trim_matches('"'),
+        "language": audio.language,
+        "audio_channels": audio.channels,
+        "tbr": audio.bitrate as f64 / 1000.0,
+        "abr": audio.bitrate as f64 / 1000.0,
+        "filesize": audio.output_len(),
+        "duration": audio.duration,
+    })
+}
@@ -0,0 +1,127 @@
+//! Hotkeys while downloading in a terminal: `p` pauses, `r` resumes.
+//!
+//! Pausing lets the segments in flight finish and starts no new requests,
+//! so the server closes the idle connections after a while; nothing of the
+//! download state is lost. Only available on Unix, where the terminal can be
+//! switched to reading single keys without affecting the output.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use crate::signals::Stop;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// The terminal settings before the first [`listen`], for
+/// [`restore_terminal`].
+#[cfg(unix)]
+static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+#[cfg(feature = "tui")]
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+/// Blocks while the download is paused, unless `stop` is requested.
+pub fn wait_while_paused(stop: &Stop) {
+    while PAUSED.load(Ordering::SeqCst) && !stop.requested() {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Listens for hotkeys until the returned guard is dropped, which restores
+/// the terminal. Does nothing unless stdin is a terminal.
+#[cfg(unix)]
+pub fn listen() -> Option<Guard> {
+    use std::io::{self, IsTerminal, Read};
+
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    // Only line buffering and echo are turned off; output processing and
+    // Ctrl+C keep working as usual.
+    let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+        return None;
+    }
+    ORIGINAL.get_or_init(|| original);
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+
+    info!("Press p to pause and r to resume");
+    thread::spawn(|| {
+        for key in io::stdin().lock().bytes() {
+            match key {
+                Ok(b'p') if !PAUSED.swap(true, Ordering::SeqCst) => {
+                    info!("Paused after the segments in flight, press r to resume")
+                }
+                Ok(b'r') if PAUSED.swap(false, Ordering::SeqCst) => info!("Resumed"),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    });
+    Some(Guard { original })
+}
+
+#[cfg(not(unix))]
+pub fn listen() -> Option<Guard> {
+    None
+}
+
+/// Puts back the terminal settings [`listen`] changed, for a process ending
+/// without dropping its [`Guard`]. Only calls what a signal handler may.
+#[cfg(unix)]
+pub fn restore_terminal() {
+    if let Some(original) = ORIGINAL.get() {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+        }
+    }
+}
+
+pub struct Guard {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_stopped_download_does_not_stay_paused() {
+        PAUSED.store(true, Ordering::SeqCst);
+        let stop = Stop::default();
+        let waiting = {
+            let stop = stop.clone();
+            thread::spawn(move || wait_while_paused(&stop))
+        };
+        thread::sleep(Duration::from_millis(300));
+        assert!(!waiting.is_finished());
+        stop.request();
+        waiting.join().unwrap();
+        PAUSED.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,284 @@
+//! Checksums of downloaded segments.
+//!
+//! While downloading, every segment written gets a line in the ledger next to
+//! the output, `<file>.ledger`, or in a segments directory:
+//!
+//! ```text
+//! <index> <offset> <size> <sha256> <url>
+//! ```
+//!
+//! `index` is zero-based, `offset` is the position in the assembled output
+//! and `url` is the segment's URL relative to its rendition, as in the
+//! manifest; ledgers written before it was added lack it. Lines are
+//! appended as segments complete, so with parallel downloads they are not
+//! in order.
+//!
+//! Ledgers of output files are also a journal: before a segment is written,
+//! a line says where it goes,
+//!
+//! ```text
+//! ? <index> <offset> <size>
+//! ```
+//!
+//! and the output and the ledger are flushed to disk every
+//! [`SYNC_INTERVAL`] and when the download stops. After a crash or a power
+//! loss, a segment with such a line but none of its own was cut off while
+//! being written, and at most the last interval of records may be missing,
+//! which `--continue` fetches again.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result};
+
+use crate::{create_locked, sha256_hex};
+
+/// File name of the ledger inside a segments directory.
+pub const FILE_NAME: &str = "ledger";
+
+/// How often the output and its ledger are flushed to disk.
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Ledger belonging to the output file `output`.
+pub fn path_for(output: &Path) -> PathBuf {
+    let mut name = OsString::from(output.as_os_str());
+    name.push(".ledger");
+    PathBuf::from(name)
+}
+
+pub struct Entry {
+    pub index: usize,
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+    pub url: Option<String>,
+}
+
+impl Entry {
+    /// The entry of segment `index` from `url`, written at `offset`.
+    pub fn new(index: usize, offset: u64, data: &[u8], url: &str) -> Entry {
+        Entry {
+            index,
+            offset,
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+            url: Some(url.to_string()),
+        }
+    }
+
+    fn line(&self) -> String {
+        let mut line = format!(
+            "{} {} {} {}",
+            self.index, self.offset, self.size, self.sha256
+        );
+        if let Some(url) = &self.url {
+            line.push(' ');
+            line.push_str(url);
+        }
+        line.push('\n');
+        line
+    }
+}
+
+pub struct Ledger {
+    file: Mutex<File>,
+    /// The output, with the last time it was flushed.
+    output: Option<Mutex<(File, Instant)>>,
+}
+
+impl Ledger {
+    /// Starts an empty ledger, replacing any existing one, and keeps it
+    /// locked while in use.
+    pub fn create(path: &Path) -> Result<Ledger> {
+        let file = create_locked(path)?;
+        Ok(Ledger {
+            file: Mutex::new(file),
+            output: None,
+        })
+    }
+
+    /// Starts a ledger holding just `entries`, for a download continuing
+    /// after them.
+    pub fn resume(path: &Path, entries: &[Entry]) -> Result<Ledger> {
+        let mut file = create_locked(path)?;
+        let lines: String = entries.iter().map(Entry::line).collect();
+        file.write_all(lines.as_bytes())?;
+        Ok(Ledger {
+            file: Mutex::new(file),
+            output: None,
+        })
+    }
+
+    /// Journals the writes to `output` and flushes it to disk with the
+    /// ledger from time to time.
+    pub fn syncing(mut self, output: File) -> Ledger {
+        self.output = Some(Mutex::new((output, Instant::now())));
+        self
+    }
+
+    /// Notes that the segments `(index, offset, size)` are about to be
+    /// written.
+    pub fn intend(&self, writes: &[(usize, u64, u64)]) -> Result<()> {
+        if self.output.is_none() {
+            return Ok(());
+        }
+        let lines: String = writes
+            .iter()
+            .map(|(index, offset, size)| format!("? {index} {offset} {size}\n"))
+            .collect();
+        self.file.lock().unwrap().write_all(lines.as_bytes())?;
+        Ok(())
+    }
+
+    /// Records the segment `index` from `url`, which was written at `offset`.
+    pub fn record(&self, index: usize, offset: u64, data: &[u8], url: &str) -> Result<()> {
+        self.append(&[Entry::new(index, offset, data, url)])
+    }
+
+    /// Records several segments with a single write.
+    pub fn append(&self, entries: &[Entry]) -> Result<()> {
+        let lines: String = entries.iter().map(Entry::line).collect();
+        self.file.lock().unwrap().write_all(lines.as_bytes())?;
+        if let Some(output) = &self.output {
+            let mut output = output.lock().unwrap();
+            if output.1.elapsed() >= SYNC_INTERVAL {
+                self.sync(&output.0)?;
+                output.1 = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes `output` to disk, then the ledger, so no record gets there
+    /// before its segment.
+    fn sync(&self, output: &File) -> io::Result<()> {
+        output.sync_data()?;
+        self.file.lock().unwrap().sync_data()
+    }
+}
+
+impl Drop for Ledger {
+    fn drop(&mut self) {
+        if let Some(output) = &self.output {
+            if let Err(e) = self.sync(&output.lock().unwrap().0) {
+                warning!("Cannot flush the download to disk: {e}");
+            }
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let file = File::open(path).map_err(|e| eyre!("Cannot open {}: {}", path.display(), e))?;
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.starts_with("? ") {
+            continue;
+        }
+        let invalid = || eyre!("Invalid line {} in {}!", number + 1, path.display());
+        // URLs go last, so they may hold spaces.
+        let fields: Vec<_> = line.splitn(5, ' ').collect();
+        let (&[index, offset, size, sha256], url) = fields.split_at(fields.len().min(4)) else {
+            return Err(invalid());
+        };
+        entries.push(Entry {
+            index: index.parse().map_err(|_| invalid())?,
+            offset: offset.parse().map_err(|_| invalid())?,
+            size: size.parse().map_err(|_| invalid())?,
+            sha256: sha256.to_string(),
+            url: url.first().map(|url| url.to_string()),
+        });
+    }
+    entries.sort_by_key(|e| e.index);
+    Ok(entries)
+}
+
+/// The segments the journal at `path` has about to be written, but not
+/// recorded: writes a crash cut off.
+pub fn torn(path: &Path) -> Result<Vec<usize>> {
+    let file = File::open(path)?;
+    let mut pending = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let index = match line.strip_prefix("? ") {
+            Some(intent) => intent.split(' ').next(),
+            None => line.split(' ').next(),
+        };
+        let Some(index) = index.and_then(|index| index.parse::<usize>().ok()) else {
+            continue;
+        };
+        pending.retain(|&pending| pending != index);
+        if line.starts_with("? ") {
+            pending.push(index);
+        }
+    }
+    pending.sort_unstable();
+    Ok(pending)
+}
+
+/// A recorded segment whose bytes in the output differ from the ledger.
+pub struct Damaged {
+    pub entry: Entry,
+    pub truncated: bool,
+}
+
+/// Reads the recorded segments back from `output` and returns those that
+/// no longer match.
+pub fn check(output: &Path) -> Result<(usize, Vec<Damaged>)> {
+    let entries = load(&path_for(output))?;
+    let count = entries.len();
+    let mut file = File::open(output)?;
+    let mut buf = Vec::new();
+    let mut damaged = Vec::new();
+    for entry in entries {
+        file.seek(io::SeekFrom::Start(entry.offset))?;
+        buf.clear();
+        (&mut file).take(entry.size).read_to_end(&mut buf)?;
+        if buf.len() as u64 != entry.size {
+            damaged.push(Damaged {
+                entry,
+                truncated: true,
+            });
+        } else if sha256_hex(&buf) != entry.sha256 {
+            damaged.push(Damaged {
+                entry,
+                truncated: false,
+            });
+        }
+    }
+    Ok((count, damaged))
+}
+
+/// Checks a downloaded file against its ledger and names every segment
+/// whose bytes differ from what was recorded.
+pub fn verify(output: &Path) -> Result<()> {
+    let (count, damaged) = check(output)?;
+    if !damaged.is_empty() {
+        let corrupt: Vec<_> = damaged
+            .iter()
+            .map(|Damaged { entry, truncated }| {
+                format!(
+                    "segment {} at offset {} {}",
+                    entry.index + 1,
+                    entry.offset,
+                    if *truncated {
+                        "is truncated"
+                    } else {
+                        "does not match its checksum"
+                    }
+                )
+            })
+            .collect();
+        return Err(eyre!(
+            "{} is corrupt, download it again with --repair to fetch just these:\n  {}",
+            output.display(),
+            corrupt.join("\n  ")
+        ));
+    }
+    info!("All {count} recorded segments are intact");
+    Ok(())
+}
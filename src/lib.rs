@@ -0,0 +1,2377 @@
+//! Downloads Vimeo event recordings.
+//!
+//! The crate is mostly the command line tool, see [`main`]; the `python`
+//! feature builds it as a Python extension module instead.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufWriter, IoSlice};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result, WrapErr};
+use ureq::serde_json;
+use url::Url;
+use vimeo_extract::{
+    AudioInfo, Codec, DrmProtected, Manifest, MediaInfo, Registry, Segment, Track, VideoInfo,
+};
+
+use clap::{Parser, Subcommand};
+use sha2::{Digest, Sha256};
+
+use cache::SegmentCache;
+use exit::Failure;
+use fetch::Fetcher;
+use manifests::ManifestCache;
+use ratelimit::TokenBucket;
+use resolve::IpFamily;
+use stats::Stats;
+use target::Target;
+
+#[macro_use]
+mod logging;
+
+mod adaptive;
+mod archive;
+mod audio;
+mod avsync;
+mod benchmark;
+mod cache;
+mod compat;
+mod discontinuity;
+mod exit;
+mod faststart;
+mod fetch;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod gaps;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "gui")]
+mod gui;
+mod har;
+mod http;
+mod httpd;
+mod infojson;
+mod keys;
+mod ledger;
+mod liveapi;
+mod mail;
+mod manifests;
+#[cfg(feature = "test-utils")]
+mod mock;
+mod mux;
+mod nfo;
+mod notify;
+mod parallel;
+mod paths;
+mod player;
+mod postprocess;
+mod probe;
+mod progressive;
+#[cfg(feature = "python")]
+mod python;
+mod ratelimit;
+mod rclone;
+mod remote;
+mod renew;
+mod repair;
+mod report;
+mod resolve;
+mod resume;
+mod retention;
+mod retry;
+mod s3;
+mod schedule;
+#[cfg(unix)]
+mod sdnotify;
+mod segments;
+mod serve;
+mod sftp;
+mod signals;
+mod sprite;
+mod stats;
+mod style;
+mod target;
+mod timestamps;
+mod tls;
+#[cfg(feature = "transcode")]
+mod transcode;
+#[cfg(feature = "tui")]
+mod tui;
+mod webdav;
+mod writer;
+
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+#[clap(after_help = exit::HELP, args_override_self = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// read default arguments from this file instead of the user's config file
+    #[clap(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// do not read default arguments from a config file
+    #[clap(long, conflicts_with = "config")]
+    no_config: bool,
+    /// URL of the vimeo event, video or showcase
+    #[clap(short, long)]
+    #[cfg_attr(not(feature = "gui"), clap(required = true))]
+    #[cfg_attr(feature = "gui", clap(required_unless_present = "gui"))]
+    url: Option<String>,
+    /// Referer
+    #[clap(short, long)]
+    #[cfg_attr(not(feature = "gui"), clap(required = true))]
+    #[cfg_attr(feature = "gui", clap(required_unless_present = "gui"))]
+    referer: Option<String>,
+    /// output filename, `-` to write to stdout, or s3://BUCKET/KEY, dav[s]://HOST/PATH (WebDAV) or sftp://[USER@]HOST/PATH to upload to, with {title}, {id}, {author} and {upload_date} in KEY or PATH replaced
+    #[clap(short, long, visible_alias = "output")]
+    #[cfg_attr(
+        not(feature = "gui"),
+        clap(required_unless_present_any = &["segments-dir", "print-info-json"])
+    )]
+    #[cfg_attr(
+        feature = "gui",
+        clap(required_unless_present_any = &["segments-dir", "print-info-json", "gui"])
+    )]
+    filename: Option<String>,
+    /// S3-compatible service to upload s3:// outputs to, e.g. http://localhost:9000 for MinIO [default: AWS]
+    #[clap(long, value_name = "URL")]
+    s3_endpoint: Option<String>,
+    /// rendition to download, by the ID in the table of renditions [default: the widest]
+    #[clap(long, value_name = "ID")]
+    video_id: Option<String>,
+    /// leave out the renditions the manifest describes incompletely, with a warning, instead of failing
+    #[clap(long)]
+    lenient: bool,
+    /// download the single MP4 file some videos offer instead of the segments, where it is as tall as the rendition
+    #[clap(
+        long,
+        conflicts_with_all = &["segments-dir", "repair", "continue-download", "workers", "play", "serve", "audio-lang", "audio-quality", "prefer-audio-codec", "audio-channels", "all-audio", "fill-gaps", "write-timestamps"]
+    )]
+    prefer_progressive: bool,
+    /// also write the SHA-256 of the output to <FILENAME>.sha256
+    #[clap(long, conflicts_with = "segments-dir")]
+    write_sha256: bool,
+    /// also write the title, description, presenters and date of the event to <FILENAME>.nfo, for media centers like Kodi
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    write_nfo: bool,
+    /// also write the wall-clock time every segment was recorded at to <FILENAME>.timestamps, where live streams carry it
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    write_timestamps: bool,
+    /// print what was found as JSON in the format of yt-dlp's info dict instead of downloading
+    #[clap(long)]
+    print_info_json: bool,
+    /// write download statistics and per-segment timings to this JSON file
+    #[clap(long, value_name = "PATH")]
+    stats_json: Option<PathBuf>,
+    /// record all requests and responses in this HAR file, for bug reports
+    #[clap(long, value_name = "PATH")]
+    har: Option<PathBuf>,
+    /// on failure, bundle the error, options, manifest, requests and last messages into a ZIP file in the current directory to attach to a bug report
+    #[clap(long)]
+    report: bool,
+    /// store the raw segments in this directory instead of concatenating them
+    #[clap(long, value_name = "DIR", conflicts_with_all = &["filename", "play", "serve"])]
+    segments_dir: Option<PathBuf>,
+    /// keep downloaded segments in this directory, shared between runs, so a failed run can be restarted cheaply and other qualities of an event reuse the segments they share
+    #[clap(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+    /// like --cache-dir, using the cache directory of the user
+    #[clap(long, conflicts_with = "cache-dir")]
+    cache: bool,
+    /// drop cached segments no download used for this many days
+    #[clap(long, value_name = "DAYS", default_value_t = 7)]
+    cache_max_age: u64,
+    /// do not ask the oEmbed endpoint for the title, author, upload date and thumbnail the player config lacks
+    #[clap(long)]
+    no_oembed: bool,
+    /// scrape the event page again instead of using the player config and manifest of a failed run
+    #[clap(long)]
+    refresh: bool,
+    /// Vimeo API token, to ask the live_event API for the status and streams of vimeo.com/event/<id> URLs instead of scraping the page
+    #[clap(long, value_name = "TOKEN")]
+    api_token: Option<String>,
+    /// URL of the Vimeo API
+    #[clap(long, value_name = "URL", default_value = "https://api.vimeo.com")]
+    api_url: String,
+    /// wait until the event is live before downloading, checking every 30 seconds
+    #[clap(long)]
+    wait_for_live: bool,
+    /// download every finished stream of the event, each to <FILENAME> with the date of the stream before the extension; needs --api-token
+    #[clap(long, requires = "api-token", conflicts_with_all = &["segments-dir", "continue-download", "repair"])]
+    all_streams: bool,
+    /// with --all-streams, keep checking every 15 minutes for streams published later and download them as well
+    #[clap(long, requires = "all-streams")]
+    follow: bool,
+    /// media player to watch the recording with while it downloads
+    #[clap(long, value_name = "PLAYER")]
+    play: Option<String>,
+    /// serve the file over HTTP on this address while it downloads
+    #[clap(long, value_name = "ADDR")]
+    serve: Option<String>,
+    /// also download the audio track in this language, e.g. de, to the output's name with <LANG>.m4a for its extension, e.g. talk.de.m4a for talk.mp4
+    #[clap(long, value_name = "LANG", conflicts_with = "segments-dir")]
+    audio_lang: Option<String>,
+    /// audio bitrate to pick: best, worst, or the highest up to a limit like 96k
+    #[clap(long, value_name = "QUALITY", conflicts_with = "segments-dir")]
+    audio_quality: Option<audio::Quality>,
+    /// download an audio track, preferring this codec
+    #[clap(long, arg_enum, value_name = "CODEC", conflicts_with = "segments-dir")]
+    prefer_audio_codec: Option<audio::Codec>,
+    /// download an audio track, preferring this layout: mono, stereo, 5.1, 7.1 or a channel count
+    #[clap(long, value_name = "LAYOUT", conflicts_with = "segments-dir")]
+    audio_channels: Option<audio::Channels>,
+    /// download every audio track and mux them into the output with ffmpeg, labeled by language
+    #[clap(
+        long,
+        conflicts_with_all = &["audio-lang", "audio-quality", "prefer-audio-codec", "audio-channels", "segments-dir", "play", "serve"]
+    )]
+    all_audio: bool,
+    /// container of the output, anything but mp4 is remuxed with ffmpeg once downloaded [default: mkv for --all-audio with a .mkv file, mp4 otherwise]
+    #[clap(long, arg_enum, value_name = "FORMAT", conflicts_with = "segments-dir")]
+    container: Option<mux::Container>,
+    /// layout of an MP4 output; progressive is rewritten with ffmpeg once downloaded
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "LAYOUT",
+        default_value = "fragmented",
+        conflicts_with = "segments-dir"
+    )]
+    mp4_layout: mux::Mp4Layout,
+    /// move the moov box of an MP4 output to the front once done, so it can be streamed right away
+    #[clap(long, conflicts_with = "segments-dir")]
+    faststart: bool,
+    /// keep the downloaded video and audio files after muxing or remuxing them
+    #[clap(long, conflicts_with = "segments-dir")]
+    keep_fragments: bool,
+    /// also write the audio track to <FILENAME> with the extension of --audio-format, for podcast apps
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    extract_audio: bool,
+    /// format of --extract-audio; mp3 is encoded with ffmpeg, m4a keeps the AAC track as it is [default: m4a]
+    #[clap(long, arg_enum, value_name = "FORMAT", requires = "extract-audio")]
+    audio_format: Option<audio::Format>,
+    /// also write N thumbnails spread over the recording into <FILENAME>.sprite.jpg, with a WebVTT track pointing at them
+    #[clap(long, value_name = "N", conflicts_with_all = &["segments-dir", "play", "serve"])]
+    preview_sprite: Option<usize>,
+    /// also write a grid of labeled frames summarizing the recording into <FILENAME>.contact.jpg
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    contact_sheet: bool,
+    /// check the streams, codecs and duration of the finished output with ffprobe, failing or warning on a mismatch
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "ON_MISMATCH",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "fail",
+        conflicts_with_all = &["segments-dir", "play", "serve"]
+    )]
+    verify_with_ffprobe: Option<probe::OnMismatch>,
+    /// write the title and the URL into the output with ffmpeg once downloaded
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    embed_metadata: bool,
+    /// make the thumbnail the cover art of the output with ffmpeg once downloaded
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    embed_thumbnail: bool,
+    /// re-encode the output with ffmpeg once downloaded, as <height>p-<codec> like 720p-h264, with codec h264, hevc or av1
+    #[cfg(feature = "transcode")]
+    #[clap(long, value_name = "PRESET", conflicts_with_all = &["segments-dir", "play", "serve"])]
+    recode: Option<transcode::Preset>,
+    /// encode --recode on the GPU, with VA-API, NVENC or VideoToolbox
+    #[cfg(feature = "transcode")]
+    #[clap(long, arg_enum, value_name = "API", requires = "recode")]
+    hwaccel: Option<transcode::Hardware>,
+    /// what to do where the recording jumps, e.g. after an encoder restart: warn, start a chapter there or split the output into parts, the latter two with ffmpeg
+    #[clap(long, arg_enum, value_name = "ACTION", default_value = "warn")]
+    on_discontinuity: discontinuity::Action,
+    /// warn when an audio track drifts from the video by more than this many seconds
+    #[clap(long, value_name = "SECONDS", default_value = "0.1")]
+    max_av_drift: f64,
+    /// before downloading, look for the video in the download history and under --archive-root, and if found skip it or hard-link the output to the copy
+    #[clap(long, arg_enum, value_name = "ACTION", conflicts_with_all = &["segments-dir", "repair"])]
+    dedupe: Option<archive::Dedupe>,
+    /// directory of earlier downloads to look for the video in with --dedupe, by their .nfo files
+    #[clap(long, value_name = "DIR", requires = "dedupe")]
+    archive_root: Vec<PathBuf>,
+    /// file listing the finished downloads by video ID [default: vimeo-event-downloader/history in the user data directory]
+    #[clap(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+    /// move the finished output and the files next to it into this directory
+    #[clap(long, value_name = "DIR", conflicts_with_all = &["segments-dir", "play", "serve"])]
+    move_to: Option<PathBuf>,
+    /// copy the finished output and the files next to it to this rclone remote, once everything else is done
+    #[clap(long, value_name = "REMOTE:PATH", conflicts_with_all = &["segments-dir", "play", "serve"])]
+    move_to_remote: Option<String>,
+    /// remove the local files once --move-to-remote copied them
+    #[clap(long, requires = "move-to-remote")]
+    delete_after_upload: bool,
+    /// run this shell command on the finished output, `{}` standing for its path; may be repeated. Runs after all other post-processing, whose steps always run in the same order
+    #[clap(
+        long,
+        value_name = "CMD",
+        multiple_occurrences = true,
+        conflicts_with_all = &["segments-dir", "play", "serve"]
+    )]
+    exec: Vec<String>,
+    /// instead of downloading, fetch just the segments of an earlier download of <FILENAME> that its ledger finds damaged
+    #[clap(
+        long,
+        conflicts_with_all = &["segments-dir", "play", "serve", "all-audio", "concurrency", "writer"]
+    )]
+    repair: bool,
+    /// continue an interrupted download of <FILENAME>, keeping the segments its ledger finds intact
+    #[clap(long = "continue", conflicts_with_all = &["segments-dir", "repair"])]
+    continue_download: bool,
+    /// have these workers, started with the worker subcommand, each download a share of the segments
+    #[clap(
+        long,
+        value_name = "ADDR",
+        multiple_occurrences = true,
+        use_value_delimiter = true,
+        conflicts_with_all = &["segments-dir", "play", "serve", "continue-download", "concurrency", "adaptive"]
+    )]
+    workers: Vec<String>,
+    /// token the workers were started with; workers given as host:port are sent it over plain HTTP, so
+    /// reach workers across untrusted networks through a tunnel or an https:// URL
+    #[clap(long, value_name = "TOKEN", requires = "workers")]
+    worker_token: Option<String>,
+    /// number of segments to download at the same time
+    #[clap(short, long, default_value_t = 1)]
+    concurrency: usize,
+    /// segments fetched ahead while earlier ones are written, without --concurrency; 0 fetches and writes in turn
+    #[clap(long, value_name = "N", default_value_t = 2)]
+    prefetch: usize,
+    /// ramp parallel requests up and down between 1 and --concurrency based on throughput
+    #[clap(long)]
+    adaptive: bool,
+    /// download a few segments from every CDN and report latency and throughput
+    #[clap(long)]
+    benchmark_cdns: bool,
+    /// like --benchmark-cdns, then download from the fastest CDN
+    #[clap(long)]
+    fastest_cdn: bool,
+    /// how segments are written into the preallocated output file
+    #[clap(long, arg_enum, value_name = "WRITER")]
+    writer: Option<writer::Backend>,
+    /// idle connections kept open per host [default: --concurrency]; requests are not pipelined, each connection carries one at a time
+    #[clap(long, value_name = "N")]
+    max_connections_per_host: Option<usize>,
+    /// seconds to keep idle connections open, 0 disables connection reuse
+    #[clap(long, value_name = "SECONDS")]
+    keep_alive: Option<u64>,
+    /// only connect over IPv4
+    #[clap(short = '4', long, conflicts_with = "force-ipv6")]
+    force_ipv4: bool,
+    /// only connect over IPv6
+    #[clap(short = '6', long)]
+    force_ipv6: bool,
+    /// use ADDR for HOST instead of looking it up, like curl's --resolve
+    #[clap(long, value_name = "HOST[:PORT]:ADDR", multiple_occurrences = true)]
+    resolve: Vec<resolve::Override>,
+    /// resolve names via this DNS-over-HTTPS JSON endpoint, e.g. https://cloudflare-dns.com/dns-query
+    #[clap(long, value_name = "URL")]
+    doh_url: Option<String>,
+    /// send all requests through this proxy, e.g. http://proxy:3128 or socks5h://127.0.0.1:1080
+    #[clap(long, value_name = "URL")]
+    proxy: Option<String>,
+    /// go through Tor's SOCKS port without DNS leaks, with few, spaced out requests
+    #[clap(long, conflicts_with_all = &["proxy", "doh-url", "resolve"])]
+    tor: bool,
+    /// wait this many seconds before each segment request
+    #[clap(long, value_name = "SECONDS")]
+    sleep_requests: Option<f64>,
+    /// maximum total download rate in bytes per second, e.g. 500K or 2M
+    #[clap(long, value_name = "RATE")]
+    limit_rate: Option<ratelimit::Rate>,
+    /// Budget shared with the other recordings of the schedule subcommand.
+    #[clap(skip)]
+    shared_rate_limit: Option<Arc<TokenBucket>>,
+    /// Run by the C or Python interface, so the terminal and the process
+    /// belong to the host: no hotkeys, progress bars or systemd reports.
+    #[clap(skip)]
+    embedded: bool,
+    /// Stops this download only, where the caller runs several.
+    #[clap(skip)]
+    stop: signals::Stop,
+    /// how often to retry a failed request; waits for a server answering 429 or 503 with Retry-After do not count
+    #[clap(long, value_name = "N", default_value_t = 3)]
+    retries: u32,
+    /// seconds to wait before the first retry, doubled for every further one
+    #[clap(long, value_name = "SECONDS", default_value_t = 1.0)]
+    retry_backoff: f64,
+    /// longest wait between two retries in seconds
+    #[clap(long, value_name = "SECONDS", default_value_t = 30.0)]
+    retry_max_delay: f64,
+    /// HTTP status codes worth retrying; connection errors always are
+    #[clap(
+        long,
+        value_name = "CODES",
+        use_value_delimiter = true,
+        default_value = "408,429,500,502,503,504"
+    )]
+    retry_status: Vec<u16>,
+    /// stop after this many segment requests in a row failed, counting retries
+    #[clap(long, value_name = "N")]
+    abort_on_failures: Option<u32>,
+    /// leave out segments that cannot be fetched instead of failing, and report the gaps
+    #[clap(long)]
+    ignore_errors: bool,
+    /// like --ignore-errors, then re-encode the spans of missing segments from the next best rendition that has them
+    #[clap(long, conflicts_with_all = &["segments-dir", "play", "serve"])]
+    fill_gaps: bool,
+    /// trust the certificates in this PEM file instead of the built-in ones
+    #[clap(long, value_name = "PEM")]
+    cacert: Option<PathBuf>,
+    /// do not verify TLS certificates
+    #[clap(short = 'k', long)]
+    insecure: bool,
+    /// when to color the output
+    #[clap(long, arg_enum, value_name = "WHEN", default_value = "auto")]
+    color: style::ColorChoice,
+    /// also send messages to the system log
+    #[clap(long, arg_enum, value_name = "TARGET")]
+    log_to: Option<logging::Target>,
+    /// mail this address when the download finishes or fails; may be repeated
+    #[clap(
+        long,
+        value_name = "ADDR",
+        multiple_occurrences = true,
+        requires = "smtp-server"
+    )]
+    notify_email: Vec<String>,
+    /// post to this chat when the download finishes or fails, KIND:URL with KIND slack, discord or matrix; may be repeated
+    #[clap(long, value_name = "KIND:URL", multiple_occurrences = true)]
+    notify_webhook: Vec<notify::Webhook>,
+    /// mail server for --notify-email, smtp[s]://[USER@]HOST[:PORT], the password from SMTP_PASSWORD
+    #[clap(long, value_name = "URL")]
+    smtp_server: Option<Url>,
+    /// sender of the mails of --notify-email [default: the first recipient]
+    #[clap(long, value_name = "ADDR")]
+    smtp_from: Option<String>,
+    /// show a full-screen dashboard while downloading
+    #[cfg(feature = "tui")]
+    #[clap(long)]
+    tui: bool,
+    /// queue and watch downloads in a window in the web browser instead
+    #[cfg(feature = "gui")]
+    #[clap(long)]
+    gui: bool,
+    /// serve the page of --gui on this address, e.g. 0.0.0.0:8080 for others on the network, instead of a random port on 127.0.0.1, and do not open a browser
+    #[cfg(feature = "gui")]
+    #[clap(long, value_name = "ADDR", requires = "gui")]
+    gui_listen: Option<String>,
+    /// with --gui, only take requests carrying this token; the page takes it once as /?token=TOKEN.
+    /// Required when --gui-listen or --grpc are reachable from other machines
+    #[cfg(feature = "gui")]
+    #[clap(long, value_name = "TOKEN", requires = "gui")]
+    gui_token: Option<String>,
+    /// with --gui, serve the page and --grpc over TLS with the certificate chain in this PEM file
+    #[cfg(feature = "gui")]
+    #[clap(long, value_name = "FILE", requires_all = &["gui", "gui-key"])]
+    gui_cert: Option<PathBuf>,
+    /// PEM file with the private key of --gui-cert
+    #[cfg(feature = "gui")]
+    #[clap(long, value_name = "FILE", requires = "gui-cert")]
+    gui_key: Option<PathBuf>,
+    /// with --gui, also serve the queue as the gRPC service of proto/vimeo_event_downloader.proto on this address, e.g. 127.0.0.1:7071
+    #[cfg(feature = "grpc")]
+    #[clap(long, value_name = "ADDR", requires = "gui")]
+    grpc: Option<String>,
+    /// fetch segments over HTTP/2, multiplexing them over fewer connections
+    #[cfg(feature = "http2")]
+    #[clap(long)]
+    http2: bool,
+    /// fetch segments over HTTP/3 (QUIC) from a QUIC-capable CDN; experimental
+    #[cfg(feature = "http3")]
+    #[clap(long)]
+    http3: bool,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Build the output file from a directory written with --segments-dir
+    Assemble {
+        /// segments directory
+        dir: PathBuf,
+        /// output filename, or `-` to write to stdout
+        #[clap(short, long)]
+        filename: String,
+    },
+    /// Check a downloaded file against the segment checksums recorded in its ledger
+    Verify {
+        /// downloaded file
+        file: PathBuf,
+    },
+    /// Record the events of an iCalendar feed as they go live
+    Schedule {
+        /// ICS file, or URL of a feed to check every 15 minutes
+        calendar: String,
+        /// page the events are embedded in, see --referer
+        #[clap(short, long)]
+        referer: String,
+        /// directory to record into, as "<summary> <date>.mp4"
+        #[clap(long, default_value = ".")]
+        dir: PathBuf,
+        /// start trying this many seconds before an event
+        #[clap(long, value_name = "SECS", default_value = "60")]
+        early: u64,
+        /// record at most this many events at once, the others wait by priority
+        #[clap(long, value_name = "N", default_value = "2")]
+        max_recordings: usize,
+        /// maximum download rate of all recordings together, e.g. 500K or 2M
+        #[clap(long, value_name = "RATE")]
+        limit_rate: Option<ratelimit::Rate>,
+        /// start no recording while the directory has less than this many GiB free
+        #[clap(long, value_name = "GIB")]
+        min_free: Option<f64>,
+        /// priority from 1, the highest, to 9 of the events whose summary matches, over that in the calendar
+        #[clap(long, value_name = "REGEX=N")]
+        priority: Vec<schedule::Priority>,
+        /// delete all but this many of the latest recordings, and the files next to them
+        #[clap(long, value_name = "N")]
+        keep_last: Option<usize>,
+        /// delete the oldest recordings while all together take more than this many GiB
+        #[clap(long, value_name = "GIB")]
+        max_total: Option<f64>,
+        /// more options for every download, after `--`
+        #[clap(last = true)]
+        options: Vec<String>,
+    },
+    /// Download segments for a coordinator started with --workers
+    Worker {
+        /// address to listen on, e.g. 0.0.0.0:7070; printed on stdout once bound
+        #[clap(long)]
+        listen: String,
+        /// only take requests carrying this token, see --worker-token; required unless listening on
+        /// loopback
+        #[clap(long)]
+        token: Option<String>,
+    },
+    /// List or reorder the downloads queued in a running --gui
+    #[cfg(feature = "gui")]
+    Queue {
+        #[clap(subcommand)]
+        action: QueueAction,
+        /// token the page was started with, see --gui-token
+        #[clap(long)]
+        token: Option<String>,
+        /// PEM bundle to check the certificate of a page served over TLS against, see --cacert
+        #[clap(long, value_name = "FILE")]
+        cacert: Option<PathBuf>,
+    },
+    /// Serve a canned event for testing offline; prints its URL and the SHA-256 a download must have
+    #[cfg(feature = "test-utils")]
+    MockServer {
+        /// address to listen on
+        #[clap(long, default_value = "127.0.0.1:0")]
+        listen: String,
+        /// break off the first request for every segment
+        #[clap(long)]
+        flaky: bool,
+        /// answer 404 for this segment of the 720p rendition, counting from 0
+        #[clap(long, value_name = "INDEX")]
+        missing: Option<usize>,
+        /// change the signature of the URLs after every N segments, refusing the old ones with 403
+        #[clap(long, value_name = "N")]
+        expire_after: Option<usize>,
+        /// answer the first N segment requests with 429 and Retry-After: 1
+        #[clap(long, value_name = "N", default_value_t = 0)]
+        throttle: usize,
+        /// list a 1080p rendition in the manifest whose width is a string
+        #[clap(long)]
+        malformed: bool,
+    },
+}
+
+#[cfg(feature = "gui")]
+#[derive(Subcommand, Clone, Debug)]
+enum QueueAction {
+    /// Print the id, state, priority and file of every job
+    List,
+    /// Run a queued job next, ahead of those with higher priority
+    Bump {
+        /// job id, as `queue list` prints it
+        id: usize,
+    },
+}
+
+/// Entry point of the command line tool.
+pub fn main() {
+    let mut args = parse_args();
+    style::init(args.color);
+    if let Some(target) = args.log_to {
+        if let Err(e) = logging::init(target) {
+            exit_usage(&e.to_string());
+        }
+    }
+    if args.tor {
+        apply_tor_preset(&mut args);
+    }
+    let started = Instant::now();
+    let mut seen = None;
+    let result = signals::install().and_then(|_| {
+        run_observed(&args, &mut |media, video, _| {
+            seen = Some((media.title.clone(), video.duration));
+        })
+    });
+    if let Some(usage) = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<exit::Usage>())
+    {
+        exit_usage(&usage.0);
+    }
+    send_notifications(&args, &result, started.elapsed(), seen);
+    if let Err(e) = result {
+        logging::emit(
+            logging::Level::Error,
+            &format!("{} {e:#}", style::error("error:")),
+        );
+        let code = exit::code(&e);
+        match report::write(&e, code, args.har.as_deref()) {
+            Ok(Some(path)) => info!("Wrote {}, please attach it to an issue", path.display()),
+            Ok(None) => {}
+            Err(e) => warning!("Cannot write the report: {e:#}"),
+        }
+        std::process::exit(code);
+    }
+}
+
+/// Tells the `--notify-email` and `--notify-webhook` recipients how the
+/// download went; `seen` has the title and length of the recording if it
+/// got that far.
+fn send_notifications(
+    args: &Args,
+    result: &Result<()>,
+    elapsed: Duration,
+    seen: Option<(Option<String>, f64)>,
+) {
+    if args.command.is_some() || (args.notify_email.is_empty() && args.notify_webhook.is_empty()) {
+        return;
+    }
+    let local = args
+        .filename
+        .as_deref()
+        .filter(|filename| *filename != "-" && Target::parse(filename).is_none())
+        .map(PathBuf::from);
+    let moved = local.as_ref().and_then(|path| {
+        let dir = args.move_to.as_ref()?;
+        Some(dir.join(path.file_name()?))
+    });
+    let local = moved.or(local);
+    let output = match (&args.move_to_remote, &local) {
+        (Some(remote), Some(path)) => path
+            .file_name()
+            .map(|name| format!("{remote}/{}", name.to_string_lossy())),
+        _ => local
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .or_else(|| args.filename.clone()),
+    };
+    let (title, duration) = seen.unzip();
+    let summary = notify::Summary {
+        title: title.flatten(),
+        url: args.url.clone().unwrap_or_default(),
+        output,
+        duration,
+        size: local
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|m| m.len()),
+        elapsed,
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    };
+    let tls = tls::Options {
+        ca_file: args.cacert.clone(),
+        insecure: args.insecure,
+    };
+
+    if let (Some(server), Some(first)) = (&args.smtp_server, args.notify_email.first()) {
+        let options = mail::Options {
+            server: server.clone(),
+            from: args.smtp_from.clone().unwrap_or_else(|| first.clone()),
+            to: args.notify_email.clone(),
+            tls: tls.clone(),
+        };
+        let message = mail::Message {
+            subject: summary.headline(),
+            body: summary.text(),
+        };
+        match mail::send(&options, &message) {
+            Ok(()) => info!("Mailed {}", args.notify_email.join(", ")),
+            Err(e) => warning!("Could not send the notification: {e:#}"),
+        }
+    }
+    if args.notify_webhook.is_empty() {
+        return;
+    }
+    let config = http::Config {
+        tls,
+        proxy: args.proxy.clone(),
+        ..default_http_config()
+    };
+    let agent = match config.agent() {
+        Ok(agent) => agent,
+        Err(e) => return warning!("Could not post the notifications: {e:#}"),
+    };
+    for webhook in &args.notify_webhook {
+        if let Err(e) = webhook.post(&agent, &summary) {
+            warning!("Could not post the notification to {}: {e:#}", webhook.url);
+        }
+    }
+}
+
+/// [`run_observed`] for a library caller: invalid arguments are returned
+/// as errors and nothing touches the terminal.
+#[cfg(any(feature = "ffi", feature = "python"))]
+fn run_embedded(
+    mut args: Args,
+    observe: &mut dyn FnMut(&MediaInfo, &VideoInfo, &Arc<Stats>),
+) -> Result<()> {
+    args.embedded = true;
+    run_observed(&args, observe)
+}
+
+/// Does what `args` ask for, handing the event, the chosen video and the
+/// statistics to `observe` before the download starts.
+fn run_observed(
+    args: &Args,
+    observe: &mut dyn FnMut(&MediaInfo, &VideoInfo, &Arc<Stats>),
+) -> Result<()> {
+    match &args.command {
+        Some(Command::Assemble { dir, filename }) => {
+            if filename == "-" {
+                let stdout = io::stdout();
+                let mut out = BufWriter::new(stdout.lock());
+                segments::assemble(dir, &mut out)?;
+                out.flush()?;
+            } else {
+                let mut file = create_locked(Path::new(filename))?;
+                segments::assemble(dir, &mut file)?;
+            }
+            return Ok(());
+        }
+        Some(Command::Verify { file }) => {
+            return ledger::verify(file).wrap_err(Failure::Verification);
+        }
+        Some(Command::Schedule {
+            calendar,
+            referer,
+            dir,
+            early,
+            max_recordings,
+            limit_rate,
+            min_free,
+            priority,
+            keep_last,
+            max_total,
+            options,
+        }) => {
+            if *max_recordings == 0 {
+                return Err(usage_error("--max-recordings must be at least 1"));
+            }
+            if *keep_last == Some(0) {
+                return Err(usage_error("--keep-last must be at least 1"));
+            }
+            if min_free.is_some_and(|gib| !(gib >= 0.0 && gib.is_finite())) {
+                return Err(usage_error(
+                    "--min-free must be a non-negative number of GiB",
+                ));
+            }
+            if max_total.is_some_and(|gib| !(gib > 0.0 && gib.is_finite())) {
+                return Err(usage_error("--max-total must be a positive number of GiB"));
+            }
+            let options = schedule::Options {
+                calendar: calendar.clone(),
+                referer: referer.clone(),
+                dir: dir.clone(),
+                early: Duration::from_secs(*early),
+                max_recordings: *max_recordings,
+                limit_rate: *limit_rate,
+                min_free: min_free.map(|gib| (gib * (1u64 << 30) as f64) as u64),
+                priorities: priority.clone(),
+                retention: retention::Policy {
+                    keep_last: *keep_last,
+                    max_total: max_total.map(|gib| (gib * (1u64 << 30) as f64) as u64),
+                },
+                options: options.clone(),
+            };
+            return schedule::run(&default_http_config().agent()?, &options);
+        }
+        #[cfg(feature = "gui")]
+        Some(Command::Queue {
+            action,
+            token,
+            cacert,
+        }) => {
+            let bump = match action {
+                QueueAction::List => None,
+                QueueAction::Bump { id } => Some(*id),
+            };
+            let config = http::Config {
+                tls: tls::Options {
+                    ca_file: cacert.clone(),
+                    insecure: false,
+                },
+                ..default_http_config()
+            };
+            return gui::remote(&config.agent()?, bump, token.as_deref());
+        }
+        Some(Command::Worker { listen, token }) => {
+            let client = http::Client::Ureq(default_http_config().agent()?);
+            let settings = fetch::Settings {
+                retry: retry::Policy::default(),
+                cache: None,
+                delay: None,
+                rate_limit: None,
+                abort_on_failures: None,
+                ignore_errors: false,
+                prefetch: 0,
+                progress_bars: false,
+                stop: signals::Stop::default(),
+            };
+            return remote::serve(listen, token.clone(), Fetcher::new(client, settings));
+        }
+        #[cfg(feature = "test-utils")]
+        Some(Command::MockServer {
+            listen,
+            flaky,
+            missing,
+            expire_after,
+            throttle,
+            malformed,
+        }) => {
+            let server = mock::MockServer::start(
+                listen,
+                *flaky,
+                *missing,
+                *expire_after,
+                *throttle,
+                *malformed,
+            )?;
+            println!("{}", server.event_url());
+            println!("{}", sha256_hex(mock::expected_output()));
+            io::stdout().flush()?;
+            while !signals::stop_requested() {
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+    #[cfg(feature = "gui")]
+    if args.gui {
+        let options = gui::Options {
+            listen: args.gui_listen.clone(),
+            #[cfg(feature = "grpc")]
+            grpc: args.grpc.clone(),
+            history: args.history.clone().or_else(paths::history_file),
+            token: args.gui_token.clone(),
+            identity: args
+                .gui_cert
+                .clone()
+                .zip(args.gui_key.clone())
+                .map(|(cert, key)| tls::Identity { cert, key }),
+        };
+        return gui::run(&options);
+    }
+
+    let url = args.url.as_deref().unwrap();
+    let referer = args.referer.as_deref().unwrap();
+    let streaming = args.play.is_some() || args.serve.is_some();
+    if args.filename.as_deref() == Some("-") && streaming {
+        return Err(usage_error("--play and --serve need a file to read from, they cannot be combined with --filename -"));
+    }
+    if args.write_sha256 && args.filename.as_deref() == Some("-") {
+        return Err(usage_error(
+            "--write-sha256 needs an output file, it cannot be combined with --filename -",
+        ));
+    }
+    #[cfg(feature = "tui")]
+    if args.tui && args.filename.as_deref() == Some("-") {
+        return Err(usage_error(
+            "--tui draws on stdout, it cannot be combined with --filename -",
+        ));
+    }
+    let audio_preferences = audio::Preferences {
+        language: args.audio_lang.clone(),
+        quality: args.audio_quality,
+        codec: args.prefer_audio_codec,
+        channels: args.audio_channels,
+    };
+    if audio_preferences.wanted() && args.filename.as_deref() == Some("-") {
+        return Err(usage_error("--audio-lang, --audio-quality, --prefer-audio-codec and --audio-channels write a file next to the output, they cannot be combined with --filename -"));
+    }
+    let container = output_container(args);
+    let remux = container != mux::Container::Mp4 || args.mp4_layout == mux::Mp4Layout::Progressive;
+    if args.faststart && container != mux::Container::Mp4 {
+        return Err(usage_error("--faststart only applies to MP4 outputs"));
+    }
+    if args.faststart && (streaming || args.filename.as_deref() == Some("-")) {
+        return Err(usage_error("--faststart rewrites the finished file, it cannot be combined with --play, --serve or --filename -"));
+    }
+    if remux && (streaming || args.filename.as_deref() == Some("-")) {
+        return Err(usage_error("--container and --mp4-layout progressive rewrite the finished file, they cannot be combined with --play, --serve or --filename -"));
+    }
+    #[cfg(feature = "transcode")]
+    if let (Some(preset), Some(hardware)) = (args.recode, args.hwaccel) {
+        if !preset.supported_by(hardware) {
+            return Err(usage_error(format!(
+                "--hwaccel {hardware:?} has no {:?} encoder, recode to h264 or hevc or leave out --hwaccel",
+                preset.codec
+            )));
+        }
+    }
+    #[cfg(feature = "transcode")]
+    let recode = args.recode.is_some();
+    #[cfg(not(feature = "transcode"))]
+    let recode = false;
+    let post_processing = recode
+        || args.fill_gaps
+        || args.extract_audio
+        || args.preview_sprite.is_some()
+        || args.contact_sheet
+        || args.verify_with_ffprobe.is_some()
+        || args.embed_metadata
+        || args.embed_thumbnail
+        || args.write_nfo
+        || args.write_timestamps
+        || args.on_discontinuity != discontinuity::Action::Warn
+        || args.move_to.is_some()
+        || args.move_to_remote.is_some()
+        || !args.exec.is_empty();
+    if post_processing && args.filename.as_deref() == Some("-") {
+        return Err(usage_error("--fill-gaps, --extract-audio, --preview-sprite, --contact-sheet, --embed-metadata, --embed-thumbnail, --recode, --write-nfo, --write-timestamps, --on-discontinuity, --verify-with-ffprobe, --move-to, --move-to-remote and --exec work on the output file, they cannot be combined with --filename -"));
+    }
+    if !(args.max_av_drift >= 0.0 && args.max_av_drift.is_finite()) {
+        return Err(usage_error(
+            "--max-av-drift must be a non-negative number of seconds",
+        ));
+    }
+    if args
+        .sleep_requests
+        .is_some_and(|seconds| !(seconds >= 0.0 && seconds.is_finite()))
+    {
+        return Err(usage_error(
+            "--sleep-requests must be a non-negative number of seconds",
+        ));
+    }
+    for (name, seconds) in [
+        ("--retry-backoff", args.retry_backoff),
+        ("--retry-max-delay", args.retry_max_delay),
+    ] {
+        if !(seconds >= 0.0 && seconds.is_finite()) {
+            return Err(usage_error(format!(
+                "{name} must be a non-negative number of seconds"
+            )));
+        }
+    }
+    if args.preview_sprite == Some(0) {
+        return Err(usage_error("--preview-sprite must be at least 1"));
+    }
+    if args.embed_thumbnail && container == mux::Container::Ts {
+        return Err(usage_error(
+            "--embed-thumbnail needs an MP4 or Matroska output",
+        ));
+    }
+    if args.all_audio && args.filename.as_deref() == Some("-") {
+        return Err(usage_error(
+            "--all-audio muxes into the output file, it cannot be combined with --filename -",
+        ));
+    }
+    if args.repair
+        && (args.filename.as_deref() == Some("-")
+            || remux
+            || post_processing
+            || args.faststart
+            || audio_preferences.wanted())
+    {
+        return Err(usage_error("--repair patches the downloaded file in place, it cannot be combined with options writing other files or rewriting it"));
+    }
+    if !args.workers.is_empty() && args.filename.as_deref() == Some("-") {
+        return Err(usage_error(
+            "--workers write segments out of order, they cannot be combined with --filename -",
+        ));
+    }
+    if args.all_streams && args.filename.as_deref() == Some("-") {
+        return Err(usage_error(
+            "--all-streams writes a file per stream, it cannot be combined with --filename -",
+        ));
+    }
+    if args.continue_download && args.filename.as_deref() == Some("-") {
+        return Err(usage_error("--continue needs the output file of the earlier run, it cannot be combined with --filename -"));
+    }
+    if args.concurrency == 0 {
+        return Err(usage_error("--concurrency must be at least 1"));
+    }
+    if args.abort_on_failures == Some(0) {
+        return Err(usage_error("--abort-on-failures must be at least 1"));
+    }
+    if args.adaptive && args.concurrency == 1 {
+        return Err(usage_error(
+            "--adaptive needs --concurrency set to the most parallel requests to try",
+        ));
+    }
+    // A specific writer only exists for the preallocated path, so asking for
+    // one opts into it even with a single connection.
+    let preallocate = args.concurrency > 1 || args.writer.is_some();
+    if preallocate && (streaming || args.filename.as_deref() == Some("-")) {
+        return Err(usage_error("--concurrency and --writer write segments out of order, they cannot be combined with --play, --serve or --filename -"));
+    }
+    let upload = args.filename.as_deref().and_then(Target::parse);
+    if upload.is_some()
+        && (preallocate
+            || streaming
+            || remux
+            || post_processing
+            || args.faststart
+            || args.write_sha256
+            || audio_preferences.wanted()
+            || args.all_audio
+            || args.repair
+            || args.continue_download
+            || !args.workers.is_empty())
+    {
+        return Err(usage_error("s3://, dav://, davs:// and sftp:// outputs are uploaded while they download, they cannot be combined with options writing other files, rewriting the output or writing it out of order"));
+    }
+    if args.prefer_progressive && (upload.is_some() || args.filename.as_deref() == Some("-")) {
+        return Err(usage_error("--prefer-progressive downloads to a file, it cannot be combined with --filename - or s3://, dav://, davs:// and sftp:// outputs"));
+    }
+    if args.s3_endpoint.is_some() && !matches!(upload, Some(Target::S3(_))) {
+        return Err(usage_error("--s3-endpoint needs an s3:// output"));
+    }
+    let http_config = http::Config {
+        max_connections_per_host: args.max_connections_per_host.unwrap_or(args.concurrency),
+        idle_timeout: args.keep_alive.map(Duration::from_secs),
+        resolve: resolve::Options {
+            family: if args.force_ipv4 {
+                IpFamily::V4
+            } else if args.force_ipv6 {
+                IpFamily::V6
+            } else {
+                IpFamily::Any
+            },
+            overrides: args.resolve.clone(),
+            doh_url: args.doh_url.clone(),
+        },
+        tls: tls::Options {
+            ca_file: args.cacert.clone(),
+            insecure: args.insecure,
+        },
+        proxy: args.proxy.clone(),
+        har: args
+            .har
+            .as_deref()
+            .map(|path| Arc::new(har::Har::new(path)))
+            .or_else(report::har),
+    };
+    let agent = http_config.agent()?;
+    if args.all_streams {
+        return download_all_streams(args, &agent, observe);
+    }
+    if args.wait_for_live {
+        wait_for_live(args, &agent, url, referer)?;
+    }
+    let cache_dir = match (&args.cache_dir, args.cache) {
+        (Some(dir), _) => Some(dir.clone()),
+        (None, true) => Some(paths::segment_cache_dir().ok_or_else(|| {
+            usage_error("--cache needs a home directory, use --cache-dir instead")
+        })?),
+        (None, false) => None,
+    };
+    let cache_max_age = Duration::from_secs(args.cache_max_age * 24 * 60 * 60);
+    let cache = cache_dir
+        .as_deref()
+        .map(|dir| SegmentCache::open(dir, cache_max_age))
+        .transpose()?;
+    let client = segment_client(args, &http_config, &agent)?;
+    let retry = retry::Policy {
+        retries: args.retries,
+        backoff: Duration::from_secs_f64(args.retry_backoff),
+        max_delay: Duration::from_secs_f64(args.retry_max_delay),
+        statuses: args.retry_status.clone(),
+        stop: args.stop.clone(),
+    };
+    let settings = fetch::Settings {
+        retry: retry.clone(),
+        cache,
+        delay: args.sleep_requests.map(Duration::from_secs_f64),
+        rate_limit: match (args.limit_rate, &args.shared_rate_limit) {
+            (Some(rate), shared) => Some(Arc::new(TokenBucket::new(rate).within(shared.clone()))),
+            (None, shared) => shared.clone(),
+        },
+        abort_on_failures: args.abort_on_failures,
+        ignore_errors: args.ignore_errors || args.fill_gaps,
+        prefetch: args.prefetch,
+        progress_bars: !args.embedded,
+        stop: args.stop.clone(),
+    };
+    let fetcher = Fetcher::new(client, settings);
+
+    let manifests = ManifestCache::new(url, referer);
+    let cached = manifests
+        .as_ref()
+        .filter(|_| !args.refresh)
+        .and_then(ManifestCache::load);
+    let (media, cached_master) = match cached {
+        Some(entry) => {
+            info!("Using the player config of an earlier run, pass --refresh to extract it again");
+            (entry.media, entry.master)
+        }
+        None => {
+            let registry = Registry::default();
+            let extractor = registry.find(url).wrap_err(Failure::Extraction)?;
+            let mut media = retry
+                .run(extractor.name(), || {
+                    extractor.extract(&mut http_get(&agent), url, referer)
+                })
+                .map_err(drm_failure)
+                .wrap_err(Failure::Extraction)?;
+            if media.is_sparse() && !args.no_oembed {
+                if let Err(e) = vimeo_extract::enrich(&mut http_get(&agent), &mut media, url) {
+                    warning!("Cannot complete the metadata from oEmbed: {e:#}");
+                }
+            }
+            (media, None)
+        }
+    };
+    let mut cdn = vimeo_extract::choose_cdn(&media.dash_config, prefer_quic(args));
+    if args.benchmark_cdns || args.fastest_cdn {
+        let results = benchmark::run(&agent, fetcher.client(), &media.dash_config["cdns"]);
+        match results.first() {
+            Some(fastest) if args.fastest_cdn => {
+                info!("Using fastest CDN {}", fastest.cdn);
+                cdn = fastest.cdn.clone();
+            }
+            Some(_) => {}
+            None => info!("No CDN could be benchmarked, using {}", cdn),
+        }
+    }
+    fetcher.set_renewal(renew::Renewal {
+        agent: agent.clone(),
+        url: url.to_string(),
+        referer: referer.to_string(),
+        cdn: cdn.clone(),
+        prefer_quic: prefer_quic(args),
+    });
+    let master_url = vimeo_extract::master_url(&media.dash_config, &cdn)
+        .ok_or(Failure::Extraction)
+        .wrap_err_with(|| format!("No manifest URL for CDN {cdn}!"))?
+        .to_string();
+    let master = match cached_master.filter(|(url, _)| *url == master_url) {
+        Some((_, master)) => master,
+        None => {
+            let (_, master) = retry
+                .run("Manifest", || {
+                    get_manifest(&agent, &media.dash_config, &cdn)
+                })
+                .wrap_err(Failure::Extraction)?;
+            master
+        }
+    };
+    let entry = manifests::Entry {
+        media,
+        master: Some((master_url, master)),
+    };
+    if let Some(manifests) = &manifests {
+        if let Err(e) = manifests.store(&entry) {
+            warning!("Cannot keep the manifest for later runs: {e:#}");
+        }
+    }
+    let (master_url, master) = entry.master.as_ref().unwrap();
+    report::config(&entry.media);
+    report::master(master);
+    let (videos, audios) =
+        renditions(args.lenient, master_url, master).wrap_err(Failure::Extraction)?;
+    let video = match &args.video_id {
+        Some(id) => videos
+            .iter()
+            .find(|v| v.id.trim_matches('"') == id)
+            .ok_or(Failure::Extraction)
+            .wrap_err_with(|| format!("No video {id} in manifest!"))?,
+        None => videos
+            .iter()
+            .max_by_key(|v| v.width)
+            .ok_or(Failure::Extraction)
+            .wrap_err("No videos in manifest!")?,
+    };
+    // --all-audio downloads every track anyway.
+    let audio = (audio_preferences.wanted() || args.extract_audio && !args.all_audio)
+        .then(|| {
+            audio::select(&audios, &audio_preferences)
+                .ok_or(Failure::Extraction)
+                .wrap_err_with(|| match &args.audio_lang {
+                    Some(language) => format!("No audio in language {language}!"),
+                    None => "No audio tracks in manifest!".to_string(),
+                })
+        })
+        .transpose()?;
+    if args.all_audio && audios.is_empty() {
+        return Err(eyre!("No audio tracks in manifest!")).wrap_err(Failure::Extraction);
+    }
+    // Status output goes to stderr so stdout stays clean for `--filename -`.
+    info!("Found {} videos", videos.len());
+    style::print_videos(&videos, video);
+    if !audios.is_empty() {
+        info!("Found {} audio tracks", audios.len());
+        style::print_audios(&audios, audio);
+    }
+    if args.print_info_json {
+        let filename = args.filename.as_deref();
+        let info = infojson::info_dict(url, &entry.media, &videos, &audios, video, filename);
+        println!("{info}");
+        return Ok(());
+    }
+    check_codecs(args, container, video, audio, &audios);
+    observe(&entry.media, video, fetcher.stats());
+    let (_keys, _dashboard) = match args.embedded {
+        true => (None, None),
+        false => interactive(args, video, &fetcher)?,
+    };
+    #[cfg(unix)]
+    let _supervisor = (!args.embedded)
+        .then(|| sdnotify::supervise(video, fetcher.stats().clone()))
+        .flatten();
+
+    let history = args.history.clone().or_else(paths::history_file);
+    let media_id = entry.media.id.as_deref();
+    let copy = match (args.dedupe, media_id) {
+        (Some(_), Some(id)) if upload.is_none() && args.filename.as_deref() != Some("-") => {
+            archive::find(history.as_deref(), &args.archive_root, id)?
+        }
+        _ => None,
+    };
+
+    let progressive = if args.prefer_progressive {
+        let file = progressive::choose(&entry.media.progressive, video);
+        if file.is_none() {
+            info!("No progressive file as tall as the rendition, downloading the segments");
+        }
+        file
+    } else {
+        None
+    };
+
+    if let Some(dir) = &args.segments_dir {
+        segments::save(dir, master, video, &fetcher)?;
+        report_stats(args, &fetcher, video)?;
+    } else if args.repair {
+        let filename = args.filename.as_deref().unwrap();
+        repair::repair(Path::new(filename), video, &fetcher)?;
+        report_stats(args, &fetcher, video)?;
+        let hash = sha256_file(Path::new(filename))?;
+        report_sha256(args, Path::new(filename), &hash)?;
+    } else if let Some(target) = upload {
+        let target = target.expand(&entry.media);
+        info!("Uploading to {target}");
+        let mut out = HashingWriter {
+            inner: target.open(&agent, args.s3_endpoint.as_deref())?,
+            hasher: Sha256::new(),
+        };
+        download(&mut out, video, &fetcher, None)?;
+        out.inner.finish()?;
+        report_stats(args, &fetcher, video)?;
+        info!("SHA-256: {}", hex(&out.hasher.finalize()));
+    } else if args.filename.as_deref() == Some("-") {
+        let stdout = io::stdout();
+        let mut out = HashingWriter {
+            inner: BufWriter::with_capacity(BATCH_BYTES, stdout.lock()),
+            hasher: Sha256::new(),
+        };
+        download(&mut out, video, &fetcher, None)?;
+        out.flush()?;
+        report_stats(args, &fetcher, video)?;
+        info!("SHA-256: {}", hex(&out.hasher.finalize()));
+    } else if let (Some(dedupe), Some(copy)) = (args.dedupe, copy) {
+        let filename = args.filename.as_deref().unwrap();
+        archive::reuse(dedupe, &copy, Path::new(filename))?;
+    } else if let Some(file) = progressive {
+        let filename = args.filename.as_deref().unwrap();
+        progressive::download(file, Path::new(filename), &fetcher)?;
+        let chain = post_processors(args, url, &entry.media, video, &[], container, &agent);
+        let mut output = postprocess::Output {
+            path: PathBuf::from(filename),
+            audios: Vec::new(),
+            companions: Vec::new(),
+            discontinuities: Vec::new(),
+        };
+        postprocess::run(&chain, &mut output)?;
+        report_stats(args, &fetcher, video)?;
+        let hash = sha256_file(&output.path)?;
+        let checksum = report_sha256(args, &output.path, &hash)?;
+        if let (Some(history), Some(id)) = (&history, media_id) {
+            let rendition = progressive::label(file);
+            if let Err(e) = archive::record(history, id, &rendition, &output.path) {
+                warning!("Cannot note the download in {}: {e:#}", history.display());
+            }
+        }
+        if let Some(remote) = &args.move_to_remote {
+            let mut files = vec![output.path];
+            files.extend(checksum);
+            let options = rclone::Options {
+                remote: remote.clone(),
+                delete: args.delete_after_upload,
+            };
+            rclone::upload(&files, &options)?;
+        }
+    } else {
+        let filename = args.filename.as_deref().unwrap();
+        let mut kept = if args.continue_download {
+            resume::intact(Path::new(filename), video)?
+        } else {
+            Vec::new()
+        };
+        // Written in order, the file can only continue after the first gap.
+        if !preallocate {
+            kept.truncate(resume::prefix(&kept));
+        }
+        let mut file = if kept.is_empty() {
+            create_locked(Path::new(filename))?
+        } else {
+            open_locked(Path::new(filename))?
+        };
+        let ledger = ledger::Ledger::resume(&ledger::path_for(Path::new(filename)), &kept)?
+            .syncing(file.try_clone()?);
+        let player = args
+            .play
+            .as_deref()
+            .map(|program| player::Player::spawn(program, Path::new(filename)))
+            .transpose()?;
+        let server = args
+            .serve
+            .as_deref()
+            .map(|addr| serve::Server::start(addr, Path::new(filename), video.output_len()))
+            .transpose()?;
+        if !args.workers.is_empty() {
+            let options = remote::Options {
+                workers: args.workers.clone(),
+                token: args.worker_token.clone(),
+                backend: args.writer.unwrap_or(writer::Backend::Pwrite),
+            };
+            remote::download(&file, video, &fetcher, &ledger, &agent, &options)?;
+        } else if preallocate {
+            let options = parallel::Options {
+                concurrency: args.concurrency,
+                backend: args.writer.unwrap_or(writer::Backend::Pwrite),
+                adaptive: args.adaptive,
+                kept: kept.iter().map(|entry| entry.index).collect(),
+            };
+            let mirrors = || mirrors(&agent, &entry.media.dash_config, &cdn, video);
+            parallel::download(&file, video, &fetcher, &ledger, &options, &mirrors)?;
+        } else if let Some(last) = kept.last() {
+            let end = last.offset + last.size;
+            file.set_len(end)?;
+            file.write_all(video.init_segment())?;
+            file.seek(io::SeekFrom::Start(end))?;
+            download_from(&mut file, video, &fetcher, Some(&ledger), kept.len(), &[])?;
+        } else {
+            download(&mut file, video, &fetcher, Some(&ledger))?;
+        }
+        let mut tracks = Vec::new();
+        if let Some(audio) = audio {
+            let label = args
+                .audio_lang
+                .as_deref()
+                .or(audio.language.as_deref())
+                .unwrap_or("audio");
+            let path = audio_path(filename, label);
+            download_audio(&path, audio, &fetcher)?;
+            tracks.push((path, audio));
+        }
+        if args.all_audio {
+            for audio in &audios {
+                let path = audio_path(filename, audio.id.trim_matches('"'));
+                download_audio(&path, audio, &fetcher)?;
+                tracks.push((path, audio));
+            }
+        }
+        drop(file);
+        drop(ledger);
+        // Checks of the segments as downloaded come before anything
+        // rewrites them.
+        let mut chain: Vec<Box<dyn postprocess::PostProcessor>> = vec![
+            Box::new(avsync::CheckSync {
+                video: video.clone(),
+                max_drift: args.max_av_drift,
+            }),
+            Box::new(discontinuity::Find {
+                video: video.clone(),
+            }),
+        ];
+        if args.write_timestamps {
+            chain.push(Box::new(timestamps::WriteTimestamps {
+                video: video.clone(),
+            }));
+        }
+        if args.fill_gaps {
+            let fills = download_fills(filename, video, &videos, &fetcher)?;
+            if !fills.is_empty() {
+                chain.push(Box::new(postprocess::FillGaps {
+                    video: video.clone(),
+                    fills,
+                    stats: fetcher.stats().clone(),
+                }));
+            }
+        }
+        chain.extend(post_processors(
+            args,
+            url,
+            &entry.media,
+            video,
+            &tracks,
+            container,
+            &agent,
+        ));
+        let mut output = postprocess::Output {
+            path: PathBuf::from(filename),
+            audios: tracks,
+            companions: vec![ledger::path_for(Path::new(filename))],
+            discontinuities: Vec::new(),
+        };
+        postprocess::run(&chain, &mut output)?;
+        report_stats(args, &fetcher, video)?;
+        let hash = sha256_file(&output.path)?;
+        let checksum = report_sha256(args, &output.path, &hash)?;
+        if let (Some(history), Some(id)) = (&history, media_id) {
+            let rendition = video.id.trim_matches('"');
+            if let Err(e) = archive::record(history, id, rendition, &output.path) {
+                warning!("Cannot note the download in {}: {e:#}", history.display());
+            }
+        }
+        if let Some(player) = player {
+            player.finish()?;
+        }
+        if let Some(server) = server {
+            server.wait();
+        }
+        if let Some(remote) = &args.move_to_remote {
+            let mut files = vec![output.path];
+            files.extend(output.audios.into_iter().map(|(path, _)| path));
+            files.extend(output.companions);
+            files.extend(checksum);
+            let options = rclone::Options {
+                remote: remote.clone(),
+                delete: args.delete_after_upload,
+            };
+            rclone::upload(&files, &options)?;
+        }
+    }
+    if let Some(manifests) = &manifests {
+        manifests.remove()?;
+    }
+    Ok(())
+}
+
+/// Parses the command line, preceded by the arguments in the config file.
+///
+/// The config file holds arguments like the command line, any number per
+/// line; double quotes keep spaces in a value and lines starting with `#`
+/// are comments. Arguments given on the command line win: those of the
+/// config file that conflict with them are left out. Subcommands do not
+/// read it.
+fn parse_args() -> Args {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+    let subcommand = cli
+        .get(1)
+        .and_then(|arg| arg.to_str())
+        .is_some_and(|arg| arg == "help" || Command::has_subcommand(arg));
+    let explicit = cli.iter().enumerate().find_map(|(i, arg)| {
+        let arg = arg.to_str()?;
+        match arg.strip_prefix("--config=") {
+            Some(path) => Some(PathBuf::from(path)),
+            None if arg == "--config" => cli.get(i + 1).map(PathBuf::from),
+            None => None,
+        }
+    });
+    let ignore = subcommand || cli.iter().any(|arg| arg == "--no-config");
+    let path = explicit.clone().or_else(paths::config_file);
+
+    let mut args = cli[..1].to_vec();
+    if let Some(path) = path.filter(|_| !ignore) {
+        match std::fs::read_to_string(&path) {
+            Ok(text) => args.extend(not_overridden(config_words(&text), &cli)),
+            Err(e) if explicit.is_some() || e.kind() != io::ErrorKind::NotFound => {
+                exit_usage(&format!("Cannot read {}: {}", path.display(), e))
+            }
+            Err(_) => {}
+        }
+    }
+    args.extend_from_slice(&cli[1..]);
+    let words: Vec<_> = args[1..]
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let parsed = Args::parse_from(args);
+    if parsed.report {
+        report::enable(words);
+    }
+    parsed
+}
+
+/// The arguments of the config file in `words` that do not conflict with
+/// those of the command line `cli`. Conflicts within the config file are
+/// kept, for clap to report.
+fn not_overridden(words: Vec<String>, cli: &[OsString]) -> Vec<OsString> {
+    let conflicts = |args: &[OsString]| {
+        Args::try_parse_from(args).is_err_and(|e| e.kind() == clap::ErrorKind::ArgumentConflict)
+    };
+    // Every option with its values.
+    let mut options: Vec<Vec<OsString>> = Vec::new();
+    for word in words {
+        match options.last_mut() {
+            Some(option) if !word.starts_with('-') || word.parse::<f64>().is_ok() => {
+                option.push(word.into())
+            }
+            _ => options.push(vec![word.into()]),
+        }
+    }
+    let mut kept = cli[..1].to_vec();
+    for option in options {
+        let alone = [&kept[..], &option].concat();
+        let with_cli = [&alone[..], &cli[1..]].concat();
+        if !conflicts(&with_cli) || conflicts(&alone) {
+            kept = alone;
+        }
+    }
+    kept.split_off(1)
+}
+
+fn config_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut word = String::new();
+        let mut in_word = false;
+        let mut quoted = false;
+        for c in line.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    in_word = true;
+                }
+                c if c.is_whitespace() && !quoted => {
+                    if in_word {
+                        words.push(std::mem::take(&mut word));
+                        in_word = false;
+                    }
+                }
+                c => {
+                    word.push(c);
+                    in_word = true;
+                }
+            }
+        }
+        if in_word {
+            words.push(word);
+        }
+    }
+    words
+}
+
+#[cfg_attr(
+    not(any(feature = "http2", feature = "http3")),
+    allow(unused_variables)
+)]
+fn segment_client(args: &Args, config: &http::Config, agent: &ureq::Agent) -> Result<http::Client> {
+    #[cfg(feature = "http3")]
+    if args.http3 {
+        return http::Client::http3(config);
+    }
+    #[cfg(feature = "http2")]
+    if args.http2 {
+        return http::Client::http2(config);
+    }
+    Ok(http::Client::Ureq(agent.clone()))
+}
+
+/// Hotkeys for the download, or the dashboard which also handles them.
+#[cfg(feature = "tui")]
+fn interactive(
+    args: &Args,
+    video: &VideoInfo,
+    fetcher: &Fetcher,
+) -> Result<(Option<keys::Guard>, Option<tui::Dashboard>)> {
+    if args.tui {
+        let dashboard = tui::Dashboard::start(video, fetcher.stats().clone())?;
+        return Ok((None, Some(dashboard)));
+    }
+    Ok((keys::listen(), None))
+}
+
+#[cfg(not(feature = "tui"))]
+fn interactive(
+    _args: &Args,
+    _video: &VideoInfo,
+    _fetcher: &Fetcher,
+) -> Result<(Option<keys::Guard>, Option<()>)> {
+    Ok((keys::listen(), None))
+}
+
+#[cfg_attr(not(feature = "http3"), allow(unused_variables))]
+fn prefer_quic(args: &Args) -> bool {
+    #[cfg(feature = "http3")]
+    return args.http3;
+    #[cfg(not(feature = "http3"))]
+    false
+}
+
+/// Port Tor listens on for SOCKS connections.
+const TOR_SOCKS_PORT: u16 = 9050;
+
+fn apply_tor_preset(args: &mut Args) {
+    // socks5h: the proxy resolves host names, so no DNS queries leave the host.
+    args.proxy = Some(format!("socks5h://127.0.0.1:{TOR_SOCKS_PORT}"));
+    if args.concurrency > 2 {
+        info!("Limiting --concurrency to 2 for --tor");
+        args.concurrency = 2;
+    }
+    args.sleep_requests.get_or_insert(0.5);
+}
+
+/// An error for invalid arguments, which ends the command line tool with
+/// [`exit::USAGE`] but leaves a library caller running.
+fn usage_error(message: impl Into<String>) -> eyre::Report {
+    eyre::Report::new(exit::Usage(message.into()))
+}
+
+/// Ends the process over invalid arguments, before anything started.
+fn exit_usage(message: &str) -> ! {
+    eprintln!("{} {message}", style::error("error:"));
+    std::process::exit(exit::USAGE);
+}
+
+/// The live_event API for the event at `url`, with `--api-token`.
+fn live_api<'a>(
+    args: &Args,
+    agent: &'a ureq::Agent,
+    url: &str,
+) -> Option<(liveapi::Api<'a>, String)> {
+    let api = liveapi::Api {
+        agent,
+        base: args.api_url.clone(),
+        token: args.api_token.clone()?,
+    };
+    Some((api, liveapi::event_id(url)?))
+}
+
+/// Returns once the event at `url` is live: when the API says so, or else
+/// when its page can be extracted.
+fn wait_for_live(args: &Args, agent: &ureq::Agent, url: &str, referer: &str) -> Result<()> {
+    if let Some((api, id)) = live_api(args, agent, url) {
+        return api.wait(&id);
+    }
+    let registry = Registry::default();
+    let extractor = registry.find(url).wrap_err(Failure::Extraction)?;
+    loop {
+        match extractor.extract(&mut http_get(agent), url, referer) {
+            Ok(_) => return Ok(()),
+            Err(e) => info!("{url} is not live yet ({e:#}), trying again in 30s"),
+        }
+        schedule::sleep_until(Instant::now() + Duration::from_secs(30))?;
+    }
+}
+
+/// Downloads every finished stream of the event, each to the output name
+/// with the date of the stream added, and with `--follow` every stream
+/// published later.
+fn download_all_streams(
+    args: &Args,
+    agent: &ureq::Agent,
+    observe: &mut dyn FnMut(&MediaInfo, &VideoInfo, &Arc<Stats>),
+) -> Result<()> {
+    let url = args.url.as_deref().unwrap();
+    let Some((api, id)) = live_api(args, agent, url) else {
+        return Err(eyre!(
+            "{url} is not a vimeo.com/event/<id> URL, which --all-streams needs"
+        ))
+        .wrap_err(Failure::Extraction);
+    };
+    let filename = Path::new(args.filename.as_deref().unwrap());
+    let stem = filename.with_extension("");
+    let extension = filename
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned());
+    let tracked = liveapi::tracking_path(filename);
+    loop {
+        let streams = match api.archived(&id) {
+            Ok(streams) => streams,
+            Err(e) if args.follow => {
+                warning!("Cannot list the streams of event {id}: {e:#}");
+                Vec::new()
+            }
+            Err(e) => return Err(e).wrap_err(Failure::Extraction),
+        };
+        let downloaded = liveapi::downloaded(&tracked)?;
+        let new = streams
+            .iter()
+            .filter(|stream| !downloaded.contains(&stream.uri))
+            .count();
+        info!(
+            "Event {id} has {} finished streams, {new} not downloaded yet",
+            streams.len()
+        );
+        // Named from the whole list, so a stream keeps its name across runs.
+        let mut used = HashSet::new();
+        for stream in streams {
+            let date = stream.created.get(..10).unwrap_or(&stream.created);
+            let mut name = format!("{} {date}", stem.display());
+            for count in 2.. {
+                if used.insert(name.clone()) {
+                    break;
+                }
+                name = format!("{} {date} {count}", stem.display());
+            }
+            if let Some(extension) = &extension {
+                name = format!("{name}.{extension}");
+            }
+            if downloaded.contains(&stream.uri) {
+                continue;
+            }
+            info!("Downloading the stream \"{}\" to {name}", stream.name);
+            let mut stream_args = args.clone();
+            stream_args.url = Some(stream.link);
+            stream_args.filename = Some(name.clone());
+            stream_args.all_streams = false;
+            stream_args.wait_for_live = false;
+            match run_observed(&stream_args, observe) {
+                Ok(()) => liveapi::track(&tracked, &stream.uri, &name)?,
+                Err(e) if args.follow && !args.stop.requested() => {
+                    warning!("Downloading {name} failed, trying again later: {e:#}")
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if !args.follow {
+            return Ok(());
+        }
+        info!(
+            "Checking for new streams again in {} minutes",
+            liveapi::FOLLOW.as_secs() / 60
+        );
+        schedule::sleep_until(Instant::now() + liveapi::FOLLOW)?;
+    }
+}
+
+/// Sends the requests of the extraction core through `agent`.
+fn http_get(agent: &ureq::Agent) -> impl FnMut(&str, Option<&str>) -> Result<String> + '_ {
+    move |url, referer| {
+        let mut request = agent.get(url);
+        if let Some(referer) = referer {
+            request = request.set("Referer", referer);
+        }
+        Ok(request.call()?.into_string()?)
+    }
+}
+
+/// Gives the DRM error of the extraction core its exit code.
+fn drm_failure(e: eyre::Report) -> eyre::Report {
+    match e.downcast::<DrmProtected>() {
+        Ok(_) => eyre::Report::new(Failure::Drm),
+        Err(e) => e,
+    }
+}
+
+/// Connection settings when there are no command line options for them.
+fn default_http_config() -> http::Config {
+    http::Config {
+        max_connections_per_host: 1,
+        idle_timeout: None,
+        resolve: resolve::Options {
+            family: IpFamily::Any,
+            overrides: Vec::new(),
+            doh_url: None,
+        },
+        tls: tls::Options::default(),
+        proxy: None,
+        har: None,
+    }
+}
+
+/// Looks up the renditions of an event with default connection settings.
+#[cfg(any(feature = "python", feature = "ffi", feature = "gui"))]
+fn extract(url: &str, referer: &str) -> Result<vimeo_extract::Extraction> {
+    let agent = default_http_config().agent()?;
+    let extraction = vimeo_extract::extract(&mut http_get(&agent), url, referer);
+    extraction.map_err(drm_failure)
+}
+
+/// Base URLs of `video` on the CDNs other than `cdn`, as far as their
+/// manifests can be fetched.
+fn mirrors(
+    agent: &ureq::Agent,
+    dash_config: &serde_json::Value,
+    cdn: &str,
+    video: &VideoInfo,
+) -> Vec<Url> {
+    let Some(cdns) = dash_config["cdns"].as_object() else {
+        return Vec::new();
+    };
+    let mut urls = Vec::new();
+    for name in cdns.keys().filter(|name| *name != cdn) {
+        let base_url = get_manifest(agent, dash_config, name)
+            .and_then(|(master_url, master)| {
+                vimeo_extract::video_infos_lenient(&master_url, &master)
+            })
+            .map(|(videos, _)| videos.into_iter().find(|v| v.id == video.id));
+        match base_url {
+            Ok(Some(mirror)) => urls.extend(Url::parse(&mirror.base_url)),
+            Ok(None) => warning!(
+                "CDN {name} does not have rendition {}",
+                video.id.trim_matches('"')
+            ),
+            Err(e) => warning!("Cannot use CDN {name}: {e:#}"),
+        }
+    }
+    urls
+}
+
+/// Fetches the manifest at `master_url`, read as it arrives.
+fn get_master(agent: &ureq::Agent, master_url: &str) -> Result<Manifest> {
+    Ok(agent.get(master_url).call()?.into_json()?)
+}
+
+/// The video and audio renditions of `master`. Those that cannot be read
+/// are left out with a warning if `lenient`, else the first one fails.
+fn renditions(
+    lenient: bool,
+    master_url: &str,
+    master: &Manifest,
+) -> Result<(Vec<VideoInfo>, Vec<AudioInfo>)> {
+    let (videos, mut errors) = vimeo_extract::video_infos_lenient(master_url, master)?;
+    let (audios, audio_errors) = vimeo_extract::audio_infos_lenient(master_url, master)?;
+    errors.extend(audio_errors);
+    if !lenient && !errors.is_empty() {
+        return Err(eyre::Report::new(errors.swap_remove(0)).wrap_err(
+            "Cannot read the manifest, --lenient leaves out the renditions it cannot read",
+        ));
+    }
+    for e in errors {
+        warning!("Leaving out a rendition of the manifest: {e}");
+    }
+    Ok((videos, audios))
+}
+
+/// The main manifest of `cdn` and its URL, with the renditions of the
+/// manifests of single codecs added.
+fn get_manifest(
+    agent: &ureq::Agent,
+    dash_config: &serde_json::Value,
+    cdn: &str,
+) -> Result<(String, Manifest)> {
+    let urls = vimeo_extract::manifest_urls(&dash_config["cdns"][cdn]);
+    let Some((master_url, others)) = urls.split_first() else {
+        return Err(eyre!("No manifest URL for CDN {cdn}!"));
+    };
+    let mut master = get_master(agent, master_url)?;
+    for url in others {
+        match get_master(agent, url) {
+            Ok(other) => vimeo_extract::merge_manifest(&mut master, master_url, &other, url)?,
+            Err(e) => {
+                warning!("Cannot fetch a manifest of CDN {cdn}, some renditions are missing: {e:#}")
+            }
+        }
+    }
+    Ok((master_url.to_string(), master))
+}
+
+fn download(
+    out: &mut impl Write,
+    track: &(dyn Track + Sync),
+    fetcher: &Fetcher,
+    ledger: Option<&ledger::Ledger>,
+) -> Result<()> {
+    download_from(out, track, fetcher, ledger, 0, track.init_segment())
+}
+
+/// Most segments written with a single syscall.
+const BATCH_SEGMENTS: usize = 64;
+/// Segments are only batched up to this many bytes.
+const BATCH_BYTES: usize = 1 << 20;
+
+/// Downloads the segments of `track` from `first` on, after those before it
+/// in `out`, writing `head` first.
+///
+/// Up to [`Fetcher::prefetch`] segments are fetched ahead while the earlier
+/// ones are written, so the connection does not sit idle on the disk.
+/// Segments that are waiting by the time the writer comes round go out with
+/// one vectored write, which saves syscalls on renditions made of thousands
+/// of small segments.
+fn download_from(
+    out: &mut impl Write,
+    track: &(dyn Track + Sync),
+    fetcher: &Fetcher,
+    ledger: Option<&ledger::Ledger>,
+    first: usize,
+    head: &[u8],
+) -> Result<()> {
+    let url = Url::parse(track.base_url())?;
+    let (done, rest) = track.segments().split_at(first);
+    let sum: u64 = track.segments().iter().map(|s| s.size).sum();
+    let bar = fetcher.progress_bar(indicatif::ProgressBar::new(sum));
+    bar.inc(done.iter().map(|s| s.size).sum());
+
+    let fetch = |index: usize, segment: &Segment| -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match fetcher.fetch(&url, track, segment, &mut buf) {
+            Ok(_) => Ok(buf),
+            Err(e) => fetcher.give_up(track, index, e),
+        }
+    };
+    let mut offset =
+        track.init_segment().len() as u64 + done.iter().map(|s| s.size + 1).sum::<u64>();
+    let mut head = Some(head);
+    let mut written = (first..).zip(rest);
+    // Writes the next segments, which are in `batch`.
+    let mut write = |batch: &[Vec<u8>]| -> Result<()> {
+        let mut slices: Vec<_> = (head.take().into_iter())
+            .chain(batch.iter().map(Vec::as_slice))
+            .map(IoSlice::new)
+            .collect();
+        if let Some(ledger) = ledger {
+            let mut at = offset;
+            let intents: Vec<_> = (written.clone().zip(batch))
+                .map(|((index, _), buf)| {
+                    at += buf.len() as u64;
+                    (index, at - buf.len() as u64, buf.len() as u64)
+                })
+                .collect();
+            ledger.intend(&intents)?;
+        }
+        writer::write_all_vectored(out, &mut slices)?;
+        let mut entries = Vec::new();
+        for (buf, (index, segment)) in batch.iter().zip(&mut written) {
+            if ledger.is_some() {
+                entries.push(ledger::Entry::new(index, offset, buf, &segment.path));
+            }
+            let count = buf.len() as u64;
+            offset += count;
+            bar.inc(count - 1);
+        }
+        if let Some(ledger) = ledger {
+            ledger.append(&entries)?;
+        }
+        Ok(())
+    };
+    match fetcher.prefetch() {
+        0 => {
+            for (index, segment) in (first..).zip(rest) {
+                write(&[fetch(index, segment)?])?;
+            }
+        }
+        prefetch => thread::scope(|scope| -> Result<()> {
+            // One segment is in flight, the others wait in the channel.
+            let (sender, receiver) = mpsc::sync_channel(prefetch - 1);
+            scope.spawn(move || {
+                for (index, segment) in (first..).zip(rest) {
+                    let result = fetch(index, segment);
+                    let failed = result.is_err();
+                    // The writer is gone once it failed.
+                    if sender.send(result).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+            while let Ok(result) = receiver.recv() {
+                let mut batch = vec![result?];
+                let mut size = batch[0].len();
+                let mut failed = None;
+                while batch.len() < BATCH_SEGMENTS && size < BATCH_BYTES {
+                    match receiver.try_recv() {
+                        Ok(Ok(buf)) => {
+                            size += buf.len();
+                            batch.push(buf);
+                        }
+                        Ok(Err(e)) => {
+                            failed = Some(e);
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                // What arrived before the failure is good.
+                write(&batch)?;
+                if let Some(e) = failed {
+                    return Err(e);
+                }
+            }
+            Ok(())
+        })?,
+    }
+    // Without segments left, the head still has to go out.
+    write(&[])?;
+
+    bar.finish();
+    fetcher.finish()?;
+
+    Ok(())
+}
+
+/// Downloads what the other renditions have of the gaps in `video`, the
+/// larger renditions first.
+fn download_fills(
+    filename: &str,
+    video: &VideoInfo,
+    videos: &[VideoInfo],
+    fetcher: &Fetcher,
+) -> Result<Vec<mux::Fill>> {
+    let id = video.id.trim_matches('"');
+    let gaps: Vec<_> = fetcher
+        .stats()
+        .gaps()
+        .into_iter()
+        .filter(|gap| gap.track == id)
+        .collect();
+    let mut others: Vec<_> = videos.iter().filter(|v| v.id != video.id).collect();
+    others.sort_by_key(|v| std::cmp::Reverse(v.width));
+    let mut fills = Vec::new();
+    for (index, gap) in gaps.iter().enumerate() {
+        let path = Path::new(filename).with_extension(format!("fill{index}.mp4"));
+        let mut filled = false;
+        for other in &others {
+            let segments: Vec<_> = other
+                .segments
+                .iter()
+                .filter(|s| s.start < gap.end && s.end > gap.start)
+                .cloned()
+                .collect();
+            let Some(offset) = segments.first().map(|s| s.start) else {
+                continue;
+            };
+            let other_id = other.id.trim_matches('"');
+            info!(
+                "Downloading {other_id} to fill the gap at {:.1}s",
+                gap.start
+            );
+            let track = VideoInfo {
+                segments,
+                ..(*other).clone()
+            };
+            download(&mut create_locked(&path)?, &track, fetcher, None)?;
+            let incomplete = fetcher.stats().gaps().iter().any(|g| g.track == other_id);
+            if incomplete {
+                // Not a gap of the output.
+                fetcher.stats().remove_missing(other_id, 0.0, f64::INFINITY);
+                continue;
+            }
+            fills.push(mux::Fill {
+                path: path.clone(),
+                offset,
+                start: gap.start,
+                end: gap.end,
+            });
+            filled = true;
+            break;
+        }
+        if !filled {
+            let _ = std::fs::remove_file(&path);
+            warning!("No other rendition has the gap at {:.1}s", gap.start);
+        }
+    }
+    Ok(fills)
+}
+
+/// The steps to run on the finished output, in the fixed order described
+/// in postprocess.rs. Those needing the segments come before them.
+fn post_processors(
+    args: &Args,
+    url: &str,
+    media: &MediaInfo,
+    video: &VideoInfo,
+    audios: &[(PathBuf, &AudioInfo)],
+    container: mux::Container,
+    agent: &ureq::Agent,
+) -> Vec<Box<dyn postprocess::PostProcessor>> {
+    let options = mux::Options {
+        container,
+        layout: args.mp4_layout,
+        keep_fragments: args.keep_fragments,
+    };
+    let remux = container != mux::Container::Mp4 || args.mp4_layout == mux::Mp4Layout::Progressive;
+    let mut chain: Vec<Box<dyn postprocess::PostProcessor>> = Vec::new();
+    // Muxing would take the audio track away.
+    if args.extract_audio {
+        let format = args.audio_format.unwrap_or(audio::Format::M4a);
+        chain.push(Box::new(postprocess::ExtractAudio(format)));
+    }
+    #[cfg(feature = "transcode")]
+    let recode = args.recode.is_some();
+    #[cfg(not(feature = "transcode"))]
+    let recode = false;
+    // A single audio track stays next to a fragmented MP4 output, unless it
+    // is recoded anyway.
+    let muxed = remux || args.all_audio || recode;
+    if muxed {
+        chain.push(Box::new(postprocess::Mux(options)));
+    }
+    #[cfg(feature = "transcode")]
+    if let Some(preset) = args.recode {
+        chain.push(Box::new(transcode::Transcode {
+            preset,
+            hardware: args.hwaccel,
+            options,
+        }));
+    }
+    if let Some(count) = args.preview_sprite {
+        let sprite = sprite::Sprite::preview(video, count);
+        chain.push(Box::new(sprite::PreviewSprite(sprite)));
+    }
+    if args.contact_sheet {
+        let sheet = sprite::Sprite::contact_sheet(video);
+        chain.push(Box::new(sprite::ContactSheet(sheet)));
+    }
+    if args.write_nfo {
+        chain.push(Box::new(nfo::WriteNfo::new(media, url, video.duration)));
+    }
+    if args.embed_metadata {
+        chain.push(Box::new(postprocess::EmbedMetadata {
+            options,
+            title: media.title.clone(),
+            author: media.author.clone(),
+            date: media.upload_date.clone(),
+            url: url.to_string(),
+        }));
+    }
+    if args.embed_thumbnail {
+        chain.push(Box::new(postprocess::EmbedThumbnail {
+            options,
+            url: media.thumbnail.clone(),
+            agent: agent.clone(),
+        }));
+    }
+    if args.on_discontinuity != discontinuity::Action::Warn {
+        chain.push(Box::new(discontinuity::Mark {
+            action: args.on_discontinuity,
+            options,
+            duration: video.duration,
+        }));
+    }
+    // Only files which went through ffmpeg above can have their moov
+    // anywhere but at the front.
+    if args.faststart {
+        chain.push(Box::new(postprocess::Faststart));
+    }
+    if let Some(on_mismatch) = args.verify_with_ffprobe {
+        let codecs = |codecs: &str, video: bool| -> Vec<Codec> {
+            Codec::parse_list(codecs)
+                .into_iter()
+                .filter(|codec| codec.is_video() == video)
+                .collect()
+        };
+        let audio = audios
+            .iter()
+            .filter(|_| muxed)
+            .map(|(_, audio)| codecs(&audio.codecs, false))
+            .collect();
+        let expected = probe::Expected {
+            video: codecs(&video.codecs, true),
+            audio,
+            duration: video.duration,
+        };
+        // Recoding leaves only the codecs of the preset.
+        #[cfg(feature = "transcode")]
+        let expected = match args.recode {
+            Some(preset) => probe::Expected {
+                video: vec![preset.codec.codec()],
+                audio: (expected.audio.iter())
+                    .map(|_| vec![Codec::parse("mp4a.40.2")])
+                    .collect(),
+                ..expected
+            },
+            None => expected,
+        };
+        chain.push(Box::new(probe::Verify {
+            expected,
+            on_mismatch,
+        }));
+    }
+    if let Some(dir) = &args.move_to {
+        chain.push(Box::new(postprocess::Move { dir: dir.clone() }));
+    }
+    for command in &args.exec {
+        chain.push(Box::new(postprocess::Exec {
+            command: command.clone(),
+        }));
+    }
+    chain
+}
+
+fn output_extension(args: &Args) -> Option<String> {
+    let name = args.filename.as_deref()?;
+    let extension = Path::new(name).extension()?;
+    Some(extension.to_string_lossy().to_ascii_lowercase())
+}
+
+fn output_container(args: &Args) -> mux::Container {
+    match args.container {
+        Some(container) => container,
+        None if args.all_audio && output_extension(args).as_deref() == Some("mkv") => {
+            mux::Container::Mkv
+        }
+        None => mux::Container::Mp4,
+    }
+}
+
+/// Warns about codecs that may not play in the output container.
+fn check_codecs(
+    args: &Args,
+    container: mux::Container,
+    video: &VideoInfo,
+    audio: Option<&AudioInfo>,
+    audios: &[AudioInfo],
+) {
+    if let Some(ext @ ("mkv" | "webm" | "ts")) = output_extension(args).as_deref() {
+        if container == mux::Container::Mp4 {
+            warning!(
+                "{}",
+                style::warning(format_args!(
+                    "The output is MP4, whatever the .{ext} file name says; see --container"
+                ))
+            );
+        }
+    }
+    let mut codecs = Codec::parse_list(&video.codecs);
+    if args.all_audio {
+        for audio in audios {
+            codecs.extend(Codec::parse_list(&audio.codecs));
+        }
+    }
+    // With an MP4 output, a single audio track is stored next to it as MP4.
+    if let Some(audio) = audio {
+        codecs.extend(Codec::parse_list(&audio.codecs));
+    }
+    for warning in compat::warnings(container, &codecs) {
+        warning!("{}", style::warning(warning));
+    }
+}
+
+/// Where an audio track is stored next to `filename`: its extension is
+/// replaced, e.g. `talk.de.m4a` for `talk.mp4`.
+fn audio_path(filename: &str, label: &str) -> PathBuf {
+    Path::new(filename).with_extension(format!("{label}.m4a"))
+}
+
+fn download_audio(path: &Path, audio: &AudioInfo, fetcher: &Fetcher) -> Result<()> {
+    info!("Downloading audio track to {}", path.display());
+    download(&mut create_locked(path)?, audio, fetcher, None)
+}
+
+/// Creates `path` holding an exclusive advisory lock, so a second run
+/// writing the same file fails instead of interleaving with this one.
+///
+/// The file is only truncated once the lock is taken.
+fn create_locked(path: &Path) -> Result<File> {
+    let file = lock(
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?,
+        path,
+    )?;
+    file.set_len(0)?;
+    Ok(file)
+}
+
+/// Opens the existing `path` for writing in place, locked like
+/// [`create_locked`].
+fn open_locked(path: &Path) -> Result<File> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| eyre!("Cannot open {}: {}", path.display(), e))?;
+    lock(file, path)
+}
+
+fn lock(file: File, path: &Path) -> Result<File> {
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(std::fs::TryLockError::WouldBlock) => {
+            return Err(eyre!(
+                "{} is being written by another process!",
+                path.display()
+            ))
+        }
+        Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+    }
+    Ok(file)
+}
+
+fn sha256_hex(data: impl AsRef<[u8]>) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hashes the file as stored, so the result also covers what the writer
+/// backends did.
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Writer hashing everything passing through, for outputs that cannot be
+/// read back.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.hasher.update(&buf[..count]);
+        Ok(count)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let count = self.inner.write_vectored(bufs)?;
+        let mut left = count;
+        for buf in bufs {
+            let hashed = left.min(buf.len());
+            self.hasher.update(&buf[..hashed]);
+            left -= hashed;
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn report_stats(args: &Args, fetcher: &Fetcher, video: &VideoInfo) -> Result<()> {
+    fetcher.stats().print_summary(video);
+    if let Some(path) = &args.stats_json {
+        fetcher.stats().write_json(video, path)?;
+    }
+    Ok(())
+}
+
+/// Prints the SHA-256 of the output and, if asked to, stores it in
+/// `<file>.sha256` in the format of `sha256sum`, returning that file.
+fn report_sha256(args: &Args, path: &Path, hash: &str) -> Result<Option<PathBuf>> {
+    info!("SHA-256: {hash}");
+    if !args.write_sha256 {
+        return Ok(None);
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sha256");
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    std::fs::write(&name, format!("{hash}  {file_name}\n"))?;
+    Ok(Some(name.into()))
+}
@@ -0,0 +1,171 @@
+//! The live_event endpoints of the Vimeo API, for `--wait-for-live` and
+//! `--all-streams` with an `--api-token`.
+//!
+//! Without a token, whether an event is live can only be told by trying to
+//! extract its page, which fails in many ways before the event starts and
+//! says nothing about when it will. The API answers with the status of the
+//! event's broadcast, its scheduled start and the videos of its earlier
+//! streams. Only events by ID have it, `vimeo.com/event/<id>` and its embed
+//! page.
+//!
+//! A recurring event, such as a weekly webinar, keeps its URL and gets a new
+//! video for every occurrence. The streams `--all-streams` downloaded are
+//! noted in `<FILENAME without extension>.streams`, a line each:
+//!
+//! ```text
+//! <video URI>\t<output>
+//! ```
+//!
+//! so that later runs, or `--follow` checking every [`FOLLOW`], download
+//! only the occurrences published since.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result};
+use ureq::serde_json::Value;
+use url::Url;
+
+use crate::schedule::sleep_until;
+
+/// How often the status of an event that is not live yet is asked for.
+const POLL: Duration = Duration::from_secs(30);
+/// How often `--follow` asks for new streams.
+pub const FOLLOW: Duration = Duration::from_secs(15 * 60);
+/// Statuses of a broadcast that has not started.
+const WAITING: [&str; 4] = ["unavailable", "pending", "ready", "streaming_preview"];
+
+/// The ID of the event at `url`, if it has one.
+pub fn event_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+    if segments.next()? != "event" {
+        return None;
+    }
+    let id = segments.next()?;
+    id.bytes()
+        .all(|b| b.is_ascii_digit())
+        .then(|| id.to_string())
+}
+
+/// An earlier stream of an event, archived as a video.
+pub struct Stream {
+    /// `/videos/<id>`.
+    pub uri: String,
+    pub name: String,
+    pub link: String,
+    /// ISO 8601.
+    pub created: String,
+}
+
+pub struct Api<'a> {
+    pub agent: &'a ureq::Agent,
+    /// Base URL, `https://api.vimeo.com` but for tests.
+    pub base: String,
+    pub token: String,
+}
+
+impl Api<'_> {
+    fn get(&self, path: &str) -> Result<Value> {
+        let url = format!("{}{path}", self.base.trim_end_matches('/'));
+        let response = self
+            .agent
+            .get(&url)
+            .set("Authorization", &format!("bearer {}", self.token))
+            .set("Accept", "application/vnd.vimeo.*+json;version=3.4")
+            .call()
+            .map_err(|e| eyre!("The Vimeo API refused {path}: {e}"))?;
+        Ok(response.into_json()?)
+    }
+
+    /// The status of the broadcast of event `id` and when it is scheduled
+    /// to start, if known.
+    pub fn status(&self, id: &str) -> Result<(String, Option<String>)> {
+        let event = self.get(&format!("/live_events/{id}"))?;
+        let live = &event["streamable_clip"]["live"];
+        let status = live["status"]
+            .as_str()
+            .ok_or_else(|| eyre!("The Vimeo API gave no status for event {id}!"))?;
+        let start = (live["scheduled_start_time"].as_str())
+            .or_else(|| event["schedule"]["start_time"].as_str())
+            .map(str::to_string);
+        Ok((status.to_string(), start))
+    }
+
+    /// Waits until the broadcast of event `id` has started.
+    pub fn wait(&self, id: &str) -> Result<()> {
+        let mut last = None;
+        loop {
+            let (status, start) = self.status(id)?;
+            if !WAITING.contains(&status.as_str()) {
+                return Ok(());
+            }
+            if last.as_ref() != Some(&status) {
+                match &start {
+                    Some(start) => info!(
+                        "Event {id} is {status}, scheduled to start at {start}, waiting for it"
+                    ),
+                    None => info!("Event {id} is {status}, waiting for it to go live"),
+                }
+                last = Some(status);
+            }
+            sleep_until(Instant::now() + POLL)?;
+        }
+    }
+
+    /// The finished streams of event `id`, the oldest first.
+    pub fn archived(&self, id: &str) -> Result<Vec<Stream>> {
+        let mut streams = Vec::new();
+        let mut next = Some(format!("/live_events/{id}/videos?per_page=100"));
+        while let Some(path) = next {
+            let page = self.get(&path)?;
+            for video in page["data"].as_array().into_iter().flatten() {
+                let done = video["live"]["status"]
+                    .as_str()
+                    .is_none_or(|status| status == "done");
+                if let (true, Some(link)) = (done, video["link"].as_str()) {
+                    streams.push(Stream {
+                        uri: video["uri"].as_str().unwrap_or(link).to_string(),
+                        name: video["name"].as_str().unwrap_or_default().to_string(),
+                        link: link.to_string(),
+                        created: video["created_time"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                    });
+                }
+            }
+            next = page["paging"]["next"].as_str().map(str::to_string);
+        }
+        streams.sort_by(|a, b| a.created.cmp(&b.created));
+        Ok(streams)
+    }
+}
+
+/// Where the streams downloaded to `output` and the like are noted.
+pub fn tracking_path(output: &Path) -> PathBuf {
+    output.with_extension("streams")
+}
+
+/// The URIs of the streams noted in `path`.
+pub fn downloaded(path: &Path) -> Result<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Notes in `path` that the stream `uri` was downloaded to `output`.
+pub fn track(path: &Path, uri: &str, output: &str) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{uri}\t{output}")?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Status messages, printed to stderr and optionally sent to the system log.
+//!
+//! With `--log-to syslog` messages go to the local syslog socket, with
+//! `--log-to journald` to the journal using its native protocol, which keeps
+//! multi-line messages together. Colors are only for the terminal and are
+//! stripped from what the system log receives.
+
+use std::sync::OnceLock;
+
+use clap::ArgEnum;
+use eyre::Result;
+
+/// Prints a status message, like `eprintln!`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::emit($crate::logging::Level::Info, &format!($($arg)*))
+    };
+}
+
+/// Prints a message about a problem the download recovers from.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::logging::emit($crate::logging::Level::Warning, &format!($($arg)*))
+    };
+}
+
+const IDENTIFIER: &str = "vimeo-event-downloader";
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Syslog,
+    Journald,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Level {
+    /// Severity as defined by syslog.
+    fn severity(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warning => 4,
+            Level::Info => 6,
+        }
+    }
+}
+
+#[cfg(unix)]
+struct Sink {
+    target: Target,
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+#[cfg(unix)]
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+#[cfg(unix)]
+pub fn init(target: Target) -> Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = match target {
+        Target::Syslog => "/dev/log",
+        Target::Journald => "/run/systemd/journal/socket",
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket
+        .connect(path)
+        .map_err(|e| eyre::eyre!("Cannot connect to {path}: {e}"))?;
+    let _ = SINK.set(Sink { target, socket });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn init(_target: Target) -> Result<()> {
+    Err(eyre::eyre!("--log-to is only available on Unix"))
+}
+
+pub fn emit(level: Level, message: &str) {
+    eprintln!("{message}");
+    crate::report::log(&strip_colors(message));
+    #[cfg(unix)]
+    if let Some(sink) = SINK.get() {
+        let message = strip_colors(message);
+        let datagram = match sink.target {
+            Target::Syslog => {
+                // Facility "user"; the syslog daemon adds time and host.
+                let priority = 8 + level.severity();
+                let pid = std::process::id();
+                format!(
+                    "<{priority}>{IDENTIFIER}[{pid}]: {}",
+                    message.replace('\n', " ")
+                )
+                .into_bytes()
+            }
+            Target::Journald => journal_entry(level, &message),
+        };
+        // Losing a log line is no reason to stop the download.
+        let _ = sink.socket.send(&datagram);
+    }
+}
+
+/// Serializes an entry in the journal's native format. Values with line
+/// breaks need the length-prefixed binary form.
+#[cfg(unix)]
+fn journal_entry(level: Level, message: &str) -> Vec<u8> {
+    let mut entry = format!(
+        "PRIORITY={}\nSYSLOG_IDENTIFIER={IDENTIFIER}\n",
+        level.severity()
+    )
+    .into_bytes();
+    if message.contains('\n') {
+        entry.extend_from_slice(b"MESSAGE\n");
+        entry.extend_from_slice(&(message.len() as u64).to_le_bytes());
+        entry.extend_from_slice(message.as_bytes());
+        entry.push(b'\n');
+    } else {
+        entry.extend_from_slice(format!("MESSAGE={message}\n").as_bytes());
+    }
+    entry
+}
+
+/// Removes the escape sequences of [`crate::style`].
+fn strip_colors(message: &str) -> String {
+    let mut plain = String::with_capacity(message.len());
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
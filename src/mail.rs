@@ -0,0 +1,206 @@
+//! Email notifications for `--notify-email`, sent when a download finishes
+//! or fails, since long recordings tend to run unattended overnight.
+//!
+//! `--smtp-server smtps://HOST[:PORT]` connects with TLS, port 465 by
+//! default. `smtp://HOST[:PORT]`, port 587 by default, starts in the clear
+//! and switches to TLS with STARTTLS if the server offers it. A user in the
+//! URL logs in, with the password from the URL or `SMTP_PASSWORD`; it is
+//! never sent over an unencrypted connection.
+
+use std::env;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime};
+
+use eyre::{eyre, Result};
+use url::Url;
+
+use crate::{har, tls};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Options {
+    pub server: Url,
+    pub from: String,
+    pub to: Vec<String>,
+    pub tls: tls::Options,
+}
+
+pub struct Message {
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends `message` to the recipients of `options`.
+pub fn send(options: &Options, message: &Message) -> Result<()> {
+    let server = &options.server;
+    let implicit = match server.scheme() {
+        "smtps" => true,
+        "smtp" => false,
+        scheme => {
+            return Err(eyre!(
+                "Unknown mail server scheme {scheme}, use smtp or smtps"
+            ))
+        }
+    };
+    let host = server
+        .host_str()
+        .ok_or_else(|| eyre!("{server} names no host"))?;
+    let port = server.port().unwrap_or(if implicit { 465 } else { 587 });
+    let tcp = TcpStream::connect((host, port))
+        .map_err(|e| eyre!("Could not connect to {host}:{port}: {e}"))?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+
+    let hello = "EHLO localhost";
+    let (mut stream, extensions, secure) = if implicit {
+        let mut stream = options.tls.connect(host, tcp)?;
+        reply(&mut stream, 220)?;
+        let extensions = command(&mut stream, hello, 250)?;
+        (stream, extensions, true)
+    } else {
+        let mut tcp = tcp;
+        reply(&mut tcp, 220)?;
+        let extensions = command(&mut tcp, hello, 250)?;
+        if extensions
+            .iter()
+            .any(|line| line.eq_ignore_ascii_case("STARTTLS"))
+        {
+            command(&mut tcp, "STARTTLS", 220)?;
+            let mut stream = options.tls.connect(host, tcp)?;
+            let extensions = command(&mut stream, hello, 250)?;
+            (stream, extensions, true)
+        } else {
+            (Box::new(tcp) as Box<dyn tls::Stream>, extensions, false)
+        }
+    };
+
+    if !server.username().is_empty() {
+        if !secure {
+            return Err(eyre!(
+                "{host} does not offer STARTTLS, not sending the password unencrypted"
+            ));
+        }
+        if !extensions
+            .iter()
+            .any(|line| line.starts_with("AUTH ") && line.contains("PLAIN"))
+        {
+            return Err(eyre!("{host} does not accept AUTH PLAIN"));
+        }
+        let decode = |text: &str| {
+            percent_encoding::percent_decode_str(text)
+                .decode_utf8_lossy()
+                .into_owned()
+        };
+        let password = server
+            .password()
+            .map(decode)
+            .or_else(|| env::var("SMTP_PASSWORD").ok())
+            .unwrap_or_default();
+        let credentials = format!("\0{}\0{password}", decode(server.username()));
+        command(
+            &mut stream,
+            &format!("AUTH PLAIN {}", base64::encode(credentials)),
+            235,
+        )?;
+    }
+    command(&mut stream, &format!("MAIL FROM:<{}>", options.from), 250)?;
+    for to in &options.to {
+        command(&mut stream, &format!("RCPT TO:<{to}>"), 250)?;
+    }
+    command(&mut stream, "DATA", 354)?;
+    stream.write_all(compose(options, message).as_bytes())?;
+    command(&mut stream, ".", 250)?;
+    // The message is out, a failing goodbye changes nothing.
+    let _ = command(&mut stream, "QUIT", 221);
+    Ok(())
+}
+
+/// The message with its headers, ready to go after DATA.
+fn compose(options: &Options, message: &Message) -> String {
+    let mut text = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        options.from,
+        options.to.join(", "),
+        encode_header(&message.subject),
+        date(SystemTime::now()),
+    );
+    for line in message.body.lines() {
+        // A lone dot would end the message.
+        if line.starts_with('.') {
+            text.push('.');
+        }
+        text += line;
+        text += "\r\n";
+    }
+    text
+}
+
+/// `text` as an RFC 2047 encoded word if it is not plain ASCII.
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_string()
+    } else {
+        format!("=?utf-8?b?{}?=", base64::encode(text))
+    }
+}
+
+/// `time` as the Date header wants it, e.g. `1 May 2024 12:00:00 +0000`.
+fn date(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    // 2024-05-01T12:00:00.000Z
+    let iso = har::timestamp(time);
+    let month: usize = iso[5..7].parse().unwrap_or(1);
+    format!(
+        "{} {} {} {} +0000",
+        iso[8..10].trim_start_matches('0'),
+        MONTHS[month - 1],
+        &iso[..4],
+        &iso[11..19]
+    )
+}
+
+/// Sends `line` and reads the reply, which has to have the class of
+/// `expected`.
+fn command(stream: &mut dyn tls::Stream, line: &str, expected: u16) -> Result<Vec<String>> {
+    stream.write_all(format!("{line}\r\n").as_bytes())?;
+    stream.flush()?;
+    reply(stream, expected).map_err(|e| {
+        // Leave the credentials out.
+        let verb = line.split(' ').next().unwrap_or_default();
+        eyre!("{verb}: {e}")
+    })
+}
+
+/// Reads a reply, returning its lines after the first without the code.
+fn reply(stream: &mut dyn tls::Stream, expected: u16) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    loop {
+        // Byte by byte, so nothing is read past the reply before STARTTLS.
+        let mut line = Vec::new();
+        let mut byte = [0];
+        while line.last() != Some(&b'\n') {
+            if stream.read(&mut byte)? == 0 {
+                return Err(eyre!("The mail server hung up"));
+            }
+            line.push(byte[0]);
+        }
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+        let code: u16 = line
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| eyre!("Invalid reply from the mail server: {line}"))?;
+        if code / 100 != expected / 100 {
+            return Err(eyre!("The mail server answered {line}"));
+        }
+        let last = line.as_bytes().get(3) != Some(&b'-');
+        lines.push(line.get(4..).unwrap_or_default().to_string());
+        if last {
+            lines.remove(0);
+            return Ok(lines);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Player config and manifest kept between runs.
+//!
+//! Scraping the event page is the request most likely to be rate limited,
+//! and after too many of them Vimeo answers with captchas instead. A run
+//! that fails after extraction leaves what it found behind, so an immediate
+//! retry can skip extraction while the signed CDN URLs are still valid.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::Result;
+use regex::Regex;
+use ureq::serde_json::{self, json, Value};
+use vimeo_extract::{Manifest, MediaInfo};
+
+use crate::{paths, sha256_hex};
+
+/// How long an entry is used if the CDN URLs carry no expiry.
+const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+pub struct Entry {
+    /// What the player config told.
+    pub media: MediaInfo,
+    /// The manifest fetched from the chosen CDN, with its URL.
+    pub master: Option<(String, Manifest)>,
+}
+
+pub struct ManifestCache {
+    path: PathBuf,
+}
+
+impl ManifestCache {
+    /// The cache for one event page, or `None` without a home directory.
+    pub fn new(url: &str, referer: &str) -> Option<ManifestCache> {
+        let dir = paths::manifest_cache_dir()?;
+        Some(ManifestCache {
+            path: dir.join(sha256_hex(format!("{url} {referer}"))),
+        })
+    }
+
+    /// The entry of an earlier run, unless it expired or cannot be read.
+    pub fn load(&self) -> Option<Entry> {
+        let text = fs::read_to_string(&self.path).ok()?;
+        let mut value: Value = serde_json::from_str(&text).ok()?;
+        if value["expires"].as_u64()? <= now() {
+            return None;
+        }
+        let master_url = value["master_url"].as_str().map(str::to_string);
+        let master = match (master_url, value["master"].take()) {
+            (Some(url), master) if !master.is_null() => {
+                Some((url, serde_json::from_value(master).ok()?))
+            }
+            _ => None,
+        };
+        Some(Entry {
+            media: MediaInfo {
+                id: value["id"].as_str().map(str::to_string),
+                title: value["title"].as_str().map(str::to_string),
+                author: value["author"].as_str().map(str::to_string),
+                upload_date: value["upload_date"].as_str().map(str::to_string),
+                description: value["description"].as_str().map(str::to_string),
+                presenters: serde_json::from_value(value["presenters"].take()).unwrap_or_default(),
+                scheduled_start: value["scheduled_start"].as_str().map(str::to_string),
+                thumbnail: value["thumbnail"].as_str().map(str::to_string),
+                dash_config: value["dash_config"].take(),
+                progressive: vimeo_extract::progressive_files(&value["progressive"]),
+            },
+            master,
+        })
+    }
+
+    pub fn store(&self, entry: &Entry) -> Result<()> {
+        let (master_url, master) = match &entry.master {
+            Some((url, master)) => (Some(url), Some(master)),
+            None => (None, None),
+        };
+        let value = json!({
+            "expires": expires(&entry.media.dash_config),
+            "id": entry.media.id,
+            "title": entry.media.title,
+            "author": entry.media.author,
+            "upload_date": entry.media.upload_date,
+            "description": entry.media.description,
+            "presenters": entry.media.presenters,
+            "scheduled_start": entry.media.scheduled_start,
+            "thumbnail": entry.media.thumbnail,
+            "dash_config": entry.media.dash_config,
+            "progressive": entry.media.progressive.iter().map(|file| json!({
+                "url": file.url,
+                "width": file.width,
+                "height": file.height,
+                "quality": file.quality,
+            })).collect::<Vec<_>>(),
+            "master_url": master_url,
+            "master": master,
+        });
+        fs::create_dir_all(self.path.parent().unwrap())?;
+        let part = self.path.with_extension("part");
+        fs::write(&part, value.to_string())?;
+        fs::rename(&part, &self.path)?;
+        Ok(())
+    }
+
+    /// Forgets the entry once the download is complete, so the next run
+    /// sees a live event's new segments.
+    pub fn remove(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// When the first of the signed CDN URLs expires.
+///
+/// The signature is part of the path, as in `.../exp=1700000000~acl=...`.
+fn expires(dash_config: &Value) -> u64 {
+    let re = Regex::new(r"exp=(\d+)").unwrap();
+    let cdns = dash_config["cdns"].as_object();
+    cdns.into_iter()
+        .flat_map(|cdns| cdns.values())
+        .filter_map(|cdn| cdn["url"].as_str())
+        .filter_map(|url| re.captures(url)?[1].parse().ok())
+        .min()
+        .unwrap_or_else(|| now() + DEFAULT_TTL.as_secs())
+}
@@ -0,0 +1,566 @@
+//! Mock of the Vimeo endpoints for testing without network access.
+//!
+//! Serves a canned event page, player config, manifest and segments in the
+//! shapes the real ones have, so the whole pipeline can run against it:
+//!
+//! ```text
+//! /event                      page linking to the player config, with
+//!                             schema.org data of the event
+//! /config                     player config with two CDNs
+//! /<cdn>/sig/video/master.json  manifest with a 360p and a 720p rendition,
+//!                             English stereo and German 5.1 audio
+//! /<cdn>/sig/<rendition>/segN.m4s
+//! /s3/<bucket>/<key>          multipart uploads like S3's, unsigned
+//! /dav/<path>                 PUT and MKCOL like a WebDAV server
+//! /api/oembed.json            oEmbed metadata of the event
+//! ```
+//!
+//! With `flaky` set, the first request for every segment breaks off after a
+//! few bytes. A `missing` segment of the 720p rendition is not found on any
+//! CDN. Odd segments carry a strong ETag and honour `If-Range`, even
+//! ones only a weak one, so both resuming and starting over get exercised.
+//! With `expire_after` set, the `sig` part of the URLs changes after every
+//! that many segments, and segment requests with an older one get 403.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, prelude::*, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use eyre::{eyre, Result};
+use ureq::serde_json::{json, Value};
+
+const INIT_SEGMENT: &[u8] = b"INITINITINITINIT";
+const SEGMENTS: usize = 6;
+const RENDITIONS: [(&str, u64); 2] = [("v360", 640), ("v720", 1280)];
+/// Audio renditions, their languages and channel counts.
+const AUDIO: [(&str, &str, u64); 2] = [("a-en", "en", 2), ("a-de", "de", 6)];
+
+/// When the event started, 2024-05-02T18:00:00Z, in NTP seconds.
+const START_NTP: u64 = 3_923_661_600;
+
+/// The bytes of segment `index`, the same in every rendition.
+///
+/// They start with a `prft` box giving the wall-clock time, as those of
+/// live events do, and are one byte longer than the manifest says, like
+/// Vimeo's.
+fn segment(index: usize) -> Vec<u8> {
+    let ntp = (START_NTP + 2 * index as u64) << 32;
+    let mut data = 32u32.to_be_bytes().to_vec();
+    data.extend(b"prft");
+    data.extend([1, 0, 0, 0]);
+    data.extend(1u32.to_be_bytes());
+    data.extend(ntp.to_be_bytes());
+    data.extend(0u64.to_be_bytes());
+    data.resize(100 + index, b'A' + index as u8);
+    data
+}
+
+/// What a download of the best rendition must produce.
+pub fn expected_output() -> Vec<u8> {
+    let mut output = INIT_SEGMENT.to_vec();
+    for index in 0..SEGMENTS {
+        output.extend(segment(index));
+    }
+    output
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+}
+
+/// The signature of the URLs handed out.
+struct Signature {
+    expire_after: Option<usize>,
+    served: AtomicUsize,
+    /// Segment requests still to be turned down with 429.
+    throttle: AtomicUsize,
+}
+
+impl Signature {
+    fn current(&self) -> String {
+        match self.expire_after {
+            Some(count) => format!("sig{}", self.served.load(Ordering::SeqCst) / count.max(1)),
+            None => "sig".to_string(),
+        }
+    }
+}
+
+/// Objects uploaded to `/s3/`, and the parts of unfinished uploads by
+/// their ID.
+#[derive(Default)]
+struct Bucket {
+    objects: HashMap<String, Vec<u8>>,
+    uploads: HashMap<String, Vec<Vec<u8>>>,
+}
+
+impl MockServer {
+    /// Binds `addr` and serves from a background thread.
+    pub fn start(
+        addr: &str,
+        flaky: bool,
+        missing: Option<usize>,
+        expire_after: Option<usize>,
+        throttle: usize,
+        malformed: bool,
+    ) -> Result<MockServer> {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| eyre!("Could not listen on {addr}: {e}"))?;
+        let addr = listener.local_addr()?;
+        let broken = Arc::new(Mutex::new(HashSet::new()));
+        let signature = Arc::new(Signature {
+            expire_after,
+            served: AtomicUsize::new(0),
+            throttle: AtomicUsize::new(throttle),
+        });
+        let bucket = Arc::new(Mutex::new(Bucket::default()));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let broken = broken.clone();
+                let signature = signature.clone();
+                let bucket = bucket.clone();
+                thread::spawn(move || {
+                    let broken = flaky.then_some(&*broken);
+                    let _ = handle_connection(
+                        stream, addr, broken, missing, malformed, &signature, &bucket,
+                    );
+                });
+            }
+        });
+        Ok(MockServer { addr })
+    }
+
+    /// URL of the event page.
+    pub fn event_url(&self) -> String {
+        format!("http://{}/event", self.addr)
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    broken: Option<&Mutex<HashSet<String>>>,
+    missing: Option<usize>,
+    malformed: bool,
+    signature: &Signature,
+    bucket: &Mutex<Bucket>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut out = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line)? == 0 {
+            return Ok(());
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+        let (mut range, mut if_range, mut length, mut chunked) = (None, None, 0, false);
+        let mut authorization = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim();
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "range" => range = Some(value.to_string()),
+                    "if-range" => if_range = Some(value.to_string()),
+                    "content-length" => length = value.parse().unwrap_or(0),
+                    "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+                    "authorization" => authorization = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        let body = if chunked {
+            read_chunked(&mut reader)?
+        } else {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            body
+        };
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+        if let Some(key) = path.strip_prefix("/s3/") {
+            let (status, headers, body) =
+                s3(&mut bucket.lock().unwrap(), &method, key, query, body);
+            let headers: Vec<_> = headers.iter().map(|(n, v)| (*n, v.as_str())).collect();
+            respond(&mut out, status, "application/xml", &headers, &body)?;
+        } else if let Some(name) = path.strip_prefix("/dav/") {
+            let mut bucket = bucket.lock().unwrap();
+            let key = format!("dav/{name}");
+            match method.as_str() {
+                "MKCOL" => respond(&mut out, "201 Created", "text/plain", &[], b"")?,
+                "PUT" => {
+                    bucket.objects.insert(key, body);
+                    respond(&mut out, "201 Created", "text/plain", &[], b"")?;
+                }
+                _ => match bucket.objects.get(&key) {
+                    Some(object) => respond(&mut out, "200 OK", "video/mp4", &[], object)?,
+                    None => respond(&mut out, "404 Not Found", "text/plain", &[], b"")?,
+                },
+            }
+        } else if let Some(endpoint) = path.strip_prefix("/api/v3/") {
+            // The live_event API of event 7, for the token "secret".
+            let event = format!("http://{addr}/event");
+            let answer = match endpoint {
+                _ if authorization.as_deref() != Some("bearer secret") => None,
+                "live_events/7" => Some(json!({
+                    "uri": "/live_events/7",
+                    "streamable_clip": { "live": {
+                        "status": "streaming",
+                        "scheduled_start_time": "2024-05-02T18:00:00+00:00",
+                    } },
+                })),
+                "live_events/7/videos" if query.contains("page=2") => Some(json!({
+                    "data": [
+                        { "uri": "/videos/102", "name": "Day 2", "link": event, "created_time": "2024-05-02T18:00:00+00:00", "live": { "status": "done" } },
+                        { "uri": "/videos/103", "name": "Day 3", "link": event, "created_time": "2024-05-03T18:00:00+00:00", "live": { "status": "streaming" } },
+                    ],
+                    "paging": { "next": null },
+                })),
+                "live_events/7/videos" => Some(json!({
+                    "data": [
+                        { "uri": "/videos/101", "name": "Day 1", "link": event, "created_time": "2024-05-01T18:00:00+00:00", "live": { "status": "done" } },
+                    ],
+                    "paging": { "next": "/live_events/7/videos?per_page=100&page=2" },
+                })),
+                _ => None,
+            };
+            match answer {
+                Some(answer) => respond(
+                    &mut out,
+                    "200 OK",
+                    "application/json",
+                    &[],
+                    answer.to_string().as_bytes(),
+                )?,
+                None => respond(&mut out, "401 Unauthorized", "text/plain", &[], b"")?,
+            }
+        } else if path == "/event" || path.starts_with("/event/") {
+            let schema = json!({
+                "@context": "https://schema.org",
+                "@type": "Event",
+                "name": "Test Event",
+                "description": "Talks &amp; demos",
+                "startDate": "2024-05-02T18:00:00Z",
+                "performer": [{ "@type": "Person", "name": "Ada Lovelace" }, "Alan Turing"],
+            });
+            let page = format!(
+                r#"<script type="application/ld+json">{schema}</script><div data-config-url="http://{addr}/config?a=1&amp;b=2"></div>"#
+            );
+            respond(&mut out, "200 OK", "text/html", &[], page.as_bytes())?;
+        } else if path == "/config" {
+            let sig = signature.current();
+            let config = json!({
+                "video": { "id": 42, "title": "Test Event", "duration": 12 },
+                "request": { "files": { "dash": {
+                    "default_cdn": "fastly",
+                    "cdns": {
+                        "fastly": {
+                            "url": format!("http://{addr}/cdn/{sig}/video/master.json"),
+                            "hevc_url": format!("http://{addr}/cdn/{sig}/hevc/playlist.json"),
+                        },
+                        "akamai": { "url": format!("http://{addr}/cdn2/{sig}/video/master.json") },
+                    },
+                },
+                "progressive": [
+                    { "url": format!("http://{addr}/progressive/360.mp4"), "width": 640, "height": 360, "quality": "360p" },
+                    { "url": format!("http://{addr}/progressive/720.mp4"), "width": 1280, "height": 720, "quality": "720p" },
+                ] } },
+            });
+            respond(
+                &mut out,
+                "200 OK",
+                "application/json",
+                &[],
+                config.to_string().as_bytes(),
+            )?;
+        } else if path == "/api/oembed.json" {
+            let oembed = json!({
+                "type": "video",
+                "title": "Test Event",
+                "author_name": "Test Channel",
+                "upload_date": "2024-05-01 12:00:00",
+                "thumbnail_url": format!("http://{addr}/thumbnail.jpg"),
+            });
+            respond(
+                &mut out,
+                "200 OK",
+                "application/json",
+                &[],
+                oembed.to_string().as_bytes(),
+            )?;
+        } else if path == "/progressive/720.mp4" {
+            respond(&mut out, "200 OK", "video/mp4", &[], &expected_output())?;
+        } else if path.ends_with("/master.json") {
+            respond(
+                &mut out,
+                "200 OK",
+                "application/json",
+                &[],
+                master(malformed).as_bytes(),
+            )?;
+        } else if path.ends_with("/playlist.json") {
+            respond(
+                &mut out,
+                "200 OK",
+                "application/json",
+                &[],
+                playlist().as_bytes(),
+            )?;
+        } else if segment_index(path).is_some()
+            && (signature.throttle)
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+        {
+            let headers = [("Retry-After", "1")];
+            respond(
+                &mut out,
+                "429 Too Many Requests",
+                "text/plain",
+                &headers,
+                b"",
+            )?;
+        } else if segment_index(path).is_some()
+            && path.split('/').nth(2) != Some(&signature.current())
+        {
+            respond(&mut out, "403 Forbidden", "text/plain", &[], b"")?;
+        } else if let Some(index) =
+            segment_index(path).filter(|&index| Some(index) != missing || !path.contains("/v720/"))
+        {
+            signature.served.fetch_add(1, Ordering::SeqCst);
+            let data = segment(index);
+            let etag = if index % 2 == 1 {
+                format!("\"e{index}\"")
+            } else {
+                "W/\"w\"".to_string()
+            };
+            let etag_header = [("ETag", etag.as_str())];
+            let first = broken.is_some_and(|b| b.lock().unwrap().insert(path.to_string()));
+            if first {
+                // Promise everything, send a little and hang up.
+                write!(
+                    out,
+                    "HTTP/1.1 200 OK\r\nContent-Type: video/mp4\r\nETag: {etag}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    data.len()
+                )?;
+                return out.write_all(&data[..10]);
+            }
+            let offset = range
+                .filter(|_| if_range.as_ref() == Some(&etag))
+                .and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok())
+                .filter(|&offset: &usize| offset < data.len());
+            match offset {
+                Some(offset) => {
+                    let content_range = format!("bytes {offset}-{}/{}", data.len() - 1, data.len());
+                    let headers = [etag_header[0], ("Content-Range", content_range.as_str())];
+                    respond(
+                        &mut out,
+                        "206 Partial Content",
+                        "video/mp4",
+                        &headers,
+                        &data[offset..],
+                    )?;
+                }
+                None => respond(&mut out, "200 OK", "video/mp4", &etag_header, &data)?,
+            }
+        } else {
+            respond(&mut out, "404 Not Found", "text/plain", &[], b"")?;
+        }
+    }
+}
+
+fn respond(
+    out: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<()> {
+    write!(out, "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\n")?;
+    for (name, value) in headers {
+        write!(out, "{name}: {value}\r\n")?;
+    }
+    write!(out, "Content-Length: {}\r\n\r\n", body.len())?;
+    out.write_all(body)
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let size = line.trim().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid chunk size"))?;
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        reader.read_line(&mut line)?;
+        if size == 0 {
+            return Ok(body);
+        }
+    }
+}
+
+/// Answers an S3 request for `key`, which includes the bucket.
+fn s3(
+    bucket: &mut Bucket,
+    method: &str,
+    key: &str,
+    query: &str,
+    body: Vec<u8>,
+) -> (&'static str, Vec<(&'static str, String)>, Vec<u8>) {
+    let query: HashMap<_, _> = url::form_urlencoded::parse(query.as_bytes()).collect();
+    let upload_id = query.get("uploadId").map(|id| id.to_string());
+    match (method, upload_id) {
+        ("POST", None) if query.contains_key("uploads") => {
+            let id = format!("upload{}", bucket.uploads.len() + bucket.objects.len());
+            bucket.uploads.insert(id.clone(), Vec::new());
+            let xml = format!("<InitiateMultipartUploadResult><UploadId>{id}</UploadId></InitiateMultipartUploadResult>");
+            ("200 OK", Vec::new(), xml.into_bytes())
+        }
+        ("PUT", Some(id)) => {
+            let number: usize = query
+                .get("partNumber")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            match bucket.uploads.get_mut(&id) {
+                Some(parts) if number == parts.len() + 1 => {
+                    parts.push(body);
+                    (
+                        "200 OK",
+                        vec![("ETag", format!("\"part{number}\""))],
+                        Vec::new(),
+                    )
+                }
+                _ => ("400 Bad Request", Vec::new(), Vec::new()),
+            }
+        }
+        ("POST", Some(id)) => match bucket.uploads.remove(&id) {
+            Some(parts) => {
+                bucket.objects.insert(key.to_string(), parts.concat());
+                (
+                    "200 OK",
+                    Vec::new(),
+                    b"<CompleteMultipartUploadResult/>".to_vec(),
+                )
+            }
+            None => ("404 Not Found", Vec::new(), Vec::new()),
+        },
+        ("DELETE", Some(id)) => {
+            bucket.uploads.remove(&id);
+            ("204 No Content", Vec::new(), Vec::new())
+        }
+        ("GET", None) => match bucket.objects.get(key) {
+            Some(object) => ("200 OK", Vec::new(), object.clone()),
+            None => ("404 Not Found", Vec::new(), Vec::new()),
+        },
+        _ => ("400 Bad Request", Vec::new(), Vec::new()),
+    }
+}
+
+fn segment_index(path: &str) -> Option<usize> {
+    let name = path.rsplit('/').next()?;
+    let index = name
+        .strip_prefix("seg")?
+        .strip_suffix(".m4s")?
+        .parse()
+        .ok()?;
+    (index < SEGMENTS).then_some(index)
+}
+
+fn segments(rendition: &str) -> Vec<Value> {
+    (0..SEGMENTS)
+        .map(|index| {
+            json!({
+                "url": format!("{rendition}/seg{index}.m4s"),
+                "size": segment(index).len() - 1,
+                "start": index as f64 * 2.0,
+                "end": (index + 1) as f64 * 2.0,
+            })
+        })
+        .collect()
+}
+
+fn master(malformed: bool) -> String {
+    let init_segment = base64::encode(INIT_SEGMENT);
+    let mut videos = RENDITIONS
+        .iter()
+        .map(|&(id, width)| {
+            json!({
+                "id": id,
+                "codecs": "avc1.64001F",
+                "bitrate": 1000 * width,
+                "duration": 12.0,
+                "width": width,
+                "height": width * 9 / 16,
+                "init_segment": init_segment,
+                "segments": segments(id),
+            })
+        })
+        .collect::<Vec<_>>();
+    if malformed {
+        videos.push(json!({
+            "id": "v1080",
+            "codecs": "avc1.640028",
+            "bitrate": 5000000,
+            "duration": 12.0,
+            "width": "1920",
+            "height": 1080,
+            "init_segment": init_segment,
+            "segments": segments("v1080"),
+        }));
+        videos.push(json!(1));
+        let mut segments = segments("v2160");
+        segments[0]["start"] = json!("0");
+        videos.push(json!({
+            "id": "v2160",
+            "codecs": "avc1.640033",
+            "bitrate": 12000000,
+            "duration": 12.0,
+            "width": 3840,
+            "height": 2160,
+            "init_segment": init_segment,
+            "segments": segments,
+        }));
+    }
+    let audios: Vec<_> = AUDIO
+        .iter()
+        .map(|&(id, language, channels)| {
+            json!({
+                "id": id,
+                "codecs": "mp4a.40.2",
+                "bitrate": 128000,
+                "duration": 12.0,
+                "language": language,
+                "channels": channels,
+                "init_segment": init_segment,
+                "segments": segments(id),
+            })
+        })
+        .collect();
+    json!({ "clip_id": "c1", "base_url": "../", "video": videos, "audio": audios }).to_string()
+}
+
+/// The HEVC manifest, in the shape of `playlist.json` with base URLs for
+/// every rendition and only the average bitrate.
+fn playlist() -> String {
+    let video = json!({
+        "id": "v540h",
+        "base_url": "video/",
+        "codecs": "hvc1.1.6.L93.90",
+        "avg_bitrate": 800000,
+        "duration": 12.0,
+        "width": 960,
+        "height": 540,
+        "init_segment": base64::encode(INIT_SEGMENT),
+        "segments": segments("v540h"),
+    });
+    json!({ "clip_id": "c1", "base_url": "../", "video": [video], "audio": [] }).to_string()
+}
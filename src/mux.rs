@@ -0,0 +1,369 @@
+//! Combining the video with its audio tracks into one file with ffmpeg, and
+//! remuxing into other containers.
+//!
+//! Streams are copied, not re-encoded. Every audio track gets its language
+//! and a title, so players can offer them by name.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::ArgEnum;
+use eyre::{eyre, Result};
+
+use crate::{audio, AudioInfo, Codec, VideoInfo};
+
+pub const FFMPEG: &str = "ffmpeg";
+
+/// Format of the output file.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    /// MP4, laid out as [`Mp4Layout`] says.
+    Mp4,
+    /// Matroska, remuxed with ffmpeg.
+    Mkv,
+    /// MPEG transport stream, remuxed with ffmpeg, for broadcast and
+    /// streaming tools.
+    Ts,
+}
+
+impl Container {
+    pub fn name(self) -> &'static str {
+        match self {
+            Container::Mp4 => "MP4",
+            Container::Mkv => "Matroska",
+            Container::Ts => "MPEG-TS",
+        }
+    }
+
+    /// The `-f` of ffmpeg.
+    pub fn format(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "matroska",
+            Container::Ts => "mpegts",
+        }
+    }
+}
+
+/// How an MP4 output is laid out.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mp4Layout {
+    /// Many small fragments, as downloaded; quick to write and streamable.
+    Fragmented,
+    /// A single moov with the index of all samples, which every player
+    /// supports; rewritten with ffmpeg once downloaded.
+    Progressive,
+}
+
+#[derive(Clone, Copy)]
+pub struct Options {
+    pub container: Container,
+    /// Applies to MP4 outputs.
+    pub layout: Mp4Layout,
+    /// Keep the downloaded video and audio files instead of deleting them.
+    pub keep_fragments: bool,
+}
+
+/// Replaces the video at `output` with a file also holding `audios`, each
+/// stored in the file it is paired with.
+///
+/// With `keep_fragments` the video is kept as `<name>.video.mp4`, which is
+/// returned.
+pub fn mux(
+    output: &Path,
+    options: &Options,
+    audios: &[(PathBuf, &AudioInfo)],
+) -> Result<Option<PathBuf>> {
+    let kept = options
+        .keep_fragments
+        .then(|| output.with_extension("video.mp4"));
+    let input = match &kept {
+        Some(kept) => {
+            fs::rename(output, kept)?;
+            kept.as_path()
+        }
+        None => output,
+    };
+
+    if audios.is_empty() && options.layout == Mp4Layout::Progressive {
+        info!("Rewriting {} as progressive MP4", output.display());
+    } else if audios.is_empty() {
+        info!(
+            "Remuxing {} as {}",
+            output.display(),
+            options.container.name()
+        );
+    } else {
+        info!(
+            "Muxing {} audio tracks into {}",
+            audios.len(),
+            output.display()
+        );
+    }
+    let result = ffmpeg(input, output, options, |command| {
+        for (path, _) in audios {
+            command.arg("-i").arg(path);
+        }
+        command.args(["-map", "0:v"]);
+        for (index, (_, audio)) in audios.iter().enumerate() {
+            command.args(["-map", &format!("{}:a", index + 1)]);
+            let title = audio
+                .language
+                .clone()
+                .unwrap_or_else(|| audio.id.trim_matches('"').to_string());
+            command.arg(format!("-metadata:s:a:{index}"));
+            command.arg(format!("title={title}"));
+            if let Some(language) = &audio.language {
+                command.arg(format!("-metadata:s:a:{index}"));
+                command.arg(format!("language={language}"));
+            }
+        }
+    });
+    if let Err(e) = result {
+        if let Some(kept) = &kept {
+            let _ = fs::rename(kept, output);
+        }
+        return Err(e);
+    }
+    if !options.keep_fragments {
+        for (path, _) in audios {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(kept)
+}
+
+/// Rewrites `output` with `metadata` as its global tags, like `title`.
+pub fn embed_metadata(output: &Path, options: &Options, metadata: &[(&str, &str)]) -> Result<()> {
+    ffmpeg(output, output, options, |command| {
+        command.args(["-map", "0"]);
+        for (key, value) in metadata {
+            command.arg("-metadata").arg(format!("{key}={value}"));
+        }
+    })
+}
+
+/// Rewrites `output` with a chapter for each of the spans of `chapters`,
+/// in seconds, called Part 1, Part 2 and so on.
+pub fn embed_chapters(output: &Path, options: &Options, chapters: &[(f64, f64)]) -> Result<()> {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for (index, (start, end)) in chapters.iter().enumerate() {
+        metadata += &format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle=Part {}\n",
+            (start * 1000.0) as u64,
+            (end * 1000.0) as u64,
+            index + 1
+        );
+    }
+    let path = output.with_extension("chapters.txt");
+    fs::write(&path, metadata)?;
+    let result = ffmpeg(output, output, options, |command| {
+        command.arg("-i").arg(&path);
+        command.args(["-map", "0", "-map_chapters", "1"]);
+    });
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Copies the span from `start` to `end` seconds of `input` to `output`.
+pub fn cut(input: &Path, output: &Path, options: &Options, start: f64, end: f64) -> Result<()> {
+    ffmpeg(input, output, options, |command| {
+        command.args(["-map", "0"]);
+        command.arg("-ss").arg(format!("{start:.3}"));
+        command.arg("-to").arg(format!("{end:.3}"));
+    })
+}
+
+/// Rewrites `output` with the image at `thumbnail` as its cover art.
+pub fn embed_thumbnail(output: &Path, options: &Options, thumbnail: &Path) -> Result<()> {
+    ffmpeg(output, output, options, |command| match options.container {
+        // Matroska keeps images as attachments, not as streams.
+        Container::Mkv => {
+            command.args(["-map", "0"]).arg("-attach").arg(thumbnail);
+            command.args(["-metadata:s:t", "mimetype=image/jpeg"]);
+            command.args(["-metadata:s:t", "filename=cover.jpg"]);
+        }
+        Container::Mp4 | Container::Ts => {
+            command.arg("-i").arg(thumbnail);
+            command.args(["-map", "0", "-map", "1"]);
+            command.args(["-disposition:v:1", "attached_pic"]);
+        }
+    })
+}
+
+/// Copies the streams of `input`, and of the inputs `args` adds, into
+/// `output` as `options` say, going through `<output>.part` so a failure
+/// leaves `output` untouched.
+fn ffmpeg(
+    input: &Path,
+    output: &Path,
+    options: &Options,
+    args: impl FnOnce(&mut Command),
+) -> Result<()> {
+    let part = part_path(output);
+    let mut command = Command::new(FFMPEG);
+    command.args(["-v", "error", "-y", "-i"]).arg(input);
+    args(&mut command);
+    command.args(["-c", "copy"]);
+    if options.container == Container::Mp4 && options.layout == Mp4Layout::Fragmented {
+        command.args(["-movflags", "frag_keyframe+empty_moov+default_base_moof"]);
+    }
+    command.args(["-f", options.container.format()]).arg(&part);
+    finish(command, &part, output)
+}
+
+/// A span of the output to take from another rendition, downloaded to
+/// `path`.
+pub struct Fill {
+    pub path: PathBuf,
+    /// Where `path` starts in the recording.
+    pub offset: f64,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Rewrites `output`, `video` as downloaded, with the spans of `fills` in
+/// place of its gaps.
+///
+/// The fills are re-encoded to the size and codec of `video`, with the
+/// parameter sets at every keyframe since they differ from those of the
+/// output, and the rest is copied.
+pub fn fill_gaps(output: &Path, video: &VideoInfo, fills: &[Fill]) -> Result<()> {
+    let encoder = Codec::parse_list(&video.codecs)
+        .iter()
+        .find_map(|codec| match codec {
+            Codec::Avc { .. } => Some("libx264"),
+            Codec::Hevc { .. } => Some("libx265"),
+            Codec::Vp9 { .. } => Some("libvpx-vp9"),
+            Codec::Av1 { .. } => Some("libaom-av1"),
+            _ => None,
+        })
+        .ok_or_else(|| eyre!("Cannot encode {} to fill gaps", video.codecs))?;
+    let mut pieces = Vec::new();
+    let result = encode_fills(video, fills, encoder, &mut pieces)
+        .and_then(|_| concat_fills(output, fills, &pieces));
+    for path in pieces.iter().chain(fills.iter().map(|fill| &fill.path)) {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Encodes the spans of `fills` into files added to `pieces`, one each.
+fn encode_fills(
+    video: &VideoInfo,
+    fills: &[Fill],
+    encoder: &str,
+    pieces: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for (index, fill) in fills.iter().enumerate() {
+        info!(
+            "Encoding gap {} of {} from another rendition",
+            index + 1,
+            fills.len()
+        );
+        let piece = fill.path.with_extension("encoded.mp4");
+        let part = part_path(&piece);
+        let mut command = Command::new(FFMPEG);
+        command.args(["-v", "error", "-y"]);
+        command.args(["-ss", &(fill.start - fill.offset).to_string()]);
+        command.args(["-t", &(fill.end - fill.start).to_string()]);
+        command.arg("-i").arg(&fill.path);
+        command.args(["-map", "0:v", "-c:v", encoder]);
+        command
+            .arg("-vf")
+            .arg(format!("scale={}:{}", video.width, video.height));
+        command.args(["-bsf:v", "dump_extra=freq=keyframe", "-f", "mp4"]);
+        command.arg(&part);
+        finish(command, &part, &piece)?;
+        pieces.push(piece);
+    }
+    Ok(())
+}
+
+/// Joins the output and the encoded `pieces` with ffmpeg's concat demuxer,
+/// cutting the output around every gap; the result is fragmented like the
+/// download was.
+fn concat_fills(output: &Path, fills: &[Fill], pieces: &[PathBuf]) -> Result<()> {
+    let source = output.with_extension("unfilled.mp4");
+    let mut list = String::new();
+    let mut position = 0.0;
+    for (fill, piece) in fills.iter().zip(pieces) {
+        let _ = writeln!(list, "file '{}'", concat_path(&source));
+        let _ = writeln!(list, "inpoint {position}\noutpoint {}", fill.start);
+        let _ = writeln!(list, "file '{}'", concat_path(piece));
+        position = fill.end;
+    }
+    let _ = writeln!(list, "file '{}'\ninpoint {position}", concat_path(&source));
+    let list_path = output.with_extension("fill.txt");
+    fs::write(&list_path, list)?;
+
+    fs::rename(output, &source)?;
+    let part = part_path(output);
+    let mut command = Command::new(FFMPEG);
+    command.args(["-v", "error", "-y", "-f", "concat", "-safe", "0", "-i"]);
+    command.arg(&list_path).args(["-c", "copy"]);
+    command.args(["-movflags", "frag_keyframe+empty_moov+default_base_moof"]);
+    command.args(["-f", "mp4"]).arg(&part);
+    let result = finish(command, &part, output);
+    let _ = fs::remove_file(&list_path);
+    match &result {
+        Ok(()) => fs::remove_file(&source)?,
+        Err(_) => fs::rename(&source, output)?,
+    }
+    result
+}
+
+/// `path` quoted for a concat list.
+fn concat_path(path: &Path) -> String {
+    path.display().to_string().replace('\'', r"'\''")
+}
+
+/// Writes the audio track at `input` to `output` as `format`, transcoding
+/// it unless it is M4A.
+pub fn extract_audio(input: &Path, output: &Path, format: audio::Format) -> Result<()> {
+    let part = part_path(output);
+    let mut command = Command::new(FFMPEG);
+    command.args(["-v", "error", "-y", "-i"]).arg(input);
+    command.args(["-map", "0:a"]);
+    match format {
+        audio::Format::Mp3 => command.args(["-c:a", "libmp3lame", "-q:a", "2", "-f", "mp3"]),
+        // Podcast apps want the index up front.
+        audio::Format::M4a => command.args(["-c", "copy", "-movflags", "+faststart", "-f", "ipod"]),
+    };
+    command.arg(&part);
+    finish(command, &part, output)
+}
+
+/// `<path>.part`, which ffmpeg writes to.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Runs `command`, then moves the `part` it wrote over `output`.
+pub fn finish(mut command: Command, part: &Path, output: &Path) -> Result<()> {
+    let result = match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(eyre!(
+            "{FFMPEG} failed to write {} ({status})",
+            output.display()
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(eyre::Report::new(e).wrap_err(format!(
+                "{FFMPEG} is needed to write {}, but was not found",
+                output.display()
+            )))
+        }
+        Err(e) => Err(eyre!("Could not start {FFMPEG}: {e}")),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(part);
+    }
+    result?;
+    fs::rename(part, output)?;
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! `--write-nfo`, describing the recording in a `<name>.nfo` next to it,
+//! in the format Kodi, Jellyfin and Plex read for movies:
+//!
+//! ```text
+//! <movie>
+//!   <title>, <plot>, <studio> (the account), <premiered>, <runtime>,
+//!   <actor> per presenter, <thumb>, <uniqueid type="vimeo">
+//! </movie>
+//! ```
+
+use std::fs;
+
+use eyre::Result;
+use html_escape::encode_text;
+
+use crate::postprocess::{Output, PostProcessor};
+use crate::MediaInfo;
+
+/// The fields of the event that go into the file.
+pub struct WriteNfo {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub presenters: Vec<String>,
+    /// `YYYY-MM-DD`, of the scheduled start or else the upload.
+    pub date: Option<String>,
+    pub thumbnail: Option<String>,
+    pub url: String,
+    /// Length of the recording in seconds.
+    pub duration: f64,
+}
+
+impl WriteNfo {
+    pub fn new(media: &MediaInfo, url: &str, duration: f64) -> WriteNfo {
+        let scheduled = media
+            .scheduled_start
+            .as_ref()
+            .and_then(|start| start.get(..10))
+            .map(str::to_string);
+        WriteNfo {
+            id: media.id.clone(),
+            title: media.title.clone(),
+            author: media.author.clone(),
+            description: media.description.clone(),
+            presenters: media.presenters.clone(),
+            date: scheduled.or_else(|| media.upload_date.clone()),
+            thumbnail: media.thumbnail.clone(),
+            url: url.to_string(),
+            duration,
+        }
+    }
+
+    fn xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<movie>\n",
+        );
+        let mut element = |name: &str, value: &str| {
+            xml += &format!("  <{name}>{}</{name}>\n", encode_text(value));
+        };
+        if let Some(title) = &self.title {
+            element("title", title);
+        }
+        let plot = match &self.description {
+            Some(description) => format!("{description}\n\n{}", self.url),
+            None => self.url.clone(),
+        };
+        element("plot", &plot);
+        if let Some(author) = &self.author {
+            element("studio", author);
+        }
+        if let Some(date) = &self.date {
+            element("premiered", date);
+            if let Some(year) = date.get(..4) {
+                element("year", year);
+            }
+        }
+        element("runtime", &format!("{}", (self.duration / 60.0).round()));
+        if let Some(thumbnail) = &self.thumbnail {
+            element("thumb", thumbnail);
+        }
+        if let Some(id) = &self.id {
+            xml += &format!(
+                "  <uniqueid type=\"vimeo\" default=\"true\">{}</uniqueid>\n",
+                encode_text(id)
+            );
+        }
+        for presenter in &self.presenters {
+            xml += &format!(
+                "  <actor>\n    <name>{}</name>\n    <role>Presenter</role>\n  </actor>\n",
+                encode_text(presenter)
+            );
+        }
+        xml += "</movie>\n";
+        xml
+    }
+}
+
+impl PostProcessor for WriteNfo {
+    fn name(&self) -> &'static str {
+        "write-nfo"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let path = output.path.with_extension("nfo");
+        info!("Writing {}", path.display());
+        fs::write(&path, self.xml())?;
+        output.companions.push(path);
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Notifications about a finished or failed download, for teams sharing an
+//! archiving machine: by mail with `--notify-email`, see mail.rs, and as
+//! chat messages with `--notify-webhook KIND:URL`:
+//!
+//! ```text
+//! slack:https://hooks.slack.com/services/...    incoming webhook
+//! discord:https://discord.com/api/webhooks/...  channel webhook
+//! matrix:https://HOMESERVER/ROOM_ID             room message, with the
+//!                                               token from MATRIX_ACCESS_TOKEN
+//! ```
+
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::{eyre, Result};
+use ureq::serde_json::{json, Value};
+use url::Url;
+
+/// What there is to say about a download.
+pub struct Summary {
+    pub title: Option<String>,
+    pub url: String,
+    /// Where the output ended up.
+    pub output: Option<String>,
+    /// Length of the recording in seconds.
+    pub duration: Option<f64>,
+    pub size: Option<u64>,
+    pub elapsed: Duration,
+    pub error: Option<String>,
+}
+
+impl Summary {
+    pub fn headline(&self) -> String {
+        let name = self
+            .title
+            .as_deref()
+            .or(self.output.as_deref())
+            .unwrap_or(&self.url);
+        match self.error {
+            None => format!("Downloaded {name}"),
+            Some(_) => format!("Download of {name} failed"),
+        }
+    }
+
+    /// Name and value of everything known beyond the headline.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("Event", self.url.clone())];
+        if let Some(output) = &self.output {
+            fields.push(("Output", output.clone()));
+        }
+        if let Some(duration) = self.duration {
+            fields.push(("Duration", clock(duration as u64)));
+        }
+        if let Some(size) = self.size {
+            fields.push(("Size", format!("{:.1} MiB", size as f64 / 1048576.0)));
+        }
+        fields.push(("Took", clock(self.elapsed.as_secs())));
+        if let Some(error) = &self.error {
+            fields.push(("Error", error.clone()));
+        }
+        fields
+    }
+
+    /// The fields as plain text, one per line.
+    pub fn text(&self) -> String {
+        self.fields()
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\n"))
+            .collect()
+    }
+}
+
+fn clock(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Service {
+    Slack,
+    Discord,
+    Matrix,
+}
+
+/// A chat to post to.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    pub service: Service,
+    pub url: Url,
+}
+
+impl FromStr for Webhook {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Webhook, String> {
+        let (kind, url) = text
+            .split_once(':')
+            .ok_or("expected KIND:URL, like slack:https://hooks.slack.com/...")?;
+        let service = match kind {
+            "slack" => Service::Slack,
+            "discord" => Service::Discord,
+            "matrix" => Service::Matrix,
+            _ => return Err(format!("unknown kind {kind}, use slack, discord or matrix")),
+        };
+        let url = Url::parse(url).map_err(|e| format!("invalid URL {url}: {e}"))?;
+        Ok(Webhook { service, url })
+    }
+}
+
+impl Webhook {
+    /// Posts `summary` to the chat.
+    pub fn post(&self, agent: &ureq::Agent, summary: &Summary) -> Result<()> {
+        let result = match self.service {
+            Service::Slack => agent.post(self.url.as_str()).send_json(slack(summary)),
+            Service::Discord => agent.post(self.url.as_str()).send_json(discord(summary)),
+            Service::Matrix => {
+                let token = env::var("MATRIX_ACCESS_TOKEN")
+                    .map_err(|_| eyre!("Posting to Matrix needs MATRIX_ACCESS_TOKEN"))?;
+                let room = self.url.path().trim_start_matches('/');
+                // Matrix drops messages sent twice under the same ID.
+                let id = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                let url = self.url.join(&format!(
+                    "/_matrix/client/v3/rooms/{room}/send/m.room.message/{id}"
+                ))?;
+                agent
+                    .put(url.as_str())
+                    .set("Authorization", &format!("Bearer {token}"))
+                    .send_json(matrix(summary))
+            }
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, _)) => {
+                Err(eyre!("{:?} answered with {status}", self.service))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn slack(summary: &Summary) -> Value {
+    let fields: Vec<_> = summary
+        .fields()
+        .into_iter()
+        .map(|(name, value)| json!({ "type": "mrkdwn", "text": format!("*{name}*\n{value}") }))
+        .collect();
+    json!({
+        "text": summary.headline(),
+        "blocks": [
+            { "type": "header", "text": { "type": "plain_text", "text": summary.headline() } },
+            { "type": "section", "fields": fields },
+        ],
+    })
+}
+
+fn discord(summary: &Summary) -> Value {
+    let fields: Vec<_> = summary
+        .fields()
+        .into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value, "inline": name != "Error" }))
+        .collect();
+    let color = if summary.error.is_none() {
+        0x2e_a0_43
+    } else {
+        0xd0_31_2d
+    };
+    json!({
+        "embeds": [{
+            "title": summary.headline(),
+            "url": summary.url,
+            "color": color,
+            "fields": fields,
+        }],
+    })
+}
+
+fn matrix(summary: &Summary) -> Value {
+    let escape = |text: &str| html_escape::encode_text(text).into_owned();
+    let mut html = format!("<strong>{}</strong><br>", escape(&summary.headline()));
+    for (name, value) in summary.fields() {
+        html += &format!("<b>{name}:</b> {}<br>", escape(&value));
+    }
+    json!({
+        "msgtype": "m.notice",
+        "body": format!("{}\n{}", summary.headline(), summary.text()),
+        "format": "org.matrix.custom.html",
+        "formatted_body": html,
+    })
+}
@@ -0,0 +1,196 @@
+//! Downloading segments concurrently into a preallocated file.
+//!
+//! The size of every segment is known from the manifest, so each one has a
+//! fixed position in the output. Workers claim segments in order and write
+//! them straight to that position, without waiting on each other.
+//!
+//! In adaptive mode `concurrency` workers exist, but only as many of them
+//! fetch at the same time as the [`Limiter`] allows.
+//!
+//! A segment that still fails after its retries does not stop the others.
+//! It is put aside and tried once more when all the rest are done, from the
+//! same CDN and then from the others, and only if that fails too does the
+//! download.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use eyre::{eyre, Result};
+use url::Url;
+
+use crate::adaptive::Limiter;
+use crate::fetch::Fetcher;
+use crate::ledger::Ledger;
+use crate::writer::{Backend, Writer};
+use crate::{Track, VideoInfo};
+
+pub struct Options {
+    /// Maximum number of segments fetched at the same time.
+    pub concurrency: usize,
+    pub backend: Backend,
+    /// Adjust the number of concurrent fetches to the observed throughput.
+    pub adaptive: bool,
+    /// Segments already in the file from an earlier run, left as they are.
+    pub kept: HashSet<usize>,
+}
+
+/// Downloads `video` into `file`; `mirrors` gives the base URLs of the
+/// rendition on other CDNs, for the segments failing on this one.
+pub fn download(
+    file: &File,
+    video: &VideoInfo,
+    fetcher: &Fetcher,
+    ledger: &Ledger,
+    options: &Options,
+    mirrors: &dyn Fn() -> Vec<Url>,
+) -> Result<()> {
+    let url = Url::parse(&video.base_url)?;
+
+    file.set_len(video.output_len())?;
+    let writer = Writer::new(options.backend, file)?;
+    writer.write_all_at(&video.init_segment, 0)?;
+    let mut offsets = Vec::with_capacity(video.segments.len());
+    let mut offset = video.init_segment.len() as u64;
+    for segment in &video.segments {
+        offsets.push(offset);
+        offset += segment.size + 1;
+    }
+
+    let sum: u64 = video.segments.iter().map(|s| s.size).sum();
+    let bar = fetcher.progress_bar(indicatif::ProgressBar::new(sum));
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let deferred = Mutex::new(Vec::new());
+    let limiter = options.adaptive.then(|| Limiter::new(options.concurrency));
+
+    let results: Vec<Result<()>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.concurrency)
+            .map(|_| {
+                scope.spawn(|| -> Result<()> {
+                    let mut buf = Vec::new();
+                    while !failed.load(Ordering::SeqCst) {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(segment) = video.segments.get(index) else {
+                            break;
+                        };
+                        if options.kept.contains(&index) {
+                            bar.inc(segment.size);
+                            continue;
+                        }
+                        buf.clear();
+                        if let Some(limiter) = &limiter {
+                            limiter.acquire();
+                        }
+                        let result = fetcher.fetch(&url, video, segment, &mut buf);
+                        if let Some(limiter) = &limiter {
+                            limiter.release(buf.len() as u64);
+                        }
+                        if let Err(e) = result {
+                            // Stopping, whether asked to or by the circuit
+                            // breaker, is not up to a later sweep.
+                            if fetcher.stop_requested() || fetcher.aborted() {
+                                failed.store(true, Ordering::SeqCst);
+                                return Err(e);
+                            }
+                            warning!(
+                                "Segment {} failed, trying again at the end: {e:#}",
+                                index + 1
+                            );
+                            deferred.lock().unwrap().push(index);
+                            continue;
+                        }
+                        let result = ledger
+                            .intend(&[(index, offsets[index], buf.len() as u64)])
+                            .and_then(|_| Ok(writer.write_all_at(&buf, offsets[index])?))
+                            .and_then(|_| {
+                                ledger.record(index, offsets[index], &buf, &segment.path)
+                            });
+                        if let Err(e) = result {
+                            failed.store(true, Ordering::SeqCst);
+                            return Err(e);
+                        }
+                        bar.inc(segment.size);
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|w| {
+                w.join()
+                    .unwrap_or_else(|_| Err(eyre!("Download worker panicked!")))
+            })
+            .collect()
+    });
+    results.into_iter().collect::<Result<()>>()?;
+    let mut deferred = deferred.into_inner().unwrap();
+    if !deferred.is_empty() {
+        deferred.sort_unstable();
+        sweep(video, fetcher, &deferred, mirrors, |index, buf| {
+            ledger.intend(&[(index, offsets[index], buf.len() as u64)])?;
+            writer.write_all_at(buf, offsets[index])?;
+            bar.inc(video.segments[index].size);
+            ledger.record(index, offsets[index], buf, &video.segments[index].path)
+        })?;
+    }
+    writer.finish()?;
+
+    bar.finish();
+    if let Some(limiter) = &limiter {
+        info!(
+            "Adaptive concurrency ended at {} parallel requests",
+            limiter.limit()
+        );
+    }
+    fetcher.finish()?;
+
+    Ok(())
+}
+
+/// Fetches the `deferred` segments one after the other, from every CDN in
+/// turn, and hands each to `write`.
+fn sweep(
+    video: &VideoInfo,
+    fetcher: &Fetcher,
+    deferred: &[usize],
+    mirrors: &dyn Fn() -> Vec<Url>,
+    mut write: impl FnMut(usize, &[u8]) -> Result<()>,
+) -> Result<()> {
+    info!("Trying {} failed segments again", deferred.len());
+    let mut urls = vec![Url::parse(&video.base_url)?];
+    // Only looked up once the first CDN failed again.
+    let mut mirrors = Some(mirrors);
+    let mut buf = Vec::new();
+    for &index in deferred {
+        let segment = &video.segments[index];
+        let mut attempt = 0;
+        loop {
+            buf.clear();
+            let url = &urls[attempt];
+            let e = match fetcher.fetch(url, video, segment, &mut buf) {
+                Ok(_) => break,
+                Err(e) if fetcher.stop_requested() || fetcher.aborted() => return Err(e),
+                Err(e) => e,
+            };
+            let host = url.host_str().unwrap_or_default().to_string();
+            attempt += 1;
+            if attempt == urls.len() {
+                if let Some(mirrors) = mirrors.take() {
+                    urls.extend(mirrors());
+                }
+            }
+            if attempt == urls.len() {
+                let e = e.wrap_err(format!("Segment {} failed on every CDN", index + 1));
+                buf = fetcher.give_up(video, index, e)?;
+                break;
+            }
+            warning!("Segment {} failed from {host}: {e:#}", index + 1);
+        }
+        write(index, &buf)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Per-user locations of configuration and cached data.
+//!
+//! These follow the XDG base directory specification on Linux and the
+//! platform conventions elsewhere, e.g. `~/Library/Caches` on macOS and
+//! `%LOCALAPPDATA%` on Windows. Every location can be overridden with a flag.
+
+use std::path::PathBuf;
+
+const APP: &str = "vimeo-event-downloader";
+
+/// Default argument file, `$XDG_CONFIG_HOME/vimeo-event-downloader/config`.
+pub fn config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP).join("config"))
+}
+
+/// Segment cache used with `--cache`.
+pub fn segment_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP).join("segments"))
+}
+
+/// Player configs and manifests left behind by failed runs.
+pub fn manifest_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP).join("manifests"))
+}
+
+/// Finished downloads, for `--dedupe`.
+pub fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP).join("history"))
+}
+
+/// Address of the page of a running `--gui`, for the `queue` subcommand.
+#[cfg(feature = "gui")]
+pub fn gui_address_file() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP).join("gui"))
+}
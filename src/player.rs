@@ -0,0 +1,78 @@
+//! Watching a recording while it is still being downloaded.
+//!
+//! The player reads from a pipe that is fed from the output file as it grows,
+//! so a slow or paused player never throttles the download itself.
+
+use std::fs::File;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use eyre::{eyre, Result};
+
+pub struct Player {
+    child: Child,
+    feeder: JoinHandle<()>,
+    done: Arc<AtomicBool>,
+}
+
+impl Player {
+    /// Spawns `program -` and starts feeding it the contents of `path`.
+    pub fn spawn(program: &str, path: &Path) -> Result<Player> {
+        let mut child = Command::new(program)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("Could not start player {program}: {e}"))?;
+        let stdin = child.stdin.take().ok_or(eyre!("Player has no stdin!"))?;
+        let file = File::open(path)?;
+        let done = Arc::new(AtomicBool::new(false));
+        let feeder = {
+            let done = done.clone();
+            thread::spawn(move || {
+                // A closed player shows up as a broken pipe; the download
+                // simply carries on without it.
+                let _ = feed(file, stdin, &done);
+            })
+        };
+
+        Ok(Player {
+            child,
+            feeder,
+            done,
+        })
+    }
+
+    /// Signals that the download is complete and waits for the player to exit.
+    pub fn finish(mut self) -> Result<()> {
+        self.done.store(true, Ordering::SeqCst);
+        self.feeder
+            .join()
+            .map_err(|_| eyre!("Player feeder thread panicked!"))?;
+        info!("Waiting for player to exit");
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+fn feed(mut file: File, mut stdin: impl Write, done: &AtomicBool) -> io::Result<()> {
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        // Check before reading so the bytes written right before completion
+        // are still delivered.
+        let finished = done.load(Ordering::SeqCst);
+        let count = file.read(&mut buf)?;
+        if count == 0 {
+            if finished {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        stdin.write_all(&buf[..count])?;
+    }
+}
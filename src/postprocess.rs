@@ -0,0 +1,320 @@
+//! What happens to the output once it is downloaded.
+//!
+//! Every step is a [`PostProcessor`]. The arguments only pick the steps, the
+//! order is fixed, since most of them depend on what an earlier one did:
+//!
+//! 1. checking audio/video sync and finding discontinuities, on the
+//!    segments as downloaded
+//! 2. writing timestamps and filling gaps
+//! 3. extracting the audio, before muxing takes it into the output
+//! 4. muxing or remuxing, then recoding
+//! 5. preview sprite, contact sheet and NFO file
+//! 6. embedding metadata and the thumbnail, marking discontinuities
+//! 7. faststart, for files that went through ffmpeg above
+//! 8. verifying with ffprobe
+//! 9. moving the files with `--move-to`
+//! 10. running the `--exec` commands
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use eyre::{eyre, Result, WrapErr};
+
+use crate::stats::Stats;
+use crate::{audio, discontinuity, faststart, ledger, mux, AudioInfo, VideoInfo};
+
+/// The finished download, as the steps leave it for each other.
+pub struct Output<'a> {
+    pub path: PathBuf,
+    /// Audio tracks next to the output, not muxed into it.
+    pub audios: Vec<(PathBuf, &'a AudioInfo)>,
+    /// More files that go with the output, like its ledger.
+    pub companions: Vec<PathBuf>,
+    /// Where the recording jumps, once found.
+    pub discontinuities: Vec<discontinuity::Discontinuity>,
+}
+
+pub trait PostProcessor {
+    fn name(&self) -> &'static str;
+    fn run(&self, output: &mut Output) -> Result<()>;
+}
+
+/// Runs the steps of `chain` in order, stopping at the first that fails.
+pub fn run(chain: &[Box<dyn PostProcessor>], output: &mut Output) -> Result<()> {
+    for step in chain {
+        step.run(output)
+            .wrap_err_with(|| format!("Post-processing step {} failed", step.name()))?;
+    }
+    Ok(())
+}
+
+/// Puts spans of another rendition where the download has gaps.
+pub struct FillGaps {
+    pub video: VideoInfo,
+    pub fills: Vec<mux::Fill>,
+    pub stats: Arc<Stats>,
+}
+
+impl PostProcessor for FillGaps {
+    fn name(&self) -> &'static str {
+        "fill-gaps"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        mux::fill_gaps(&output.path, &self.video, &self.fills)?;
+        // The segments no longer are where the ledger says.
+        let ledger_path = ledger::path_for(&output.path);
+        output.companions.retain(|path| *path != ledger_path);
+        fs::remove_file(ledger_path)?;
+        let track = self.video.id.trim_matches('"');
+        for fill in &self.fills {
+            self.stats.remove_missing(track, fill.start, fill.end);
+        }
+        Ok(())
+    }
+}
+
+/// Writes the first audio track next to the output as `<name>.mp3` or
+/// `<name>.m4a`, for podcast apps and the like.
+pub struct ExtractAudio(pub audio::Format);
+
+impl PostProcessor for ExtractAudio {
+    fn name(&self) -> &'static str {
+        "extract-audio"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let (input, _) = output
+            .audios
+            .first()
+            .ok_or_else(|| eyre!("No audio track to extract"))?;
+        let target = output.path.with_extension(self.0.extension());
+        info!("Extracting the audio to {}", target.display());
+        match mux::extract_audio(input, &target, self.0) {
+            Ok(()) => {}
+            // The track already is M4A, only fragmented.
+            Err(e) if self.0 == audio::Format::M4a && ffmpeg_missing(&e) => {
+                warning!(
+                    "ffmpeg was not found, {} is left fragmented",
+                    target.display()
+                );
+                fs::copy(input, &target)?;
+            }
+            Err(e) => return Err(e),
+        }
+        output.companions.push(target);
+        Ok(())
+    }
+}
+
+fn ffmpeg_missing(e: &eyre::Report) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|e| e.kind() == io::ErrorKind::NotFound)
+}
+
+/// Puts the audio tracks into the output, or rewrites it into another
+/// container or layout.
+pub struct Mux(pub mux::Options);
+
+impl PostProcessor for Mux {
+    fn name(&self) -> &'static str {
+        "mux"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let audios = std::mem::take(&mut output.audios);
+        let kept = mux::mux(&output.path, &self.0, &audios)?;
+        // The segments no longer are where the ledger says, but still are in
+        // the kept video.
+        let ledger_path = ledger::path_for(&output.path);
+        output.companions.retain(|path| *path != ledger_path);
+        match kept {
+            Some(kept) => {
+                info!("Kept the downloaded video as {}", kept.display());
+                fs::rename(ledger_path, ledger::path_for(&kept))?;
+                output.companions.push(ledger::path_for(&kept));
+                output.companions.push(kept);
+                output
+                    .companions
+                    .extend(audios.into_iter().map(|(path, _)| path));
+            }
+            None => fs::remove_file(ledger_path)?,
+        }
+        Ok(())
+    }
+}
+
+/// Writes the title and the page URL into the output.
+pub struct EmbedMetadata {
+    pub options: mux::Options,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Day of the upload, `YYYY-MM-DD`.
+    pub date: Option<String>,
+    pub url: String,
+}
+
+impl PostProcessor for EmbedMetadata {
+    fn name(&self) -> &'static str {
+        "embed-metadata"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let mut metadata = vec![("comment", self.url.as_str())];
+        if let Some(title) = &self.title {
+            metadata.push(("title", title));
+        }
+        if let Some(author) = &self.author {
+            metadata.push(("artist", author));
+        }
+        if let Some(date) = &self.date {
+            metadata.push(("date", date));
+        }
+        info!("Embedding metadata into {}", output.path.display());
+        mux::embed_metadata(&output.path, &self.options, &metadata)
+    }
+}
+
+/// Downloads the thumbnail and makes it the cover art of the output.
+pub struct EmbedThumbnail {
+    pub options: mux::Options,
+    pub url: Option<String>,
+    pub agent: ureq::Agent,
+}
+
+impl PostProcessor for EmbedThumbnail {
+    fn name(&self) -> &'static str {
+        "embed-thumbnail"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let url = match &self.url {
+            Some(url) => url,
+            None => {
+                warning!("No thumbnail to embed");
+                return Ok(());
+            }
+        };
+        let path = output.path.with_extension("thumbnail.jpg");
+        let mut file = File::create(&path)?;
+        let result = self
+            .agent
+            .get(url)
+            .call()
+            .map_err(eyre::Report::new)
+            .and_then(|response| Ok(io::copy(&mut response.into_reader(), &mut file)?))
+            .and_then(|_| {
+                info!("Embedding the thumbnail into {}", output.path.display());
+                mux::embed_thumbnail(&output.path, &self.options, &path)
+            });
+        drop(file);
+        let _ = fs::remove_file(&path);
+        result
+    }
+}
+
+/// Moves the moov box of an MP4 output to the front.
+pub struct Faststart;
+
+impl PostProcessor for Faststart {
+    fn name(&self) -> &'static str {
+        "faststart"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let path = output.path.display();
+        if faststart::faststart(&output.path)? {
+            info!("Moved the moov box of {path} to the front");
+        } else {
+            info!("{path} already starts with its moov box");
+        }
+        Ok(())
+    }
+}
+
+/// Moves the output and everything that goes with it into a directory.
+pub struct Move {
+    pub dir: PathBuf,
+}
+
+impl PostProcessor for Move {
+    fn name(&self) -> &'static str {
+        "move"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        info!("Moving {} to {}", output.path.display(), self.dir.display());
+        output.path = move_file(&output.path, &self.dir)?;
+        for (path, _) in &mut output.audios {
+            *path = move_file(path, &self.dir)?;
+        }
+        for path in &mut output.companions {
+            *path = move_file(path, &self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+fn move_file(path: &Path, dir: &Path) -> Result<PathBuf> {
+    let target = dir.join(path.file_name().unwrap());
+    // Across file systems a rename fails and the file is copied instead.
+    if fs::rename(path, &target).is_err() {
+        fs::copy(path, &target)
+            .wrap_err_with(|| format!("Cannot move {} to {}", path.display(), dir.display()))?;
+        fs::remove_file(path)?;
+    }
+    Ok(target)
+}
+
+/// Runs a shell command on the output; `{}` in it stands for its path,
+/// which is appended if there is no `{}`.
+pub struct Exec {
+    pub command: String,
+}
+
+impl PostProcessor for Exec {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let status = shell(&self.command, &output.path)
+            .status()
+            .map_err(|e| eyre!("Could not run {}: {e}", self.command))?;
+        if !status.success() {
+            return Err(eyre!("{} failed ({status})", self.command));
+        }
+        Ok(())
+    }
+}
+
+/// The path is handed over as `$1` so the shell never parses it.
+#[cfg(unix)]
+fn shell(command: &str, path: &Path) -> Command {
+    let script = if command.contains("{}") {
+        command.replace("{}", "\"$1\"")
+    } else {
+        format!("{command} \"$1\"")
+    };
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(script).arg("sh").arg(path);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str, path: &Path) -> Command {
+    let path = format!("\"{}\"", path.display());
+    let script = if command.contains("{}") {
+        command.replace("{}", &path)
+    } else {
+        format!("{command} {path}")
+    };
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(script);
+    shell
+}
@@ -0,0 +1,167 @@
+//! Checking the finished output with ffprobe: the streams it holds, their
+//! codecs and its duration have to be those of the renditions that went
+//! into it.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use clap::ArgEnum;
+use eyre::{eyre, Result, WrapErr};
+use ureq::serde_json::{self, Value};
+
+use crate::exit::Failure;
+use crate::postprocess::{Output, PostProcessor};
+use crate::Codec;
+
+const FFPROBE: &str = "ffprobe";
+
+/// How far the duration may be off, in seconds; the last segment of a
+/// rendition tends to be cut a little differently.
+const DURATION_TOLERANCE: f64 = 1.0;
+
+/// What a mismatch does.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnMismatch {
+    Fail,
+    Warn,
+}
+
+/// What the output should hold.
+pub struct Expected {
+    pub video: Vec<Codec>,
+    /// One entry per audio stream.
+    pub audio: Vec<Vec<Codec>>,
+    pub duration: f64,
+}
+
+pub struct Verify {
+    pub expected: Expected,
+    pub on_mismatch: OnMismatch,
+}
+
+impl PostProcessor for Verify {
+    fn name(&self) -> &'static str {
+        "verify-with-ffprobe"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let probe = ffprobe(&output.path)?;
+        let problems = mismatches(&self.expected, &probe);
+        if problems.is_empty() {
+            info!(
+                "ffprobe agrees with the manifest on {}",
+                output.path.display()
+            );
+            return Ok(());
+        }
+        match self.on_mismatch {
+            OnMismatch::Warn => {
+                for problem in &problems {
+                    warning!("{problem}");
+                }
+                Ok(())
+            }
+            OnMismatch::Fail => {
+                Err(eyre!("{}", problems.join("; "))).wrap_err(Failure::Verification)
+            }
+        }
+    }
+}
+
+fn ffprobe(path: &Path) -> Result<Value> {
+    let output = Command::new(FFPROBE)
+        .args(["-v", "error", "-of", "json"])
+        .args([
+            "-show_entries",
+            "stream=codec_type,codec_name:stream_disposition=attached_pic:format=duration",
+        ])
+        .arg(path)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                eyre!("{FFPROBE} is needed to verify the output, but was not found")
+            }
+            _ => eyre!("Could not start {FFPROBE}: {e}"),
+        })?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "{FFPROBE} cannot read {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .wrap_err(Failure::Verification);
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Everything about `probe` that is not as `expected`.
+fn mismatches(expected: &Expected, probe: &Value) -> Vec<String> {
+    let streams = probe["streams"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    // Cover art shows up as a video stream of its own.
+    let streams_of = |kind: &str| -> Vec<&str> {
+        streams
+            .iter()
+            .filter(|s| s["codec_type"] == kind && s["disposition"]["attached_pic"] != 1)
+            .map(|s| s["codec_name"].as_str().unwrap_or("unknown"))
+            .collect()
+    };
+    let mut problems = Vec::new();
+    let expected_streams = [
+        ("video", vec![expected.video.clone()]),
+        ("audio", expected.audio.clone()),
+    ];
+    for (kind, expected) in expected_streams {
+        let found = streams_of(kind);
+        if found.len() != expected.len() {
+            problems.push(format!(
+                "expected {} {kind} streams, found {}",
+                expected.len(),
+                found.len()
+            ));
+            continue;
+        }
+        for (codecs, name) in expected.iter().zip(found) {
+            let names: Vec<_> = codecs.iter().filter_map(ffmpeg_name).collect();
+            if !names.is_empty() && !names.contains(&name) {
+                problems.push(format!(
+                    "expected a {} {kind} stream, found {name}",
+                    names.join("/")
+                ));
+            }
+        }
+    }
+    let duration = probe["format"]["duration"]
+        .as_str()
+        .and_then(|d| d.parse::<f64>().ok());
+    match duration {
+        Some(duration) if (duration - expected.duration).abs() > DURATION_TOLERANCE => problems
+            .push(format!(
+                "expected a duration of {:.1}s, found {duration:.1}s",
+                expected.duration
+            )),
+        Some(_) => {}
+        None => problems.push("ffprobe reports no duration".to_string()),
+    }
+    problems
+}
+
+/// The `codec_name` ffprobe reports for `codec`.
+fn ffmpeg_name(codec: &Codec) -> Option<&'static str> {
+    Some(match codec {
+        Codec::Avc { .. } => "h264",
+        Codec::Hevc { .. } => "hevc",
+        Codec::Av1 { .. } => "av1",
+        Codec::Vp9 { .. } => "vp9",
+        Codec::Aac { .. } => "aac",
+        Codec::Mp3 => "mp3",
+        Codec::Opus => "opus",
+        Codec::Ac3 => "ac3",
+        Codec::Eac3 => "eac3",
+        Codec::Flac => "flac",
+        Codec::Unknown(_) => return None,
+    })
+}
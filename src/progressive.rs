@@ -0,0 +1,78 @@
+//! `--prefer-progressive`: downloading the single MP4 file some player
+//! configs list under `request.files.progressive` instead of the DASH
+//! segments.
+//!
+//! The file has video and audio in one, so it needs no muxing, and one long
+//! request has less to go wrong than hundreds of short ones. It is only
+//! offered for some videos, and often not in the top quality, so it is used
+//! only if it is as tall as the rendition the segments would give; else the
+//! segments are downloaded as usual.
+
+use std::io::{self, prelude::*, BufWriter};
+use std::path::Path;
+
+use eyre::{Result, WrapErr};
+use indicatif::{ProgressBar, ProgressStyle};
+use url::Url;
+use vimeo_extract::ProgressiveFile;
+
+use crate::exit::Failure;
+use crate::fetch::Fetcher;
+use crate::{create_locked, VideoInfo};
+
+/// The smallest of `files` at least as tall as `video`.
+pub fn choose<'a>(files: &'a [ProgressiveFile], video: &VideoInfo) -> Option<&'a ProgressiveFile> {
+    files
+        .iter()
+        .filter(|file| file.height >= video.height)
+        .min_by_key(|file| file.height)
+}
+
+/// Names `file` where a rendition ID would go, as in the history.
+pub fn label(file: &ProgressiveFile) -> String {
+    match &file.quality {
+        Some(quality) => format!("progressive-{quality}"),
+        None => format!("progressive-{}p", file.height),
+    }
+}
+
+/// Downloads `file` to `path`.
+pub fn download(file: &ProgressiveFile, path: &Path, fetcher: &Fetcher) -> Result<()> {
+    info!(
+        "Downloading the progressive file of {}x{} instead of the segments",
+        file.width, file.height
+    );
+    let url = Url::parse(&file.url).wrap_err(Failure::Extraction)?;
+    // The size is not known up front.
+    let bar = fetcher.progress_bar(ProgressBar::new_spinner());
+    bar.set_style(
+        ProgressStyle::default_spinner().template("{spinner} {bytes} {binary_bytes_per_sec}"),
+    );
+    let mut out = Progress {
+        inner: BufWriter::new(create_locked(path)?),
+        bar: bar.clone(),
+    };
+    let count = fetcher.fetch_file(&url, &mut out)?;
+    out.flush()?;
+    bar.finish();
+    info!("Downloaded {count} bytes to {}", path.display());
+    Ok(())
+}
+
+/// Moves a progress bar along with the bytes written through it.
+struct Progress<W> {
+    inner: W,
+    bar: ProgressBar,
+}
+
+impl<W: Write> Write for Progress<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.bar.inc(count as u64);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
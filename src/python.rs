@@ -0,0 +1,101 @@
+//! Python bindings.
+//!
+//! Built as an extension module with `maturin build --features python`:
+//!
+//! ```python
+//! import vimeo_event_downloader as ved
+//!
+//! info = ved.extract("https://vimeo.com/event/123/embed", "https://example.com/")
+//! print(info["videos"][0]["width"])
+//! ved.download("https://vimeo.com/event/123/embed", "https://example.com/", "event.mp4")
+//! ```
+//!
+//! Both release the GIL while they work. Ctrl+C is left to Python, so it
+//! only takes effect once a call returns.
+
+// Triggered by the code `#[pyfunction]` generates.
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+use crate::{exit, run_embedded, Args};
+use clap::Parser;
+
+fn runtime_error(e: eyre::Report) -> PyErr {
+    PyRuntimeError::new_err(format!("{e:#}"))
+}
+
+/// Looks up the renditions of an event without downloading anything.
+///
+/// Returns a dict with the chosen `cdn`, the `master_url` of its manifest
+/// and the `videos`, each with `id`, `codecs`, `bitrate`, `duration`,
+/// `width`, `height` and the number of `segments`.
+#[pyfunction]
+fn extract(py: Python<'_>, url: &str, referer: &str) -> PyResult<PyObject> {
+    let extraction = py
+        .allow_threads(|| crate::extract(url, referer))
+        .map_err(runtime_error)?;
+    let videos = PyList::empty_bound(py);
+    for video in &extraction.videos {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("id", video.id.trim_matches('"'))?;
+        dict.set_item("codecs", video.codecs.trim_matches('"'))?;
+        dict.set_item("bitrate", video.bitrate)?;
+        dict.set_item("duration", video.duration)?;
+        dict.set_item("width", video.width)?;
+        dict.set_item("height", video.height)?;
+        dict.set_item("segments", video.segments.len())?;
+        videos.append(dict)?;
+    }
+    let info = PyDict::new_bound(py);
+    info.set_item("cdn", extraction.cdn)?;
+    info.set_item("master_url", extraction.master_url)?;
+    info.set_item("videos", videos)?;
+    Ok(info.into())
+}
+
+/// Downloads the best rendition of an event to `filename`, like the
+/// command line tool does, but without reading keys from the terminal.
+/// Invalid combinations of arguments raise `ValueError`.
+#[pyfunction]
+#[pyo3(signature = (url, referer, filename, concurrency = 1, retries = 3))]
+fn download(
+    py: Python<'_>,
+    url: &str,
+    referer: &str,
+    filename: &str,
+    concurrency: usize,
+    retries: u32,
+) -> PyResult<()> {
+    if concurrency == 0 {
+        return Err(PyValueError::new_err("concurrency must be at least 1"));
+    }
+    let args = Args::try_parse_from([
+        "vimeo-event-downloader",
+        "--url",
+        url,
+        "--referer",
+        referer,
+        "--filename",
+        filename,
+        "--concurrency",
+        &concurrency.to_string(),
+        "--retries",
+        &retries.to_string(),
+    ])
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    py.allow_threads(|| run_embedded(args, &mut |_, _, _| {}))
+        .map_err(|e| match e.downcast_ref::<exit::Usage>() {
+            Some(usage) => PyValueError::new_err(usage.0.clone()),
+            None => runtime_error(e),
+        })
+}
+
+#[pymodule]
+fn vimeo_event_downloader(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(extract, module)?)?;
+    module.add_function(wrap_pyfunction!(download, module)?)?;
+    Ok(())
+}
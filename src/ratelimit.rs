@@ -0,0 +1,173 @@
+//! Bandwidth limiting shared by all connections.
+//!
+//! A single token bucket holds the byte budget; every segment reader takes
+//! tokens for what it has read, so the limit applies to the total transfer
+//! rate no matter how many requests run in parallel. A bucket can draw
+//! from another as well, which recordings running side by side share.
+//!
+//! The number of requests is limited separately, by a [`Pace`] that spaces
+//! them out once the server answers 429 Too Many Requests.
+
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::retry;
+use crate::signals::Stop;
+
+/// Spacing of requests after the first throttled one.
+const MIN_PACE: Duration = Duration::from_millis(250);
+/// Widest spacing of requests.
+const MAX_PACE: Duration = Duration::from_secs(10);
+
+/// Bytes per second, parsed like curl's `--limit-rate` (`500K`, `2M`, `1G`).
+#[derive(Clone, Copy, Debug)]
+pub struct Rate(pub u64);
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rate, String> {
+        let (number, unit) = match s.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+            Some((index, _)) => s.split_at(index),
+            None => (s, ""),
+        };
+        let factor = match unit.to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => return Err(format!("unknown unit {unit}, use K, M or G")),
+        };
+        let number: f64 = number.parse().map_err(|_| format!("invalid rate {s}"))?;
+        let rate = (number * factor as f64) as u64;
+        if rate == 0 {
+            return Err("the rate must be positive".to_string());
+        }
+        Ok(Rate(rate))
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>,
+    parent: Option<Arc<TokenBucket>>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Rate) -> TokenBucket {
+        let rate = rate.0 as f64;
+        // Allow a quarter second worth of bytes in one go, so the reads of
+        // parallel connections are not serialised into tiny slices.
+        let burst = (rate / 4.0).max(16.0 * 1024.0);
+        TokenBucket {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+            parent: None,
+        }
+    }
+
+    /// Makes every take also take from `parent`, if there is one.
+    pub fn within(self, parent: Option<Arc<TokenBucket>>) -> TokenBucket {
+        TokenBucket { parent, ..self }
+    }
+
+    /// Takes `count` tokens, sleeping until the budget allows it.
+    ///
+    /// The bucket may go into debt, the sleep is then taken by whoever asks
+    /// next. This keeps large reads from starving behind small ones.
+    fn take(&self, count: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, last) = &mut *state;
+            let now = Instant::now();
+            *tokens =
+                (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.burst);
+            *last = now;
+            *tokens -= count as f64;
+            if *tokens < 0.0 {
+                Duration::from_secs_f64(-*tokens / self.rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        if let Some(parent) = &self.parent {
+            parent.take(count);
+        }
+    }
+}
+
+/// Reader drawing from a shared [`TokenBucket`].
+pub struct Throttled<'a, R> {
+    inner: R,
+    bucket: &'a TokenBucket,
+}
+
+impl<'a, R> Throttled<'a, R> {
+    pub fn new(inner: R, bucket: &'a TokenBucket) -> Self {
+        Throttled { inner, bucket }
+    }
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Small reads keep the rate smooth even for large buffers.
+        let len = buf.len().min(16 * 1024);
+        let count = self.inner.read(&mut buf[..len])?;
+        self.bucket.take(count);
+        Ok(count)
+    }
+}
+
+/// Spacing of the requests of all connections, widened whenever the server
+/// answers that it gets too many and narrowed again as requests succeed.
+#[derive(Debug, Default)]
+pub struct Pace {
+    /// The spacing, and when the next request may start.
+    state: Mutex<(Duration, Option<Instant>)>,
+}
+
+impl Pace {
+    /// Waits until the next request may start, or `stop` is requested.
+    pub fn wait(&self, stop: &Stop) {
+        let start = {
+            let mut state = self.state.lock().unwrap();
+            let (interval, next) = &mut *state;
+            let start = next.map_or(Instant::now(), |next| next.max(Instant::now()));
+            *next = Some(start + *interval);
+            start
+        };
+        retry::wait(start.saturating_duration_since(Instant::now()), stop);
+    }
+
+    /// Doubles the spacing after a throttled request and holds back every
+    /// request for `retry_after`, or the new spacing. Returns that wait.
+    pub fn throttled(&self, retry_after: Option<Duration>) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (interval, next) = &mut *state;
+        *interval = (*interval * 2).clamp(MIN_PACE, MAX_PACE);
+        let wait = retry_after.unwrap_or(*interval);
+        let until = Instant::now() + wait;
+        *next = Some(next.map_or(until, |next| next.max(until)));
+        wait
+    }
+
+    /// Narrows the spacing after a successful request, a little at a time
+    /// so the server is not flooded again right away.
+    pub fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        let interval = &mut state.0;
+        *interval -= *interval / 8;
+        if *interval < MIN_PACE / 2 {
+            *interval = Duration::ZERO;
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Copying the finished output to any storage rclone knows, for
+//! `--move-to-remote REMOTE:PATH`.
+//!
+//! This runs once everything else is done, including hashing and the
+//! checks, so only outputs known to be good leave the machine. The
+//! remotes are the ones in rclone's own config.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use eyre::{eyre, Result};
+
+pub const RCLONE: &str = "rclone";
+
+pub struct Options {
+    /// `remote:path` as rclone takes it.
+    pub remote: String,
+    /// Remove the local files once all of them are copied.
+    pub delete: bool,
+}
+
+/// Copies `files` into the directory of `options`.
+pub fn upload(files: &[PathBuf], options: &Options) -> Result<()> {
+    for file in files {
+        info!("Copying {} to {}", file.display(), options.remote);
+        let status = Command::new(RCLONE)
+            .arg("copy")
+            .arg(file)
+            .arg(&options.remote)
+            .status()
+            .map_err(|e| eyre!("Could not run {RCLONE}: {e}"))?;
+        if !status.success() {
+            return Err(eyre!(
+                "{RCLONE} could not copy {} to {} ({status})",
+                file.display(),
+                options.remote
+            ));
+        }
+    }
+    if options.delete {
+        for file in files {
+            fs::remove_file(file)?;
+        }
+        info!("Removed the local copies");
+    }
+    Ok(())
+}
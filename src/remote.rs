@@ -0,0 +1,325 @@
+//! Spreading a download over remote workers with `--workers`, e.g. cheap
+//! servers close to the CDN.
+//!
+//! A worker is this program running `worker --listen ADDR`. The coordinator
+//! splits the segments into one run per worker, of about the same number of
+//! bytes, and posts each run to its worker:
+//!
+//! ```text
+//! POST /segments
+//! Authorization: Bearer <token>
+//!
+//! {"base_url": "...", "segments": [{"path": "...", "size": 123}, ...]}
+//! ```
+//!
+//! The worker fetches the segments in order and sends them back as one body,
+//! just as they go into the output, which the coordinator writes into the
+//! preallocated file. Whatever a worker does not deliver, because a segment
+//! failed or the connection broke, the coordinator fetches itself at the end.
+//!
+//! Workers fetch whatever they are sent, so off loopback they require a
+//! token, see httpd.rs. It is sent over plain HTTP unless a worker is given
+//! as an `https://` URL, e.g. of a proxy in front of it.
+
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use eyre::{eyre, Result, WrapErr};
+use ureq::serde_json::{self, json, Value};
+use url::Url;
+
+use crate::exit::Failure;
+use crate::fetch::Fetcher;
+use crate::httpd::{self, Connections};
+use crate::ledger::Ledger;
+use crate::stats::SegmentRecord;
+use crate::writer::{Backend, Writer};
+use crate::{signals, Segment, Track, VideoInfo};
+
+/// Largest run a worker takes, the JSON of some hundred thousand segments.
+const MAX_RUN: usize = 16 * 1024 * 1024;
+
+pub struct Options {
+    /// Addresses of the workers, `host:port` or a URL.
+    pub workers: Vec<String>,
+    /// Sent to the workers, which may require it.
+    pub token: Option<String>,
+    pub backend: Backend,
+}
+
+/// Downloads `video` into `file` through the workers of `options`.
+pub fn download(
+    file: &File,
+    video: &VideoInfo,
+    fetcher: &Fetcher,
+    ledger: &Ledger,
+    agent: &ureq::Agent,
+    options: &Options,
+) -> Result<()> {
+    file.set_len(video.output_len())?;
+    let writer = Writer::new(options.backend, file)?;
+    writer.write_all_at(&video.init_segment, 0)?;
+    let mut offsets = Vec::with_capacity(video.segments.len());
+    let mut offset = video.init_segment.len() as u64;
+    for segment in &video.segments {
+        offsets.push(offset);
+        offset += segment.size + 1;
+    }
+    let write = |index: usize, buf: &[u8]| -> Result<()> {
+        ledger.intend(&[(index, offsets[index], buf.len() as u64)])?;
+        writer.write_all_at(buf, offsets[index])?;
+        ledger.record(index, offsets[index], buf, &video.segments[index].path)
+    };
+
+    let sum: u64 = video.segments.iter().map(|s| s.size).sum();
+    let bar = fetcher.progress_bar(indicatif::ProgressBar::new(sum));
+    let leftover = Mutex::new(Vec::new());
+    let runs = split(&video.segments, options.workers.len());
+    thread::scope(|scope| {
+        for (worker, run) in options.workers.iter().zip(runs) {
+            let (write, bar, leftover) = (&write, &bar, &leftover);
+            scope.spawn(move || {
+                let mut next = run.start;
+                let result = delegate(agent, worker, options, video, run.clone(), |index, buf| {
+                    fetcher.stats().record(SegmentRecord {
+                        path: video.segments[index].path.clone(),
+                        host: worker.clone(),
+                        bytes: buf.len() as u64,
+                        started: fetcher.stats().now(),
+                        elapsed: Duration::ZERO,
+                        attempts: 1,
+                    });
+                    write(index, buf)?;
+                    bar.inc(video.segments[index].size);
+                    next = index + 1;
+                    Ok(())
+                });
+                if let Err(e) = result {
+                    if next < run.end {
+                        warning!(
+                            "Worker {worker} stopped after {} of {} segments: {e:#}",
+                            next - run.start,
+                            run.len()
+                        );
+                    }
+                    leftover.lock().unwrap().extend(next..run.end);
+                }
+            });
+        }
+    });
+
+    let mut leftover = leftover.into_inner().unwrap();
+    if !leftover.is_empty() {
+        leftover.sort_unstable();
+        info!("Fetching the {} segments the workers left", leftover.len());
+        let url = Url::parse(&video.base_url)?;
+        let mut buf = Vec::new();
+        for index in leftover {
+            let segment = &video.segments[index];
+            buf.clear();
+            if let Err(e) = fetcher.fetch(&url, video, segment, &mut buf) {
+                buf = fetcher.give_up(video, index, e)?;
+            }
+            write(index, &buf)?;
+            bar.inc(segment.size);
+        }
+    }
+    writer.finish()?;
+    bar.finish();
+    fetcher.finish()?;
+    Ok(())
+}
+
+/// `segments` cut into `count` runs of about the same number of bytes.
+fn split(segments: &[Segment], count: usize) -> Vec<Range<usize>> {
+    let total: u64 = segments.iter().map(|s| s.size + 1).sum();
+    let mut runs = Vec::with_capacity(count);
+    let (mut start, mut bytes) = (0, 0);
+    for (index, segment) in segments.iter().enumerate() {
+        bytes += segment.size + 1;
+        if bytes * count as u64 >= total * (runs.len() as u64 + 1) {
+            runs.push(start..index + 1);
+            start = index + 1;
+        }
+    }
+    runs.resize(count, segments.len()..segments.len());
+    runs
+}
+
+/// Has `worker` fetch the segments of `run`, handing each to `write`.
+///
+/// A worker given as `host:port` is reached over plain `http://`, so the
+/// token of `options` crosses the network in the clear.
+fn delegate(
+    agent: &ureq::Agent,
+    worker: &str,
+    options: &Options,
+    video: &VideoInfo,
+    run: Range<usize>,
+    mut write: impl FnMut(usize, &[u8]) -> Result<()>,
+) -> Result<()> {
+    if run.is_empty() {
+        return Ok(());
+    }
+    let url = if worker.contains("://") {
+        format!("{worker}/segments")
+    } else {
+        format!("http://{worker}/segments")
+    };
+    let segments: Vec<_> = video.segments[run.clone()]
+        .iter()
+        .map(|segment| json!({ "path": segment.path, "size": segment.size }))
+        .collect();
+    let mut request = agent.post(&url);
+    if let Some(token) = &options.token {
+        request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+    let response =
+        request.send_json(json!({ "base_url": video.base_url, "segments": segments }))?;
+    let mut body = response.into_reader();
+    let mut buf = Vec::new();
+    for index in run {
+        if signals::stop_requested() {
+            return Err(eyre!("Download stopped")).wrap_err(Failure::Interrupted);
+        }
+        buf.resize(video.segments[index].size as usize + 1, 0);
+        body.read_exact(&mut buf)?;
+        write(index, &buf)?;
+    }
+    Ok(())
+}
+
+/// Segments a worker was asked for.
+struct Run {
+    base_url: String,
+    segments: Vec<Segment>,
+}
+
+impl Track for Run {
+    fn id(&self) -> &str {
+        "remote"
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn init_segment(&self) -> &[u8] {
+        &[]
+    }
+
+    fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    fn duration(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Runs a worker on `listen` until the process is interrupted; requests
+/// have to carry `token` if there is one.
+pub fn serve(listen: &str, token: Option<String>, fetcher: Fetcher) -> Result<()> {
+    let listener =
+        TcpListener::bind(listen).map_err(|e| eyre!("Could not listen on {listen}: {e}"))?;
+    httpd::require_token(listener.local_addr()?, token.as_deref(), "--token")?;
+    // On stdout, for scripts starting workers on any free port.
+    println!("{}", listener.local_addr()?);
+    io::stdout().flush()?;
+    info!("Waiting for segments to fetch");
+    let fetcher = Arc::new(fetcher);
+    let token = Arc::new(token);
+    let connections = Connections::default();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Some(admitted) = connections.admit(&stream) else {
+                continue;
+            };
+            let (fetcher, token) = (fetcher.clone(), token.clone());
+            thread::spawn(move || {
+                let _admitted = admitted;
+                if let Err(e) = handle_connection(stream, token.as_deref(), &fetcher) {
+                    warning!("Request failed: {e:#}");
+                }
+            });
+        }
+    });
+    while !signals::stop_requested() {
+        thread::sleep(Duration::from_millis(200));
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, token: Option<&str>, fetcher: &Fetcher) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut out = stream;
+    let head = httpd::read_head(&mut reader)?;
+    if head.method != "POST" || head.path() != "/segments" {
+        write!(
+            out,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?;
+        return Ok(());
+    }
+    if token.is_some_and(|token| !head.bearer(token)) {
+        write!(
+            out,
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?;
+        return Err(eyre!("Refused a request without the token"));
+    }
+    let length = head.content_length();
+    if length > MAX_RUN {
+        write!(
+            out,
+            "HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?;
+        return Err(eyre!("Refused a run of {length} bytes"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let run = parse_run(&body)?;
+    let url = Url::parse(&run.base_url)?;
+
+    let len: u64 = run.segments.iter().map(|s| s.size + 1).sum();
+    write!(
+        out,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n"
+    )?;
+    info!("Fetching {} segments", run.segments.len());
+    for segment in &run.segments {
+        // Hanging up tells the coordinator to fetch the rest itself.
+        fetcher.fetch(&url, &run, segment, &mut out)?;
+    }
+    out.flush()?;
+    info!("Sent {} segments", run.segments.len());
+    Ok(())
+}
+
+fn parse_run(body: &[u8]) -> Result<Run> {
+    let value: Value = serde_json::from_slice(body)?;
+    let invalid = || eyre!("Invalid request: {value}");
+    let segments = value["segments"]
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|segment| {
+            Some(Segment {
+                path: segment["path"].as_str()?.to_string(),
+                size: segment["size"].as_u64()?,
+                start: 0.0,
+                end: 0.0,
+            })
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)?;
+    Ok(Run {
+        base_url: value["base_url"].as_str().ok_or_else(invalid)?.to_string(),
+        segments,
+    })
+}
@@ -0,0 +1,54 @@
+//! Resolving an event again when the CDN starts refusing its segment URLs
+//! partway through a download, because their signature expired or was
+//! revoked.
+//!
+//! Page, player config and manifest are fetched again like for a new
+//! download and the renditions matched up by id, so the download carries on
+//! into the same output with the fresh URLs.
+
+use std::collections::HashMap;
+
+use eyre::Result;
+use url::Url;
+use vimeo_extract::{Registry, Track};
+
+use crate::{drm_failure, get_manifest, http_get};
+
+/// Where the renditions being downloaded came from.
+pub struct Renewal {
+    pub agent: ureq::Agent,
+    pub url: String,
+    pub referer: String,
+    /// CDN the download uses, kept as long as the event still offers it.
+    pub cdn: String,
+    pub prefer_quic: bool,
+}
+
+impl Renewal {
+    /// Fresh base URLs of all renditions, by id.
+    pub fn base_urls(&self) -> Result<HashMap<String, Url>> {
+        let registry = Registry::default();
+        let extractor = registry.find(&self.url)?;
+        let media = extractor
+            .extract(&mut http_get(&self.agent), &self.url, &self.referer)
+            .map_err(drm_failure)?;
+        let cdn = match vimeo_extract::master_url(&media.dash_config, &self.cdn) {
+            Some(_) => self.cdn.clone(),
+            None => vimeo_extract::choose_cdn(&media.dash_config, self.prefer_quic),
+        };
+        let (master_url, master) = get_manifest(&self.agent, &media.dash_config, &cdn)?;
+        // Only the renditions being downloaded matter, which were read
+        // before.
+        let (videos, _) = vimeo_extract::video_infos_lenient(&master_url, &master)?;
+        let (audios, _) = vimeo_extract::audio_infos_lenient(&master_url, &master)?;
+        let tracks = videos
+            .iter()
+            .map(|video| video as &dyn Track)
+            .chain(audios.iter().map(|audio| audio as &dyn Track));
+        let mut urls = HashMap::new();
+        for track in tracks {
+            urls.insert(track.id().to_string(), Url::parse(track.base_url())?);
+        }
+        Ok(urls)
+    }
+}
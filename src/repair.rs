@@ -0,0 +1,56 @@
+//! Patching a downloaded file whose ledger names damaged segments, by
+//! downloading just those segments again and writing them back in place.
+
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use eyre::{eyre, Result, WrapErr};
+use url::Url;
+
+use crate::exit::Failure;
+use crate::fetch::Fetcher;
+use crate::ledger::{self, Damaged};
+use crate::{open_locked, sha256_hex, Track};
+
+/// Repairs `output`, which was downloaded from `track`; returns how many
+/// segments were replaced.
+pub fn repair(output: &Path, track: &dyn Track, fetcher: &Fetcher) -> Result<usize> {
+    let (count, damaged) = ledger::check(output)?;
+    if damaged.is_empty() {
+        info!("All {count} recorded segments are intact, nothing to repair");
+        return Ok(0);
+    }
+    info!(
+        "Downloading {} damaged segments of {} again",
+        damaged.len(),
+        output.display()
+    );
+    let mut file = open_locked(output)?;
+    let url = Url::parse(track.base_url())?;
+    let mut buf = Vec::new();
+    for Damaged { entry, .. } in &damaged {
+        let number = entry.index + 1;
+        // The ledger of another rendition has segments of other sizes.
+        let segment = track
+            .segments()
+            .get(entry.index)
+            .filter(|segment| segment.size + 1 == entry.size)
+            .ok_or(Failure::Verification)
+            .wrap_err_with(|| {
+                format!("Segment {number} of the ledger is not in the chosen rendition")
+            })?;
+        buf.clear();
+        fetcher.fetch(&url, track, segment, &mut buf)?;
+        if sha256_hex(&buf) != entry.sha256 {
+            return Err(eyre!(
+                "Segment {number} differs from the one downloaded before, download the whole file again"
+            ))
+            .wrap_err(Failure::Verification);
+        }
+        file.seek(io::SeekFrom::Start(entry.offset))?;
+        file.write_all(&buf)?;
+        info!("Repaired segment {number} at offset {}", entry.offset);
+    }
+    fetcher.finish()?;
+    Ok(damaged.len())
+}
@@ -0,0 +1,256 @@
+//! Name resolution for all HTTP clients.
+//!
+//! ureq connects to the resolved addresses strictly in order, so a host with
+//! an unreachable IPv6 address stalls every new connection until the connect
+//! times out. The resolver races the addresses itself (RFC 8305 "Happy
+//! Eyeballs") and remembers the winner for the rest of the run.
+//!
+//! Hosts can also be pinned to fixed addresses (`--resolve`) or looked up via
+//! DNS-over-HTTPS instead of the system resolver.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::ArgEnum;
+use ureq::serde_json;
+
+/// Delay before the next address is tried while earlier attempts are pending.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpFamily {
+    Any,
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => addr.is_ipv4(),
+            IpFamily::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// A fixed address for a host, in curl's `--resolve` syntax:
+/// `HOST[:PORT]:ADDR[,ADDR...]`, with IPv6 addresses in brackets.
+#[derive(Clone, Debug)]
+pub struct Override {
+    host: String,
+    port: Option<u16>,
+    addrs: Vec<IpAddr>,
+}
+
+impl FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Override, String> {
+        let (host, rest) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected HOST[:PORT]:ADDR, got {s}"))?;
+        let (port, addrs) = match rest.split_once(':') {
+            Some((port, addrs)) if !port.starts_with('[') => {
+                let port = port.parse().map_err(|_| format!("invalid port {port}"))?;
+                (Some(port), addrs)
+            }
+            _ => (None, rest),
+        };
+        let addrs = addrs
+            .split(',')
+            .map(|addr| {
+                let addr = addr.trim_start_matches('[').trim_end_matches(']');
+                addr.parse().map_err(|_| format!("invalid address {addr}"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Override {
+            host: host.to_ascii_lowercase(),
+            port,
+            addrs,
+        })
+    }
+}
+
+/// How names are resolved.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub family: IpFamily,
+    pub overrides: Vec<Override>,
+    /// DNS-over-HTTPS endpoint speaking the JSON API (`application/dns-json`).
+    pub doh_url: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Resolver {
+    options: Options,
+    preferred: Mutex<HashMap<String, SocketAddr>>,
+    doh_cache: Mutex<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl Resolver {
+    pub fn new(options: Options) -> Resolver {
+        Resolver {
+            options,
+            preferred: Mutex::new(HashMap::new()),
+            doh_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves `netloc` (`host:port`) to the addresses of the allowed family.
+    fn lookup(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let (host, port) = netloc
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, netloc.to_string()))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let lower = host.to_ascii_lowercase();
+
+        let pinned = self
+            .options
+            .overrides
+            .iter()
+            .find(|o| o.host == lower && o.port.is_none_or(|p| p == port));
+        let ips = match (pinned, &self.options.doh_url) {
+            (Some(pinned), _) => pinned.addrs.clone(),
+            (None, _) if host.parse::<IpAddr>().is_ok() => vec![host.parse().unwrap()],
+            (None, Some(doh_url)) => self.doh_lookup(doh_url, &lower)?,
+            (None, None) => netloc.to_socket_addrs()?.map(|a| a.ip()).collect(),
+        };
+        let family = self.options.family;
+        let addrs: Vec<_> = ips
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .filter(|addr| family.allows(addr))
+            .collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{netloc} has no {family:?} address"),
+            ));
+        }
+        Ok(addrs)
+    }
+
+    fn doh_lookup(&self, doh_url: &str, host: &str) -> io::Result<Vec<IpAddr>> {
+        if let Some(ips) = self.doh_cache.lock().unwrap().get(host) {
+            return Ok(ips.clone());
+        }
+        let types: &[&str] = match self.options.family {
+            IpFamily::Any => &["AAAA", "A"],
+            IpFamily::V4 => &["A"],
+            IpFamily::V6 => &["AAAA"],
+        };
+        // The DoH server itself is resolved by the system resolver.
+        let agent = ureq::agent();
+        let mut ips = Vec::new();
+        for record_type in types {
+            let response = doh_query(&agent, doh_url, host, record_type)
+                .map_err(|e| io::Error::other(format!("DNS-over-HTTPS lookup failed: {e}")))?;
+            let answers = response["Answer"].as_array().cloned().unwrap_or_default();
+            ips.extend(
+                answers
+                    .iter()
+                    // Only A (1) and AAAA (28) records, not the CNAME chain.
+                    .filter(|a| matches!(a["type"].as_u64(), Some(1) | Some(28)))
+                    .filter_map(|a| a["data"].as_str()?.parse::<IpAddr>().ok()),
+            );
+        }
+        self.doh_cache
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), ips.clone());
+        Ok(ips)
+    }
+}
+
+impl ureq::Resolver for Resolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let mut addrs = interleave(self.lookup(netloc)?);
+        if addrs.len() < 2 {
+            return Ok(addrs);
+        }
+
+        let known = self.preferred.lock().unwrap().get(netloc).copied();
+        let winner = known.filter(|addr| addrs.contains(addr)).or_else(|| {
+            let winner = race(&addrs)?;
+            self.preferred
+                .lock()
+                .unwrap()
+                .insert(netloc.to_string(), winner);
+            Some(winner)
+        });
+        if let Some(winner) = winner {
+            addrs.retain(|addr| *addr != winner);
+            addrs.insert(0, winner);
+        }
+        Ok(addrs)
+    }
+}
+
+fn doh_query(
+    agent: &ureq::Agent,
+    doh_url: &str,
+    host: &str,
+    record_type: &str,
+) -> eyre::Result<serde_json::Value> {
+    Ok(agent
+        .get(doh_url)
+        .query("name", host)
+        .query("type", record_type)
+        .set("Accept", "application/dns-json")
+        .call()?
+        .into_json()?)
+}
+
+/// Alternates between address families, starting with the first one returned.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = addrs.first().is_none_or(|addr| addr.is_ipv6());
+    let (mut first, mut second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut result = Vec::with_capacity(first.len() + second.len());
+    first.reverse();
+    second.reverse();
+    while !first.is_empty() || !second.is_empty() {
+        result.extend(first.pop());
+        result.extend(second.pop());
+    }
+    result
+}
+
+/// Starts connecting to each address in turn, `ATTEMPT_DELAY` apart, and
+/// returns the first one that accepts the connection.
+fn race(addrs: &[SocketAddr]) -> Option<SocketAddr> {
+    let (tx, rx) = mpsc::channel();
+    for (index, addr) in addrs.iter().copied().enumerate() {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            thread::sleep(ATTEMPT_DELAY * index as u32);
+            if TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).is_ok() {
+                let _ = tx.send(addr);
+            }
+        });
+    }
+    drop(tx);
+    rx.recv().ok()
+}
+
+#[cfg(any(feature = "http2", feature = "http3"))]
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        // hyper already races the addresses, only the family is applied here.
+        // The port is replaced by the client.
+        let result = self
+            .lookup(&format!("{}:0", name.as_str()))
+            .map(|addrs| Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+            .map_err(|e| e.into());
+        Box::pin(std::future::ready(result))
+    }
+}
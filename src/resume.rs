@@ -0,0 +1,86 @@
+//! Continuing an interrupted download of a file with `--continue`.
+//!
+//! The ledger of the file says which segments were written where. Before a
+//! segment is kept, its bytes are read back and checked against the ledger,
+//! and its place against the manifest, so a file truncated or changed since
+//! the last run is not taken as it is: whatever does not match is
+//! downloaded again. Segments the journal in the ledger has cut off by a
+//! crash are named, see ledger.rs.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use eyre::Result;
+
+use crate::gaps;
+use crate::ledger::{self, Damaged, Entry};
+use crate::{sha256_hex, Track};
+
+/// The segments of `track` that an earlier run wrote intact to `output`,
+/// by index.
+pub fn intact(output: &Path, track: &dyn Track) -> Result<Vec<Entry>> {
+    let ledger_path = ledger::path_for(output);
+    if !output.exists() || !ledger_path.exists() {
+        info!("Nothing to continue in {}, starting over", output.display());
+        return Ok(Vec::new());
+    }
+    let torn = ledger::torn(&ledger_path)?;
+    if !torn.is_empty() {
+        let numbers: Vec<_> = torn.iter().map(|index| (index + 1).to_string()).collect();
+        warning!(
+            "The last run stopped while writing segment {}, downloading again",
+            numbers.join(", ")
+        );
+    }
+    let (count, damaged) = ledger::check(output)?;
+    let damaged: HashSet<_> = damaged
+        .iter()
+        .map(|Damaged { entry, .. }| entry.index)
+        .collect();
+    let mut offsets = Vec::with_capacity(track.segments().len());
+    let mut offset = track.init_segment().len() as u64;
+    for segment in track.segments() {
+        offsets.push(offset);
+        offset += segment.size + 1;
+    }
+    let mut kept: Vec<_> = ledger::load(&ledger_path)?
+        .into_iter()
+        .filter(|entry| {
+            let Some(segment) = track.segments().get(entry.index) else {
+                return false;
+            };
+            // Another rendition, or one the CDN changed since.
+            let same = segment.size + 1 == entry.size
+                && offsets[entry.index] == entry.offset
+                && entry.url.as_ref().is_none_or(|url| *url == segment.path);
+            // Left out with --ignore-errors, worth another try.
+            let placeholder = entry.sha256 == sha256_hex(gaps::placeholder(entry.size));
+            same && !placeholder && !damaged.contains(&entry.index)
+        })
+        .collect();
+    kept.dedup_by_key(|entry| entry.index);
+    if damaged.is_empty() {
+        info!(
+            "Continuing {}, {} of {} segments are already there",
+            output.display(),
+            kept.len(),
+            track.segments().len()
+        );
+    } else {
+        warning!(
+            "{} of the {count} segments in {} changed since the last run, downloading them again",
+            damaged.len(),
+            output.display()
+        );
+    }
+    Ok(kept)
+}
+
+/// How many segments at the start of `kept` follow each other from the
+/// first one on, which is what a sequential download can continue after.
+pub fn prefix(kept: &[Entry]) -> usize {
+    kept.iter()
+        .enumerate()
+        .take_while(|(index, entry)| entry.index == *index)
+        .count()
+}
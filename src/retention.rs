@@ -0,0 +1,126 @@
+//! Deleting old recordings of the `schedule` subcommand, with
+//! `--keep-last N` and `--max-total GIB`, so a recorder left running does
+//! not fill the disk.
+//!
+//! Each finished recording is noted in `.recordings` in the record
+//! directory, as a line with the start of its event, in seconds since the
+//! epoch, and the name of its output. Only recordings listed there are ever
+//! deleted, the oldest first, together with the files next to them that
+//! share the name up to the extension: ledger, NFO, sprites and the like.
+//! The newest recording is kept whatever its size.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+
+const INDEX: &str = ".recordings";
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Policy {
+    /// Number of recordings to keep.
+    pub keep_last: Option<usize>,
+    /// Bytes all recordings may take together.
+    pub max_total: Option<u64>,
+}
+
+/// Notes the recording of an event started at `start` to `output`.
+pub fn add(dir: &Path, start: i64, output: &Path) -> Result<()> {
+    let Some(name) = output.file_name() else {
+        return Ok(());
+    };
+    let mut index = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(INDEX))?;
+    writeln!(index, "{start}\t{}", name.to_string_lossy())?;
+    Ok(())
+}
+
+/// Deletes the recordings in `dir` the policy has no room for.
+pub fn apply(dir: &Path, policy: Policy) -> Result<()> {
+    if policy.keep_last.is_none() && policy.max_total.is_none() {
+        return Ok(());
+    }
+    let text = match fs::read_to_string(dir.join(INDEX)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    // A name recorded again counts from its latest start.
+    let mut starts = HashMap::new();
+    for line in text.lines() {
+        if let Some((start, name)) = line.split_once('\t') {
+            if let Ok(start) = start.parse::<i64>() {
+                let latest = starts.entry(name.to_string()).or_insert(start);
+                *latest = start.max(*latest);
+            }
+        }
+    }
+    let mut recordings: Vec<_> = starts
+        .into_iter()
+        .map(|(name, start)| {
+            let files = files_of(dir, &name)?;
+            Ok((start, name, files))
+        })
+        .collect::<Result<_>>()?;
+    recordings.retain(|(_, _, files)| !files.is_empty());
+    recordings.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+
+    let mut total = 0;
+    let mut kept = String::new();
+    for (count, (start, name, files)) in recordings.into_iter().enumerate() {
+        let size: u64 = files
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        total += size;
+        let reason = if policy.keep_last.is_some_and(|keep| count >= keep) {
+            Some("--keep-last")
+        } else if policy.max_total.is_some_and(|max| total > max) && count > 0 {
+            Some("--max-total")
+        } else {
+            None
+        };
+        let Some(reason) = reason else {
+            kept += &format!("{start}\t{name}\n");
+            continue;
+        };
+        info!(
+            "Deleting {name} and {} files next to it, {:.1} MiB, to stay within {reason}",
+            files.len() - 1,
+            size as f64 / (1 << 20) as f64
+        );
+        for path in files {
+            fs::remove_file(path)?;
+        }
+    }
+    // Oldest first, as they were added.
+    let mut lines: Vec<_> = kept.lines().collect();
+    lines.reverse();
+    let index = dir.join(INDEX);
+    let part = index.with_extension("part");
+    fs::write(&part, lines.join("\n") + "\n")?;
+    fs::rename(part, index)?;
+    Ok(())
+}
+
+/// The output `name` in `dir` and the files next to it.
+fn files_of(dir: &Path, name: &str) -> Result<Vec<PathBuf>> {
+    let stem = Path::new(name)
+        .file_stem()
+        .map(|stem| format!("{}.", stem.to_string_lossy()))
+        .unwrap_or_default();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if (file_name == name || file_name.starts_with(&stem)) && entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
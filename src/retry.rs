@@ -0,0 +1,161 @@
+//! Retrying failed requests with exponential backoff.
+//!
+//! Connection problems and truncated transfers are always worth another
+//! try; HTTP errors only if their status code is in the policy, so a 403 or
+//! 404 fails right away instead of after minutes of waiting.
+//!
+//! A server turning requests down because it gets too many, with 429 or a
+//! 503 with `Retry-After`, is waited for as long as it asks instead. Those
+//! waits do not use up the retries, only [`MAX_THROTTLED`] does.
+
+use std::fmt::Display;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result, WrapErr};
+
+use crate::exit::Failure;
+use crate::signals::Stop;
+use crate::{http, style};
+
+/// How long one request waits for a throttling server at most, in total.
+const MAX_THROTTLED: Duration = Duration::from_secs(10 * 60);
+
+/// Requests currently held back on purpose, see [`wait`].
+static WAITING: AtomicUsize = AtomicUsize::new(0);
+
+/// Sleeps for `delay` before a request, which the systemd watchdog does not
+/// take for a hung download, or until `stop` is requested.
+pub fn wait(delay: Duration, stop: &Stop) {
+    WAITING.fetch_add(1, Ordering::SeqCst);
+    let end = Instant::now() + delay;
+    while !stop.requested() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(Duration::from_millis(200)));
+    }
+    WAITING.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Whether a request is held back by [`wait`] right now.
+pub fn waiting() -> bool {
+    WAITING.load(Ordering::SeqCst) > 0
+}
+
+#[derive(Clone, Debug)]
+pub struct Policy {
+    /// Attempts after the first one.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every further one.
+    pub backoff: Duration,
+    pub max_delay: Duration,
+    /// HTTP status codes worth retrying.
+    pub statuses: Vec<u16>,
+    /// Cuts the waits short.
+    pub stop: Stop,
+}
+
+/// The defaults of the command line.
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            statuses: vec![408, 429, 500, 502, 503, 504],
+            stop: Stop::default(),
+        }
+    }
+}
+
+impl Policy {
+    /// Calls `f` until it succeeds, fails for good or the retries run out.
+    ///
+    /// `what` names the request in the messages about failed attempts.
+    pub fn run<T>(&self, what: impl Display, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let (mut attempt, mut throttles, mut throttled) = (0, 0, Duration::ZERO);
+        loop {
+            match f() {
+                Err(e)
+                    if http::is_throttled(&e)
+                        && self.is_retryable(&e)
+                        && throttled < MAX_THROTTLED =>
+                {
+                    let delay = http::retry_after(&e)
+                        .unwrap_or_else(|| self.delay(throttles))
+                        .min(MAX_THROTTLED - throttled);
+                    throttles += 1;
+                    throttled += delay;
+                    warning!(
+                        "{}",
+                        style::warning(format_args!(
+                            "{} throttled by the server (HTTP {}); waiting {:.1}s",
+                            what,
+                            http::status(&e).unwrap_or_default(),
+                            delay.as_secs_f64()
+                        ))
+                    );
+                    self.wait(delay)?;
+                }
+                Err(e) if attempt < self.retries && self.is_retryable(&e) => {
+                    let delay = self.delay(attempt);
+                    attempt += 1;
+                    warning!(
+                        "{}",
+                        style::warning(format_args!(
+                            "{} failed: {}; retry {}/{} in {:.1}s",
+                            what,
+                            e,
+                            attempt,
+                            self.retries,
+                            delay.as_secs_f64()
+                        ))
+                    );
+                    self.wait(delay)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn wait(&self, delay: Duration) -> Result<()> {
+        wait(delay, &self.stop);
+        if self.stop.requested() {
+            return Err(eyre!("Stopped while waiting to retry")).wrap_err(Failure::Interrupted);
+        }
+        Ok(())
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+
+    fn is_retryable(&self, error: &eyre::Report) -> bool {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+                return match e {
+                    ureq::Error::Status(status, _) => self.statuses.contains(status),
+                    ureq::Error::Transport(_) => true,
+                };
+            }
+            #[cfg(any(feature = "http2", feature = "http3"))]
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                return e
+                    .status()
+                    .is_none_or(|status| self.statuses.contains(&status.as_u16()));
+            }
+            // ureq reports unparsable JSON as invalid data, which another
+            // attempt will not fix.
+            if let Some(e) = cause.downcast_ref::<io::Error>() {
+                return e.kind() != io::ErrorKind::InvalidData;
+            }
+        }
+        false
+    }
+}
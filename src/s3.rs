@@ -0,0 +1,313 @@
+//! Uploading the output to S3, or a service speaking its API like MinIO,
+//! for `--filename s3://BUCKET/KEY`.
+//!
+//! The output goes up in parts while it downloads, as a multipart upload,
+//! so it never needs space on the local disk. Credentials come from
+//! `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`, the
+//! region from `AWS_REGION` or `AWS_DEFAULT_REGION`. Requests are signed
+//! with Signature Version 4.
+
+use std::env;
+use std::fmt;
+use std::io::{self, prelude::*};
+use std::time::SystemTime;
+
+use eyre::{eyre, Result};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::target::Sink;
+use crate::{har, hex};
+
+/// Bytes per part; S3 wants at least 5 MiB for all but the last one.
+const PART_SIZE: usize = 8 << 20;
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// An object in a bucket.
+pub struct Location {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl Location {
+    /// `s3://BUCKET/KEY`, or `None` for a local file name.
+    pub fn parse(target: &str) -> Option<Location> {
+        let (bucket, key) = target.strip_prefix("s3://")?.split_once('/')?;
+        Some(Location {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "s3://{}/{}", self.bucket, self.key)
+    }
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    region: String,
+}
+
+impl Credentials {
+    fn from_env() -> Result<Credentials> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let (Some(access_key), Some(secret_key)) =
+            (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY"))
+        else {
+            return Err(eyre!(
+                "Uploading to S3 needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY"
+            ));
+        };
+        Ok(Credentials {
+            access_key,
+            secret_key,
+            session_token: var("AWS_SESSION_TOKEN"),
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| DEFAULT_REGION.to_string()),
+        })
+    }
+}
+
+/// A multipart upload, written to like a file. Dropping it before
+/// [`Sink::finish`] aborts the upload.
+pub struct Upload {
+    agent: ureq::Agent,
+    credentials: Credentials,
+    /// URL of the object, without query.
+    url: String,
+    upload_id: String,
+    /// ETags of the parts uploaded so far.
+    parts: Vec<String>,
+    buf: Vec<u8>,
+    finished: bool,
+}
+
+impl Upload {
+    /// Starts uploading to `location`, on AWS or at `endpoint`.
+    pub fn start(
+        agent: &ureq::Agent,
+        endpoint: Option<&str>,
+        location: &Location,
+    ) -> Result<Upload> {
+        let credentials = Credentials::from_env()?;
+        let key = uri_encode(&location.key, false);
+        // Other services rarely have a DNS name per bucket.
+        let url = match endpoint {
+            Some(endpoint) => format!(
+                "{}/{}/{key}",
+                endpoint.trim_end_matches('/'),
+                uri_encode(&location.bucket, true)
+            ),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{key}",
+                location.bucket, credentials.region
+            ),
+        };
+        let mut upload = Upload {
+            agent: agent.clone(),
+            credentials,
+            url,
+            upload_id: String::new(),
+            parts: Vec::new(),
+            buf: Vec::with_capacity(PART_SIZE),
+            // Nothing to abort yet.
+            finished: true,
+        };
+        let response = upload.send("POST", &[("uploads", "")], b"")?;
+        upload.upload_id = element(&response.into_string()?, "UploadId")
+            .ok_or_else(|| eyre!("S3 did not start the upload"))?
+            .to_string();
+        upload.finished = false;
+        Ok(upload)
+    }
+
+    fn upload_part(&mut self) -> Result<()> {
+        let number = (self.parts.len() + 1).to_string();
+        let upload_id = self.upload_id.clone();
+        let query = [("partNumber", number.as_str()), ("uploadId", &upload_id)];
+        let response = self.send("PUT", &query, &self.buf)?;
+        let etag = response
+            .header("ETag")
+            .ok_or_else(|| eyre!("S3 sent no ETag for part {number}"))?;
+        self.parts.push(etag.to_string());
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Sends a signed request for the object with `query`, which has to be
+    /// sorted by name.
+    fn send(&self, method: &str, query: &[(&str, &str)], body: &[u8]) -> Result<ureq::Response> {
+        let query: Vec<_> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        let query = query.join("&");
+        let url = Url::parse(&format!("{}?{query}", self.url))?;
+        let mut request = self.agent.request(method, url.as_str());
+        for (name, value) in self.sign(method, &url, &query, body) {
+            request = request.set(&name, &value);
+        }
+        match request.send_bytes(body) {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(status, response)) => {
+                let text = response.into_string().unwrap_or_default();
+                let message = element(&text, "Message").unwrap_or(&text);
+                Err(eyre!("S3 answered {method} with {status}: {message}"))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Headers authenticating a request, including the signature.
+    fn sign(&self, method: &str, url: &Url, query: &str, body: &[u8]) -> Vec<(String, String)> {
+        let credentials = &self.credentials;
+        let iso = har::timestamp(SystemTime::now());
+        let time = format!("{}Z", iso[..19].replace(['-', ':'], ""));
+        let date = &time[..8];
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let payload = hex(&Sha256::digest(body));
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload.clone()),
+            ("x-amz-date".to_string(), time.clone()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let signed: Vec<_> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        let signed = signed.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let canonical = format!(
+            "{method}\n{}\n{query}\n{canonical_headers}\n{signed}\n{payload}",
+            url.path()
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", credentials.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{time}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical))
+        );
+        let mut key = hmac(
+            format!("AWS4{}", credentials.secret_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [credentials.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, to_sign.as_bytes()));
+        headers.remove(0);
+        headers.push((
+            "Authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed}, Signature={signature}",
+                credentials.access_key
+            ),
+        ));
+        headers
+    }
+}
+
+impl Sink for Upload {
+    /// Uploads what is left and puts the parts together.
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if !self.buf.is_empty() || self.parts.is_empty() {
+            self.upload_part()?;
+        }
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in self.parts.iter().enumerate() {
+            body += &format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>",
+                index + 1
+            );
+        }
+        body += "</CompleteMultipartUpload>";
+        let upload_id = self.upload_id.clone();
+        let response = self.send("POST", &[("uploadId", &upload_id)], body.as_bytes())?;
+        // Failures after the response started come with status 200.
+        let text = response.into_string()?;
+        if let Some(message) = element(&text, "Message") {
+            return Err(eyre!("S3 could not complete the upload: {message}"));
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= PART_SIZE {
+            self.upload_part().map_err(io::Error::other)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if !self.finished {
+            let upload_id = self.upload_id.clone();
+            if let Err(e) = self.send("DELETE", &[("uploadId", &upload_id)], b"") {
+                warning!("Could not abort the upload to S3: {e:#}");
+            }
+        }
+    }
+}
+
+/// Percent-encodes everything but unreserved characters the way
+/// signatures expect it, slashes only with `encode_slash`.
+fn uri_encode(text: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded += &format!("%{byte:02X}"),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    const BLOCK: usize = 64;
+    let mut block = [0; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(move |b| b ^ byte).collect::<Vec<_>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+/// Text of the first `<name>` element in an XML response.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{name}>"))?;
+    Some(&xml[start..end])
+}
@@ -0,0 +1,528 @@
+//! Recording the events of an iCalendar feed as they go live, for the
+//! `schedule` subcommand.
+//!
+//! Every VEVENT with a start and end time and an event URL in its URL,
+//! LOCATION or DESCRIPTION is recorded, a little before its start. Until
+//! the event is live its page cannot be resolved, so the download is tried
+//! again every [`RETRY`] until the event is over. With `--api-token` among
+//! the options, events by ID are waited for through the live_event API
+//! instead, see liveapi.rs.
+//!
+//! Events going live at the same time are recorded side by side, up to
+//! `--max-recordings` of them, all within one `--limit-rate`. The others
+//! wait for a recording to finish, and so do all while the record directory
+//! has less than `--min-free` left. Which event goes first is decided by
+//! its priority, the PRIORITY of the calendar from 1, the highest, to 9, or
+//! that of the first `--priority` matching its summary; without either it
+//! is 5. Among equals the one starting first goes first.
+//!
+//! Recordings older than `--keep-last` of them, or beyond `--max-total`,
+//! are deleted as new ones finish, see retention.rs.
+//!
+//! A feed given by URL is fetched again every [`REFRESH`], so events added
+//! or moved later are picked up, and the schedule runs until it is
+//! interrupted. A file is read once and the schedule ends with its last
+//! event.
+//!
+//! Times with a time zone are taken as local times of this machine, which
+//! is right as long as the calendar and the machine agree.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use eyre::{eyre, Result, WrapErr};
+use regex::Regex;
+
+use crate::exit::Failure;
+use crate::ratelimit::{Rate, TokenBucket};
+use crate::{liveapi, retention, run_observed, send_notifications, signals, Args};
+
+const RETRY: Duration = Duration::from_secs(30);
+const REFRESH: Duration = Duration::from_secs(15 * 60);
+/// How often running recordings are looked after.
+const TICK: Duration = Duration::from_secs(1);
+/// Priority of events the calendar and `--priority` say nothing about.
+const DEFAULT_PRIORITY: u8 = 5;
+const GIB: f64 = (1u64 << 30) as f64;
+
+#[derive(Clone)]
+pub struct Options {
+    /// ICS file or URL.
+    pub calendar: String,
+    pub referer: String,
+    pub dir: PathBuf,
+    /// How long before its start an event is first tried.
+    pub early: Duration,
+    /// Recordings running at once.
+    pub max_recordings: usize,
+    /// Bandwidth of all recordings together.
+    pub limit_rate: Option<Rate>,
+    /// Bytes to leave free in `dir`; no recording starts below.
+    pub min_free: Option<u64>,
+    pub priorities: Vec<Priority>,
+    pub retention: retention::Policy,
+    /// More arguments for every download.
+    pub options: Vec<String>,
+}
+
+/// `--priority <REGEX>=<N>`, the priority of the events whose summary
+/// matches.
+#[derive(Clone, Debug)]
+pub struct Priority {
+    pattern: Regex,
+    level: u8,
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Priority, String> {
+        let (pattern, level) = text
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected <regex>=<priority>, not {text}"))?;
+        let level = match level.parse() {
+            Ok(level @ 1..=9) => level,
+            _ => return Err(format!("the priority must be 1 to 9, not {level}")),
+        };
+        let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(Priority { pattern, level })
+    }
+}
+
+/// An entry of the calendar.
+#[derive(Clone, Debug)]
+struct Event {
+    uid: String,
+    summary: String,
+    url: String,
+    /// Seconds since the epoch.
+    start: i64,
+    end: i64,
+    /// 1 is the highest, 9 the lowest.
+    priority: u8,
+}
+
+/// A recording running on its own thread.
+struct Recording {
+    summary: String,
+    /// Start of the event.
+    start: i64,
+    output: PathBuf,
+    thread: JoinHandle<Result<()>>,
+}
+
+pub fn run(agent: &ureq::Agent, options: &Options) -> Result<()> {
+    let mut running = Vec::new();
+    let result = schedule(agent, options, &mut running);
+    // Interrupted recordings stop on their own, with their state saved.
+    for recording in running {
+        finish(recording, options)?;
+    }
+    result
+}
+
+fn schedule(agent: &ureq::Agent, options: &Options, running: &mut Vec<Recording>) -> Result<()> {
+    let feed = options.calendar.starts_with("http://") || options.calendar.starts_with("https://");
+    let bucket = options
+        .limit_rate
+        .map(|rate| Arc::new(TokenBucket::new(rate)));
+    let mut recorded = HashSet::new();
+    let mut events = Vec::new();
+    let mut fetched: Option<Instant> = None;
+    let (mut waiting, mut low_on_space) = (0, false);
+    loop {
+        let (done, left): (Vec<_>, Vec<_>) = std::mem::take(running)
+            .into_iter()
+            .partition(|recording| recording.thread.is_finished());
+        *running = left;
+        for recording in done {
+            finish(recording, options)?;
+        }
+
+        let fetched_at = match fetched {
+            Some(at) if !feed || at.elapsed() < REFRESH => at,
+            _ => {
+                let text = if feed {
+                    agent.get(&options.calendar).call()?.into_string()?
+                } else {
+                    std::fs::read_to_string(&options.calendar)
+                        .wrap_err_with(|| format!("Cannot read {}", options.calendar))?
+                };
+                events = parse(&text);
+                for event in &mut events {
+                    let priority = options
+                        .priorities
+                        .iter()
+                        .find(|p| p.pattern.is_match(&event.summary));
+                    if let Some(priority) = priority {
+                        event.priority = priority.level;
+                    }
+                }
+                *fetched.insert(Instant::now())
+            }
+        };
+        let now = unix_now();
+        let early = options.early.as_secs() as i64;
+        let pending: Vec<&Event> = events
+            .iter()
+            .filter(|event| event.end > now && !recorded.contains(&key(event)))
+            .collect();
+        if pending.is_empty() && running.is_empty() && !feed {
+            info!("No more events in {}", options.calendar);
+            return Ok(());
+        }
+
+        let mut ready: Vec<&Event> = pending
+            .iter()
+            .copied()
+            .filter(|event| event.start - early <= now)
+            .collect();
+        ready.sort_by_key(|event| (event.priority, event.start));
+        let space_left = match options.min_free {
+            Some(min_free) => free_space(&options.dir).is_none_or(|free| free >= min_free),
+            None => true,
+        };
+        if !space_left && !low_on_space && !ready.is_empty() {
+            warning!(
+                "Less than {:.1} GiB left in {}, starting no recordings",
+                options.min_free.unwrap_or_default() as f64 / GIB,
+                options.dir.display()
+            );
+        }
+        low_on_space = !space_left;
+        let mut ready = ready.into_iter();
+        while space_left && running.len() < options.max_recordings {
+            let Some(event) = ready.next() else {
+                break;
+            };
+            recorded.insert(key(event));
+            let (event, options, bucket) = (event.clone(), options.clone(), bucket.clone());
+            running.push(Recording {
+                summary: event.summary.clone(),
+                start: event.start,
+                output: output_path(&event, &options),
+                thread: thread::spawn(move || record(&event, &options, bucket)),
+            });
+        }
+        let left = ready.len();
+        if left > waiting {
+            info!("{left} events wait for another recording to finish");
+        }
+        waiting = left;
+
+        if !running.is_empty() || left > 0 {
+            sleep_until(Instant::now() + TICK)?;
+            continue;
+        }
+        let Some(next) = pending.iter().min_by_key(|event| event.start) else {
+            sleep_until(fetched_at + REFRESH)?;
+            continue;
+        };
+        info!(
+            "Next up: {} at {}, {} events scheduled",
+            next.summary,
+            crate::har::timestamp(UNIX_EPOCH + Duration::from_secs(next.start as u64)),
+            pending.len()
+        );
+        let wait = Duration::from_secs((next.start - early - now).max(0) as u64);
+        // Sleep through to the event, or look at the feed again first.
+        let wait = if feed {
+            wait.min(REFRESH.saturating_sub(fetched_at.elapsed()))
+        } else {
+            wait
+        };
+        sleep_until(Instant::now() + wait)?;
+    }
+}
+
+/// Waits for `recording` and makes room for the next; an error means the
+/// schedule was stopped.
+fn finish(recording: Recording, options: &Options) -> Result<()> {
+    let result = recording
+        .thread
+        .join()
+        .map_err(|_| eyre!("Recording {} crashed", recording.summary))?;
+    if recording.output.exists() {
+        retention::add(&options.dir, recording.start, &recording.output)?;
+        retention::apply(&options.dir, options.retention)?;
+    }
+    result
+}
+
+/// Identifies an occurrence, so a moved event is recorded again.
+fn key(event: &Event) -> (String, i64) {
+    (event.uid.clone(), event.start)
+}
+
+/// `<summary> <date>.mp4` in the record directory.
+fn output_path(event: &Event, options: &Options) -> PathBuf {
+    let name: String = event
+        .summary
+        .chars()
+        .map(|c| if r#"/\:*?"<>|"#.contains(c) { '_' } else { c })
+        .collect();
+    let date = crate::har::timestamp(UNIX_EPOCH + Duration::from_secs(event.start as u64));
+    options.dir.join(format!("{name} {}.mp4", &date[..10]))
+}
+
+/// Downloads `event`, trying again until it is live or over.
+fn record(event: &Event, options: &Options, bucket: Option<Arc<TokenBucket>>) -> Result<()> {
+    let filename = output_path(event, options);
+    let mut argv = vec![
+        "vimeo-event-downloader".to_string(),
+        "--url".to_string(),
+        event.url.clone(),
+        "--referer".to_string(),
+        options.referer.clone(),
+        "--filename".to_string(),
+        filename.to_string_lossy().into_owned(),
+    ];
+    argv.extend(options.options.iter().cloned());
+    let mut args = Args::try_parse_from(argv)?;
+    args.shared_rate_limit = bucket;
+    if args.api_token.is_some() && liveapi::event_id(&event.url).is_some() {
+        args.wait_for_live = true;
+    }
+    info!("Recording {} to {}", event.summary, filename.display());
+
+    let started = Instant::now();
+    loop {
+        let mut seen = None;
+        let result = run_observed(&args, &mut |media, video, _| {
+            seen = Some((media.title.clone(), video.duration));
+        });
+        if let Err(e) = &result {
+            if signals::stop_requested() {
+                return result;
+            }
+            // Before it is live the event does not resolve to a video.
+            if seen.is_none() && unix_now() < event.end {
+                warning!(
+                    "{} is not live yet ({e:#}), trying again in {}s",
+                    event.summary,
+                    RETRY.as_secs()
+                );
+                sleep_until(Instant::now() + RETRY)?;
+                continue;
+            }
+            warning!("Recording {} failed: {e:#}", event.summary);
+        }
+        send_notifications(&args, &result, started.elapsed(), seen);
+        return Ok(());
+    }
+}
+
+pub fn sleep_until(deadline: Instant) -> Result<()> {
+    while Instant::now() < deadline {
+        if signals::stop_requested() {
+            return Err(eyre!("Schedule stopped")).wrap_err(Failure::Interrupted);
+        }
+        thread::sleep(Duration::from_millis(200).min(deadline - Instant::now()));
+    }
+    Ok(())
+}
+
+/// Bytes available to this user on the file system of `dir`.
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only writes the struct it is given.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// The events of an iCalendar text that can be recorded.
+fn parse(text: &str) -> Vec<Event> {
+    // Long lines are folded, continuing with a space or tab.
+    let unfolded = text
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut events = Vec::new();
+    let mut properties: Option<Vec<(String, String, String)>> = None;
+    for line in unfolded.lines() {
+        match line.trim_end() {
+            "BEGIN:VEVENT" => properties = Some(Vec::new()),
+            "END:VEVENT" => events.extend(properties.take().and_then(|p| event(&p))),
+            line => {
+                let (Some(properties), Some((name, value))) =
+                    (&mut properties, line.split_once(':'))
+                else {
+                    continue;
+                };
+                let (name, params) = name.split_once(';').unwrap_or((name, ""));
+                properties.push((
+                    name.to_ascii_uppercase(),
+                    params.to_string(),
+                    value.to_string(),
+                ));
+            }
+        }
+    }
+    events
+}
+
+fn event(properties: &[(String, String, String)]) -> Option<Event> {
+    let get = |name: &str| {
+        properties
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, params, value)| (params.as_str(), value.as_str()))
+    };
+    let text = |name: &str| get(name).map(|(_, value)| unescape(value));
+    let start = get("DTSTART").and_then(|(params, value)| time(params, value))?;
+    let end = match get("DTEND").and_then(|(params, value)| time(params, value)) {
+        Some(end) => end,
+        None => start + duration(get("DURATION")?.1)?,
+    };
+    let urls = Regex::new(r#"https?://[^\s"<>]+"#).unwrap();
+    let candidates: Vec<String> = ["URL", "LOCATION", "DESCRIPTION"]
+        .into_iter()
+        .filter_map(text)
+        .flat_map(|text| {
+            urls.find_iter(&text)
+                .map(|m| m.as_str().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let url = candidates
+        .iter()
+        .find(|url| url.contains("vimeo.com/"))
+        .or(candidates.first())?
+        .clone();
+    Some(Event {
+        uid: text("UID").unwrap_or_default(),
+        summary: text("SUMMARY").unwrap_or_else(|| "event".to_string()),
+        url,
+        start,
+        end,
+        // 0 leaves it undefined.
+        priority: match text("PRIORITY").and_then(|p| p.trim().parse().ok()) {
+            Some(priority @ 1..=9) => priority,
+            _ => DEFAULT_PRIORITY,
+        },
+    })
+}
+
+fn unescape(value: &str) -> String {
+    let mut text = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(other) => text.push(other),
+            None => {}
+        }
+    }
+    text
+}
+
+/// A DATE-TIME value as seconds since the epoch; dates without a time
+/// give `None`.
+fn time(params: &str, value: &str) -> Option<i64> {
+    let (date, clock) = value.split_once('T')?;
+    let number = |text: &str, range: std::ops::Range<usize>| -> Option<i64> {
+        text.get(range)?.parse().ok()
+    };
+    let (year, month, day) = (
+        number(date, 0..4)?,
+        number(date, 4..6)?,
+        number(date, 6..8)?,
+    );
+    let (hour, minute, second) = (
+        number(clock, 0..2)?,
+        number(clock, 2..4)?,
+        number(clock, 4..6)?,
+    );
+    let utc =
+        clock.ends_with('Z') || params.contains("TZID=UTC") || params.contains("TZID=Etc/UTC");
+    if utc {
+        let days = days_from_civil(year, month, day);
+        Some(days * 86400 + hour * 3600 + minute * 60 + second)
+    } else {
+        local_time(year, month, day, hour, minute, second)
+    }
+}
+
+/// Days since 1970-01-01, after Howard Hinnant.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(unix)]
+fn local_time(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> Option<i64> {
+    // SAFETY: mktime only reads and normalizes the struct it is given.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = (year - 1900) as libc::c_int;
+    tm.tm_mon = (month - 1) as libc::c_int;
+    tm.tm_mday = day as libc::c_int;
+    tm.tm_hour = hour as libc::c_int;
+    tm.tm_min = minute as libc::c_int;
+    tm.tm_sec = second as libc::c_int;
+    tm.tm_isdst = -1;
+    let time = unsafe { libc::mktime(&mut tm) };
+    (time != -1).then_some(time as i64)
+}
+
+#[cfg(not(unix))]
+fn local_time(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> Option<i64> {
+    // Without the local time zone at hand, UTC is the best guess.
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// An iCalendar DURATION like `PT1H30M` in seconds.
+fn duration(value: &str) -> Option<i64> {
+    let value = value.strip_prefix('+').unwrap_or(value).strip_prefix('P')?;
+    let mut total = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            unit => {
+                let n: i64 = std::mem::take(&mut number).parse().ok()?;
+                total += n * match unit {
+                    'W' => 7 * 86400,
+                    'D' => 86400,
+                    'H' => 3600,
+                    'M' => 60,
+                    'S' => 1,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(total)
+}
@@ -0,0 +1,109 @@
+//! Reporting to systemd when running as a `Type=notify` service.
+//!
+//! Readiness is signaled once the download starts, the status line shows the
+//! progress, and with `WatchdogSec=` set the watchdog is only fed while the
+//! download makes progress, so a hung download gets restarted. Bytes of a
+//! segment still arriving count as progress, and so do the waits of the
+//! retry policy, e.g. for a server throttling requests for minutes.
+
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::stats::Stats;
+use crate::{keys, retry, VideoInfo};
+
+struct Notifier {
+    socket: UnixDatagram,
+}
+
+impl Notifier {
+    fn from_env() -> Option<Notifier> {
+        let path = env::var("NOTIFY_SOCKET").ok()?;
+        let socket = UnixDatagram::unbound().ok()?;
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name).ok()?;
+            socket.connect_addr(&addr).ok()?;
+            return Some(Notifier { socket });
+        }
+        socket.connect(path).ok()?;
+        Some(Notifier { socket })
+    }
+
+    fn send(&self, state: &str) {
+        let _ = self.socket.send(state.as_bytes());
+    }
+}
+
+pub struct Supervisor {
+    notifier: Arc<Notifier>,
+    done: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Starts reporting on the download of `video`, if systemd asked for it.
+pub fn supervise(video: &VideoInfo, stats: Arc<Stats>) -> Option<Supervisor> {
+    let notifier = Arc::new(Notifier::from_env()?);
+    let watchdog = env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse().ok())
+        .map(Duration::from_micros)
+        // Only meant for this process, not for children like the player.
+        .filter(|_| {
+            env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string())
+        });
+    notifier.send("READY=1");
+
+    let done = Arc::new(AtomicBool::new(false));
+    let name = video.id.trim_matches('"').to_string();
+    let segments = video.segments.len();
+    let handle = {
+        let notifier = notifier.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            // Pinging at half the timeout, as systemd recommends.
+            let interval = watchdog.map_or(Duration::from_secs(5), |w| w / 2);
+            let mut last = (Instant::now(), stats.progress(), stats.received());
+            while !done.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(250).min(interval));
+                if last.0.elapsed() < interval {
+                    continue;
+                }
+                let progress = stats.progress();
+                notifier.send(&format!(
+                    "STATUS=Downloading {name}: {} of {segments} segments, {:.1} MiB",
+                    progress.0,
+                    progress.1 as f64 / (1 << 20) as f64
+                ));
+                let received = stats.received();
+                let alive =
+                    progress != last.1 || received != last.2 || keys::paused() || retry::waiting();
+                if watchdog.is_some() && alive {
+                    notifier.send("WATCHDOG=1");
+                }
+                last = (Instant::now(), progress, received);
+            }
+        })
+    };
+    Some(Supervisor {
+        notifier,
+        done,
+        handle: Some(handle),
+    })
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        self.notifier.send("STOPPING=1");
+    }
+}
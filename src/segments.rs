@@ -0,0 +1,149 @@
+//! Storing init and media segments as individual files.
+//!
+//! A segments directory contains `master.json` (the manifest as fetched,
+//! with only the fields of segments that are used), `init.mp4`, one
+//! numbered `NNNNN.m4s` file per media segment and the checksums of the
+//! segments in a [`ledger`](crate::ledger). Files are only
+//! renamed into place once complete, so a partially filled directory never
+//! contains truncated segments.
+
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+
+use eyre::{eyre, Result, WrapErr};
+use ureq::serde_json;
+use url::Url;
+use vimeo_extract::Manifest;
+
+use crate::exit::Failure;
+use crate::fetch::Fetcher;
+use crate::ledger::{self, Ledger};
+use crate::{sha256_hex, Track, VideoInfo};
+
+pub const MANIFEST: &str = "master.json";
+pub const INIT_SEGMENT: &str = "init.mp4";
+
+/// File name of the media segment with the given zero-based index.
+pub fn segment_name(index: usize) -> String {
+    format!("{:05}.m4s", index + 1)
+}
+
+pub fn save(dir: &Path, master: &Manifest, video: &VideoInfo, fetcher: &Fetcher) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    write_atomic(&dir.join(MANIFEST), |f| {
+        serde_json::to_writer_pretty(f, master)?;
+        Ok(())
+    })?;
+    write_atomic(&dir.join(INIT_SEGMENT), |f| {
+        f.write_all(&video.init_segment)?;
+        Ok(())
+    })?;
+
+    let ledger = Ledger::create(&dir.join(ledger::FILE_NAME))?;
+    let url = Url::parse(&video.base_url)?;
+    let sum: u64 = video.segments.iter().map(|s| s.size).sum();
+    let bar = fetcher.progress_bar(indicatif::ProgressBar::new(sum));
+
+    let mut offset = video.init_segment.len() as u64;
+    let mut buf = Vec::new();
+    for (index, segment) in video.segments.iter().enumerate() {
+        buf.clear();
+        let count = fetcher.fetch(&url, video, segment, &mut buf)?;
+        write_atomic(&dir.join(segment_name(index)), |f| {
+            f.write_all(&buf)?;
+            Ok(())
+        })?;
+        ledger.record(index, offset, &buf, &segment.path)?;
+        offset += count;
+        bar.inc(count - 1);
+    }
+
+    bar.finish();
+    fetcher.finish()?;
+
+    Ok(())
+}
+
+fn write_atomic(path: &Path, write: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let mut part = PathBuf::from(path);
+    part.set_extension("part");
+    let mut file = File::create(&part)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    fs::rename(&part, path)?;
+    Ok(())
+}
+
+/// Concatenates a segments directory into `out`.
+///
+/// The rendition is identified by matching `init.mp4` against the manifest,
+/// and every media segment must be present with the expected size, and the
+/// recorded checksum if there is a ledger, before anything is written.
+pub fn assemble(dir: &Path, out: &mut impl Write) -> Result<()> {
+    let manifest = BufReader::new(File::open(dir.join(MANIFEST))?);
+    let master: Manifest = serde_json::from_reader(manifest)?;
+    let dir_url = Url::from_directory_path(fs::canonicalize(dir)?)
+        .map_err(|_| eyre!("Invalid segments directory {}", dir.display()))?;
+    let videos = vimeo_extract::video_infos(dir_url.as_str(), &master)?;
+    let init_segment = fs::read(dir.join(INIT_SEGMENT))?;
+    let video = videos
+        .iter()
+        .find(|v| v.init_segment == init_segment)
+        .ok_or(eyre!(
+            "{INIT_SEGMENT} does not match any video in {MANIFEST}!"
+        ))?;
+    info!("Assembling {}", video);
+
+    let mut problems = Vec::new();
+    for (index, segment) in video.segments.iter().enumerate() {
+        let name = segment_name(index);
+        match fs::metadata(dir.join(&name)) {
+            Ok(meta) if meta.len() == segment.size + 1 => {}
+            Ok(meta) => problems.push(format!(
+                "{name} has {} bytes, expected {}",
+                meta.len(),
+                segment.size + 1
+            )),
+            Err(_) => problems.push(format!("{name} is missing")),
+        }
+    }
+    let ledger_path = dir.join(ledger::FILE_NAME);
+    if ledger_path.exists() {
+        for entry in ledger::load(&ledger_path)? {
+            let name = segment_name(entry.index);
+            match fs::read(dir.join(&name)) {
+                Ok(data) if sha256_hex(&data) != entry.sha256 => {
+                    problems.push(format!("{name} does not match its checksum"))
+                }
+                _ => {}
+            }
+        }
+    }
+    let extra = segment_name(video.segments.len());
+    if dir.join(&extra).exists() {
+        problems.push(format!(
+            "{extra} exists but the video only has {} segments",
+            video.segments.len()
+        ));
+    }
+    if !problems.is_empty() {
+        return Err(eyre!(
+            "Segments directory is incomplete or corrupt:\n  {}",
+            problems.join("\n  ")
+        ))
+        .wrap_err(Failure::Verification);
+    }
+
+    out.write_all(&init_segment)?;
+    let bar = indicatif::ProgressBar::new(video.output_len());
+    bar.inc(init_segment.len() as u64);
+    for index in 0..video.segments.len() {
+        let mut file = File::open(dir.join(segment_name(index)))?;
+        let count = io::copy(&mut file, out)?;
+        bar.inc(count);
+    }
+    bar.finish();
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Minimal HTTP server exposing the output file while it is being written.
+//!
+//! The full length of the recording is known before the first segment
+//! arrives, so clients are told the final size up front and reads beyond the
+//! bytes written so far simply wait for the download to catch up.
+
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader, SeekFrom};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use eyre::{eyre, Result};
+
+use crate::signals;
+
+pub struct Server {
+    handle: JoinHandle<()>,
+}
+
+impl Server {
+    /// Binds `addr` and serves `path`, which will eventually be `len` bytes long.
+    pub fn start(addr: &str, path: &Path, len: u64) -> Result<Server> {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| eyre!("Could not listen on {addr}: {e}"))?;
+        info!("Serving on http://{}/", listener.local_addr()?);
+        let path = path.to_path_buf();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let path = path.clone();
+                thread::spawn(move || {
+                    // Clients hanging up mid-transfer is routine for players
+                    // that seek around, so errors are not reported.
+                    let _ = handle_connection(stream, &path, len);
+                });
+            }
+        });
+        Ok(Server { handle })
+    }
+
+    /// Keeps serving until the process is interrupted.
+    pub fn wait(self) {
+        info!("Download complete, still serving. Press Ctrl+C to stop.");
+        while !signals::stop_requested() && !self.handle.is_finished() {
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, path: &Path, len: u64) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut out = stream;
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    if method != "GET" && method != "HEAD" {
+        return write!(
+            out,
+            "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, HEAD\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    let (start, end, status) = match range.as_deref().map(|r| parse_range(r, len)) {
+        None => (0, len, "200 OK"),
+        Some(Some((start, end))) => (start, end, "206 Partial Content"),
+        Some(None) => {
+            return write!(
+                out,
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{len}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+        }
+    };
+
+    write!(
+        out,
+        "HTTP/1.1 {status}\r\nContent-Type: video/mp4\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n",
+        end - start
+    )?;
+    if status.starts_with("206") {
+        write!(out, "Content-Range: bytes {}-{}/{len}\r\n", start, end - 1)?;
+    }
+    write!(out, "Connection: close\r\n\r\n")?;
+
+    if method == "GET" {
+        send_range(&mut out, path, start, end)?;
+    }
+    out.flush()
+}
+
+/// Parses a single `bytes=` range into a half-open interval.
+fn parse_range(header: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = header.strip_prefix("bytes=")?;
+    // Multiple ranges are answered with the first one only.
+    let spec = spec.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start, end) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len)
+        }
+        (start, "") => (start.parse().ok()?, len),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.saturating_add(1).min(len),
+        ),
+    };
+    if start >= end || start >= len {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn send_range(out: &mut impl Write, path: &Path, start: u64, end: u64) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut pos = start;
+    let mut buf = vec![0; 64 * 1024];
+    while pos < end {
+        let want = buf.len().min((end - pos) as usize);
+        let count = file.read(&mut buf[..want])?;
+        if count == 0 {
+            // Not downloaded yet, wait for the file to grow.
+            thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        out.write_all(&buf[..count])?;
+        pos += count as u64;
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Uploading the output to a server reachable over SSH, for
+//! `--filename sftp://[USER@]HOST[:PORT]/PATH`.
+//!
+//! There is no SSH implementation built in: the output is piped through
+//! the `ssh` program into `cat` on the server, so keys, agents, known hosts
+//! and `~/.ssh/config` all work as they do for the user. PATH is absolute,
+//! unless it starts with `/~/`, which stands for the home directory. The
+//! output is written to `PATH.part` and renamed once it is complete.
+
+use std::io::{self, prelude::*};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use eyre::{eyre, Result};
+use url::Url;
+
+use crate::target::Sink;
+
+const SSH: &str = "ssh";
+
+/// An upload in progress. Dropping it before [`Sink::finish`] stops it and
+/// removes the partial file.
+pub struct Upload {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// `[user@]host` and the options for `ssh`.
+    destination: Vec<String>,
+    path: String,
+    finished: bool,
+}
+
+impl Upload {
+    /// Starts uploading to `url`, an `sftp://` URL.
+    pub fn start(url: &Url) -> Result<Upload> {
+        let host = url.host_str().ok_or_else(|| eyre!("{url} names no host"))?;
+        let mut destination = Vec::new();
+        if let Some(port) = url.port() {
+            destination.extend(["-p".to_string(), port.to_string()]);
+        }
+        destination.push(match url.username() {
+            "" => host.to_string(),
+            user => format!("{}@{host}", decode(user)),
+        });
+        let path = decode(url.path());
+        let path = match path.strip_prefix("/~/") {
+            Some(relative) => relative.to_string(),
+            None => path,
+        };
+        let part = quote(&format!("{path}.part"));
+        let script = match path.rsplit_once('/') {
+            Some((directory, _)) if !directory.is_empty() => {
+                format!("mkdir -p {} && cat > {part}", quote(directory))
+            }
+            _ => format!("cat > {part}"),
+        };
+        let mut child = Command::new(SSH)
+            .args(&destination)
+            .arg(script)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("Could not run {SSH}: {e}"))?;
+        let stdin = child.stdin.take();
+        Ok(Upload {
+            child,
+            stdin,
+            destination,
+            path,
+            finished: false,
+        })
+    }
+
+    /// Runs `script` on the server.
+    fn run(&self, script: &str) -> Result<()> {
+        let status = Command::new(SSH)
+            .args(&self.destination)
+            .arg(script)
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|e| eyre!("Could not run {SSH}: {e}"))?;
+        if !status.success() {
+            return Err(eyre!("{SSH} {script} failed ({status})"));
+        }
+        Ok(())
+    }
+
+    fn part(&self) -> String {
+        quote(&format!("{}.part", self.path))
+    }
+}
+
+impl Sink for Upload {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(eyre!("Uploading with {SSH} failed ({status})"));
+        }
+        self.run(&format!("mv {} {}", self.part(), quote(&self.path)))?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            if let Err(e) = self.run(&format!("rm -f {}", self.part())) {
+                warning!("Could not remove the partial upload: {e:#}");
+            }
+        }
+    }
+}
+
+fn decode(text: &str) -> String {
+    percent_encoding::percent_decode_str(text)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// `text` as a single word for the server's shell.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
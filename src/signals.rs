@@ -0,0 +1,98 @@
+//! Reacting to signals between segments.
+//!
+//! Ctrl+C and SIGTERM stop the download once the segments in flight are
+//! written, so the ledger and cache stay consistent; a second one ends the
+//! process right away. Either way the exit code is that of a shell for the
+//! signal, 130 for Ctrl+C and 143 for SIGTERM. On Unix, SIGUSR1 prints the progress so far, for
+//! downloads running under systemd or in a container without a terminal.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use eyre::Result;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::{flag, low_level};
+
+struct Flags {
+    stop: Arc<AtomicBool>,
+    /// The stop came from SIGTERM.
+    terminated: Arc<AtomicBool>,
+    progress: Arc<AtomicBool>,
+}
+
+static FLAGS: OnceLock<Flags> = OnceLock::new();
+
+fn flags() -> &'static Flags {
+    FLAGS.get_or_init(|| Flags {
+        stop: Arc::new(AtomicBool::new(false)),
+        terminated: Arc::new(AtomicBool::new(false)),
+        progress: Arc::new(AtomicBool::new(false)),
+    })
+}
+
+pub fn install() -> Result<()> {
+    let flags = flags();
+    for (signal, code) in [(SIGINT, 130), (SIGTERM, 143)] {
+        // Registered first, so it only sees the flag set by earlier signals.
+        let stop = flags.stop.clone();
+        // SAFETY: an atomic load, tcsetattr and _exit are all safe to call
+        // in a signal handler.
+        unsafe {
+            low_level::register(signal, move || {
+                if stop.load(Ordering::SeqCst) {
+                    // The guard of the hotkeys is not dropped on the way out.
+                    #[cfg(unix)]
+                    crate::keys::restore_terminal();
+                    low_level::exit(code);
+                }
+            })?;
+        }
+        flag::register(signal, flags.stop.clone())?;
+    }
+    flag::register(SIGTERM, flags.terminated.clone())?;
+    #[cfg(unix)]
+    flag::register(signal_hook::consts::SIGUSR1, flags.progress.clone())?;
+    Ok(())
+}
+
+pub fn stop_requested() -> bool {
+    flags().stop.load(Ordering::SeqCst)
+}
+
+/// Whether SIGTERM, rather than Ctrl+C, stopped the download.
+pub fn terminated() -> bool {
+    flags().terminated.load(Ordering::SeqCst)
+}
+
+/// Stops the download as if Ctrl+C was pressed.
+#[cfg(any(feature = "tui", feature = "gui"))]
+pub fn request_stop() {
+    flags().stop.store(true, Ordering::SeqCst);
+}
+
+/// Lets downloads run again after [`request_stop`].
+#[cfg(feature = "gui")]
+pub fn clear_stop() {
+    flags().stop.store(false, Ordering::SeqCst);
+}
+
+/// Stop flag of a single download, for library callers running several.
+/// Signals still stop all of them.
+#[derive(Clone, Debug, Default)]
+pub struct Stop(Arc<AtomicBool>);
+
+impl Stop {
+    #[cfg(any(feature = "ffi", test))]
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst) || stop_requested()
+    }
+}
+
+/// Whether SIGUSR1 arrived since the last call.
+pub fn take_progress_request() -> bool {
+    flags().progress.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,208 @@
+//! Preview sprites: thumbnails spread over the recording, tiled into one
+//! image, with a WebVTT track telling web players which tile shows when.
+//! Contact sheets are the same with bigger, labeled tiles, for people.
+//!
+//! Frames are taken at segment starts, which are keyframes, so ffmpeg
+//! decodes a single frame for each instead of reading the whole recording.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use eyre::{eyre, Result};
+
+use crate::postprocess::{Output, PostProcessor};
+use crate::{mux, Track, VideoInfo};
+
+/// Width of a preview sprite tile in pixels.
+const SPRITE_WIDTH: u64 = 160;
+/// Most tiles in a row of a preview sprite.
+const SPRITE_COLUMNS: usize = 10;
+/// Frames on a contact sheet, enough to tell the parts of a long event
+/// apart.
+const SHEET_FRAMES: usize = 24;
+const SHEET_WIDTH: u64 = 320;
+const SHEET_COLUMNS: usize = 4;
+
+/// A grid of frames and where they are taken from.
+pub struct Sprite {
+    /// Second of each frame, and the span of the recording it stands for.
+    frames: Vec<(f64, f64, f64)>,
+    columns: usize,
+    width: u64,
+    height: u64,
+    /// Whether each tile shows its timestamp.
+    labels: bool,
+}
+
+impl Sprite {
+    /// `count` small tiles for a web player.
+    pub fn preview(video: &VideoInfo, count: usize) -> Sprite {
+        Sprite::new(video, count, SPRITE_COLUMNS, SPRITE_WIDTH, false)
+    }
+
+    /// Labeled tiles summarizing the recording.
+    pub fn contact_sheet(video: &VideoInfo) -> Sprite {
+        Sprite::new(video, SHEET_FRAMES, SHEET_COLUMNS, SHEET_WIDTH, true)
+    }
+
+    fn new(
+        video: &VideoInfo,
+        count: usize,
+        max_columns: usize,
+        width: u64,
+        labels: bool,
+    ) -> Sprite {
+        let duration = video.duration;
+        let frames = (0..count)
+            .map(|index| {
+                let from = duration * index as f64 / count as f64;
+                let to = duration * (index + 1) as f64 / count as f64;
+                (keyframe(video, (from + to) / 2.0), from, to)
+            })
+            .collect();
+        // Even heights, which every encoder takes.
+        let height = (width * video.height / video.width.max(1) + 1) & !1;
+        Sprite {
+            frames,
+            columns: count.min(max_columns),
+            width,
+            height,
+            labels,
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.frames.len().div_ceil(self.columns)
+    }
+
+    /// Cues pointing into `image` with a media fragment per tile.
+    fn vtt(&self, image: &str) -> String {
+        let mut vtt = String::from("WEBVTT\n");
+        for (index, &(_, from, to)) in self.frames.iter().enumerate() {
+            let x = (index % self.columns) as u64 * self.width;
+            let y = (index / self.columns) as u64 * self.height;
+            let _ = write!(
+                vtt,
+                "\n{} --> {}\n{image}#xywh={x},{y},{},{}\n",
+                timestamp(from),
+                timestamp(to),
+                self.width,
+                self.height
+            );
+        }
+        vtt
+    }
+}
+
+/// Start of the segment playing at `second`.
+fn keyframe(video: &VideoInfo, second: f64) -> f64 {
+    video
+        .segments()
+        .iter()
+        .rev()
+        .find(|segment| segment.start <= second && segment.end > segment.start)
+        .map_or(second, |segment| segment.start)
+}
+
+pub fn timestamp(second: f64) -> String {
+    let millis = (second * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Writes the tiles of `sprite`, taken from `input`, into the JPEG at
+/// `output`.
+pub fn render(input: &Path, sprite: &Sprite, output: &Path) -> Result<()> {
+    let dir = output.with_extension("frames");
+    fs::create_dir_all(&dir)?;
+    let result = render_frames(input, sprite, &dir).and_then(|_| {
+        let part = mux::part_path(output);
+        let mut command = Command::new(mux::FFMPEG);
+        command.args(["-v", "error", "-y", "-i"]);
+        command.arg(dir.join("%d.jpg"));
+        command
+            .arg("-vf")
+            .arg(format!("tile={}x{}", sprite.columns, sprite.rows()));
+        command.args(["-frames:v", "1", "-f", "mjpeg"]).arg(&part);
+        mux::finish(command, &part, output)
+    });
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Extracts the frames of `sprite` as `<dir>/<index>.jpg`, scaled to the
+/// size of a tile.
+fn render_frames(input: &Path, sprite: &Sprite, dir: &Path) -> Result<()> {
+    for (index, &(second, ..)) in sprite.frames.iter().enumerate() {
+        let frame = dir.join(format!("{index}.jpg"));
+        let part = mux::part_path(&frame);
+        let mut command = Command::new(mux::FFMPEG);
+        command.args(["-v", "error", "-y", "-ss", &second.to_string(), "-i"]);
+        command.arg(input);
+        let mut filter = format!("scale={}:{}", sprite.width, sprite.height);
+        if sprite.labels {
+            // Colons end an option, and the graph and the filter each take
+            // away one level of escaping.
+            let label = timestamp(second)[..8].replace(':', r"\\:");
+            let _ = write!(
+                filter,
+                ",drawtext=text={label}:x=6:y=h-th-6:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=3"
+            );
+        }
+        command.arg("-vf").arg(filter);
+        command.args(["-frames:v", "1", "-f", "mjpeg"]).arg(&part);
+        mux::finish(command, &part, &frame)?;
+    }
+    Ok(())
+}
+
+/// Writes `<name>.sprite.jpg` and `<name>.sprite.vtt` next to the output.
+pub struct PreviewSprite(pub Sprite);
+
+impl PostProcessor for PreviewSprite {
+    fn name(&self) -> &'static str {
+        "preview-sprite"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let image = output.path.with_extension("sprite.jpg");
+        let vtt = output.path.with_extension("sprite.vtt");
+        info!(
+            "Rendering {} thumbnails into {}",
+            self.0.frames.len(),
+            image.display()
+        );
+        render(&output.path, &self.0, &image)?;
+        let name = image
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| eyre!("{} is no valid file name", image.display()))?;
+        fs::write(&vtt, self.0.vtt(name))?;
+        output.companions.extend([image, vtt]);
+        Ok(())
+    }
+}
+
+/// Writes `<name>.contact.jpg` next to the output.
+pub struct ContactSheet(pub Sprite);
+
+impl PostProcessor for ContactSheet {
+    fn name(&self) -> &'static str {
+        "contact-sheet"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let image = output.path.with_extension("contact.jpg");
+        info!("Rendering a contact sheet into {}", image.display());
+        render(&output.path, &self.0, &image)?;
+        output.companions.push(image);
+        Ok(())
+    }
+}
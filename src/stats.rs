@@ -0,0 +1,266 @@
+//! Numbers about a run, for finding out why it was slow.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use eyre::Result;
+use ureq::serde_json::{self, json};
+
+use crate::gaps::{self, Gap};
+use crate::{Segment, Track, VideoInfo};
+
+/// Window the peak throughput is measured over.
+const PEAK_WINDOW: Duration = Duration::from_secs(1);
+
+/// A segment fetched over the network.
+pub struct SegmentRecord {
+    pub path: String,
+    /// Host the segment came from, which identifies the CDN.
+    pub host: String,
+    pub bytes: u64,
+    /// Since the start of the run.
+    pub started: Duration,
+    pub elapsed: Duration,
+    pub attempts: u32,
+}
+
+pub struct Stats {
+    start: Instant,
+    segments: Mutex<Vec<SegmentRecord>>,
+    cached: AtomicUsize,
+    /// Requests the server turned down as too many.
+    throttled: AtomicU32,
+    /// Bytes received, also of segments not complete yet.
+    received: AtomicU64,
+    missing: Mutex<Vec<Gap>>,
+}
+
+pub struct Summary {
+    pub bytes: u64,
+    /// From the first segment request until the last segment arrived.
+    pub wall_time: Duration,
+    /// Bytes per second.
+    pub average_throughput: f64,
+    pub peak_throughput: f64,
+    pub retries: u32,
+    pub throttled: u32,
+    pub segments_per_cdn: BTreeMap<String, usize>,
+    pub cached_segments: usize,
+    /// Bits per second of the video, from its size and duration.
+    pub effective_bitrate: f64,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            start: Instant::now(),
+            segments: Mutex::new(Vec::new()),
+            cached: AtomicUsize::new(0),
+            throttled: AtomicU32::new(0),
+            received: AtomicU64::new(0),
+            missing: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Time since the start of the run, for [`SegmentRecord::started`].
+    pub fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn record(&self, record: SegmentRecord) {
+        self.segments.lock().unwrap().push(record);
+    }
+
+    pub fn record_cached(&self) {
+        self.cached.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn record_throttled(&self) {
+        self.throttled.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// How often the server turned requests down as too many.
+    pub fn throttled(&self) -> u32 {
+        self.throttled.load(Ordering::SeqCst)
+    }
+
+    pub fn record_received(&self, bytes: u64) {
+        self.received.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    /// Bytes received so far, which tells a slow segment from a hung one.
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::SeqCst)
+    }
+
+    /// Notes that `segment` of `track` is left out of the output.
+    pub fn record_missing(&self, track: &dyn Track, segment: &Segment) {
+        self.missing.lock().unwrap().push(Gap {
+            track: track.id().trim_matches('"').to_string(),
+            start: segment.start,
+            end: segment.end,
+        });
+    }
+
+    /// Forgets the segments of `track` left out between `start` and `end`,
+    /// once that span is made up for otherwise.
+    pub fn remove_missing(&self, track: &str, start: f64, end: f64) {
+        self.missing
+            .lock()
+            .unwrap()
+            .retain(|gap| gap.track != track || gap.start < start || gap.end > end);
+    }
+
+    /// The parts of the recording left out, adjacent ones merged.
+    pub fn gaps(&self) -> Vec<Gap> {
+        gaps::merge(&self.missing.lock().unwrap())
+    }
+
+    pub fn summary(&self, track: &dyn Track) -> Summary {
+        let segments = self.segments.lock().unwrap();
+        let bytes = segments.iter().map(|s| s.bytes).sum();
+        let first = segments.iter().map(|s| s.started).min();
+        let last = segments.iter().map(|s| s.started + s.elapsed).max();
+        let wall_time = match (first, last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        };
+        let average_throughput = bytes as f64 / wall_time.as_secs_f64().max(1e-6);
+
+        // Bytes are counted in the window their segment finished in.
+        let mut windows = BTreeMap::new();
+        for segment in segments.iter() {
+            let window = (segment.started + segment.elapsed).as_nanos() / PEAK_WINDOW.as_nanos();
+            *windows.entry(window).or_insert(0) += segment.bytes;
+        }
+        let peak_window = windows.values().copied().max().unwrap_or(0);
+        // Runs shorter than a window have no full one to measure.
+        let peak_throughput =
+            (peak_window as f64 / PEAK_WINDOW.as_secs_f64()).max(average_throughput);
+
+        let mut segments_per_cdn = BTreeMap::new();
+        for segment in segments.iter() {
+            *segments_per_cdn.entry(segment.host.clone()).or_insert(0) += 1;
+        }
+
+        Summary {
+            bytes,
+            wall_time,
+            average_throughput,
+            peak_throughput,
+            retries: segments.iter().map(|s| s.attempts - 1).sum(),
+            throttled: self.throttled(),
+            segments_per_cdn,
+            cached_segments: self.cached.load(Ordering::SeqCst),
+            effective_bitrate: track.output_len() as f64 * 8.0 / track.duration(),
+        }
+    }
+
+    /// Segments finished, fetched or from the cache, and bytes fetched.
+    pub fn progress(&self) -> (usize, u64) {
+        let segments = self.segments.lock().unwrap();
+        let bytes = segments.iter().map(|s| s.bytes).sum();
+        (segments.len() + self.cached.load(Ordering::SeqCst), bytes)
+    }
+
+    /// Prints how far the download of `track` got.
+    pub fn print_progress(&self, track: &dyn Track) {
+        let (done, bytes) = self.progress();
+        let summary = self.summary(track);
+        let throttled = match summary.throttled {
+            0 => String::new(),
+            count => format!(", throttled {count} times"),
+        };
+        info!(
+            "Progress: {} of {} segments, {} bytes in {:.1}s, {:.1} KiB/s, {} retries{}",
+            done,
+            track.segments().len(),
+            bytes,
+            self.now().as_secs_f64(),
+            summary.average_throughput / 1024.0,
+            summary.retries,
+            throttled
+        );
+    }
+
+    pub fn print_summary(&self, video: &VideoInfo) {
+        let summary = self.summary(video);
+        info!(
+            "Downloaded {} bytes in {:.1}s, {:.1} KiB/s on average, {:.1} KiB/s peak",
+            summary.bytes,
+            summary.wall_time.as_secs_f64(),
+            summary.average_throughput / 1024.0,
+            summary.peak_throughput / 1024.0
+        );
+        info!("Retries: {}", summary.retries);
+        if summary.throttled > 0 {
+            info!("Requests throttled by the server: {}", summary.throttled);
+        }
+        for (cdn, count) in &summary.segments_per_cdn {
+            info!("Segments from {}: {}", cdn, count);
+        }
+        if summary.cached_segments > 0 {
+            info!("Segments from the cache: {}", summary.cached_segments);
+        }
+        info!(
+            "Effective bitrate: {:.1} kbit/s",
+            summary.effective_bitrate / 1000.0
+        );
+        gaps::print_report(&self.gaps());
+    }
+
+    /// Writes the summary and the timing of every segment as JSON.
+    ///
+    /// Durations are in seconds, throughputs in bytes per second and segment
+    /// start times relative to the start of the run.
+    pub fn write_json(&self, video: &VideoInfo, path: &Path) -> Result<()> {
+        let summary = self.summary(video);
+        let segments: Vec<_> = self
+            .segments
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| {
+                json!({
+                    "path": s.path,
+                    "host": s.host,
+                    "bytes": s.bytes,
+                    "started": s.started.as_secs_f64(),
+                    "elapsed": s.elapsed.as_secs_f64(),
+                    "attempts": s.attempts,
+                })
+            })
+            .collect();
+        let stats = json!({
+            "video": {
+                "id": video.id.trim_matches('"'),
+                "width": video.width,
+                "height": video.height,
+                "bitrate": video.bitrate,
+                "duration": video.duration,
+            },
+            "bytes": summary.bytes,
+            "wall_time": summary.wall_time.as_secs_f64(),
+            "average_throughput": summary.average_throughput,
+            "peak_throughput": summary.peak_throughput,
+            "retries": summary.retries,
+            "throttled": summary.throttled,
+            "segments_per_cdn": summary.segments_per_cdn,
+            "cached_segments": summary.cached_segments,
+            "effective_bitrate": summary.effective_bitrate,
+            "segments": segments,
+            "gaps": self.gaps().iter().map(|gap| json!({
+                "track": gap.track,
+                "start": gap.start,
+                "duration": gap.end - gap.start,
+            })).collect::<Vec<_>>(),
+        });
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &stats)?;
+        Ok(())
+    }
+}
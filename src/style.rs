@@ -0,0 +1,137 @@
+//! Colored terminal output.
+//!
+//! Everything goes to stderr, so colors are decided by whether stderr is a
+//! terminal, unless `--color` says otherwise. `NO_COLOR` is honored in auto
+//! mode.
+
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ArgEnum;
+
+use crate::{AudioInfo, Codec, VideoInfo};
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn init(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Auto => io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+fn paint(code: &str, text: impl Display) -> String {
+    if ENABLED.load(Ordering::SeqCst) {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn error(text: impl Display) -> String {
+    paint("1;31", text)
+}
+
+pub fn warning(text: impl Display) -> String {
+    paint("33", text)
+}
+
+pub fn selected(text: impl Display) -> String {
+    paint("1;32", text)
+}
+
+pub fn header(text: impl Display) -> String {
+    paint("1", text)
+}
+
+/// Prints the renditions as a table, highlighting the one to download.
+pub fn print_videos(videos: &[VideoInfo], chosen: &VideoInfo) {
+    let rows: Vec<_> = videos
+        .iter()
+        .map(|v| {
+            vec![
+                v.id.trim_matches('"').to_string(),
+                codec_names(&v.codecs),
+                format!("{}x{}", v.width, v.height),
+                format!("{} kbit/s", v.bitrate / 1000),
+                format!("{}s", v.duration),
+            ]
+        })
+        .collect();
+    let chosen = videos.iter().position(|v| std::ptr::eq(v, chosen));
+    print_table(
+        &["ID", "CODECS", "RESOLUTION", "BITRATE", "DURATION"],
+        &rows,
+        chosen,
+    );
+}
+
+/// Prints the audio renditions as a table, highlighting the one to
+/// download, if any.
+pub fn print_audios(audios: &[AudioInfo], chosen: Option<&AudioInfo>) {
+    let rows: Vec<_> = audios
+        .iter()
+        .map(|a| {
+            vec![
+                a.id.trim_matches('"').to_string(),
+                codec_names(&a.codecs),
+                a.language.clone().unwrap_or_else(|| "-".to_string()),
+                a.layout().unwrap_or_else(|| "-".to_string()),
+                format!("{} kbit/s", a.bitrate / 1000),
+            ]
+        })
+        .collect();
+    let chosen = chosen.and_then(|chosen| audios.iter().position(|a| std::ptr::eq(a, chosen)));
+    print_table(
+        &["ID", "CODECS", "LANGUAGE", "CHANNELS", "BITRATE"],
+        &rows,
+        chosen,
+    );
+}
+
+/// The codecs of a rendition by name, like `H.264 High@4.1`.
+pub fn codec_names(codecs: &str) -> String {
+    let names: Vec<_> = Codec::parse_list(codecs)
+        .iter()
+        .map(Codec::to_string)
+        .collect();
+    names.join(", ")
+}
+
+fn print_table(titles: &[&str], rows: &[Vec<String>], chosen: Option<usize>) {
+    let mut widths: Vec<_> = titles.iter().map(|t| t.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let line = |cells: &[&str]| -> String {
+        let cells: Vec<_> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{cell:width$}"))
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    };
+
+    info!("  {}", header(line(titles)));
+    for (index, row) in rows.iter().enumerate() {
+        let cells: Vec<_> = row.iter().map(String::as_str).collect();
+        let text = line(&cells);
+        if Some(index) == chosen {
+            info!("{}", selected(format!("* {text}")));
+        } else {
+            info!("  {text}");
+        }
+    }
+}
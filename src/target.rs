@@ -0,0 +1,110 @@
+//! Outputs that are not local files: the download streams to another
+//! machine while it runs, and never touches the local disk.
+//!
+//! ```text
+//! s3://BUCKET/KEY              S3 or a service like MinIO, see s3.rs
+//! dav://HOST/PATH              WebDAV over HTTP, like Nextcloud, see webdav.rs
+//! davs://HOST/PATH             WebDAV over HTTPS
+//! sftp://[USER@]HOST[:PORT]/PATH  a server reachable over SSH, see sftp.rs
+//! ```
+//!
+//! `{title}`, `{id}`, `{author}` and `{upload_date}` in the path are
+//! replaced by those of the event.
+
+use std::fmt;
+use std::io::prelude::*;
+
+use eyre::Result;
+use url::Url;
+
+use crate::{s3, sftp, webdav, MediaInfo};
+
+/// Where the output goes.
+pub enum Target {
+    S3(s3::Location),
+    WebDav(Url),
+    Sftp(Url),
+}
+
+/// An output being written to a target. Dropping it before
+/// [`Sink::finish`] abandons the upload, leaving as little behind as the
+/// target allows.
+pub trait Sink: Write {
+    /// Writes out what is left and makes the output appear in full.
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+impl Target {
+    /// The target `output` names, or `None` for a local file name.
+    pub fn parse(output: &str) -> Option<Target> {
+        if let Some(location) = s3::Location::parse(output) {
+            return Some(Target::S3(location));
+        }
+        let url = Url::parse(output).ok()?;
+        match url.scheme() {
+            "dav" | "davs" => Some(Target::WebDav(url)),
+            "sftp" => Some(Target::Sftp(url)),
+            _ => None,
+        }
+    }
+
+    /// The target with the placeholders replaced by the fields of `media`.
+    pub fn expand(self, media: &MediaInfo) -> Target {
+        let expand_url = |mut url: Url| {
+            // The placeholders got percent-encoded along with the path.
+            let path = PLACEHOLDERS
+                .iter()
+                .fold(url.path().to_string(), |path, name| {
+                    path.replace(&format!("%7B{name}%7D"), &format!("{{{name}}}"))
+                });
+            url.set_path(&expand(&path, media, |field| field.replace('%', "%25")));
+            url
+        };
+        match self {
+            Target::S3(location) => Target::S3(s3::Location {
+                key: expand(&location.key, media, |field| field),
+                ..location
+            }),
+            Target::WebDav(url) => Target::WebDav(expand_url(url)),
+            Target::Sftp(url) => Target::Sftp(expand_url(url)),
+        }
+    }
+
+    /// Starts writing to the target, with `s3_endpoint` for S3 instead of
+    /// AWS.
+    pub fn open(&self, agent: &ureq::Agent, s3_endpoint: Option<&str>) -> Result<Box<dyn Sink>> {
+        Ok(match self {
+            Target::S3(location) => Box::new(s3::Upload::start(agent, s3_endpoint, location)?),
+            Target::WebDav(url) => Box::new(webdav::Upload::start(agent, url)?),
+            Target::Sftp(url) => Box::new(sftp::Upload::start(url)?),
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::S3(location) => location.fmt(f),
+            Target::WebDav(url) | Target::Sftp(url) => {
+                // Leave a password in the URL out of the log.
+                let mut url = url.clone();
+                let _ = url.set_password(None);
+                url.fmt(f)
+            }
+        }
+    }
+}
+
+/// Fields of the event that can go into paths as `{name}`.
+const PLACEHOLDERS: [&str; 4] = ["title", "id", "author", "upload_date"];
+
+/// `text` with the [`PLACEHOLDERS`] replaced by those fields of `media`,
+/// after `escape`.
+fn expand(text: &str, media: &MediaInfo, escape: fn(String) -> String) -> String {
+    // A slash would start another directory.
+    let field = |value: &Option<String>| escape(value.as_deref().unwrap_or("").replace('/', "_"));
+    text.replace("{title}", &field(&media.title))
+        .replace("{id}", &field(&media.id))
+        .replace("{author}", &field(&media.author))
+        .replace("{upload_date}", &field(&media.upload_date))
+}
@@ -0,0 +1,108 @@
+//! `--write-timestamps`, mapping the recording to the wall clock, so a
+//! moment in the video can be matched with what happened at the time.
+//!
+//! Encoders of live streams stamp each fragment with the time it was
+//! captured, in a Producer Reference Time box (`prft`, ISO/IEC 14496-12)
+//! in front of its `moof`. Where the segments have one, a line per segment
+//! goes to `<file>.timestamps`:
+//!
+//! ```text
+//! <index> <start> <wall clock>
+//! 3 6.000 2024-05-02T18:00:06.000Z
+//! ```
+//!
+//! `index` is zero-based and `start` the position in the recording in
+//! seconds. Recordings without the boxes get no file.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{prelude::*, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::Result;
+
+use crate::postprocess::{Output, PostProcessor};
+use crate::{har, VideoInfo};
+
+/// Seconds from the NTP epoch, 1900, to the Unix one.
+const NTP_TO_UNIX: u64 = 2_208_988_800;
+
+pub fn path_for(output: &Path) -> PathBuf {
+    let mut name = OsString::from(output.as_os_str());
+    name.push(".timestamps");
+    PathBuf::from(name)
+}
+
+/// The boxes in `data` with their bodies, up to the first that does not
+/// fit.
+pub fn boxes(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let (size, header) = match size {
+            0 => (data.len(), 8),
+            1 => match data.get(8..16) {
+                Some(large) => (u64::from_be_bytes(large.try_into().unwrap()) as usize, 16),
+                None => break,
+            },
+            size => (size, 8),
+        };
+        if size < header || size > data.len() {
+            break;
+        }
+        boxes.push((&data[4..8], &data[header..size]));
+        data = &data[size..];
+    }
+    boxes
+}
+
+/// The wall-clock time in the `prft` box of a segment, if it has one.
+pub fn producer_time(segment: &[u8]) -> Option<SystemTime> {
+    let (_, prft) = boxes(segment)
+        .into_iter()
+        .find(|(kind, _)| kind == b"prft")?;
+    // Version and flags, then the reference track ID.
+    let ntp = u64::from_be_bytes(prft.get(8..16)?.try_into().unwrap());
+    let secs = (ntp >> 32).checked_sub(NTP_TO_UNIX)?;
+    let nanos = ((ntp & 0xffff_ffff) * 1_000_000_000) >> 32;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos as u32))
+}
+
+/// Reads the segments of the output where the download put them, so it
+/// runs before anything rewrites the file.
+pub struct WriteTimestamps {
+    pub video: VideoInfo,
+}
+
+impl PostProcessor for WriteTimestamps {
+    fn name(&self) -> &'static str {
+        "write-timestamps"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let mut file = File::open(&output.path)?;
+        let mut offset = self.video.init_segment.len() as u64;
+        let mut lines = String::new();
+        let mut buf = Vec::new();
+        for (index, segment) in self.video.segments.iter().enumerate() {
+            buf.resize((segment.size + 1) as usize, 0);
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut buf)?;
+            offset += segment.size + 1;
+            if let Some(time) = producer_time(&buf) {
+                let clock = har::timestamp(time);
+                lines += &format!("{index} {:.3} {clock}\n", segment.start);
+            }
+        }
+        if lines.is_empty() {
+            warning!("The segments carry no wall-clock times, not writing timestamps");
+            return Ok(());
+        }
+        let path = path_for(&output.path);
+        info!("Writing {}", path.display());
+        fs::write(&path, lines)?;
+        output.companions.push(path);
+        Ok(())
+    }
+}
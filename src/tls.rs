@@ -0,0 +1,240 @@
+//! TLS settings shared by all HTTP clients.
+//!
+//! The backend is picked at build time with the `rustls` (default) or
+//! `native-tls` feature; rustls wins if both are enabled.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+use eyre::Result;
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!(
+    "enable the `rustls` or the `native-tls` feature, Vimeo is only reachable via HTTPS"
+);
+
+/// A connection, encrypted or not.
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// PEM bundle replacing the built-in root certificates.
+    pub ca_file: Option<PathBuf>,
+    /// Accept any certificate, e.g. behind a TLS-intercepting proxy.
+    pub insecure: bool,
+}
+
+impl Options {
+    #[cfg(feature = "rustls")]
+    pub fn apply_ureq(&self, builder: ureq::AgentBuilder) -> Result<ureq::AgentBuilder> {
+        Ok(builder.tls_config(rustls_tls::config(self)?))
+    }
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    pub fn apply_ureq(&self, builder: ureq::AgentBuilder) -> Result<ureq::AgentBuilder> {
+        Ok(builder.tls_connector(native::connector(self)?))
+    }
+
+    /// Starts TLS on `stream`, a connection to `host`, for protocols
+    /// other than HTTP.
+    #[cfg(feature = "rustls")]
+    pub fn connect(&self, host: &str, stream: TcpStream) -> Result<Box<dyn Stream>> {
+        let name = rustls::pki_types::ServerName::try_from(host.to_string())?;
+        let connection = rustls::ClientConnection::new(rustls_tls::config(self)?, name)?;
+        Ok(Box::new(rustls::StreamOwned::new(connection, stream)))
+    }
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    pub fn connect(&self, host: &str, stream: TcpStream) -> Result<Box<dyn Stream>> {
+        Ok(Box::new(native::connector(self)?.connect(host, stream)?))
+    }
+
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    pub fn apply_reqwest(
+        &self,
+        mut builder: reqwest::blocking::ClientBuilder,
+    ) -> Result<reqwest::blocking::ClientBuilder> {
+        #[cfg(feature = "rustls")]
+        {
+            builder = builder.use_rustls_tls();
+        }
+        if let Some(path) = &self.ca_file {
+            builder = builder.tls_built_in_root_certs(false);
+            for cert in reqwest::Certificate::from_pem_bundle(&std::fs::read(path)?)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder.danger_accept_invalid_certs(self.insecure))
+    }
+}
+
+/// Certificate and key of a server this program runs, such as the page of
+/// `--gui`.
+#[cfg(feature = "gui")]
+#[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+#[derive(Clone, Debug)]
+pub struct Identity {
+    /// PEM file with the certificate chain.
+    pub cert: PathBuf,
+    /// PEM file with the private key.
+    pub key: PathBuf,
+}
+
+#[cfg(feature = "gui")]
+impl Identity {
+    /// The TLS settings of a server offering the protocols `alpn`.
+    #[cfg(feature = "rustls")]
+    pub fn server_config(&self, alpn: &[&[u8]]) -> Result<std::sync::Arc<rustls::ServerConfig>> {
+        rustls_tls::server_config(self, alpn)
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod rustls_tls {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::sync::Arc;
+
+    use eyre::{eyre, Result};
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{self, CryptoProvider};
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    #[cfg(feature = "gui")]
+    use rustls::ServerConfig;
+    use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+    #[cfg(feature = "gui")]
+    use super::Identity;
+    use super::Options;
+
+    #[cfg(feature = "gui")]
+    pub fn server_config(identity: &Identity, alpn: &[&[u8]]) -> Result<Arc<ServerConfig>> {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&identity.cert)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        if certs.is_empty() {
+            return Err(eyre!(
+                "No certificates found in {}",
+                identity.cert.display()
+            ));
+        }
+        let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&identity.key)?))?
+            .ok_or_else(|| eyre!("No private key found in {}", identity.key.display()))?;
+        let provider = Arc::new(crypto::ring::default_provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+        Ok(Arc::new(config))
+    }
+
+    pub fn config(options: &Options) -> Result<Arc<ClientConfig>> {
+        let mut roots = RootCertStore::empty();
+        match &options.ca_file {
+            Some(path) => {
+                let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (added, _) = roots.add_parsable_certificates(certs);
+                if added == 0 {
+                    return Err(eyre!("No certificates found in {}", path.display()));
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+
+        let provider = Arc::new(crypto::ring::default_provider());
+        let mut config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        if options.insecure {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(NoVerification(provider)));
+        }
+        Ok(Arc::new(config))
+    }
+
+    /// Accepts any server certificate. Handshake signatures are still checked,
+    /// so the connection is at least made with whoever holds the presented key.
+    #[derive(Debug)]
+    struct NoVerification(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+mod native {
+    use std::sync::Arc;
+
+    use eyre::{eyre, Result};
+    use native_tls::{Certificate, TlsConnector};
+
+    use super::Options;
+
+    pub fn connector(options: &Options) -> Result<Arc<TlsConnector>> {
+        let mut builder = TlsConnector::builder();
+        if let Some(path) = &options.ca_file {
+            builder.disable_built_in_roots(true);
+            let pem = std::fs::read_to_string(path)?;
+            // `Certificate::from_pem` only reads the first certificate.
+            const END: &str = "-----END CERTIFICATE-----";
+            let mut count = 0;
+            for block in pem.split_inclusive(END).filter(|b| b.contains(END)) {
+                builder.add_root_certificate(Certificate::from_pem(block.as_bytes())?);
+                count += 1;
+            }
+            if count == 0 {
+                return Err(eyre!("No certificates found in {}", path.display()));
+            }
+        }
+        builder.danger_accept_invalid_certs(options.insecure);
+        Ok(Arc::new(builder.build()?))
+    }
+}
@@ -0,0 +1,195 @@
+//! Re-encoding the output into a standard mezzanine format with ffmpeg, for
+//! `--recode PRESET` behind the `transcode` feature.
+//!
+//! A preset is `<height>p-<codec>`, like `720p-h264` or `2160p-hevc`, with
+//! the codec one of `h264`, `hevc` and `av1`. The video is scaled down to
+//! the height, never up, keeping its aspect ratio and frame rate; audio
+//! becomes 48 kHz AAC. MP4 outputs come out progressive, with the index in
+//! front.
+//!
+//! Software encoding of a multi-hour 4K event takes longer than the event.
+//! `--hwaccel` decodes and encodes on the GPU instead: `vaapi` on Intel and
+//! AMD under Linux, with the device from `VAAPI_DEVICE` or
+//! `/dev/dri/renderD128`, `nvenc` on NVIDIA and `videotoolbox` on macOS.
+//! Scaling stays on the CPU, which is cheap next to the encoding.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::str::FromStr;
+
+use clap::ArgEnum;
+use eyre::{eyre, Result};
+
+use crate::postprocess::{Output, PostProcessor};
+use crate::{ledger, mux, Codec};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+    Av1,
+}
+
+impl VideoCodec {
+    /// The encoder of ffmpeg and its arguments for archive quality, or
+    /// `None` if `hardware` cannot encode this codec.
+    fn encoder_args(self, hardware: Option<Hardware>) -> Option<Vec<&'static str>> {
+        use Hardware::*;
+        use VideoCodec::*;
+        let mut args = match (self, hardware) {
+            (H264, None) => vec!["-c:v", "libx264", "-preset", "medium", "-crf", "20"],
+            (Hevc, None) => vec!["-c:v", "libx265", "-preset", "medium", "-crf", "22"],
+            (Av1, None) => vec!["-c:v", "libsvtav1", "-preset", "8", "-crf", "30"],
+            (H264, Some(Vaapi)) => vec!["-c:v", "h264_vaapi", "-qp", "20"],
+            (Hevc, Some(Vaapi)) => vec!["-c:v", "hevc_vaapi", "-qp", "22"],
+            (Av1, Some(Vaapi)) => vec!["-c:v", "av1_vaapi", "-qp", "30"],
+            (H264, Some(Nvenc)) => vec!["-c:v", "h264_nvenc", "-preset", "p5", "-cq", "20"],
+            (Hevc, Some(Nvenc)) => vec!["-c:v", "hevc_nvenc", "-preset", "p5", "-cq", "22"],
+            (Av1, Some(Nvenc)) => vec!["-c:v", "av1_nvenc", "-preset", "p5", "-cq", "30"],
+            (H264, Some(VideoToolbox)) => vec!["-c:v", "h264_videotoolbox", "-q:v", "65"],
+            (Hevc, Some(VideoToolbox)) => vec!["-c:v", "hevc_videotoolbox", "-q:v", "65"],
+            (Av1, Some(VideoToolbox)) => return None,
+        };
+        if self == Hevc {
+            // Apple players want this tag.
+            args.extend(["-tag:v", "hvc1"]);
+        }
+        Some(args)
+    }
+
+    /// The codec the output has, for checking it with ffprobe.
+    pub fn codec(self) -> Codec {
+        Codec::parse(match self {
+            VideoCodec::H264 => "avc1.640028",
+            VideoCodec::Hevc => "hvc1.1.6.L120.90",
+            VideoCodec::Av1 => "av01.0.08M.08",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preset {
+    pub height: u32,
+    pub codec: VideoCodec,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Preset, String> {
+        let invalid = || format!("expected <height>p-<codec> like 720p-h264, not {text}");
+        let (height, codec) = text.split_once("p-").ok_or_else(invalid)?;
+        let height = height.parse().map_err(|_| invalid())?;
+        let codec = match codec {
+            "h264" => VideoCodec::H264,
+            "hevc" | "h265" => VideoCodec::Hevc,
+            "av1" => VideoCodec::Av1,
+            _ => return Err(format!("unknown codec {codec}, use h264, hevc or av1")),
+        };
+        if height < 144 || height % 2 != 0 {
+            return Err(format!("cannot scale to a height of {height}"));
+        }
+        Ok(Preset { height, codec })
+    }
+}
+
+impl Preset {
+    /// Whether `hardware` has an encoder for the codec.
+    pub fn supported_by(self, hardware: Hardware) -> bool {
+        self.codec.encoder_args(Some(hardware)).is_some()
+    }
+}
+
+/// `--hwaccel`, the GPU API to code with.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hardware {
+    Vaapi,
+    Nvenc,
+    #[clap(name = "videotoolbox")]
+    VideoToolbox,
+}
+
+impl Hardware {
+    /// Input arguments of ffmpeg, decoding on the GPU.
+    fn decoder_args(self) -> Vec<String> {
+        match self {
+            Hardware::Vaapi => {
+                let device =
+                    env::var("VAAPI_DEVICE").unwrap_or_else(|_| "/dev/dri/renderD128".into());
+                vec![
+                    "-vaapi_device".into(),
+                    device,
+                    "-hwaccel".into(),
+                    "vaapi".into(),
+                ]
+            }
+            Hardware::Nvenc => vec!["-hwaccel".into(), "cuda".into()],
+            Hardware::VideoToolbox => vec!["-hwaccel".into(), "videotoolbox".into()],
+        }
+    }
+}
+
+/// Replaces the output with its recoding as `preset` says.
+pub struct Transcode {
+    pub preset: Preset,
+    pub hardware: Option<Hardware>,
+    pub options: mux::Options,
+}
+
+impl PostProcessor for Transcode {
+    fn name(&self) -> &'static str {
+        "recode"
+    }
+
+    fn run(&self, output: &mut Output) -> Result<()> {
+        let Preset { height, codec } = self.preset;
+        let encoder = codec
+            .encoder_args(self.hardware)
+            .ok_or_else(|| eyre!("{:?} cannot encode {codec:?}", self.hardware))?;
+        let with = match self.hardware {
+            Some(hardware) => format!(" with {hardware:?}"),
+            None => String::new(),
+        };
+        info!(
+            "Recoding {} to {height}p {codec:?}{with}, this takes a while",
+            output.path.display()
+        );
+        let part = mux::part_path(&output.path);
+        let mut command = Command::new(mux::FFMPEG);
+        command.args(["-v", "error", "-y"]);
+        if let Some(hardware) = self.hardware {
+            command.args(hardware.decoder_args());
+        }
+        command.arg("-i").arg(&output.path);
+        command.args(["-map", "0:v:0", "-map", "0:a?"]);
+        command.args(encoder);
+        // Decoded frames come back to the CPU for scaling, VA-API wants
+        // them on the GPU again for encoding.
+        let scale = format!("scale=-2:'min({height},ih)'");
+        if self.hardware == Some(Hardware::Vaapi) {
+            command
+                .arg("-vf")
+                .arg(format!("{scale},format=nv12,hwupload"));
+        } else {
+            command.arg("-vf").arg(scale);
+            command.args(["-pix_fmt", "yuv420p"]);
+        }
+        command.args(["-c:a", "aac", "-b:a", "192k", "-ar", "48000"]);
+        if self.options.container == mux::Container::Mp4 {
+            command.args(["-movflags", "+faststart"]);
+        }
+        command
+            .args(["-f", self.options.container.format()])
+            .arg(&part);
+        mux::finish(command, &part, &output.path)?;
+
+        // The segments are gone from the output.
+        let ledger_path = ledger::path_for(&output.path);
+        if output.companions.contains(&ledger_path) {
+            output.companions.retain(|path| *path != ledger_path);
+            fs::remove_file(ledger_path)?;
+        }
+        Ok(())
+    }
+}
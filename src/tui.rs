@@ -0,0 +1,266 @@
+//! Full-screen dashboard for `--tui`.
+//!
+//! The dashboard takes over the terminal while segments download. Messages
+//! written to stderr meanwhile are captured and shown in the log panel, and
+//! printed again once the terminal is restored so none of them get lost.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use eyre::Result;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Gauge, List, Paragraph, Sparkline};
+use ratatui::Frame;
+
+use crate::stats::Stats;
+use crate::{keys, signals, Track, VideoInfo};
+
+/// Log lines kept for the log panel.
+const LOG_LINES: usize = 200;
+/// Throughput samples kept for the graph, one per second.
+const SAMPLES: usize = 300;
+
+/// What the dashboard shows about the current download.
+pub struct Job {
+    pub name: String,
+    pub segments: usize,
+    pub bytes: u64,
+}
+
+pub struct Dashboard {
+    done: Arc<AtomicBool>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+    log: Arc<Mutex<VecDeque<String>>>,
+    capture: Option<StderrCapture>,
+}
+
+impl Dashboard {
+    pub fn start(video: &VideoInfo, stats: Arc<Stats>) -> Result<Dashboard> {
+        let log = Arc::new(Mutex::new(VecDeque::new()));
+        let capture = StderrCapture::start(log.clone())?;
+        let job = Job {
+            name: video.to_string(),
+            segments: video.segments.len(),
+            bytes: video.output_len(),
+        };
+
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), EnterAlternateScreen)?;
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let done = done.clone();
+            let log = log.clone();
+            thread::spawn(move || run(&job, &stats, &log, &done))
+        };
+        Ok(Dashboard {
+            done,
+            handle: Some(handle),
+            log,
+            capture: Some(capture),
+        })
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let _ = crossterm::execute!(io::stdout(), LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        if let Some(capture) = self.capture.take() {
+            capture.stop();
+        }
+        for line in self.log.lock().unwrap().iter() {
+            eprintln!("{line}");
+        }
+    }
+}
+
+fn run(
+    job: &Job,
+    stats: &Stats,
+    log: &Mutex<VecDeque<String>>,
+    done: &AtomicBool,
+) -> io::Result<()> {
+    let mut terminal =
+        ratatui::Terminal::new(ratatui::backend::CrosstermBackend::new(io::stdout()))?;
+    let mut samples = VecDeque::new();
+    let mut last_sample = (Instant::now(), 0);
+    while !done.load(Ordering::SeqCst) {
+        let (segments, bytes) = stats.progress();
+        if last_sample.0.elapsed() >= Duration::from_secs(1) {
+            let rate = (bytes - last_sample.1) as f64 / last_sample.0.elapsed().as_secs_f64();
+            samples.push_back(rate as u64);
+            if samples.len() > SAMPLES {
+                samples.pop_front();
+            }
+            last_sample = (Instant::now(), bytes);
+        }
+        terminal.draw(|frame| {
+            draw(frame, job, segments, bytes, &samples, &log.lock().unwrap());
+        })?;
+
+        if event::poll(Duration::from_millis(250))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('p') => keys::set_paused(true),
+                    KeyCode::Char('r') => keys::set_paused(false),
+                    // Raw mode swallows Ctrl+C, so it is handled here.
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        signals::request_stop()
+                    }
+                    KeyCode::Char('q') => signals::request_stop(),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw(
+    frame: &mut Frame,
+    job: &Job,
+    segments: usize,
+    bytes: u64,
+    samples: &VecDeque<u64>,
+    log: &VecDeque<String>,
+) {
+    let [queue, progress, graph, log_area, help] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(8),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let status = if keys::paused() {
+        "paused"
+    } else {
+        "downloading"
+    };
+    frame.render_widget(
+        Paragraph::new(format!("{} ({status})", job.name)).block(Block::bordered().title("Queue")),
+        queue,
+    );
+
+    let ratio = (bytes as f64 / job.bytes.max(1) as f64).min(1.0);
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title("Progress"))
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(ratio)
+            .label(format!(
+                "{segments}/{} segments, {:.1} of {:.1} MiB",
+                job.segments,
+                bytes as f64 / (1 << 20) as f64,
+                job.bytes as f64 / (1 << 20) as f64
+            )),
+        progress,
+    );
+
+    // Newest samples on the right.
+    let visible = (graph.width as usize).saturating_sub(2);
+    let data: Vec<u64> = samples
+        .iter()
+        .skip(samples.len().saturating_sub(visible))
+        .copied()
+        .collect();
+    let current = data.last().copied().unwrap_or(0);
+    frame.render_widget(
+        Sparkline::default()
+            .block(
+                Block::bordered()
+                    .title(format!("Throughput: {:.1} KiB/s", current as f64 / 1024.0)),
+            )
+            .style(Style::default().fg(Color::Cyan))
+            .data(&data),
+        graph,
+    );
+
+    let height = (log_area.height as usize).saturating_sub(2);
+    let lines: Vec<&str> = log
+        .iter()
+        .skip(log.len().saturating_sub(height))
+        .map(String::as_str)
+        .collect();
+    frame.render_widget(
+        List::new(lines).block(Block::bordered().title("Log")),
+        log_area,
+    );
+
+    frame.render_widget(Paragraph::new("p pause  r resume  q stop"), help);
+}
+
+/// Redirects stderr into the log panel.
+struct StderrCapture {
+    #[cfg(unix)]
+    saved: std::os::fd::OwnedFd,
+    reader: JoinHandle<()>,
+}
+
+impl StderrCapture {
+    #[cfg(unix)]
+    fn start(log: Arc<Mutex<VecDeque<String>>>) -> Result<StderrCapture> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let saved = unsafe { libc::dup(libc::STDERR_FILENO) };
+        if saved < 0 || unsafe { libc::dup2(write.as_raw_fd(), libc::STDERR_FILENO) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let saved = unsafe { OwnedFd::from_raw_fd(saved) };
+        drop(write);
+
+        let reader = thread::spawn(move || {
+            for line in BufReader::new(std::fs::File::from(read)).lines() {
+                let Ok(line) = line else { break };
+                let mut log = log.lock().unwrap();
+                log.push_back(line);
+                if log.len() > LOG_LINES {
+                    log.pop_front();
+                }
+            }
+        });
+        Ok(StderrCapture { saved, reader })
+    }
+
+    /// Other platforms keep writing to the terminal, which may garble the
+    /// dashboard until the next redraw.
+    #[cfg(not(unix))]
+    fn start(_log: Arc<Mutex<VecDeque<String>>>) -> Result<StderrCapture> {
+        Ok(StderrCapture {
+            reader: thread::spawn(|| {}),
+        })
+    }
+
+    /// Puts stderr back and waits for the captured lines to be read.
+    fn stop(self) {
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+
+            // Replacing fd 2 closes the last write end of the pipe, which
+            // ends the reader.
+            unsafe { libc::dup2(self.saved.as_raw_fd(), libc::STDERR_FILENO) };
+        }
+        let _ = self.reader.join();
+    }
+}
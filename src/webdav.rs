@@ -0,0 +1,175 @@
+//! Uploading the output to a WebDAV server, like Nextcloud, for
+//! `--filename dav://HOST/PATH` or `davs://` for HTTPS.
+//!
+//! The output goes up in a single PUT with a chunked body, fed while it
+//! downloads. Missing directories are created first. A user name and
+//! password can be part of the URL, the password also comes from
+//! `WEBDAV_PASSWORD`, which keeps it out of the process list.
+
+use std::env;
+use std::io::{self, prelude::*};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use eyre::{eyre, Result};
+use url::Url;
+
+use crate::target::Sink;
+
+/// Bytes handed to the request at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+/// Chunks waiting for the request before writes block.
+const QUEUED_CHUNKS: usize = 4;
+
+/// An upload in progress. Dropping it before [`Sink::finish`] breaks the
+/// request off, so the server keeps no partial file.
+pub struct Upload {
+    /// `None` ends the body, hanging up without it aborts the request.
+    chunks: SyncSender<Option<Vec<u8>>>,
+    buf: Vec<u8>,
+    request: JoinHandle<Result<()>>,
+}
+
+impl Upload {
+    /// Starts uploading to `url`, a `dav://` or `davs://` URL.
+    pub fn start(agent: &ureq::Agent, url: &Url) -> Result<Upload> {
+        let scheme = if url.scheme() == "davs" {
+            "https"
+        } else {
+            "http"
+        };
+        let mut http = Url::parse(&format!("{scheme}{}", &url.as_str()[url.scheme().len()..]))?;
+        let authorization = authorization(url);
+        let _ = http.set_username("");
+        let _ = http.set_password(None);
+
+        let request = |method: &str, url: &Url| {
+            let request = agent.request(method, url.as_str());
+            match &authorization {
+                Some(authorization) => request.set("Authorization", authorization),
+                None => request,
+            }
+        };
+        let segments: Vec<_> = http.path_segments().into_iter().flatten().collect();
+        let mut directory = http.clone();
+        for depth in 1..segments.len() {
+            directory.set_path(&format!("{}/", segments[..depth].join("/")));
+            match request("MKCOL", &directory).call() {
+                // 405: it exists already.
+                Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+                Err(e) => return Err(failure("MKCOL", &directory, e)),
+            }
+        }
+
+        let (chunks, received) = mpsc::sync_channel(QUEUED_CHUNKS);
+        let put = request("PUT", &http);
+        let request = thread::spawn(move || {
+            let body = Chunks {
+                received,
+                chunk: Vec::new(),
+                pos: 0,
+                done: false,
+            };
+            put.send(body)
+                .map(drop)
+                .map_err(|e| failure("PUT", &http, e))
+        });
+        Ok(Upload {
+            chunks,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            request,
+        })
+    }
+
+    fn send(&mut self, chunk: Option<Vec<u8>>) -> io::Result<()> {
+        // The request ended early; finish reports why.
+        self.chunks
+            .send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "The WebDAV server hung up"))
+    }
+}
+
+impl Sink for Upload {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let buf = std::mem::take(&mut self.buf);
+        let sent = self.send(Some(buf)).and_then(|()| self.send(None));
+        let Upload {
+            chunks, request, ..
+        } = *self;
+        drop(chunks);
+        request
+            .join()
+            .map_err(|_| eyre!("WebDAV upload thread panicked!"))??;
+        Ok(sent?)
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+            self.send(Some(chunk))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The body of the PUT, read from the chunks sent to it.
+struct Chunks {
+    received: Receiver<Option<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            match self.received.recv() {
+                Ok(Some(chunk)) => (self.chunk, self.pos) = (chunk, 0),
+                Ok(None) => self.done = true,
+                Err(_) => return Err(io::Error::other("Upload abandoned")),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..][..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Basic authentication with the user in `url`, if there is one.
+fn authorization(url: &Url) -> Option<String> {
+    if url.username().is_empty() {
+        return None;
+    }
+    let decode = |text: &str| {
+        percent_encoding::percent_decode_str(text)
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    let password = url
+        .password()
+        .map(decode)
+        .or_else(|| env::var("WEBDAV_PASSWORD").ok())
+        .unwrap_or_default();
+    let credentials = format!("{}:{password}", decode(url.username()));
+    Some(format!("Basic {}", base64::encode(credentials)))
+}
+
+fn failure(method: &str, url: &Url, error: ureq::Error) -> eyre::Report {
+    match error {
+        ureq::Error::Status(status, _) => {
+            eyre!("The WebDAV server answered {method} {url} with {status}")
+        }
+        e => eyre!("{method} {url} failed: {e}"),
+    }
+}
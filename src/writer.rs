@@ -0,0 +1,180 @@
+//! Backends for writing segments into the preallocated output file, and
+//! batched writes for outputs written in order.
+
+use std::fs::File;
+use std::io::{self, prelude::*, IoSlice};
+
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// positional writes, one syscall per segment
+    Pwrite,
+    /// copy segments into a memory mapping of the file
+    #[cfg(feature = "mmap")]
+    Mmap,
+    /// submit positional writes through io_uring, with a ring per worker
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    IoUring,
+}
+
+/// Writes segments at fixed offsets; shared by all download workers.
+pub enum Writer<'a> {
+    Pwrite(&'a File),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::MmapRaw),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    IoUring(&'a File),
+}
+
+/// Entries of the ring of each worker.
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+const RING_ENTRIES: u32 = 8;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+thread_local! {
+    /// The ring of the worker on this thread, so workers do not wait for
+    /// each other's writes.
+    static RING: std::cell::RefCell<Option<io_uring::IoUring>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+impl<'a> Writer<'a> {
+    /// Creates a writer for `file`, which must already have its final length.
+    pub fn new(backend: Backend, file: &'a File) -> io::Result<Writer<'a>> {
+        match backend {
+            Backend::Pwrite => Ok(Writer::Pwrite(file)),
+            #[cfg(feature = "mmap")]
+            Backend::Mmap => Ok(Writer::Mmap(memmap2::MmapRaw::map_raw(file)?)),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Backend::IoUring => {
+                // Fail here rather than in the first worker if io_uring is
+                // not available.
+                RING.with(|ring| init_ring(&mut ring.borrow_mut()).map(drop))?;
+                Ok(Writer::IoUring(file))
+            }
+        }
+    }
+
+    pub fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        match self {
+            Writer::Pwrite(file) => write_all_at(file, buf, offset),
+            #[cfg(feature = "mmap")]
+            Writer::Mmap(map) => {
+                let end = offset as usize + buf.len();
+                if end > map.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "write beyond the end of the mapping",
+                    ));
+                }
+                // SAFETY: the range is within the mapping, and every segment
+                // has its own disjoint range, so concurrent writers never
+                // touch the same bytes.
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        buf.as_ptr(),
+                        map.as_mut_ptr().add(offset as usize),
+                        buf.len(),
+                    );
+                }
+                Ok(())
+            }
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Writer::IoUring(file) => RING.with(|ring| {
+                let mut ring = ring.borrow_mut();
+                uring_write_all_at(init_ring(&mut ring)?, file, buf, offset)
+            }),
+        }
+    }
+
+    /// Makes sure everything written so far reaches the file.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Writer::Pwrite(_) => Ok(()),
+            #[cfg(feature = "mmap")]
+            Writer::Mmap(map) => map.flush(),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Writer::IoUring(..) => Ok(()),
+        }
+    }
+}
+
+/// Writes all of `bufs` to `out`, several of them per syscall where `out`
+/// supports it.
+pub fn write_all_vectored(out: &mut impl Write, mut bufs: &mut [IoSlice]) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match out.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(count) => IoSlice::advance_slices(&mut bufs, count),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let count = file.seek_write(buf, offset)?;
+        buf = &buf[count..];
+        offset += count as u64;
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn init_ring(ring: &mut Option<io_uring::IoUring>) -> io::Result<&mut io_uring::IoUring> {
+    if ring.is_none() {
+        *ring = Some(io_uring::IoUring::new(RING_ENTRIES)?);
+    }
+    Ok(ring.as_mut().unwrap())
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn uring_write_all_at(
+    ring: &mut io_uring::IoUring,
+    file: &File,
+    mut buf: &[u8],
+    mut offset: u64,
+) -> io::Result<()> {
+    use io_uring::{opcode, types};
+    use std::os::unix::io::AsRawFd;
+
+    while !buf.is_empty() {
+        let len = buf.len().min(u32::MAX as usize) as u32;
+        let entry = opcode::Write::new(types::Fd(file.as_raw_fd()), buf.as_ptr(), len)
+            .offset(offset)
+            .build();
+        // SAFETY: `buf` outlives the submission, we wait for its completion
+        // before returning.
+        unsafe {
+            ring.submission()
+                .push(&entry)
+                .map_err(|_| io::Error::other("io_uring queue is full"))?;
+        }
+        ring.submit_and_wait(1)?;
+        let cqe = ring
+            .completion()
+            .next()
+            .ok_or_else(|| io::Error::other("missing io_uring completion"))?;
+        let count = cqe.result();
+        if count < 0 {
+            return Err(io::Error::from_raw_os_error(-count));
+        }
+        if count == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[count as usize..];
+        offset += count as u64;
+    }
+    Ok(())
+}
@@ -0,0 +1,1376 @@
+//! End-to-end runs of the binary against its own mock server.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+
+use sha2::{Digest, Sha256};
+
+const BIN: &str = env!("CARGO_BIN_EXE_vimeo-event-downloader");
+
+struct Mock {
+    child: Child,
+    event_url: String,
+    sha256: String,
+}
+
+impl Mock {
+    fn start(flaky: bool) -> Mock {
+        Mock::with_args(if flaky { &["--flaky"] } else { &[] })
+    }
+
+    fn with_args(args: &[&str]) -> Mock {
+        let mut command = Command::new(BIN);
+        command.arg("mock-server").args(args).stdout(Stdio::piped());
+        let mut child = command.spawn().unwrap();
+        let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        let event_url = lines.next().unwrap().unwrap();
+        let sha256 = lines.next().unwrap().unwrap();
+        Mock {
+            child,
+            event_url,
+            sha256,
+        }
+    }
+}
+
+impl Drop for Mock {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Another long-running subcommand, with the first line it printed.
+struct Daemon {
+    child: Child,
+    line: String,
+}
+
+impl Daemon {
+    fn start(args: &[&str]) -> Daemon {
+        let mut child = Command::new(BIN)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        let line = lines.next().unwrap().unwrap();
+        Daemon { child, line }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A fresh directory, also used as home so no user config or cache is read.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "vimeo-event-downloader-{}-{name}",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(BIN)
+        .args(args)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .output()
+        .unwrap()
+}
+
+fn download(mock: &Mock, dir: &PathBuf, extra: &[&str]) -> Output {
+    let output = dir.join("out.mp4");
+    let mut args = vec![
+        "-u",
+        &mock.event_url,
+        "-r",
+        "https://vimeo.com/",
+        "-f",
+        output.to_str().unwrap(),
+    ];
+    args.extend_from_slice(extra);
+    run(dir, &args)
+}
+
+/// Runs `download` with a stand-in for ffmpeg, returning the output and
+/// the arguments of every call of ffmpeg, a line each.
+#[cfg(unix)]
+fn download_with_ffmpeg(mock: &Mock, dir: &PathBuf, extra: &[&str]) -> (Output, String) {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Copies the first input to the output, the last argument, or leaves
+    // the output empty for inputs only ffmpeg would find, like patterns.
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::write(
+        bin.join("ffmpeg"),
+        r#"#!/bin/sh
+echo "$@" >>"$(dirname "$0")/ffmpeg.log"
+input=
+previous=
+for arg; do
+    [ "$previous" = -i ] && [ -z "$input" ] && input=$arg
+    previous=$arg
+done
+cp "$input" "$arg" 2>/dev/null || : >"$arg"
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("ffmpeg"), fs::Permissions::from_mode(0o755)).unwrap();
+    let _ = fs::remove_file(bin.join("ffmpeg.log"));
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let output = Command::new(BIN)
+        .args(["-u", &mock.event_url, "-r", "https://vimeo.com/", "-f"])
+        .arg(dir.join("out.mp4"))
+        .args(extra)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    let log = fs::read_to_string(bin.join("ffmpeg.log")).unwrap_or_default();
+    (output, log)
+}
+
+fn sha256_of(path: PathBuf) -> String {
+    Sha256::digest(fs::read(path).unwrap())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn sha256_of_url(url: &str) -> String {
+    let mut data = Vec::new();
+    ureq::get(url)
+        .call()
+        .unwrap()
+        .into_reader()
+        .read_to_end(&mut data)
+        .unwrap();
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[test]
+fn downloads_best_rendition() {
+    let mock = Mock::start(false);
+    let dir = scratch("best");
+    let output = download(&mock, &dir, &[]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn retries_broken_transfers() {
+    let mock = Mock::start(true);
+    let dir = scratch("flaky");
+    let output = download(&mock, &dir, &["--retry-backoff", "0"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn downloads_without_prefetch() {
+    let mock = Mock::start(true);
+    let dir = scratch("no-prefetch");
+    let output = download(&mock, &dir, &["--prefetch", "0", "--retry-backoff", "0"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn reuses_cached_segments() {
+    let mock = Mock::start(false);
+    let dir = scratch("cache");
+    let cache = dir.join("segments");
+    let cache = ["--cache-dir", cache.to_str().unwrap()];
+    assert!(download(&mock, &dir, &cache).status.success());
+    fs::remove_file(dir.join("out.mp4")).unwrap();
+    let output = download(&mock, &dir, &cache);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Segments from the cache: 6"));
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn command_line_overrides_config_file() {
+    let mock = Mock::start(false);
+    let dir = scratch("config");
+    let config = dir.join("config").join("vimeo-event-downloader");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(config.join("config"), "--cache\n").unwrap();
+    let cache = dir.join("segments");
+    let output = download(&mock, &dir, &["--cache-dir", cache.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(cache.is_dir());
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn parallel_download_matches() {
+    let mock = Mock::start(false);
+    let dir = scratch("parallel");
+    let output = download(&mock, &dir, &["--concurrency", "4"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn parallel_download_sweeps_failed_segments() {
+    let mock = Mock::start(true);
+    let dir = scratch("sweep");
+    let output = download(&mock, &dir, &["--concurrency", "4", "--retries", "0"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn renews_expired_segment_urls() {
+    let mock = Mock::with_args(&["--expire-after", "2"]);
+    let dir = scratch("expire");
+    for concurrency in ["1", "2"] {
+        let output = download(&mock, &dir, &["--concurrency", concurrency]);
+        assert!(output.status.success(), "{output:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("resolving the event again"));
+        assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+    }
+}
+
+#[test]
+fn waits_for_throttling_server() {
+    let mock = Mock::with_args(&["--throttle", "2"]);
+    let dir = scratch("throttle");
+    let output = download(&mock, &dir, &["--concurrency", "2", "--retries", "0"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("throttled by the server (HTTP 429); waiting 1.0s"));
+    assert!(stderr.contains("Requests throttled by the server: 2"));
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_cuts_a_retry_wait_short() {
+    let mock = Mock::with_args(&["--missing", "0"]);
+    let dir = scratch("sigterm");
+    let output = dir.join("out.mp4");
+    let mut child = Command::new(BIN)
+        .args(["-u", &mock.event_url, "-r", "https://vimeo.com/"])
+        .args(["-f", output.to_str().unwrap(), "--retry-status", "404"])
+        .args([
+            "--retries",
+            "1",
+            "--retry-backoff",
+            "600",
+            "--retry-max-delay",
+            "600",
+        ])
+        .env("HOME", &dir)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("retry 1/1") {
+        line.clear();
+        assert_ne!(stderr.read_line(&mut line).unwrap(), 0, "no retry");
+    }
+    let started = std::time::Instant::now();
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let status = child.wait().unwrap();
+    assert!(started.elapsed().as_secs() < 10);
+    assert_eq!(status.code(), Some(143));
+}
+
+#[test]
+fn downloads_progressive_file() {
+    let mock = Mock::start(false);
+    let dir = scratch("progressive");
+    let output = download(&mock, &dir, &["--prefer-progressive"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Downloading the progressive file of 1280x720"));
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn reports_stats_of_progressive_file() {
+    let mock = Mock::start(false);
+    let dir = scratch("progressive-stats");
+    let stats = dir.join("stats.json");
+    let output = download(
+        &mock,
+        &dir,
+        &[
+            "--prefer-progressive",
+            "--stats-json",
+            stats.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stats: ureq::serde_json::Value =
+        ureq::serde_json::from_slice(&fs::read(&stats).unwrap()).unwrap();
+    let size = fs::metadata(dir.join("out.mp4")).unwrap().len();
+    assert_eq!(stats["bytes"], size);
+    assert_eq!(stats["segments"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn downloads_rendition_of_codec_specific_manifest() {
+    let mock = Mock::start(false);
+    let dir = scratch("hevc");
+    let output = download(&mock, &dir, &["--video-id", "v540h"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn lenient_leaves_out_malformed_renditions() {
+    let mock = Mock::with_args(&["--malformed"]);
+    let dir = scratch("malformed");
+    let output = download(&mock, &dir, &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(r#"video[2].width: expected a non-negative integer, found "1920""#));
+    let output = download(&mock, &dir, &["--lenient"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Leaving out a rendition of the manifest: video[2].width"));
+    assert!(
+        stderr.contains("Leaving out a rendition of the manifest: video[3]: expected an object")
+    );
+    assert!(stderr.contains(
+        r#"Leaving out a rendition of the manifest: video[4].segments[0].start: expected a number, found "0""#
+    ));
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[test]
+fn distributes_segments_over_workers() {
+    let mock = Mock::start(false);
+    let dir = scratch("workers");
+    let workers: Vec<_> = (0..2)
+        .map(|_| Daemon::start(&["worker", "--listen", "127.0.0.1:0", "--token", "secret"]))
+        .collect();
+    // Nothing listens there, so the coordinator fetches that share itself.
+    let addrs = format!("{},{},127.0.0.1:1", workers[0].line, workers[1].line);
+    let output = download(
+        &mock,
+        &dir,
+        &["--workers", &addrs, "--worker-token", "secret"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+/// Sends `request` to `addr` as is, returning the status line of the
+/// response, empty if the server hung up without one.
+fn status_of(addr: &str, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[test]
+fn worker_refuses_requests_without_token_or_over_limits() {
+    let worker = Daemon::start(&["worker", "--listen", "127.0.0.1:0", "--token", "secret"]);
+    let post = |headers: &str| {
+        let request = format!("POST /segments HTTP/1.1\r\nHost: worker\r\n{headers}\r\n");
+        status_of(&worker.line, &request)
+    };
+    assert_eq!(post(""), "HTTP/1.1 401 Unauthorized");
+    assert_eq!(
+        post("Authorization: Bearer secreT\r\n"),
+        "HTTP/1.1 401 Unauthorized"
+    );
+    assert_eq!(
+        post("Authorization: Bearer secret\r\nContent-Length: 1000000000\r\n"),
+        "HTTP/1.1 413 Payload Too Large"
+    );
+    assert_eq!(post(&"X-Filler: 1\r\n".repeat(1000)), "");
+    assert_eq!(post(&format!("X-Filler: {}\r\n", "1".repeat(10000))), "");
+
+    let dir = scratch("open-worker");
+    let output = run(&dir, &["worker", "--listen", "0.0.0.0:0"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+/// An address on 127.0.0.1 nothing listens on.
+#[cfg(feature = "gui")]
+fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Runs `--gui` on a free address, and waits for `addrs` as well.
+#[cfg(feature = "gui")]
+fn start_gui(dir: &PathBuf, args: &[&str], addrs: &[&str]) -> Daemon {
+    let addr = free_addr();
+    let child = Command::new(BIN)
+        .args(["--gui", "--gui-listen", &addr])
+        .args(args)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    for addr in addrs.iter().copied().chain([addr.as_str()]) {
+        while TcpStream::connect(addr).is_err() {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+    }
+    Daemon { child, line: addr }
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn gui_refuses_requests_without_token_or_over_limits() {
+    let dir = scratch("gui-token");
+    let gui = start_gui(&dir, &["--gui-token", "secret"], &[]);
+    let request = |request: &str, headers: &str| {
+        let request = format!("{request} HTTP/1.1\r\nHost: {}\r\n{headers}\r\n", gui.line);
+        status_of(&gui.line, &request)
+    };
+    assert_eq!(request("GET /jobs", ""), "HTTP/1.1 401 Unauthorized");
+    assert_eq!(
+        request("GET /jobs", "Authorization: Bearer secreT\r\n"),
+        "HTTP/1.1 401 Unauthorized"
+    );
+    assert_eq!(
+        request("GET /jobs", "Cookie: theme=dark; token=secret\r\n"),
+        "HTTP/1.1 200 OK"
+    );
+    assert_eq!(request("GET /?token=secret", ""), "HTTP/1.1 200 OK");
+    assert_eq!(
+        request(
+            "POST /queue",
+            "Authorization: Bearer secret\r\nContent-Length: 1000000000\r\n"
+        ),
+        "HTTP/1.1 413 Payload Too Large"
+    );
+    assert_eq!(request("GET /jobs", &"X-Filler: 1\r\n".repeat(1000)), "");
+
+    let output = run(&dir, &["--gui", "--gui-listen", "0.0.0.0:0"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn gui_without_token_only_answers_its_page() {
+    let dir = scratch("gui-origin");
+    let gui = start_gui(&dir, &[], &[]);
+    let jobs =
+        |headers: &str| status_of(&gui.line, &format!("GET /jobs HTTP/1.1\r\n{headers}\r\n"));
+    let host = format!("Host: {}\r\n", gui.line);
+    let page = format!(
+        "{host}Origin: http://{}\r\nSec-Fetch-Site: same-origin\r\n",
+        gui.line
+    );
+    assert_eq!(jobs(&page), "HTTP/1.1 200 OK");
+    assert_eq!(jobs(&host), "HTTP/1.1 200 OK");
+    assert_eq!(jobs("Host: attacker.example\r\n"), "HTTP/1.1 403 Forbidden");
+    assert_eq!(
+        jobs(&format!("{host}Origin: http://attacker.example\r\n")),
+        "HTTP/1.1 403 Forbidden"
+    );
+    assert_eq!(
+        jobs(&format!("{host}Sec-Fetch-Site: cross-site\r\n")),
+        "HTTP/1.1 403 Forbidden"
+    );
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn gui_shows_the_download_history() {
+    let mock = Mock::start(false);
+    let dir = scratch("gui-history");
+    let output = download(&mock, &dir, &[]);
+    assert!(output.status.success(), "{output:?}");
+    let gui = start_gui(&dir, &[], &[]);
+    let downloads = || -> ureq::serde_json::Value {
+        (ureq::get(&format!("http://{}/history", gui.line)).call())
+            .unwrap()
+            .into_json()
+            .unwrap()
+    };
+    let path = dir.join("out.mp4");
+    let history = downloads();
+    assert_eq!(history.as_array().unwrap().len(), 1, "{history}");
+    assert_eq!(history[0]["rendition"], "v720");
+    assert_eq!(history[0]["size"], fs::metadata(&path).unwrap().len());
+    assert_eq!(history[0]["path"], path.to_str().unwrap());
+    assert_eq!(history[0]["present"], true);
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(downloads()[0]["present"], false);
+}
+
+#[cfg(feature = "grpc")]
+#[test]
+fn grpc_refuses_calls_without_token_or_over_limits() {
+    let dir = scratch("grpc-limits");
+    let grpc = free_addr();
+    let _gui = start_gui(&dir, &["--gui-token", "secret", "--grpc", &grpc], &[&grpc]);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let status = |token: &str, message: Vec<u8>| {
+        runtime.block_on(async {
+            let socket = tokio::net::TcpStream::connect(&grpc).await.unwrap();
+            let (client, connection) = h2::client::handshake(socket).await.unwrap();
+            tokio::spawn(connection);
+            let request = http::Request::post("/vimeo_event_downloader.v1.Queue/Cancel")
+                .header("content-type", "application/grpc")
+                .header("authorization", format!("Bearer {token}"))
+                .body(())
+                .unwrap();
+            let (response, mut body) = client
+                .ready()
+                .await
+                .unwrap()
+                .send_request(request, false)
+                .unwrap();
+            let mut frame = vec![0];
+            frame.extend((message.len() as u32).to_be_bytes());
+            frame.extend(message);
+            let _ = body.send_data(bytes::Bytes::from(frame), true);
+            let response = response.await.unwrap();
+            response.headers()["grpc-status"]
+                .to_str()
+                .unwrap()
+                .to_string()
+        })
+    };
+    // No job 0 to cancel, but the call got through.
+    assert_eq!(status("secret", Vec::new()), "5");
+    assert_eq!(status("secreT", Vec::new()), "16");
+    assert_eq!(status("secret", vec![0; 1 << 20]), "8");
+}
+
+#[test]
+fn uploads_to_s3() {
+    let mock = Mock::start(false);
+    let dir = scratch("s3");
+    let host = mock.event_url.trim_end_matches("/event");
+    let output = Command::new(BIN)
+        .args(["-u", &mock.event_url, "-r", "https://vimeo.com/"])
+        .args(["-f", "s3://bucket/{title}.mp4", "--s3-endpoint"])
+        .arg(format!("{host}/s3"))
+        .env("HOME", &dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("AWS_ACCESS_KEY_ID", "key")
+        .env("AWS_SECRET_ACCESS_KEY", "secret")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let object = format!("{host}/s3/bucket/Test%20Event.mp4");
+    assert_eq!(sha256_of_url(&object), mock.sha256);
+}
+
+#[test]
+fn uploads_to_webdav() {
+    let mock = Mock::start(false);
+    let dir = scratch("webdav");
+    let host = mock.event_url.trim_end_matches("/event");
+    let target = format!(
+        "{}/dav/videos/{{title}}.mp4",
+        host.replacen("http", "dav", 1)
+    );
+    let output = run(
+        &dir,
+        &[
+            "-u",
+            &mock.event_url,
+            "-r",
+            "https://vimeo.com/",
+            "-f",
+            &target,
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let object = format!("{host}/dav/videos/Test%20Event.mp4");
+    assert_eq!(sha256_of_url(&object), mock.sha256);
+}
+
+/// Accepts one mail on a free port, returning its address and the text of
+/// the mail once it is in.
+fn smtp_server() -> (String, std::thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let mail = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut out = stream.try_clone().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut mail = String::new();
+        out.write_all(b"220 mock\r\n").unwrap();
+        while let Some(Ok(line)) = lines.next() {
+            let reply = match line.split(' ').next().unwrap() {
+                "EHLO" => "250-mock\r\n250 8BITMIME\r\n",
+                "DATA" => {
+                    out.write_all(b"354 go ahead\r\n").unwrap();
+                    for line in lines.by_ref().map(Result::unwrap) {
+                        if line == "." {
+                            break;
+                        }
+                        mail += &line;
+                        mail.push('\n');
+                    }
+                    "250 queued\r\n"
+                }
+                "QUIT" => {
+                    out.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                }
+                _ => "250 ok\r\n",
+            };
+            out.write_all(reply.as_bytes()).unwrap();
+        }
+        mail
+    });
+    (addr, mail)
+}
+
+#[test]
+fn mails_notification() {
+    let mock = Mock::start(false);
+    let dir = scratch("notify");
+    let (addr, mail) = smtp_server();
+    let server = format!("smtp://{addr}");
+    let output = download(
+        &mock,
+        &dir,
+        &["--notify-email", "me@example.com", "--smtp-server", &server],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let mail = mail.join().unwrap();
+    assert!(mail.contains("To: me@example.com"), "{mail}");
+    assert!(mail.contains("Subject: Downloaded "), "{mail}");
+}
+
+/// Answers one HTTP request on a free port with 204, returning its address
+/// and the body of the request once it is in.
+fn webhook_server() -> (String, std::thread::JoinHandle<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let body = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut out = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        out.write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        body
+    });
+    (addr, body)
+}
+
+#[test]
+fn posts_webhook_notification() {
+    let mock = Mock::start(false);
+    let dir = scratch("webhook");
+    let (addr, body) = webhook_server();
+    let webhook = format!("discord:http://{addr}/api/webhooks/1/token");
+    let output = download(&mock, &dir, &["--notify-webhook", &webhook]);
+    assert!(output.status.success(), "{output:?}");
+    let message: ureq::serde_json::Value =
+        ureq::serde_json::from_slice(&body.join().unwrap()).unwrap();
+    let embed = &message["embeds"][0];
+    assert_eq!(embed["title"], "Downloaded Test Event");
+    assert!(embed["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|field| field["name"] == "Duration" && field["value"] == "0:00:12"));
+}
+
+#[test]
+fn rejects_invalid_durations() {
+    let dir = scratch("durations");
+    for option in [
+        "--max-av-drift=NaN",
+        "--max-av-drift=inf",
+        "--max-av-drift=-1",
+        "--sleep-requests=NaN",
+        "--retry-backoff=inf",
+        "--retry-max-delay=-1",
+    ] {
+        // Nothing listens there, the options are checked first.
+        let output = run(
+            &dir,
+            &[
+                "-u",
+                "http://127.0.0.1:1/",
+                "-r",
+                "https://vimeo.com/",
+                "-f",
+                "out.mp4",
+                option,
+            ],
+        );
+        assert_eq!(output.status.code(), Some(2), "{option}: {output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("number of seconds"), "{option}: {stderr}");
+    }
+}
+
+#[test]
+fn schedule_rejects_invalid_sizes() {
+    let dir = scratch("schedule-sizes");
+    for option in [
+        "--max-total=0",
+        "--max-total=-1",
+        "--max-total=NaN",
+        "--min-free=-1",
+        "--min-free=inf",
+    ] {
+        let output = run(
+            &dir,
+            &["schedule", "events.ics", "-r", "https://vimeo.com/", option],
+        );
+        assert_eq!(output.status.code(), Some(2), "{option}: {output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("number of GiB"), "{option}: {stderr}");
+    }
+}
+
+#[test]
+fn records_calendar_events() {
+    let mock = Mock::start(false);
+    let dir = scratch("schedule");
+    let calendar = dir.join("events.ics");
+    // An event going on, with its URL folded into the description, one
+    // long over and an all-day one without a time to start at.
+    let (head, tail) = mock.event_url.split_at(10);
+    let ics = format!(
+        "BEGIN:VCALENDAR\r\n\
+         BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Weekly webinar\r\n\
+         DTSTART:20000101T100000Z\r\nDTEND:29991231T100000Z\r\n\
+         DESCRIPTION:Join at {head}\r\n {tail}\\nSee you there\r\nEND:VEVENT\r\n\
+         BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Over\r\n\
+         DTSTART:20000101T100000Z\r\nDURATION:PT1H\r\nURL:{0}\r\nEND:VEVENT\r\n\
+         BEGIN:VEVENT\r\nUID:3\r\nSUMMARY:All day\r\n\
+         DTSTART;VALUE=DATE:20000101\r\nLOCATION:{0}\r\nEND:VEVENT\r\n\
+         END:VCALENDAR\r\n",
+        mock.event_url
+    );
+    fs::write(&calendar, ics).unwrap();
+    let output = run(
+        &dir,
+        &[
+            "schedule",
+            calendar.to_str().unwrap(),
+            "-r",
+            "https://vimeo.com/",
+            "--dir",
+            dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let recorded = dir.join("Weekly webinar 2000-01-01.mp4");
+    assert_eq!(sha256_of(recorded), mock.sha256);
+    assert!(!dir.join("Over 2000-01-01.mp4").exists());
+}
+
+#[test]
+fn records_overlapping_events_by_priority_and_keeps_the_latest() {
+    let mock = Mock::start(false);
+    let dir = scratch("schedule-priority");
+    let calendar = dir.join("events.ics");
+    let ics = format!(
+        "BEGIN:VCALENDAR\r\n\
+         BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Side stage\r\n\
+         DTSTART:20000101T100000Z\r\nDTEND:29991231T100000Z\r\nURL:{0}\r\nEND:VEVENT\r\n\
+         BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Keynote\r\nPRIORITY:1\r\n\
+         DTSTART:20000101T110000Z\r\nDTEND:29991231T100000Z\r\nURL:{0}\r\nEND:VEVENT\r\n\
+         BEGIN:VEVENT\r\nUID:3\r\nSUMMARY:Workshop\r\n\
+         DTSTART:20000101T120000Z\r\nDTEND:29991231T100000Z\r\nURL:{0}\r\nEND:VEVENT\r\n\
+         END:VCALENDAR\r\n",
+        mock.event_url
+    );
+    fs::write(&calendar, ics).unwrap();
+    let output = run(
+        &dir,
+        &[
+            "schedule",
+            calendar.to_str().unwrap(),
+            "-r",
+            "https://vimeo.com/",
+            "--dir",
+            dir.to_str().unwrap(),
+            "--max-recordings",
+            "1",
+            "--limit-rate",
+            "10M",
+            "--priority",
+            "^Work=2",
+            "--keep-last",
+            "2",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("2 events wait for another recording to finish"),
+        "{stderr}"
+    );
+    let order: Vec<_> = ["Keynote", "Workshop", "Side stage"]
+        .iter()
+        .map(|summary| stderr.find(&format!("Recording {summary} to")).unwrap())
+        .collect();
+    assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{stderr}");
+    for summary in ["Keynote", "Workshop"] {
+        let recorded = dir.join(format!("{summary} 2000-01-01.mp4"));
+        assert_eq!(sha256_of(recorded), mock.sha256);
+    }
+    // The event starting first is the oldest recording.
+    assert!(
+        stderr.contains("Deleting Side stage 2000-01-01.mp4"),
+        "{stderr}"
+    );
+    let left: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert!(
+        !left.iter().any(|name| name.starts_with("Side stage")),
+        "{left:?}"
+    );
+}
+
+#[test]
+fn dedupe_links_earlier_downloads() {
+    let mock = Mock::start(false);
+    let dir = scratch("dedupe");
+    let history = dir.join("history");
+    let output = download(&mock, &dir, &["--history", history.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let second = dir.join("second name.mp4");
+    let output = run(
+        &dir,
+        &[
+            "-u",
+            &mock.event_url,
+            "-r",
+            "https://vimeo.com/",
+            "-f",
+            second.to_str().unwrap(),
+            "--history",
+            history.to_str().unwrap(),
+            "--dedupe",
+            "link",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("to the earlier download"), "{stderr}");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = |path: &PathBuf| fs::metadata(path).unwrap().ino();
+        assert_eq!(inode(&second), inode(&dir.join("out.mp4")));
+    }
+    assert_eq!(sha256_of(second), mock.sha256);
+}
+
+#[test]
+fn ignore_errors_reports_gaps() {
+    let mock = Mock::with_args(&["--missing", "2"]);
+    let dir = scratch("ignore-errors");
+    let output = download(&mock, &dir, &["--retries", "0"]);
+    assert!(!output.status.success(), "{output:?}");
+
+    let output = download(&mock, &dir, &["--retries", "0", "--ignore-errors"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("v720 from 00:00:04.000 to 00:00:06.000 (2.0s)"),
+        "{stderr}"
+    );
+    let data = fs::read(dir.join("out.mp4")).unwrap();
+    assert_eq!(&data[16 + 100 + 101 + 4..][..4], b"free");
+}
+
+#[test]
+fn report_bundles_a_failed_download() {
+    let mock = Mock::with_args(&["--missing", "2"]);
+    let dir = scratch("report");
+    let output = Command::new(BIN)
+        .args([
+            "-u",
+            &mock.event_url,
+            "-r",
+            "https://vimeo.com/",
+            "-f",
+            "out.mp4",
+        ])
+        .args(["--retries", "0", "--report"])
+        .current_dir(&dir)
+        .env("HOME", &dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    let report = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|extension| extension == "zip"))
+        .unwrap();
+    let data = fs::read(report).unwrap();
+    assert_eq!(&data[..4], b"PK\x03\x04");
+    let text = String::from_utf8_lossy(&data);
+    for name in [
+        "error.txt",
+        "version.txt",
+        "options.json",
+        "config.json",
+        "master.json",
+        "requests.har",
+    ] {
+        assert!(text.contains(name), "{name}");
+    }
+}
+
+#[test]
+fn verify_detects_and_repair_fixes_corruption() {
+    let mock = Mock::start(false);
+    let dir = scratch("verify");
+    assert!(download(&mock, &dir, &[]).status.success());
+    let file = dir.join("out.mp4");
+    assert!(run(&dir, &["verify", file.to_str().unwrap()])
+        .status
+        .success());
+
+    let mut data = fs::read(&file).unwrap();
+    data[20] ^= 0xff;
+    fs::write(&file, data).unwrap();
+    let output = run(&dir, &["verify", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(8), "{output:?}");
+
+    let output = download(&mock, &dir, &["--repair"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(file), mock.sha256);
+}
+
+#[test]
+fn continue_redownloads_changed_segments() {
+    let mock = Mock::start(false);
+    let dir = scratch("continue");
+    let file = dir.join("out.mp4");
+    for concurrency in ["1", "2"] {
+        assert!(download(&mock, &dir, &[]).status.success());
+        let mut data = fs::read(&file).unwrap();
+        data[20] ^= 0xff;
+        data.truncate(data.len() - 50);
+        fs::write(&file, data).unwrap();
+
+        let output = download(&mock, &dir, &["--continue", "-c", concurrency]);
+        assert!(output.status.success(), "{output:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("changed since the last run"));
+        assert_eq!(sha256_of(file.clone()), mock.sha256);
+    }
+}
+
+#[test]
+fn continue_redownloads_torn_segments() {
+    let mock = Mock::start(false);
+    let dir = scratch("continue-torn");
+    let file = dir.join("out.mp4");
+    assert!(download(&mock, &dir, &[]).status.success());
+    // As if the power went out while the last segment was being written.
+    let ledger = dir.join("out.mp4.ledger");
+    let text = fs::read_to_string(&ledger).unwrap();
+    let mut lines: Vec<_> = text
+        .lines()
+        .filter(|line| !line.starts_with("? "))
+        .collect();
+    let last = lines
+        .iter()
+        .max_by_key(|line| line.split(' ').next().unwrap().parse::<usize>().unwrap())
+        .copied()
+        .unwrap();
+    lines.retain(|line| *line != last);
+    let fields: Vec<_> = last.split(' ').collect();
+    let (offset, size) = (
+        fields[1].parse::<usize>().unwrap(),
+        fields[2].parse::<usize>().unwrap(),
+    );
+    let intent = format!("? {} {offset} {size}", fields[0]);
+    fs::write(&ledger, lines.join("\n") + "\n" + &intent + "\n").unwrap();
+    let mut data = fs::read(&file).unwrap();
+    data[offset + size / 2..offset + size].fill(0);
+    fs::write(&file, data).unwrap();
+
+    let output = download(&mock, &dir, &["--continue"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "stopped while writing segment {}",
+            fields[0].parse::<usize>().unwrap() + 1
+        )),
+        "{stderr}"
+    );
+    assert_eq!(sha256_of(file.clone()), mock.sha256);
+}
+
+#[test]
+fn downloads_all_streams_listed_by_the_api() {
+    let mock = Mock::start(false);
+    let dir = scratch("all-streams");
+    let event = format!("{}/7/embed", mock.event_url);
+    let api = mock.event_url.replace("/event", "/api/v3");
+    let output = dir.join("out.mp4");
+    let args = [
+        "-u",
+        &event,
+        "-r",
+        "https://vimeo.com/",
+        "-f",
+        output.to_str().unwrap(),
+        "--api-token",
+        "secret",
+        "--api-url",
+        &api,
+    ];
+    let result = run(&dir, &[&args[..], &["--wait-for-live"]].concat());
+    assert!(result.status.success(), "{result:?}");
+    assert_eq!(sha256_of(output.clone()), mock.sha256);
+
+    let result = run(&dir, &[&args[..], &["--all-streams"]].concat());
+    assert!(result.status.success(), "{result:?}");
+    for day in ["2024-05-01", "2024-05-02"] {
+        assert_eq!(sha256_of(dir.join(format!("out {day}.mp4"))), mock.sha256);
+    }
+    // Still streaming, not archived yet.
+    assert!(!dir.join("out 2024-05-03.mp4").exists());
+
+    // Another run downloads only the occurrences it has not noted.
+    fs::remove_file(dir.join("out 2024-05-01.mp4")).unwrap();
+    let tracked = fs::read_to_string(dir.join("out.streams")).unwrap();
+    assert_eq!(tracked.lines().count(), 2);
+    let tracked: Vec<_> = tracked
+        .lines()
+        .filter(|line| !line.starts_with("/videos/102\t"))
+        .collect();
+    fs::write(dir.join("out.streams"), tracked.join("\n") + "\n").unwrap();
+    let result = run(&dir, &[&args[..], &["--all-streams"]].concat());
+    assert!(result.status.success(), "{result:?}");
+    assert!(String::from_utf8_lossy(&result.stderr).contains("1 not downloaded yet"));
+    assert!(!dir.join("out 2024-05-01.mp4").exists());
+    assert_eq!(sha256_of(dir.join("out 2024-05-02.mp4")), mock.sha256);
+}
+
+#[test]
+fn prints_info_json() {
+    let mock = Mock::start(false);
+    let dir = scratch("info-json");
+    let output = run(
+        &dir,
+        &[
+            "-u",
+            &mock.event_url,
+            "-r",
+            "https://vimeo.com/",
+            "--print-info-json",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let info: ureq::serde_json::Value = ureq::serde_json::from_slice(&output.stdout).unwrap();
+    // Two videos of the main manifest, one of the HEVC one and two audios.
+    assert_eq!(info["formats"].as_array().unwrap().len(), 5);
+    assert_eq!(info["requested_downloads"][0]["height"], 720);
+    // From oEmbed, the player config lacks them.
+    assert_eq!(info["uploader"], "Test Channel");
+    assert_eq!(info["upload_date"], "20240501");
+    // From the schema.org data of the page.
+    assert_eq!(info["description"], "Talks & demos");
+    assert_eq!(
+        info["cast"],
+        ureq::serde_json::json!(["Ada Lovelace", "Alan Turing"])
+    );
+    assert_eq!(info["release_date"], "20240502");
+}
+
+#[test]
+fn downloads_audio_language() {
+    let mock = Mock::start(false);
+    let dir = scratch("audio-lang");
+    let output = download(&mock, &dir, &["--audio-lang", "de"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sha256_of(dir.join("out.de.m4a")), mock.sha256);
+
+    let output = download(&mock, &dir, &["--audio-lang", "fr"]);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+}
+
+#[test]
+fn downloads_audio_channel_layout() {
+    let mock = Mock::start(false);
+    let dir = scratch("audio-channels");
+    let output = download(&mock, &dir, &["--audio-channels", "5.1"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("CHANNELS"), "{stderr}");
+    assert!(stderr.contains("stereo"), "{stderr}");
+    assert!(dir.join("out.de.m4a").exists());
+    assert!(!dir.join("out.en.m4a").exists());
+
+    // Nothing in mono, so the best of the others.
+    let output = download(&mock, &dir, &["--audio-channels", "mono"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = download(&mock, &dir, &["--audio-channels", "0"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[cfg(unix)]
+#[test]
+fn post_processes_output() {
+    let mock = Mock::start(false);
+    let dir = scratch("post-process");
+    let done = dir.join("done");
+    let output = download(
+        &mock,
+        &dir,
+        &[
+            "--move-to",
+            done.to_str().unwrap(),
+            "--exec",
+            "cp {} {}.copy",
+            "--write-nfo",
+            "--write-timestamps",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(!dir.join("out.mp4").exists());
+    assert!(done.join("out.mp4.ledger").exists());
+    let nfo = fs::read_to_string(done.join("out.nfo")).unwrap();
+    assert!(nfo.contains("<premiered>2024-05-02</premiered>"), "{nfo}");
+    assert!(nfo.contains("<name>Ada Lovelace</name>"), "{nfo}");
+    let timestamps = fs::read_to_string(done.join("out.mp4.timestamps")).unwrap();
+    assert_eq!(
+        timestamps.lines().nth(3),
+        Some("3 6.000 2024-05-02T18:00:06.000Z")
+    );
+    assert_eq!(sha256_of(done.join("out.mp4.copy")), mock.sha256);
+}
+
+#[cfg(unix)]
+#[test]
+fn moves_output_to_rclone_remote() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mock = Mock::start(false);
+    let dir = scratch("rclone");
+    // Stands in for rclone, with a local directory as the remote.
+    let bin = dir.join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("rclone"),
+        "#!/bin/sh\nmkdir -p \"$3\" && cp \"$2\" \"$3\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("rclone"), fs::Permissions::from_mode(0o755)).unwrap();
+    let remote = dir.join("remote");
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let output = Command::new(BIN)
+        .args(["-u", &mock.event_url, "-r", "https://vimeo.com/", "-f"])
+        .arg(dir.join("out.mp4"))
+        .args([
+            "--write-sha256",
+            "--delete-after-upload",
+            "--move-to-remote",
+        ])
+        .arg(&remote)
+        .env("HOME", &dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert!(!dir.join("out.mp4").exists());
+    assert!(remote.join("out.mp4.ledger").exists());
+    assert!(remote.join("out.mp4.sha256").exists());
+    assert_eq!(sha256_of(remote.join("out.mp4")), mock.sha256);
+}
+
+#[cfg(unix)]
+#[test]
+fn muxes_every_audio_track() {
+    let mock = Mock::start(false);
+    let dir = scratch("all-audio");
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--all-audio"]);
+    assert!(output.status.success(), "{output:?}");
+    let audio = |id: &str| dir.join(format!("out.{id}.m4a")).display().to_string();
+    assert!(
+        ffmpeg.contains(&format!("-i {} -i {}", audio("a-en"), audio("a-de"))),
+        "{ffmpeg}"
+    );
+    assert!(
+        ffmpeg.contains(
+            "-map 0:v -map 1:a -metadata:s:a:0 title=en -metadata:s:a:0 language=en \
+             -map 2:a -metadata:s:a:1 title=de -metadata:s:a:1 language=de"
+        ),
+        "{ffmpeg}"
+    );
+    assert!(!dir.join("out.a-en.m4a").exists());
+    assert!(!dir.join("out.a-de.m4a").exists());
+    assert_eq!(sha256_of(dir.join("out.mp4")), mock.sha256);
+}
+
+#[cfg(unix)]
+#[test]
+fn rewrites_output_as_progressive_mp4() {
+    let mock = Mock::start(false);
+    let dir = scratch("mp4-layout");
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--mp4-layout", "fragmented"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(ffmpeg, "");
+
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--mp4-layout", "progressive"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(ffmpeg.contains("-c copy -f mp4"), "{ffmpeg}");
+    assert!(!ffmpeg.contains("frag_keyframe"), "{ffmpeg}");
+
+    // The layout is of MP4 outputs only.
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--container", "mkv"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(ffmpeg.contains("-c copy -f matroska"), "{ffmpeg}");
+}
+
+#[cfg(unix)]
+#[test]
+fn renders_preview_sprite_at_keyframes() {
+    let mock = Mock::start(false);
+    let dir = scratch("preview-sprite");
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--preview-sprite", "4"]);
+    assert!(output.status.success(), "{output:?}");
+    // The middle of each quarter, moved back to the start of its segment.
+    for second in [0, 4, 6, 10] {
+        assert!(ffmpeg.contains(&format!("-ss {second} -i")), "{ffmpeg}");
+    }
+    assert!(ffmpeg.contains("scale=160:90"), "{ffmpeg}");
+    assert!(ffmpeg.contains("tile=4x1"), "{ffmpeg}");
+    assert!(dir.join("out.sprite.jpg").exists());
+    assert!(!dir.join("out.sprite.frames").exists());
+    let vtt = fs::read_to_string(dir.join("out.sprite.vtt")).unwrap();
+    assert_eq!(
+        vtt,
+        "WEBVTT\n\
+         \n00:00:00.000 --> 00:00:03.000\nout.sprite.jpg#xywh=0,0,160,90\n\
+         \n00:00:03.000 --> 00:00:06.000\nout.sprite.jpg#xywh=160,0,160,90\n\
+         \n00:00:06.000 --> 00:00:09.000\nout.sprite.jpg#xywh=320,0,160,90\n\
+         \n00:00:09.000 --> 00:00:12.000\nout.sprite.jpg#xywh=480,0,160,90\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn fills_gaps_from_another_rendition() {
+    let mock = Mock::with_args(&["--missing", "2"]);
+    let dir = scratch("fill-gaps");
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--retries", "0", "--fill-gaps"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Downloading v540h to fill the gap at 4.0s"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("v720 from 00:00:04.000"), "{stderr}");
+    let fill = dir.join("out.fill0.mp4");
+    assert!(
+        ffmpeg.contains(&format!(
+            "-ss 0 -t 2 -i {} -map 0:v -c:v libx264 -vf scale=1280:720",
+            fill.display()
+        )),
+        "{ffmpeg}"
+    );
+    // The stand-in copies its first input, here the list of pieces.
+    let list = fs::read_to_string(dir.join("out.mp4")).unwrap();
+    let unfilled = dir.join("out.unfilled.mp4");
+    let encoded = dir.join("out.fill0.encoded.mp4");
+    assert_eq!(
+        list,
+        format!(
+            "file '{0}'\ninpoint 0\noutpoint 4\nfile '{1}'\nfile '{0}'\ninpoint 6\n",
+            unfilled.display(),
+            encoded.display()
+        )
+    );
+    assert!(!fill.exists() && !encoded.exists() && !unfilled.exists());
+}
+
+#[cfg(all(unix, feature = "transcode"))]
+#[test]
+fn recodes_output_to_preset() {
+    let mock = Mock::start(false);
+    let dir = scratch("recode");
+    let (output, ffmpeg) = download_with_ffmpeg(&mock, &dir, &["--recode", "540p-h264"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        ffmpeg.contains(
+            "-c:v libx264 -preset medium -crf 20 -vf scale=-2:'min(540,ih)' -pix_fmt yuv420p \
+             -c:a aac -b:a 192k -ar 48000 -movflags +faststart -f mp4"
+        ),
+        "{ffmpeg}"
+    );
+    // The segments are not where the ledger says anymore.
+    assert!(!dir.join("out.mp4.ledger").exists());
+
+    for args in [
+        ["--recode", "541p-h264"],
+        ["--recode", "720p-vp8"],
+        ["--recode=720p-av1", "--hwaccel=videotoolbox"],
+    ] {
+        let output = download(&mock, &dir, &args);
+        assert_eq!(output.status.code(), Some(2), "{output:?}");
+    }
+}
+
+#[test]
+fn extracts_audio() {
+    let mock = Mock::start(false);
+    let dir = scratch("extract-audio");
+    let output = download(&mock, &dir, &["--extract-audio"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(fs::metadata(dir.join("out.m4a")).unwrap().len() > 0);
+}